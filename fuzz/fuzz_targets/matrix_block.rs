@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use matrix_mul::Encoding;
+
+// Blocks come from the shared log, so any string can show up. A decoded
+// binary block must encode back to the same bits.
+fuzz_target!(|value: &str| {
+    if let Ok(block) = Encoding::Binary.decode_block(value) {
+        let encoded = Encoding::Binary.encode_block(&block);
+        let decoded = Encoding::Binary.decode_block(&encoded).expect("re-encoded block must decode");
+
+        assert_eq!(block.len(), decoded.len());
+        for (a, b) in block.iter().flatten().zip(decoded.iter().flatten()) {
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+    }
+});
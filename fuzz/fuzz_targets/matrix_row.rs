@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Rows come from the shared log, so any string can show up. A parsed row
+// must survive being written back the way `load_matrices` writes it.
+fuzz_target!(|value: &str| {
+    if let Ok(row) = matrix_mul::parse_row(value) {
+        let encoded = row.iter().map(f64::to_string).collect::<Vec<_>>().join(",");
+        let reparsed = matrix_mul::parse_row(&encoded).expect("re-encoded row must parse");
+
+        assert_eq!(row.len(), reparsed.len());
+        for (a, b) in row.iter().zip(&reparsed) {
+            // NaN loses its sign and payload in Display.
+            assert!(a == b || (a.is_nan() && b.is_nan()), "{} became {}", a, b);
+        }
+    }
+});
@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use log_snapshot_format::{decode, encode, Decoder};
+
+// Snapshots are read from disk by the server, possibly cut short by a crash
+// mid-write, and from the network by clients. Feeding the same bytes in
+// arbitrary pieces must not change the result, and whatever decodes must
+// encode back to the same bytes.
+fuzz_target!(|input: (u16, &[u8])| {
+    let (chunk, data) = input;
+    let whole = decode(data);
+
+    let mut decoder = Decoder::new();
+    let mut records = Vec::new();
+    let mut fed = Ok(());
+    for piece in data.chunks(chunk.max(1) as usize) {
+        fed = decoder.feed(piece, |key, value| records.push((key, value)));
+        if fed.is_err() {
+            break;
+        }
+    }
+    let chunked = fed.and_then(|()| decoder.finish()).map(|()| records);
+    assert_eq!(whole, chunked);
+
+    if let Ok(records) = whole {
+        // Keys are decoded lossily, so only valid UTF-8 round-trips exactly.
+        if records.iter().all(|(key, _)| !key.contains('\u{FFFD}')) {
+            assert_eq!(encode(&records).unwrap(), data);
+        }
+    }
+});
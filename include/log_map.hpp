@@ -3,6 +3,8 @@
 #include <string>
 #include <stdexcept>
 #include <optional>
+#include <vector>
+#include <cstdint>
 
 extern "C" {
     struct LogMapHandle;
@@ -16,10 +18,29 @@ extern "C" {
         LOGMAP_GET_ERROR = 4,
         LOGMAP_INSERT_ERROR = 5,
         LOGMAP_REMOVE_ERROR = 6,
+        LOGMAP_INVALID_ARGUMENT = 7,
+        LOGMAP_UNSUPPORTED = 8,
+        LOGMAP_VALUE_TOO_LARGE = 9,
+        LOGMAP_IN_CALLBACK = 10,
         LOGMAP_INTERNAL_ERROR = 99
     };
 
+    // Null pointers leave a setting unset. TLS needs the library built
+    // with the `tls` feature, otherwise connecting fails with
+    // LOGMAP_UNSUPPORTED.
+    struct LogMapConnectOptions {
+        int use_tls;
+        const char* ca_cert_pem;
+        const char* tls_domain;
+        const char* token;
+    };
+
     ErrorCode logmap_connect(const char* addr, logmap_handle_t* handle_out);
+    ErrorCode logmap_connect_ex(const char* addr, const LogMapConnectOptions* options, logmap_handle_t* handle_out);
+    // A log-server of the handle's own, in this process and in memory, for
+    // tests. Needs the library built with the `embedded` feature,
+    // otherwise fails with LOGMAP_UNSUPPORTED.
+    ErrorCode logmap_connect_in_memory(logmap_handle_t* handle_out);
     ErrorCode logmap_free(logmap_handle_t handle);
     ErrorCode logmap_get(logmap_handle_t handle, long key, char** value_out);
     ErrorCode logmap_insert(logmap_handle_t handle, long key, const char* value);
@@ -27,7 +48,34 @@ extern "C" {
     int logmap_contains_key(logmap_handle_t handle, long key);
     size_t logmap_len(logmap_handle_t handle);
     int logmap_is_empty(logmap_handle_t handle);
+    // Keys come back in ascending order; free them with logmap_keys_free.
+    ErrorCode logmap_keys(logmap_handle_t handle, long** keys_out, size_t* len_out);
+    void logmap_keys_free(long* keys, size_t len);
     void logmap_string_free(char* s);
+
+    // Async variants return at once; on LOGMAP_SUCCESS the callback runs
+    // exactly once, on a library thread. `value` is only set by
+    // logmap_get_async (null if the key is absent) and must be freed
+    // with logmap_string_free. Callbacks here and below must not make
+    // blocking calls (logmap_connect, logmap_get, logmap_insert, ...):
+    // those fail with LOGMAP_IN_CALLBACK on library threads. Use the
+    // async variants instead.
+    typedef void (*logmap_callback_t)(void* user_data, ErrorCode error, char* value);
+    typedef void (*logmap_connect_callback_t)(void* user_data, ErrorCode error, logmap_handle_t handle);
+
+    ErrorCode logmap_connect_async(const char* addr, const LogMapConnectOptions* options, logmap_connect_callback_t callback, void* user_data);
+    ErrorCode logmap_get_async(logmap_handle_t handle, long key, logmap_callback_t callback, void* user_data);
+    ErrorCode logmap_insert_async(logmap_handle_t handle, long key, const char* value, logmap_callback_t callback, void* user_data);
+    ErrorCode logmap_remove_async(logmap_handle_t handle, long key, logmap_callback_t callback, void* user_data);
+
+    // Change callbacks run on a library thread, one at a time. `value` is
+    // null when `deleted` is set and only valid during the call. A
+    // callback already running may finish after logmap_unsubscribe.
+    using logmap_subscription_t = void*;
+    typedef void (*logmap_change_callback_t)(void* user_data, long key, const char* value, uint64_t ordinal, int deleted);
+
+    ErrorCode logmap_subscribe(logmap_handle_t handle, const char* key_prefix, logmap_change_callback_t callback, void* user_data, logmap_subscription_t* subscription_out);
+    ErrorCode logmap_unsubscribe(logmap_subscription_t subscription);
 }
 
 namespace log_map {
@@ -51,6 +99,10 @@ private:
             case LOGMAP_GET_ERROR:         return "Get error";
             case LOGMAP_INSERT_ERROR:      return "Insert error";
             case LOGMAP_REMOVE_ERROR:      return "Remove error";
+            case LOGMAP_INVALID_ARGUMENT:  return "Invalid argument";
+            case LOGMAP_UNSUPPORTED:       return "Not supported by this build";
+            case LOGMAP_VALUE_TOO_LARGE:   return "Value too large for the server";
+            case LOGMAP_IN_CALLBACK:       return "Blocking call from a callback";
             case LOGMAP_INTERNAL_ERROR:    return "Internal error";
             default:                       return "Unknown error";
         }
@@ -103,6 +155,13 @@ private:
     char* _ptr;
 };
 
+struct connect_options {
+    bool tls = false;
+    std::string ca_cert_pem;
+    std::string tls_domain;
+    std::string token;
+};
+
 class LogMap {
 public:
     LogMap() : _handle(nullptr) {}
@@ -133,12 +192,38 @@ public:
         return *this;
     }
 
+    LogMap(const std::string& addr, const connect_options& options) : _handle(nullptr) {
+        connect(addr, options);
+    }
+
     void connect(const std::string& addr) {
         logmap_handle_t handle;
         check_error(logmap_connect(addr.c_str(), &handle));
         _handle = handle;
     }
 
+    void connect(const std::string& addr, const connect_options& options) {
+        auto optional = [](const std::string& s) { return s.empty() ? nullptr : s.c_str(); };
+        LogMapConnectOptions raw{
+            options.tls ? 1 : 0,
+            optional(options.ca_cert_pem),
+            optional(options.tls_domain),
+            optional(options.token),
+        };
+        logmap_handle_t handle;
+        check_error(logmap_connect_ex(addr.c_str(), &raw, &handle));
+        _handle = handle;
+    }
+
+    // A map on a private in-memory server, for tests.
+    static LogMap in_memory() {
+        logmap_handle_t handle;
+        check_error(logmap_connect_in_memory(&handle));
+        LogMap map;
+        map._handle = handle;
+        return map;
+    }
+
     std::optional<std::string> get(long key) const {
         char* value_out;
         check_error(logmap_get(_handle, key, &value_out));
@@ -163,6 +248,15 @@ public:
         return logmap_contains_key(_handle, key) != 0;
     }
 
+    std::vector<long> keys() const {
+        long* keys_out;
+        size_t len;
+        check_error(logmap_keys(_handle, &keys_out, &len));
+        std::vector<long> keys(keys_out, keys_out + len);
+        logmap_keys_free(keys_out, len);
+        return keys;
+    }
+
     size_t len() const {
         return logmap_len(_handle);
     }
@@ -0,0 +1,87 @@
+//! Key distributions for generated writes.
+
+use std::str::FromStr;
+
+use rand::Rng;
+
+/// How writers pick keys out of `0..keys`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyDistribution {
+    /// Every key equally likely.
+    Uniform,
+    /// Key `i` has weight `1 / (i + 1)^s`; higher `s` means hotter keys and
+    /// more conflicts.
+    Zipf(f64),
+    /// Each writer walks the key space in order.
+    Sequential,
+}
+
+impl FromStr for KeyDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(KeyDistribution::Uniform),
+            "sequential" => Ok(KeyDistribution::Sequential),
+            other => {
+                let exponent = other
+                    .strip_prefix("zipf:")
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .filter(|s| *s > 0.0);
+                exponent.map(KeyDistribution::Zipf).ok_or_else(|| {
+                    format!(
+                        "unknown distribution '{}', expected uniform, sequential or zipf:<s>",
+                        other
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// Per-writer key generator.
+pub struct KeySampler {
+    keys: u64,
+    next: u64,
+    /// Cumulative weights, only for Zipf.
+    cdf: Vec<f64>,
+}
+
+impl KeySampler {
+    pub fn new(distribution: &KeyDistribution, keys: u64, offset: u64) -> Self {
+        let cdf = match distribution {
+            KeyDistribution::Zipf(s) => {
+                let mut total = 0.0;
+                (0..keys)
+                    .map(|i| {
+                        total += 1.0 / ((i + 1) as f64).powf(*s);
+                        total
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        Self {
+            keys,
+            next: offset % keys,
+            cdf,
+        }
+    }
+
+    pub fn sample(&mut self, distribution: &KeyDistribution, rng: &mut impl Rng) -> u64 {
+        match distribution {
+            KeyDistribution::Uniform => rng.gen_range(0..self.keys),
+            KeyDistribution::Sequential => {
+                let key = self.next;
+                self.next = (self.next + 1) % self.keys;
+                key
+            }
+            KeyDistribution::Zipf(_) => {
+                let total = self.cdf.last().copied().unwrap_or(1.0);
+                let target = rng.gen_range(0.0..total);
+                self.cdf.partition_point(|weight| *weight < target) as u64
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Load generator for log-server.
+//!
+//! [`run`] drives a server with concurrent writers and subscribers for a fixed
+//! duration and returns a [`Report`] with throughput, write latency,
+//! delivery lag and the conflict rate. Writers keep one write stream open
+//! each and wait for every response before sending the next request, so the
+//! measured latency is the full round trip of a single write.
+
+pub mod dist;
+pub mod report;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use log_server_types::kv::kv_server_client::KvServerClient;
+use log_server_types::kv::{StatsRequest, SubscribeRequest, WriteRequest};
+use log_server_types::metadata::Handshake;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Channel, Endpoint};
+
+pub use crate::dist::KeyDistribution;
+pub use crate::report::Latency;
+
+use crate::dist::KeySampler;
+
+type Client = KvServerClient<InterceptedService<Channel, Handshake>>;
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What to run against the server.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `host:port` of the server.
+    pub addr: String,
+    pub writers: usize,
+    pub subscribers: usize,
+    pub duration: Duration,
+    /// Size of the key space, keys are `<prefix><n>`.
+    pub keys: u64,
+    pub key_prefix: String,
+    pub distribution: KeyDistribution,
+    pub value_size: usize,
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            addr: "localhost:50051".to_string(),
+            writers: 4,
+            subscribers: 1,
+            duration: Duration::from_secs(10),
+            keys: 1000,
+            key_prefix: "bench:".to_string(),
+            distribution: KeyDistribution::Uniform,
+            value_size: 64,
+            seed: 0,
+        }
+    }
+}
+
+/// Results of a run.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub elapsed: Duration,
+    pub accepted: u64,
+    pub conflicts: u64,
+    pub errors: u64,
+    /// Round trip of each write, accepted or not.
+    pub write_latency: Latency,
+    /// Records received over all subscribers.
+    pub delivered: u64,
+    /// Time from the server stamping a record to a subscriber receiving it.
+    /// Millisecond resolution, since that is what record timestamps carry.
+    pub delivery_lag: Latency,
+}
+
+impl Report {
+    pub fn writes(&self) -> u64 {
+        self.accepted + self.conflicts + self.errors
+    }
+
+    pub fn writes_per_sec(&self) -> f64 {
+        self.writes() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn conflict_rate(&self) -> f64 {
+        match self.writes() {
+            0 => 0.0,
+            writes => self.conflicts as f64 / writes as f64,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        writeln!(f, "duration:      {:.1}s", secs)?;
+        writeln!(
+            f,
+            "writes:        {} ({:.0}/s), {} accepted, {} conflicts ({:.2}%), {} errors",
+            self.writes(),
+            self.writes_per_sec(),
+            self.accepted,
+            self.conflicts,
+            self.conflict_rate() * 100.0,
+            self.errors
+        )?;
+        writeln!(f, "write latency: {}", self.write_latency)?;
+        writeln!(
+            f,
+            "delivered:     {} ({:.0}/s)",
+            self.delivered,
+            self.delivered as f64 / secs
+        )?;
+        writeln!(f, "delivery lag:  {}", self.delivery_lag)
+    }
+}
+
+#[derive(Default)]
+struct WriterResult {
+    accepted: u64,
+    conflicts: u64,
+    errors: u64,
+    latencies: Vec<Duration>,
+}
+
+/// Runs the load described by `config` and waits for it to finish.
+pub async fn run(config: &Config) -> Result<Report, BoxError> {
+    if config.keys == 0 {
+        return Err("the key space must not be empty".into());
+    }
+
+    let mut client = connect(&config.addr).await?;
+    let start_ordinal = client.stats(StatsRequest {}).await?.into_inner().latest_ordinal;
+
+    let started = Instant::now();
+    let deadline = started + config.duration;
+
+    let mut subscribers = Vec::with_capacity(config.subscribers);
+    for _ in 0..config.subscribers {
+        subscribers.push(tokio::spawn(subscriber(
+            client.clone(),
+            start_ordinal,
+            deadline,
+        )));
+    }
+
+    let mut writers = Vec::with_capacity(config.writers);
+    for id in 0..config.writers {
+        writers.push(tokio::spawn(writer(
+            client.clone(),
+            config.clone(),
+            id as u64,
+            start_ordinal,
+            deadline,
+        )));
+    }
+
+    let mut report = Report::default();
+    let mut write_latencies = Vec::new();
+    for handle in writers {
+        let result = handle.await??;
+        report.accepted += result.accepted;
+        report.conflicts += result.conflicts;
+        report.errors += result.errors;
+        write_latencies.extend(result.latencies);
+    }
+    report.elapsed = started.elapsed();
+
+    let mut lags = Vec::new();
+    for handle in subscribers {
+        let received = handle.await??;
+        report.delivered += received.len() as u64;
+        lags.extend(received);
+    }
+
+    report.write_latency = Latency::from_samples(write_latencies);
+    report.delivery_lag = Latency::from_samples(lags);
+    Ok(report)
+}
+
+async fn connect(addr: &str) -> Result<Client, tonic::transport::Error> {
+    let channel = Endpoint::from_shared(format!("http://{}", addr))?
+        .connect()
+        .await?;
+    let handshake = Handshake::new(concat!("log-bench/", env!("CARGO_PKG_VERSION")), &[]);
+    Ok(KvServerClient::with_interceptor(channel, handshake))
+}
+
+async fn writer(
+    mut client: Client,
+    config: Config,
+    id: u64,
+    mut latest_known: u64,
+    deadline: Instant,
+) -> Result<WriterResult, BoxError> {
+    let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(id));
+    let mut keys = KeySampler::new(&config.distribution, config.keys, id * config.keys / config.writers as u64);
+    let mut value = vec![0u8; config.value_size];
+    let client_id = format!("log-bench-{:x}-{}", std::process::id(), id);
+
+    let (requests, rx) = mpsc::channel(1);
+    let mut responses = client.write(ReceiverStream::new(rx)).await?.into_inner();
+    let mut result = WriterResult::default();
+
+    while Instant::now() < deadline {
+        rng.fill(value.as_mut_slice());
+        let key = keys.sample(&config.distribution, &mut rng);
+        let request = WriteRequest {
+            ordinal: 0,
+            key: format!("{}{}", config.key_prefix, key),
+            value: value.clone(),
+            latest_known,
+            client_id: client_id.clone(),
+            worker_label: format!("writer-{}", id),
+            if_unchanged: false,
+            expires_at: 0,
+        };
+
+        let sent = Instant::now();
+        requests.send(request).await?;
+        let response = responses
+            .message()
+            .await?
+            .ok_or("server closed the write stream")?;
+        result.latencies.push(sent.elapsed());
+
+        if response.accepted {
+            result.accepted += 1;
+        } else if response.error.starts_with("Conflict") {
+            result.conflicts += 1;
+        } else {
+            result.errors += 1;
+        }
+        latest_known = latest_known.max(response.assigned_ordinal);
+    }
+
+    Ok(result)
+}
+
+/// Follows the log from `from` until `deadline`, returning the delivery lag
+/// of every record received.
+async fn subscriber(
+    mut client: Client,
+    from: u64,
+    deadline: Instant,
+) -> Result<Vec<Duration>, BoxError> {
+    let mut stream = client
+        .subscribe(SubscribeRequest { start_ordinal: from, ..Default::default() })
+        .await?
+        .into_inner();
+    let mut lags = Vec::new();
+
+    loop {
+        let record = match tokio::time::timeout_at(deadline.into(), stream.message()).await {
+            Err(_) => break,
+            Ok(record) => match record? {
+                Some(record) => record,
+                None => break,
+            },
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        let lag = u64::try_from(now - record.timestamp).unwrap_or(0);
+        lags.push(Duration::from_millis(lag));
+    }
+
+    Ok(lags)
+}
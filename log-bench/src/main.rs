@@ -0,0 +1,77 @@
+//! `log-bench` — generate load against a log-server and report how it held up.
+
+use std::env;
+use std::time::Duration;
+
+use log_bench::Config;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if has_flag(&args, "--help") {
+        usage();
+    }
+
+    let defaults = Config::default();
+    let config = Config {
+        addr: flag_value(&args, "--addr").map_or(defaults.addr, str::to_string),
+        writers: parse_flag(&args, "--writers", defaults.writers),
+        subscribers: parse_flag(&args, "--subscribers", defaults.subscribers),
+        duration: Duration::from_secs(parse_flag(&args, "--duration", defaults.duration.as_secs())),
+        keys: parse_flag(&args, "--keys", defaults.keys),
+        key_prefix: flag_value(&args, "--key-prefix").map_or(defaults.key_prefix, str::to_string),
+        distribution: parse_flag(&args, "--distribution", defaults.distribution),
+        value_size: parse_flag(&args, "--value-size", defaults.value_size),
+        seed: parse_flag(&args, "--seed", defaults.seed),
+    };
+
+    eprintln!(
+        "{} writers, {} subscribers, {} keys ({:?}), {}-byte values for {}s against {}",
+        config.writers,
+        config.subscribers,
+        config.keys,
+        config.distribution,
+        config.value_size,
+        config.duration.as_secs(),
+        config.addr
+    );
+    let report = log_bench::run(&config).await?;
+    print!("{}", report);
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: log-bench [options]");
+    eprintln!("Options:");
+    eprintln!("  --addr <host:port>      - Server to load, localhost:50051 by default");
+    eprintln!("  --writers <n>           - Concurrent writers (4)");
+    eprintln!("  --subscribers <n>       - Concurrent subscribers following the log (1)");
+    eprintln!("  --duration <secs>       - How long to run (10)");
+    eprintln!("  --keys <n>              - Size of the key space (1000)");
+    eprintln!("  --key-prefix <p>        - Prefix for generated keys (bench:)");
+    eprintln!("  --distribution <d>      - uniform, sequential or zipf:<s> (uniform)");
+    eprintln!("  --value-size <bytes>    - Size of each written value (64)");
+    eprintln!("  --seed <n>              - Seed for key and value generation (0)");
+    std::process::exit(2);
+}
+
+fn parse_flag<T: std::str::FromStr>(args: &[String], name: &str, default: T) -> T {
+    match flag_value(args, name) {
+        None => default,
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("invalid value for {}: {}", name, value);
+            usage();
+        }),
+    }
+}
+
+fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
@@ -0,0 +1,46 @@
+//! Latency summaries for the final report.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Percentiles over a set of samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Latency {
+    pub count: usize,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Latency {
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+
+        let percentile = |p: f64| {
+            let index = ((samples.len() as f64 * p).ceil() as usize).saturating_sub(1);
+            samples[index.min(samples.len() - 1)]
+        };
+
+        Self {
+            count: samples.len(),
+            p50: percentile(0.50),
+            p99: percentile(0.99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+            self.p50.as_secs_f64() * 1000.0,
+            self.p99.as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0
+        )
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use log_bench::{Config, KeyDistribution};
+use log_server_test::TestServer;
+
+#[tokio::test]
+async fn test_short_run_reports_writes_and_deliveries() {
+    let server = TestServer::spawn().await;
+    let config = Config {
+        addr: server.addr().to_string(),
+        writers: 2,
+        subscribers: 1,
+        duration: Duration::from_millis(300),
+        keys: 10,
+        distribution: KeyDistribution::Zipf(1.2),
+        value_size: 16,
+        ..Config::default()
+    };
+
+    let report = log_bench::run(&config).await.unwrap();
+
+    assert!(report.accepted > 0);
+    assert_eq!(report.errors, 0);
+    assert_eq!(report.write_latency.count as u64, report.writes());
+    assert!(report.delivered > 0);
+    assert!(report.delivered <= report.accepted);
+    assert!(report.write_latency.p50 <= report.write_latency.p99);
+
+    server.shutdown().await;
+}
+
+#[test]
+fn test_parses_distributions() {
+    assert_eq!("uniform".parse(), Ok(KeyDistribution::Uniform));
+    assert_eq!("sequential".parse(), Ok(KeyDistribution::Sequential));
+    assert_eq!("zipf:1.1".parse(), Ok(KeyDistribution::Zipf(1.1)));
+    assert!("zipf:0".parse::<KeyDistribution>().is_err());
+    assert!("normal".parse::<KeyDistribution>().is_err());
+}
@@ -1,5 +1,10 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 use std::ffi::{c_char, c_void, CStr, CString};
 use std::ptr;
+use std::sync::{Arc, OnceLock};
+
+use futures_util::StreamExt;
 
 type LogMapHandle = *mut c_void;
 
@@ -12,16 +17,47 @@ pub enum ErrorCode {
     GetError = 4,
     InsertError = 5,
     RemoveError = 6,
+    InvalidArgument = 7,
+    Unsupported = 8,
+    ValueTooLarge = 9,
+    /// A blocking call made from a callback, on one of the library's own
+    /// threads; use the async variant there instead.
+    InCallback = 10,
     InternalError = 99,
 }
 
+/// Options for [`logmap_connect_ex`]. Null pointers leave a setting unset.
+#[repr(C)]
+pub struct LogMapConnectOptions {
+    /// Non-zero to connect over TLS; needs the library built with `tls`.
+    pub use_tls: i32,
+    /// PEM certificate of a private CA to trust besides the system roots.
+    pub ca_cert_pem: *const c_char,
+    /// Name the server certificate must be issued for.
+    pub tls_domain: *const c_char,
+    /// Sent as `authorization: Bearer <token>` with every call.
+    pub token: *const c_char,
+}
+
 impl From<log_map::Error> for ErrorCode {
     fn from(err: log_map::Error) -> Self {
         match err {
             log_map::Error::Transport(_) => ErrorCode::ConnectError,
             log_map::Error::Status(_) => ErrorCode::GetError,
             log_map::Error::Conflict(_) => ErrorCode::InsertError,
+            log_map::Error::Timeout(_) => ErrorCode::InsertError,
             log_map::Error::ConnectionClosed => ErrorCode::InternalError,
+            log_map::Error::NoServers => ErrorCode::InvalidArgument,
+            log_map::Error::UnexpectedResponse { .. } => ErrorCode::InternalError,
+            log_map::Error::Codec(_) => ErrorCode::InternalError,
+            log_map::Error::ValueTooLarge { .. } => ErrorCode::ValueTooLarge,
+            log_map::Error::Unsupported(_) => ErrorCode::Unsupported,
+            log_map::Error::InvalidToken => ErrorCode::InvalidArgument,
+            log_map::Error::InvalidNamespace(_) => ErrorCode::InvalidArgument,
+            log_map::Error::Lagged(_) => ErrorCode::InternalError,
+            log_map::Error::SyncTimeout(_) => ErrorCode::GetError,
+            log_map::Error::LeaseLost(_) => ErrorCode::InternalError,
+            log_map::Error::Http(_) => ErrorCode::InternalError,
             log_map::Error::Internal(_) => ErrorCode::InternalError,
         }
     }
@@ -29,6 +65,17 @@ impl From<log_map::Error> for ErrorCode {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn logmap_connect(addr: *const c_char, handle_out: *mut LogMapHandle) -> ErrorCode {
+    logmap_connect_ex(addr, ptr::null(), handle_out)
+}
+
+/// Like [`logmap_connect`], with TLS and a token from `options`, which may
+/// be null.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_connect_ex(
+    addr: *const c_char,
+    options: *const LogMapConnectOptions,
+    handle_out: *mut LogMapHandle,
+) -> ErrorCode {
     if addr.is_null() || handle_out.is_null() {
         return ErrorCode::NullPointer;
     }
@@ -39,21 +86,89 @@ pub extern "C" fn logmap_connect(addr: *const c_char, handle_out: *mut LogMapHan
         Err(_) => return ErrorCode::InvalidUtf8,
     };
 
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let map = rt.block_on(log_map::LogMap::connect(addr));
+    let config = match unsafe { options.as_ref() }.map(connect_config).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(code) => return code,
+    };
+
+    let map = match block_on(log_map::LogMap::connect_with_config(addr, config)) {
+        Ok(map) => map,
+        Err(code) => return code,
+    };
 
     let map = match map {
         Ok(m) => m,
         Err(e) => return ErrorCode::from(e),
     };
 
-    let wrapper = LogMapWrapper { map, rt };
-    let boxed = Box::new(wrapper);
-    unsafe { *handle_out = Box::into_raw(boxed) as *mut c_void };
+    unsafe { *handle_out = new_handle(map) };
 
     ErrorCode::Success
 }
 
+/// Connects to a log-server of the handle's own, inside this process and
+/// in memory, for tests. It stops when the handle is freed. Needs the
+/// library built with `embedded`.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_connect_in_memory(handle_out: *mut LogMapHandle) -> ErrorCode {
+    if handle_out.is_null() {
+        return ErrorCode::NullPointer;
+    }
+
+    #[cfg(feature = "embedded")]
+    {
+        let map = match block_on(log_map::LogMap::in_memory()) {
+            Ok(Ok(m)) => m,
+            Ok(Err(e)) => return ErrorCode::from(e),
+            Err(code) => return code,
+        };
+        unsafe { *handle_out = new_handle(map) };
+        ErrorCode::Success
+    }
+    #[cfg(not(feature = "embedded"))]
+    {
+        ErrorCode::Unsupported
+    }
+}
+
+fn connect_config(options: &LogMapConnectOptions) -> Result<log_map::ConnectConfig, ErrorCode> {
+    let token = optional_str(options.token)?;
+    let ca_cert = optional_str(options.ca_cert_pem)?;
+    let domain = optional_str(options.tls_domain)?;
+
+    let mut config = log_map::ConnectConfig {
+        token,
+        ..Default::default()
+    };
+    if options.use_tls == 0 {
+        return Ok(config);
+    }
+    #[cfg(feature = "tls")]
+    {
+        config.tls = Some(log_map::TlsConfig {
+            ca_cert: ca_cert.map(String::into_bytes),
+            domain,
+        });
+        Ok(config)
+    }
+    #[cfg(not(feature = "tls"))]
+    {
+        let _ = (&mut config, ca_cert, domain);
+        Err(ErrorCode::Unsupported)
+    }
+}
+
+/// Copies a nullable C string.
+fn optional_str(s: *const c_char) -> Result<Option<String>, ErrorCode> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    match unsafe { CStr::from_ptr(s) }.to_str() {
+        Ok(s) => Ok(Some(s.to_string())),
+        Err(_) => Err(ErrorCode::InvalidUtf8),
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn logmap_free(handle: LogMapHandle) -> ErrorCode {
     if handle.is_null() {
@@ -78,9 +193,11 @@ pub extern "C" fn logmap_get(
     }
 
     let wrapper = unsafe { &*(handle as *const LogMapWrapper) };
-    let rt = &wrapper.rt;
 
-    let result = rt.block_on(wrapper.map.get(key));
+    let result = match block_on(wrapper.map.get(key)) {
+        Ok(result) => result,
+        Err(code) => return code,
+    };
 
     match result {
         Ok(Some(value)) => {
@@ -116,9 +233,11 @@ pub extern "C" fn logmap_insert(
     };
 
     let wrapper = unsafe { &*(handle as *const LogMapWrapper) };
-    let rt = &wrapper.rt;
 
-    let result = rt.block_on(wrapper.map.insert(key, value));
+    let result = match block_on(wrapper.map.insert(key, value)) {
+        Ok(result) => result,
+        Err(code) => return code,
+    };
 
     match result {
         Ok(_) => ErrorCode::Success,
@@ -133,9 +252,11 @@ pub extern "C" fn logmap_remove(handle: LogMapHandle, key: i64) -> ErrorCode {
     }
 
     let wrapper = unsafe { &*(handle as *const LogMapWrapper) };
-    let rt = &wrapper.rt;
 
-    let result = rt.block_on(wrapper.map.remove(key));
+    let result = match block_on(wrapper.map.remove(key)) {
+        Ok(result) => result,
+        Err(code) => return code,
+    };
 
     match result {
         Ok(_) => ErrorCode::Success,
@@ -173,6 +294,36 @@ pub extern "C" fn logmap_is_empty(handle: LogMapHandle) -> i32 {
     if wrapper.map.is_empty() { 1 } else { 0 }
 }
 
+/// Writes the cached keys, in ascending order, to a new array of
+/// `*len_out` keys. Free it with `logmap_keys_free`; it is null when the
+/// map is empty.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_keys(handle: LogMapHandle, keys_out: *mut *mut i64, len_out: *mut usize) -> ErrorCode {
+    if handle.is_null() || keys_out.is_null() || len_out.is_null() {
+        return ErrorCode::NullPointer;
+    }
+
+    let wrapper = unsafe { &*(handle as *const LogMapWrapper) };
+    let keys = wrapper.map.keys().into_boxed_slice();
+    unsafe {
+        *len_out = keys.len();
+        *keys_out = match keys.is_empty() {
+            true => ptr::null_mut(),
+            false => Box::into_raw(keys) as *mut i64,
+        };
+    }
+    ErrorCode::Success
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_keys_free(keys: *mut i64, len: usize) {
+    if !keys.is_null() {
+        unsafe {
+            let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(keys, len));
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn logmap_string_free(s: *mut c_char) {
     if !s.is_null() {
@@ -181,6 +332,231 @@ pub extern "C" fn logmap_string_free(s: *mut c_char) {
 }
 
 struct LogMapWrapper {
-    map: log_map::LogMap,
-    rt: tokio::runtime::Runtime,
+    /// Shared with async calls still in flight, which keep the map alive
+    /// past `logmap_free`.
+    map: Arc<log_map::LogMap>,
+}
+
+fn new_handle(map: log_map::LogMap) -> LogMapHandle {
+    Box::into_raw(Box::new(LogMapWrapper { map: Arc::new(map) })) as *mut c_void
+}
+
+/// The runtime behind every handle, started on first use.
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Runtime::new().expect("failed to start the tokio runtime")
+    })
+}
+
+/// Runs `future` to completion on the shared runtime for a blocking call.
+/// Fails with [`ErrorCode::InCallback`] on a runtime thread, i.e. inside a
+/// callback, where blocking would panic.
+fn block_on<F: Future>(future: F) -> Result<F::Output, ErrorCode> {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Err(ErrorCode::InCallback);
+    }
+    Ok(runtime().block_on(future))
+}
+
+/// Called once when an async operation finishes, on a runtime thread:
+/// with the `user_data` passed in, the result and, for
+/// `logmap_get_async`, the value or null if the key is absent. Free the
+/// value with `logmap_string_free`.
+pub type LogMapCallback =
+    extern "C" fn(user_data: *mut c_void, error: ErrorCode, value: *mut c_char);
+
+/// Called once when `logmap_connect_async` finishes, on a runtime thread.
+/// The handle is null unless `error` is `Success`.
+pub type LogMapConnectCallback =
+    extern "C" fn(user_data: *mut c_void, error: ErrorCode, handle: LogMapHandle);
+
+/// The caller's pointer, handed back untouched on another thread.
+struct UserData(*mut c_void);
+
+// Safety: only passed back to the caller, who chose to use it from the
+// callback thread.
+unsafe impl Send for UserData {}
+
+/// Runs `operation` on the shared runtime and reports its outcome to
+/// `callback`. Returns at once.
+fn spawn<F>(callback: LogMapCallback, user_data: *mut c_void, operation: F)
+where
+    F: Future<Output = Result<Option<String>, log_map::Error>> + Send + 'static,
+{
+    let user_data = UserData(user_data);
+    runtime().spawn(async move {
+        let user_data = user_data;
+        let (error, value) = match operation.await {
+            Ok(Some(value)) => match CString::new(value) {
+                Ok(value) => (ErrorCode::Success, value.into_raw()),
+                Err(_) => (ErrorCode::InvalidUtf8, ptr::null_mut()),
+            },
+            Ok(None) => (ErrorCode::Success, ptr::null_mut()),
+            Err(e) => (ErrorCode::from(e), ptr::null_mut()),
+        };
+        callback(user_data.0, error, value);
+    });
+}
+
+/// Like [`logmap_connect_ex`], without blocking; `options` is read before
+/// returning. `callback` is only called if this returns `Success`.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_connect_async(
+    addr: *const c_char,
+    options: *const LogMapConnectOptions,
+    callback: LogMapConnectCallback,
+    user_data: *mut c_void,
+) -> ErrorCode {
+    if addr.is_null() {
+        return ErrorCode::NullPointer;
+    }
+    let addr = match unsafe { CStr::from_ptr(addr) }.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return ErrorCode::InvalidUtf8,
+    };
+    let config = match unsafe { options.as_ref() }.map(connect_config).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(code) => return code,
+    };
+
+    let user_data = UserData(user_data);
+    runtime().spawn(async move {
+        let user_data = user_data;
+        match log_map::LogMap::connect_with_config(addr, config).await {
+            Ok(map) => callback(user_data.0, ErrorCode::Success, new_handle(map)),
+            Err(e) => callback(user_data.0, ErrorCode::from(e), ptr::null_mut()),
+        }
+    });
+    ErrorCode::Success
+}
+
+/// Like [`logmap_get`], reporting to `callback` instead of blocking.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_get_async(
+    handle: LogMapHandle,
+    key: i64,
+    callback: LogMapCallback,
+    user_data: *mut c_void,
+) -> ErrorCode {
+    if handle.is_null() {
+        return ErrorCode::NullPointer;
+    }
+    let map = Arc::clone(&unsafe { &*(handle as *const LogMapWrapper) }.map);
+    spawn(callback, user_data, async move { map.get(key).await });
+    ErrorCode::Success
+}
+
+/// Like [`logmap_insert`], reporting to `callback` instead of blocking.
+/// `value` is copied before returning.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_insert_async(
+    handle: LogMapHandle,
+    key: i64,
+    value: *const c_char,
+    callback: LogMapCallback,
+    user_data: *mut c_void,
+) -> ErrorCode {
+    if handle.is_null() || value.is_null() {
+        return ErrorCode::NullPointer;
+    }
+    let value = match unsafe { CStr::from_ptr(value) }.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return ErrorCode::InvalidUtf8,
+    };
+    let map = Arc::clone(&unsafe { &*(handle as *const LogMapWrapper) }.map);
+    spawn(callback, user_data, async move { map.insert(key, value).await.map(|_| None) });
+    ErrorCode::Success
+}
+
+/// Like [`logmap_remove`], reporting to `callback` instead of blocking.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_remove_async(
+    handle: LogMapHandle,
+    key: i64,
+    callback: LogMapCallback,
+    user_data: *mut c_void,
+) -> ErrorCode {
+    if handle.is_null() {
+        return ErrorCode::NullPointer;
+    }
+    let map = Arc::clone(&unsafe { &*(handle as *const LogMapWrapper) }.map);
+    spawn(callback, user_data, async move { map.remove(key).await.map(|_| None) });
+    ErrorCode::Success
+}
+
+type LogMapSubscription = *mut c_void;
+
+/// Called for every change a subscription matches, on a runtime thread,
+/// one change at a time. `value` is null if the key was removed
+/// (`deleted` is then 1) and only valid during the call.
+pub type LogMapChangeCallback = extern "C" fn(
+    user_data: *mut c_void,
+    key: i64,
+    value: *const c_char,
+    ordinal: u64,
+    deleted: i32,
+);
+
+struct SubscriptionWrapper {
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// Calls `callback` for every change the background sync applies to a key
+/// whose decimal form starts with `key_prefix` (null or empty for every
+/// key), until `logmap_unsubscribe`. Changes made before the call are not
+/// reported. A subscriber too slow to keep up skips the changes it missed.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_subscribe(
+    handle: LogMapHandle,
+    key_prefix: *const c_char,
+    callback: LogMapChangeCallback,
+    user_data: *mut c_void,
+    subscription_out: *mut LogMapSubscription,
+) -> ErrorCode {
+    if handle.is_null() || subscription_out.is_null() {
+        return ErrorCode::NullPointer;
+    }
+    let key_prefix = match optional_str(key_prefix) {
+        Ok(prefix) => prefix.unwrap_or_default(),
+        Err(code) => return code,
+    };
+    let wrapper = unsafe { &*(handle as *const LogMapWrapper) };
+
+    let changes = wrapper.map.watch_prefix(key_prefix);
+    let user_data = UserData(user_data);
+    let task = runtime().spawn(async move {
+        let user_data = user_data;
+        let mut changes = std::pin::pin!(changes);
+        while let Some(change) = changes.next().await {
+            // The only error is `Lagged`; the missed changes are gone.
+            let Ok(change) = change else { continue };
+            // Values holding a NUL can't cross as C strings.
+            let value = match change.value.map(CString::new).transpose() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let (value_ptr, deleted) = match &value {
+                Some(value) => (value.as_ptr(), 0),
+                None => (ptr::null(), 1),
+            };
+            callback(user_data.0, change.key, value_ptr, change.ordinal, deleted);
+        }
+    });
+
+    let boxed = Box::new(SubscriptionWrapper { task });
+    unsafe { *subscription_out = Box::into_raw(boxed) as *mut c_void };
+    ErrorCode::Success
+}
+
+/// Stops a subscription and frees it. A callback already running may
+/// still finish after this returns; no new one starts.
+#[unsafe(no_mangle)]
+pub extern "C" fn logmap_unsubscribe(subscription: LogMapSubscription) -> ErrorCode {
+    if subscription.is_null() {
+        return ErrorCode::NullPointer;
+    }
+    let subscription = unsafe { Box::from_raw(subscription as *mut SubscriptionWrapper) };
+    subscription.task.abort();
+    ErrorCode::Success
 }
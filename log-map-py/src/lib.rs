@@ -0,0 +1,90 @@
+//! Python bindings for log-map and matrix-mul.
+//!
+//! Built with maturin as the `log_map` module. The classes wrap the Rust
+//! clients directly, so the bindings share their retries, failover and
+//! caching; no C layer is involved. Calls that talk to the server return
+//! awaitables for asyncio and run on a tokio runtime owned by the module.
+//!
+//! ```python
+//! import asyncio
+//! from log_map import LogMap
+//!
+//! async def main():
+//!     m = await LogMap.connect("127.0.0.1:50051")
+//!     await m.insert(1, "one")
+//!     async for change in m.watch(1):
+//!         print(change.key, change.value)
+//!
+//! asyncio.run(main())
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+mod map;
+mod matrix;
+
+pub use map::{Change, LogMap, Watch};
+pub use matrix::{MatrixMul, Progress, WorkStats};
+
+create_exception!(log_map, LogMapError, PyException, "A log-map operation failed.");
+create_exception!(log_map, ConflictError, LogMapError, "A write kept conflicting with other writers.");
+create_exception!(log_map, ValueTooLargeError, LogMapError, "A value is over the server's size limit.");
+create_exception!(log_map, UnsupportedError, LogMapError, "The server lacks a feature the call needs.");
+create_exception!(log_map, MatrixMulError, PyException, "A matrix-mul operation failed.");
+
+/// The Python exception for `err`.
+fn map_error(err: log_map::Error) -> PyErr {
+    let message = err.to_string();
+    match err {
+        log_map::Error::Conflict(_) => ConflictError::new_err(message),
+        log_map::Error::ValueTooLarge { .. } => ValueTooLargeError::new_err(message),
+        log_map::Error::Unsupported(_) => UnsupportedError::new_err(message),
+        _ => LogMapError::new_err(message),
+    }
+}
+
+/// The Python exception for `err`; log-map failures keep their own class.
+fn matrix_error(err: matrix_mul::Error) -> PyErr {
+    match err {
+        matrix_mul::Error::LogMap(err) => map_error(err),
+        err => MatrixMulError::new_err(err.to_string()),
+    }
+}
+
+/// One server address or several to fail over between, as accepted by
+/// `connect`.
+#[derive(FromPyObject)]
+enum Servers {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<Servers> for log_map::ServerList {
+    fn from(servers: Servers) -> Self {
+        match servers {
+            Servers::One(addr) => addr.into(),
+            Servers::Many(addrs) => addrs.into(),
+        }
+    }
+}
+
+/// The `log_map` module.
+#[pymodule]
+#[pyo3(name = "log_map")]
+pub fn bindings(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<LogMap>()?;
+    m.add_class::<Change>()?;
+    m.add_class::<Watch>()?;
+    m.add_class::<MatrixMul>()?;
+    m.add_class::<Progress>()?;
+    m.add_class::<WorkStats>()?;
+    m.add("LogMapError", py.get_type::<LogMapError>())?;
+    m.add("ConflictError", py.get_type::<ConflictError>())?;
+    m.add("ValueTooLargeError", py.get_type::<ValueTooLargeError>())?;
+    m.add("UnsupportedError", py.get_type::<UnsupportedError>())?;
+    m.add("MatrixMulError", py.get_type::<MatrixMulError>())?;
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! The `LogMap` class: integer keys, string values.
+
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+use pyo3::exceptions::PyStopAsyncIteration;
+use pyo3::prelude::*;
+use pyo3_async_runtimes::tokio::future_into_py;
+
+use crate::{Servers, map_error};
+
+/// A log-map client. Reads come from the local cache, which a background
+/// task keeps in sync; writes go to the server and are awaitable.
+#[pyclass(module = "log_map", frozen)]
+pub struct LogMap {
+    map: Arc<log_map::LogMap>,
+}
+
+#[pymethods]
+impl LogMap {
+    /// Connects to `addr`, one address or a list to fail over between.
+    /// Awaitable.
+    #[staticmethod]
+    #[pyo3(signature = (addr, namespace = None, token = None))]
+    fn connect(
+        py: Python<'_>,
+        addr: Servers,
+        namespace: Option<String>,
+        token: Option<String>,
+    ) -> PyResult<Bound<'_, PyAny>> {
+        let config = log_map::ConnectConfig {
+            namespace,
+            token,
+            ..Default::default()
+        };
+        future_into_py(py, async move {
+            let map = log_map::LogMap::connect_with_config(addr, config).await.map_err(map_error)?;
+            Ok(LogMap { map: Arc::new(map) })
+        })
+    }
+
+    #[getter]
+    fn namespace(&self) -> &str {
+        self.map.namespace()
+    }
+
+    /// The cached value for `key`, or `None`.
+    fn get(&self, key: i64) -> Option<String> {
+        self.map.entry(key).map(|entry| entry.value)
+    }
+
+    /// Like `get`, after the cache has caught up with this map's own
+    /// writes. Awaitable.
+    fn get_consistent<'py>(&self, py: Python<'py>, key: i64) -> PyResult<Bound<'py, PyAny>> {
+        let map = Arc::clone(&self.map);
+        future_into_py(py, async move { map.get_consistent(key).await.map_err(map_error) })
+    }
+
+    /// Awaitable.
+    fn insert<'py>(&self, py: Python<'py>, key: i64, value: String) -> PyResult<Bound<'py, PyAny>> {
+        let map = Arc::clone(&self.map);
+        future_into_py(py, async move { map.insert(key, value).await.map_err(map_error) })
+    }
+
+    /// Awaitable.
+    fn remove<'py>(&self, py: Python<'py>, key: i64) -> PyResult<Bound<'py, PyAny>> {
+        let map = Arc::clone(&self.map);
+        future_into_py(py, async move { map.remove(key).await.map_err(map_error) })
+    }
+
+    /// The cached keys, in order.
+    fn keys(&self) -> Vec<i64> {
+        self.map.keys()
+    }
+
+    /// Changes to `key` from now on, as an async iterator.
+    fn watch(&self, key: i64) -> Watch {
+        Watch::new(self.map.watch(key).boxed())
+    }
+
+    /// Like `watch`, for every key whose decimal form starts with `prefix`.
+    fn watch_prefix(&self, prefix: &str) -> Watch {
+        Watch::new(self.map.watch_prefix(prefix).boxed())
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.len()
+    }
+
+    fn __contains__(&self, key: i64) -> bool {
+        self.map.contains_key(key)
+    }
+}
+
+/// A change the sync applied; `value` is `None` for a removal.
+#[pyclass(module = "log_map", frozen, get_all)]
+pub struct Change {
+    key: i64,
+    value: Option<String>,
+    ordinal: u64,
+    /// Unix milliseconds, assigned by the server.
+    timestamp: i64,
+}
+
+#[pymethods]
+impl Change {
+    fn __repr__(&self) -> String {
+        format!(
+            "Change(key={}, value={:?}, ordinal={}, timestamp={})",
+            self.key, self.value, self.ordinal, self.timestamp
+        )
+    }
+}
+
+type Changes = BoxStream<'static, Result<log_map::Change, log_map::Error>>;
+
+/// The async iterator `LogMap.watch` returns. Ends when the map is gone.
+#[pyclass(module = "log_map", frozen)]
+pub struct Watch {
+    changes: Arc<tokio::sync::Mutex<Changes>>,
+}
+
+impl Watch {
+    fn new(changes: Changes) -> Self {
+        Self {
+            changes: Arc::new(tokio::sync::Mutex::new(changes)),
+        }
+    }
+}
+
+#[pymethods]
+impl Watch {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let changes = Arc::clone(&self.changes);
+        future_into_py(py, async move {
+            match changes.lock().await.next().await {
+                Some(Ok(change)) => Ok(Change {
+                    key: change.key,
+                    value: change.value,
+                    ordinal: change.ordinal,
+                    timestamp: change.timestamp,
+                }),
+                Some(Err(e)) => Err(map_error(e)),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+}
@@ -0,0 +1,138 @@
+//! The `MatrixMul` class, for driving multiplications from notebooks.
+
+use std::sync::Arc;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3_async_runtimes::tokio::future_into_py;
+use tokio::sync::RwLock;
+
+use crate::{Servers, matrix_error};
+
+/// A matrix-mul handle. Every method but `job_id` is awaitable.
+#[pyclass(module = "log_map", frozen)]
+pub struct MatrixMul {
+    /// Written by `load_matrices` and `set_size` only, so workers and
+    /// progress checks run side by side.
+    inner: Arc<RwLock<matrix_mul::MatrixMul>>,
+    job_id: Option<String>,
+}
+
+impl MatrixMul {
+    fn new(mm: matrix_mul::MatrixMul) -> Self {
+        Self {
+            job_id: mm.job_id().map(ToString::to_string),
+            inner: Arc::new(RwLock::new(mm)),
+        }
+    }
+}
+
+#[pymethods]
+impl MatrixMul {
+    /// Connects to `addr`, one address or a list to fail over between, for
+    /// the `map` namespace or the job `job`.
+    #[staticmethod]
+    #[pyo3(signature = (addr, job = None))]
+    fn connect(py: Python<'_>, addr: Servers, job: Option<String>) -> PyResult<Bound<'_, PyAny>> {
+        let job = job.map(|job| job.parse::<matrix_mul::JobId>()).transpose().map_err(PyValueError::new_err)?;
+        future_into_py(py, async move {
+            let mm = match job {
+                Some(job) => matrix_mul::MatrixMul::connect_job(addr, &job).await,
+                None => matrix_mul::MatrixMul::connect(addr).await,
+            };
+            Ok(MatrixMul::new(mm.map_err(matrix_error)?))
+        })
+    }
+
+    /// A handle for a new job on the same server.
+    fn create_job<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move {
+            let mm = inner.read().await.create_job().await.map_err(matrix_error)?;
+            Ok(MatrixMul::new(mm))
+        })
+    }
+
+    /// The handle's job, `None` for the `map` namespace.
+    #[getter]
+    fn job_id(&self) -> Option<&str> {
+        self.job_id.as_deref()
+    }
+
+    /// Sets the sizes, for logs loaded without a stored job.
+    fn set_size<'py>(&self, py: Python<'py>, m: usize, n: usize, p: usize) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move {
+            inner.write().await.set_size(m, n, p);
+            Ok(())
+        })
+    }
+
+    /// Stores A (m×n) and B (n×p), given as lists of rows.
+    fn load_matrices<'py>(&self, py: Python<'py>, a: Vec<Vec<f64>>, b: Vec<Vec<f64>>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move { inner.write().await.load_matrices(a, b).await.map_err(matrix_error) })
+    }
+
+    /// Marks the loaded job as started.
+    fn start<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move { inner.read().await.start().await.map_err(matrix_error) })
+    }
+
+    /// Computes tasks until none are left.
+    fn work<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move {
+            let stats = inner.read().await.work().await.map_err(matrix_error)?;
+            Ok(WorkStats {
+                tasks_computed: stats.tasks_computed,
+                conflicts: stats.conflicts,
+                failures: stats.failures,
+            })
+        })
+    }
+
+    fn wait_for_completion<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move { inner.read().await.wait_for_completion().await.map_err(matrix_error) })
+    }
+
+    fn progress<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move {
+            let progress = inner.read().await.progress().await.map_err(matrix_error)?;
+            Ok(Progress {
+                settled: progress.is_settled(),
+                total: progress.total,
+                computed: progress.computed,
+                poisoned: progress.poisoned,
+            })
+        })
+    }
+
+    /// The m×p result as a list of rows.
+    fn get_result<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        future_into_py(py, async move { inner.read().await.get_result().await.map_err(matrix_error) })
+    }
+}
+
+/// What one `work` call did.
+#[pyclass(module = "log_map", frozen, get_all)]
+pub struct WorkStats {
+    tasks_computed: usize,
+    conflicts: usize,
+    failures: usize,
+}
+
+/// How far a multiplication has got, in tasks.
+#[pyclass(module = "log_map", frozen, get_all)]
+pub struct Progress {
+    total: usize,
+    computed: usize,
+    /// Tasks `(i, j)` that failed too often and will not be retried.
+    poisoned: Vec<(usize, usize)>,
+    /// Whether every task is computed or poisoned.
+    settled: bool,
+}
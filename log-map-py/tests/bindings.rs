@@ -0,0 +1,94 @@
+use std::ffi::CStr;
+use std::sync::Once;
+
+use log_map_py::bindings;
+use log_server_test::TestServer;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Runs `code` against a fresh server, whose address is in `addr`, with
+/// the module importable as `log_map`.
+fn run_python(code: &CStr) {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| pyo3::append_to_inittab!(bindings));
+
+    let runtime = pyo3_async_runtimes::tokio::get_runtime();
+    let server = runtime.block_on(TestServer::spawn());
+    Python::with_gil(|py| {
+        let globals = PyDict::new(py);
+        globals.set_item("addr", server.addr().to_string()).unwrap();
+        if let Err(e) = py.run(code, Some(&globals), None) {
+            e.display(py);
+            panic!("python code failed: {}", e);
+        }
+    });
+    runtime.block_on(async move { drop(server) });
+}
+
+#[test]
+fn test_log_map_from_asyncio() {
+    run_python(
+        cr#"
+import asyncio
+import log_map
+
+async def main():
+    m = await log_map.LogMap.connect([addr])
+    watch = m.watch(1)
+    await m.insert(1, "one")
+    await m.insert(2, "two")
+    assert await m.get_consistent(2) == "two"
+    assert m.get(1) == "one" and 1 in m and len(m) == 2
+    assert m.keys() == [1, 2]
+
+    change = await anext(watch)
+    assert (change.key, change.value) == (1, "one")
+    await m.remove(1)
+    change = await anext(watch)
+    assert (change.key, change.value) == (1, None)
+    await m.get_consistent(1)
+    assert m.get(1) is None and m.keys() == [2]
+
+    try:
+        await log_map.LogMap.connect([])
+    except log_map.LogMapError:
+        pass
+    else:
+        raise AssertionError("connected to no servers")
+
+asyncio.run(main())
+"#,
+    );
+}
+
+#[test]
+fn test_matrix_mul_from_asyncio() {
+    run_python(
+        cr#"
+import asyncio
+import log_map
+
+async def main():
+    mm = await log_map.MatrixMul.connect(addr)
+    job = await mm.create_job()
+    assert job.job_id is not None and mm.job_id is None
+    await job.load_matrices([[1.0, 2.0], [3.0, 4.0]], [[5.0, 6.0], [7.0, 8.0]])
+    await job.start()
+    stats = await job.work()
+    assert stats.tasks_computed == 4
+    await job.wait_for_completion()
+    progress = await job.progress()
+    assert progress.settled and progress.computed == 4
+    assert await job.get_result() == [[19.0, 22.0], [43.0, 50.0]]
+
+    try:
+        await mm.load_matrices([[1.0, 2.0]], [[1.0]])
+    except log_map.MatrixMulError:
+        pass
+    else:
+        raise AssertionError("loaded mismatched matrices")
+
+asyncio.run(main())
+"#,
+    );
+}
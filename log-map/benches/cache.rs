@@ -0,0 +1,68 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use log_map::Cache;
+
+const KEYS: i64 = 10_000;
+
+fn filled_cache() -> Arc<Cache> {
+    let cache = Arc::new(Cache::new());
+    cache.insert_all((0..KEYS).map(|k| (k, format!("value-{}", k))).collect());
+    cache
+}
+
+/// Reader threads doing `get` while one writer keeps inserting.
+fn bench_read_contention(c: &mut Criterion) {
+    const READS_PER_THREAD: i64 = 10_000;
+
+    let mut group = c.benchmark_group("cache_read_contention");
+    for readers in [1usize, 4, 16] {
+        group.bench_with_input(BenchmarkId::from_parameter(readers), &readers, |b, &readers| {
+            let cache = filled_cache();
+            b.iter_custom(|iters| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let stop = Arc::new(AtomicBool::new(false));
+                    let writer = {
+                        let cache = Arc::clone(&cache);
+                        let stop = Arc::clone(&stop);
+                        thread::spawn(move || {
+                            let mut k = 0;
+                            while !stop.load(Ordering::Relaxed) {
+                                cache.insert(k % KEYS, "updated".to_string());
+                                k += 1;
+                            }
+                        })
+                    };
+
+                    let start = Instant::now();
+                    let handles: Vec<_> = (0..readers)
+                        .map(|_| {
+                            let cache = Arc::clone(&cache);
+                            thread::spawn(move || {
+                                for k in 0..READS_PER_THREAD {
+                                    std::hint::black_box(cache.get(&(k % KEYS)));
+                                }
+                            })
+                        })
+                        .collect();
+                    for handle in handles {
+                        handle.join().unwrap();
+                    }
+                    total += start.elapsed();
+
+                    stop.store(true, Ordering::Relaxed);
+                    writer.join().unwrap();
+                }
+                total
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_read_contention);
+criterion_main!(benches);
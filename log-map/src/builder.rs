@@ -0,0 +1,255 @@
+//! Connection options for [`LogMap`].
+
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::service::Interceptor;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tonic::transport::{Channel, Endpoint};
+
+use crate::codec::{Codec, Plain};
+use crate::chunk::DEFAULT_CHUNK_SIZE;
+use crate::error::Error;
+use crate::map::{DEFAULT_NAMESPACE, ServerAddr, ServerList, TypedLogMap};
+use crate::protocol::Extra;
+use crate::retry::RetryPolicy;
+
+/// Configures a [`LogMap`](crate::LogMap) or [`TypedLogMap`] before
+/// connecting.
+///
+/// Metadata and interceptors apply to every call the map makes: the
+/// handshake, writes, the subscription and snapshot fetches. They run after
+/// the map's own handshake metadata, so gateways that need a tenant id,
+/// tracing headers or a token can be reached without forking the client.
+///
+/// ```no_run
+/// use log_map::LogMap;
+/// use tonic::metadata::MetadataMap;
+///
+/// # async fn example() -> Result<(), log_map::Error> {
+/// let mut metadata = MetadataMap::new();
+/// metadata.insert("x-tenant-id", "acme".parse().unwrap());
+///
+/// let map = LogMap::builder()
+///     .metadata(metadata)
+///     .interceptor(|mut request: tonic::Request<()>| {
+///         request.metadata_mut().insert("authorization", "Bearer secret".parse().unwrap());
+///         Ok(request)
+///     })
+///     .connect("localhost:50051")
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct LogMapBuilder<K = i64, V = String, C = Plain> {
+    extra: Extra,
+    replica: Option<ServerAddr>,
+    namespace: String,
+    token: Option<String>,
+    retry: RetryPolicy,
+    chunk_size: usize,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    map: PhantomData<(K, V, C)>,
+}
+
+impl<K, V, C> Default for LogMapBuilder<K, V, C> {
+    fn default() -> Self {
+        Self {
+            extra: Extra::default(),
+            replica: None,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            token: None,
+            retry: RetryPolicy::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            #[cfg(feature = "tls")]
+            tls: None,
+            map: PhantomData,
+        }
+    }
+}
+
+/// Settings for [`TypedLogMap::connect_with_config`]; the defaults connect
+/// in plaintext without credentials, like [`TypedLogMap::connect`].
+///
+/// ```no_run
+/// use log_map::{ConnectConfig, LogMap};
+///
+/// # async fn example() -> Result<(), log_map::Error> {
+/// let config = ConnectConfig {
+///     token: Some("s3cr3t".to_string()),
+///     ..Default::default()
+/// };
+/// let map = LogMap::connect_with_config("logs.internal:50051", config).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnectConfig {
+    /// Connect over TLS. Needs the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+    /// Sent as `authorization: Bearer <token>` with every call, for
+    /// servers configured with `auth_token`.
+    pub token: Option<String>,
+    /// See [`TypedLogMap::connect_namespace`]; `map` if unset.
+    pub namespace: Option<String>,
+    /// How writes retry conflicts, see [`RetryPolicy`].
+    pub retry: RetryPolicy,
+    /// See [`LogMapBuilder::chunk_size`]; 1 MiB if unset.
+    pub chunk_size: Option<usize>,
+}
+
+/// How to verify the server's certificate. The defaults trust the
+/// platform's root certificates and check the host name of the address.
+#[cfg(feature = "tls")]
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// PEM certificate of a private CA to trust as well.
+    pub ca_cert: Option<Vec<u8>>,
+    /// Name the certificate must be issued for, if not the host name.
+    pub domain: Option<String>,
+}
+
+impl<K, V, C> LogMapBuilder<K, V, C>
+where
+    K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    C: Codec,
+{
+    /// Adds `metadata` to every request. Later calls add to, and on equal
+    /// keys replace, what earlier calls set.
+    pub fn metadata(mut self, metadata: MetadataMap) -> Self {
+        let mut merged = self.extra.metadata.into_headers();
+        merged.extend(metadata.into_headers());
+        self.extra.metadata = MetadataMap::from_headers(merged);
+        self
+    }
+
+    /// Runs `interceptor` on every request. Interceptors run in the order
+    /// they were added; an error from one fails the call with that status.
+    pub fn interceptor<I>(mut self, interceptor: I) -> Self
+    where
+        I: Interceptor + Send + 'static,
+    {
+        self.extra.interceptors.push(Arc::new(Mutex::new(interceptor)));
+        self
+    }
+
+    /// Reads (the snapshot, the background subscription and
+    /// [`TypedLogMap::replay_since`]) from `addr`, typically the nearest replica,
+    /// while writes keep going to the address passed to
+    /// [`connect`](Self::connect). Falls back to that address if the replica
+    /// cannot be reached on connect. Reads stay with the replica when the
+    /// writes [fail over](ServerList) to another server.
+    pub fn prefer_replica(mut self, addr: impl Into<ServerAddr>) -> Self {
+        self.replica = Some(addr.into());
+        self
+    }
+
+    /// Stores keys under `namespace`, see [`TypedLogMap::connect_namespace`].
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Authenticates every call with `authorization: Bearer <token>`.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Retries conflicting writes as `policy` says.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Writes values over `bytes` in pieces of at most that size, see
+    /// [Large Values](TypedLogMap#large-values). The default is 1 MiB, or the server's
+    /// [`max_value_size`](crate::ServerInfo::max_value_size) if lower; 0
+    /// turns chunking off, so larger values fail with
+    /// [`Error::ValueTooLarge`]. Chunking needs a server with the `history`
+    /// feature.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+
+    /// Connects over TLS, to the replica as well.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Applies everything set in `config`.
+    pub fn config(self, config: ConnectConfig) -> Self {
+        let mut builder = self;
+        if let Some(token) = config.token {
+            builder = builder.token(token);
+        }
+        if let Some(namespace) = config.namespace {
+            builder = builder.namespace(namespace);
+        }
+        builder = builder.retry_policy(config.retry);
+        if let Some(chunk_size) = config.chunk_size {
+            builder = builder.chunk_size(chunk_size);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(tls) = config.tls {
+            builder = builder.tls(tls);
+        }
+        builder
+    }
+
+    /// Connects to a log-server, or the first of several that answers, like
+    /// [`TypedLogMap::connect`].
+    pub async fn connect(self, addr: impl Into<ServerList>) -> Result<TypedLogMap<K, V, C>, Error> {
+        let (extra, namespace) = self.finish()?;
+        let endpoints = addr.into().0.iter().map(|addr| self.endpoint(addr)).collect::<Result<_, _>>()?;
+        let replica = self.replica.as_ref().map(|replica| self.endpoint(replica)).transpose()?;
+        TypedLogMap::open(endpoints, replica, extra, &namespace, self.retry.clone(), self.chunk_size).await
+    }
+
+    /// Uses an already established channel, like
+    /// [`TypedLogMap::with_channel`]. [`prefer_replica`](Self::prefer_replica)
+    /// and TLS do not apply here.
+    pub async fn with_channel(self, channel: Channel) -> Result<TypedLogMap<K, V, C>, Error> {
+        let (extra, namespace) = self.finish()?;
+        TypedLogMap::open_channel(channel, None, extra, &namespace, self.retry.clone(), self.chunk_size).await
+    }
+
+    /// Where to connect for `addr`, over TLS if configured.
+    fn endpoint(&self, addr: &ServerAddr) -> Result<Endpoint, Error> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            use tonic::transport::{Certificate, ClientTlsConfig};
+
+            let mut config = ClientTlsConfig::new().with_native_roots();
+            if let Some(ca_cert) = &tls.ca_cert {
+                config = config.ca_certificate(Certificate::from_pem(ca_cert));
+            }
+            if let Some(domain) = &tls.domain {
+                config = config.domain_name(domain.clone());
+            }
+            return Ok(Endpoint::from_shared(format!("https://{}", addr.0))?.tls_config(config)?);
+        }
+        Ok(Endpoint::from_shared(format!("http://{}", addr.0))?)
+    }
+
+    /// The metadata to send, with the token added, and the namespace.
+    fn finish(&self) -> Result<(Extra, String), Error> {
+        let mut extra = self.extra.clone();
+        if let Some(token) = &self.token {
+            if token.is_empty() {
+                return Err(Error::InvalidToken);
+            }
+            let value: MetadataValue<_> = format!("Bearer {}", token).parse().map_err(|_| Error::InvalidToken)?;
+            extra.metadata.insert("authorization", value);
+        }
+        Ok((extra, self.namespace.clone()))
+    }
+}
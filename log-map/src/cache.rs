@@ -1,45 +1,170 @@
 //! Thread-safe in-memory cache for key-value pairs.
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::ops::RangeBounds;
 use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub struct Cache {
-    inner: RwLock<HashMap<i64, String>>,
+/// Local view of the map that [`LogMap`](crate::LogMap) reads from, ordered
+/// by key.
+///
+/// Entries past their `expires_at` are hidden from reads right away, but
+/// stay in the cache, and in [`len`](Self::len), until the server's
+/// tombstone for them arrives.
+///
+/// Public mainly so the read path can be benchmarked in isolation.
+pub struct Cache<K = i64, V = String> {
+    inner: RwLock<BTreeMap<K, Entry<V>>>,
 }
 
-impl Cache {
+/// A cached value and when the server wrote it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<V = String> {
+    pub value: V,
+    /// Unix milliseconds of the record, or 0 when unknown, e.g. for values
+    /// loaded from a snapshot.
+    pub timestamp: i64,
+    /// Unix milliseconds after which the entry is gone, or 0 if it doesn't
+    /// expire. See [`insert_with_ttl`](crate::TypedLogMap::insert_with_ttl).
+    pub expires_at: i64,
+}
+
+impl<V> Entry<V> {
+    fn is_live(&self, now: i64) -> bool {
+        self.expires_at == 0 || self.expires_at > now
+    }
+}
+
+/// The local clock in Unix milliseconds, which expiry is judged by.
+pub(crate) fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// The `expires_at` of an entry that lives for `ttl` from now, never 0.
+pub(crate) fn expires_after(ttl: Duration) -> i64 {
+    let ttl = i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX);
+    now_millis().saturating_add(ttl).max(1)
+}
+
+impl<K: Ord + Clone, V: Clone> Cache<K, V> {
     pub fn new() -> Self {
         Self {
-            inner: RwLock::new(HashMap::new()),
+            inner: RwLock::new(BTreeMap::new()),
         }
     }
 
-    pub fn get(&self, key: &i64) -> Option<String> {
-        self.inner.read().ok()?.get(key).cloned()
+    pub fn get(&self, key: &K) -> Option<V> {
+        Some(self.entry(key)?.value)
+    }
+
+    pub fn entry(&self, key: &K) -> Option<Entry<V>> {
+        let now = now_millis();
+        self.inner.read().ok()?.get(key).filter(|entry| entry.is_live(now)).cloned()
     }
 
-    pub fn insert(&self, key: i64, value: String) {
+    /// Entries written at or after `timestamp`, in key order.
+    pub fn entries_since(&self, timestamp: i64) -> Vec<(K, Entry<V>)> {
+        let now = now_millis();
+        self.inner
+            .read()
+            .map(|g| {
+                g.iter()
+                    .filter(|(_, entry)| entry.timestamp >= timestamp && entry.is_live(now))
+                    .map(|(key, entry)| (key.clone(), entry.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Every key, in order.
+    pub fn keys(&self) -> Vec<K> {
+        let now = now_millis();
+        self.inner
+            .read()
+            .map(|g| {
+                g.iter()
+                    .filter(|(_, entry)| entry.is_live(now))
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Every entry, in key order.
+    pub fn entries(&self) -> Vec<(K, V)> {
+        self.range(..)
+    }
+
+    /// Entries whose key falls in `range`, in key order.
+    pub fn range(&self, range: impl RangeBounds<K>) -> Vec<(K, V)> {
+        let now = now_millis();
+        self.inner
+            .read()
+            .map(|g| {
+                g.range(range)
+                    .filter(|(_, entry)| entry.is_live(now))
+                    .map(|(key, entry)| (key.clone(), entry.value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Inserts a value whose write time is unknown.
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_at(key, value, 0);
+    }
+
+    pub fn insert_at(&self, key: K, value: V, timestamp: i64) {
+        self.insert_expiring(key, value, timestamp, 0);
+    }
+
+    /// Inserts a value that is hidden once the clock passes `expires_at`.
+    pub fn insert_expiring(&self, key: K, value: V, timestamp: i64, expires_at: i64) {
         if let Ok(mut guard) = self.inner.write() {
-            guard.insert(key, value);
+            guard.insert(
+                key,
+                Entry {
+                    value,
+                    timestamp,
+                    expires_at,
+                },
+            );
         }
     }
 
-    pub fn insert_all(&self, records: Vec<(i64, String)>) {
+    /// Inserts values whose write time is unknown, under one lock.
+    pub fn insert_all(&self, records: Vec<(K, V)>) {
         if let Ok(mut guard) = self.inner.write() {
             for (key, value) in records {
-                guard.insert(key, value);
+                guard.insert(
+                    key,
+                    Entry {
+                        value,
+                        timestamp: 0,
+                        expires_at: 0,
+                    },
+                );
             }
         }
     }
 
-    pub fn remove(&self, key: &i64) {
+    /// Removes every entry whose key `keep` rejects, under one lock.
+    pub fn retain(&self, mut keep: impl FnMut(&K) -> bool) {
+        if let Ok(mut guard) = self.inner.write() {
+            guard.retain(|key, _| keep(key));
+        }
+    }
+
+    pub fn remove(&self, key: &K) {
         if let Ok(mut guard) = self.inner.write() {
             guard.remove(key);
         }
     }
 
-    pub fn contains_key(&self, key: &i64) -> bool {
-        self.inner.read().map(|g| g.contains_key(key)).unwrap_or(false)
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entry(key).is_some()
     }
 
     pub fn len(&self) -> usize {
@@ -51,7 +176,7 @@ impl Cache {
     }
 }
 
-impl Default for Cache {
+impl<K: Ord + Clone, V: Clone> Default for Cache<K, V> {
     fn default() -> Self {
         Self::new()
     }
@@ -0,0 +1,102 @@
+//! Values too large for one record, stored across several.
+//!
+//! A value over the map's chunk size is written in pieces first, each a
+//! record of its own under `<namespace>~chunks:`, which the map's
+//! subscription and snapshot don't include. The key's record then holds a
+//! manifest naming the pieces. Readers that come across a manifest fetch
+//! the pieces with `GetHistory` and join them, so the sync, history and
+//! replays see the whole value.
+//!
+//! Pieces are never overwritten. A writer that replaces a chunked value
+//! writes tombstones for its pieces, which compaction then drops.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use log_server_types::kv::GetHistoryRequest;
+
+use crate::error::Error;
+use crate::protocol::Client;
+
+/// Chunk size unless configured otherwise or the server allows less.
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Starts every manifest. No UTF-8 text, so no [`Json`](crate::Json) or
+/// [`Plain`](crate::Plain) value, starts with 0xFF.
+const MARKER: &[u8] = b"\xFFlog-map chunks ";
+
+/// Manifests of the chunked values the sync has applied, by record key,
+/// so writers know which pieces their write replaces.
+pub(crate) type Manifests = Mutex<HashMap<String, Manifest>>;
+
+/// Where the pieces of a chunked value are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Manifest {
+    /// Unique per chunked write: the writer's client id and a sequence
+    /// number.
+    id: String,
+    chunks: usize,
+    size: usize,
+}
+
+impl Manifest {
+    /// A manifest for `size` bytes cut into pieces of `chunk_size`.
+    pub(crate) fn new(id: String, size: usize, chunk_size: usize) -> Self {
+        Self {
+            id,
+            chunks: size.div_ceil(chunk_size),
+            size,
+        }
+    }
+
+    /// The manifest a record value holds, if it holds one.
+    pub(crate) fn parse(value: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(value.strip_prefix(MARKER)?).ok()?;
+        let mut fields = text.split(' ');
+        let manifest = Self {
+            id: fields.next()?.to_string(),
+            chunks: fields.next()?.parse().ok()?,
+            size: fields.next()?.parse().ok()?,
+        };
+        fields.next().is_none().then_some(manifest)
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut value = MARKER.to_vec();
+        value.extend_from_slice(format!("{} {} {}", self.id, self.chunks, self.size).as_bytes());
+        value
+    }
+
+    /// Record keys of the pieces, in order.
+    pub(crate) fn chunk_keys<'a>(&'a self, chunk_prefix: &'a str) -> impl Iterator<Item = String> + 'a {
+        (0..self.chunks).map(move |index| format!("{}{}:{}", chunk_prefix, self.id, index))
+    }
+}
+
+/// `<namespace>~chunks:` for the map prefix `<namespace>:`.
+pub(crate) fn chunk_prefix(prefix: &str) -> String {
+    format!("{}~chunks:", prefix.trim_end_matches(':'))
+}
+
+/// `value` with a manifest replaced by the value it stands for. `None` if
+/// a piece is gone, which happens once the value has been replaced.
+pub(crate) async fn reassemble(client: &Client, chunk_prefix: &str, value: Vec<u8>) -> Result<Option<Vec<u8>>, Error> {
+    let Some(manifest) = Manifest::parse(&value) else {
+        return Ok(Some(value));
+    };
+    let mut client = client.clone();
+    let mut joined = Vec::with_capacity(manifest.size);
+    for key in manifest.chunk_keys(chunk_prefix) {
+        let request = GetHistoryRequest {
+            key,
+            until_ordinal: 0,
+            limit: 1,
+        };
+        let mut records = client.get_history(request).await?.into_inner().records;
+        match records.pop() {
+            Some(record) if !record.value.is_empty() => joined.extend_from_slice(&record.value),
+            _ => return Ok(None),
+        }
+    }
+    Ok((joined.len() == manifest.size).then_some(joined))
+}
@@ -0,0 +1,115 @@
+//! Coalesced watch notifications, for keys that change faster than
+//! watchers care to hear about.
+//!
+//! A task drains the map's change broadcast as it comes and keeps only the
+//! latest change per key, so a watcher that is slow to poll neither lags
+//! behind nor sees intermediate states. The stream hands out what is
+//! pending at most once per interval.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{Stream, stream};
+use tokio::sync::Notify;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+
+use crate::error::Error;
+use crate::map::Change;
+
+/// Changes waiting for the next emission.
+struct Pending<K, V> {
+    /// The latest change to each key since the last emission.
+    changes: BTreeMap<K, Change<K, V>>,
+    /// Changes the drain task missed, reported as [`Error::Lagged`].
+    missed: u64,
+    /// The map is gone; end once everything pending is out.
+    closed: bool,
+}
+
+struct Shared<K, V> {
+    pending: Mutex<Pending<K, V>>,
+    changed: Notify,
+}
+
+/// The changes of `receiver` that `matches` accepts, at most one per key
+/// and `interval`, each the latest for its key. A batch comes out in log
+/// order, after any [`Error::Lagged`] for changes missed before it.
+pub(crate) fn coalesce<K, V>(
+    mut receiver: broadcast::Receiver<Change<K, V>>,
+    matches: impl Fn(&Change<K, V>) -> bool + Send + 'static,
+    interval: Duration,
+) -> impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static
+where
+    K: Ord + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        pending: Mutex::new(Pending {
+            changes: BTreeMap::new(),
+            missed: 0,
+            closed: false,
+        }),
+        changed: Notify::new(),
+    });
+
+    // Holds the state weakly, so a dropped stream stops it at the next
+    // change.
+    let weak = Arc::downgrade(&shared);
+    tokio::spawn(async move {
+        loop {
+            let received = receiver.recv().await;
+            let Some(shared) = weak.upgrade() else { return };
+            let closed = {
+                let mut pending = shared.pending.lock().unwrap();
+                match received {
+                    Ok(change) if matches(&change) => {
+                        pending.changes.insert(change.key.clone(), change);
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => pending.missed += missed,
+                    Err(RecvError::Closed) => pending.closed = true,
+                }
+                pending.closed
+            };
+            shared.changed.notify_one();
+            if closed {
+                return;
+            }
+        }
+    });
+
+    let batch = Vec::new().into_iter();
+    stream::unfold((shared, batch, Instant::now()), move |(shared, mut batch, mut next)| async move {
+        loop {
+            if let Some(item) = batch.next() {
+                return Some((item, (shared, batch, next)));
+            }
+            // Wait for something to emit, then for the interval to pass.
+            loop {
+                {
+                    let pending = shared.pending.lock().unwrap();
+                    if !pending.changes.is_empty() || pending.missed > 0 {
+                        break;
+                    }
+                    if pending.closed {
+                        return None;
+                    }
+                }
+                shared.changed.notified().await;
+            }
+            tokio::time::sleep_until(next).await;
+            next = Instant::now() + interval;
+
+            let (missed, changes) = {
+                let mut pending = shared.pending.lock().unwrap();
+                (std::mem::take(&mut pending.missed), std::mem::take(&mut pending.changes))
+            };
+            let mut changes: Vec<_> = changes.into_values().collect();
+            changes.sort_by_key(|change| change.ordinal);
+            let lagged = (missed > 0).then_some(Err(Error::Lagged(missed)));
+            batch = lagged.into_iter().chain(changes.into_iter().map(Ok)).collect::<Vec<_>>().into_iter();
+        }
+    })
+}
@@ -0,0 +1,75 @@
+//! How typed keys and values are turned into log records and back.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::error::Error;
+
+/// Encodes map keys into the text after the `map:` prefix of a record key,
+/// and values into the record's bytes.
+///
+/// An encoded value must not be empty: empty records are tombstones. Nor
+/// may it start with the byte 0xFF, which marks the manifest of a value
+/// stored in pieces.
+/// Implement this to store values with a format of your choice, e.g.
+/// bincode, and pass it as the `C` parameter of
+/// [`TypedLogMap`](crate::TypedLogMap).
+pub trait Codec: Send + Sync + 'static {
+    fn encode_key<K: Serialize>(key: &K) -> Result<String, Error>;
+    fn decode_key<K: DeserializeOwned>(key: &str) -> Result<K, Error>;
+    fn encode_value<V: Serialize>(value: &V) -> Result<Vec<u8>, Error>;
+    fn decode_value<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, Error>;
+}
+
+/// Keys and values as JSON.
+pub struct Json;
+
+impl Codec for Json {
+    fn encode_key<K: Serialize>(key: &K) -> Result<String, Error> {
+        serde_json::to_string(key).map_err(codec_error)
+    }
+
+    fn decode_key<K: DeserializeOwned>(key: &str) -> Result<K, Error> {
+        serde_json::from_str(key).map_err(codec_error)
+    }
+
+    fn encode_value<V: Serialize>(value: &V) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(codec_error)
+    }
+
+    fn decode_value<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, Error> {
+        serde_json::from_slice(bytes).map_err(codec_error)
+    }
+}
+
+/// Like [`Json`], except that string values are stored as their raw UTF-8
+/// bytes. This is the format [`LogMap`](crate::LogMap) has always used, so
+/// logs written by older clients and by other languages read back
+/// unchanged.
+pub struct Plain;
+
+impl Codec for Plain {
+    fn encode_key<K: Serialize>(key: &K) -> Result<String, Error> {
+        Json::encode_key(key)
+    }
+
+    fn decode_key<K: DeserializeOwned>(key: &str) -> Result<K, Error> {
+        Json::decode_key(key)
+    }
+
+    fn encode_value<V: Serialize>(value: &V) -> Result<Vec<u8>, Error> {
+        match serde_json::to_value(value).map_err(codec_error)? {
+            serde_json::Value::String(text) => Ok(text.into_bytes()),
+            other => serde_json::to_vec(&other).map_err(codec_error),
+        }
+    }
+
+    fn decode_value<V: DeserializeOwned>(bytes: &[u8]) -> Result<V, Error> {
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        V::deserialize(serde_json::Value::String(text)).or_else(|_| Json::decode_value(bytes))
+    }
+}
+
+fn codec_error(err: serde_json::Error) -> Error {
+    Error::Codec(err.to_string())
+}
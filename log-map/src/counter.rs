@@ -0,0 +1,85 @@
+//! Counters that many clients add to without conflicting.
+
+use std::ops::Bound;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::codec::{Codec, Json};
+use crate::error::Error;
+use crate::map::{ServerList, TypedLogMap};
+
+/// Tells the counters of one client apart.
+static NEXT_SLOT: AtomicU64 = AtomicU64::new(1);
+
+/// Keys are `(counter name, slot)`, values the slot's running total.
+type CounterMap = TypedLogMap<(String, String), i64>;
+
+/// A shared integer that clients change by deltas, e.g. "tasks done"
+/// across a pool of workers.
+///
+/// Every `Counter` handle writes only its own slot, the sum of its
+/// increments, and [`value`](Self::value) adds up the slots of all
+/// handles as the sync applies them. Nobody else writes a handle's
+/// slot, so increments never conflict or retry, and because each slot
+/// holds a total rather than a delta, compaction and snapshots keep the
+/// sum intact. Each handle leaves one record per slot in the log, so
+/// share a handle (it is `Sync`) rather than creating one per increment.
+pub struct Counter {
+    map: Arc<CounterMap>,
+    name: String,
+    slot: (String, String),
+    /// This handle's total, locked across the write so totals reach the
+    /// log in order.
+    total: tokio::sync::Mutex<i64>,
+}
+
+impl Counter {
+    /// The namespace [`connect`](Self::connect) keeps counters in.
+    pub const NAMESPACE: &str = "counter";
+
+    /// Connects to a log-server for the counter `name`, keeping counters in
+    /// the [`NAMESPACE`](Self::NAMESPACE) namespace.
+    pub async fn connect(addr: impl Into<ServerList>, name: &str) -> Result<Self, Error> {
+        let map = CounterMap::connect_namespace(addr, Self::NAMESPACE).await?;
+        Ok(Self::new(Arc::new(map), name))
+    }
+
+    /// The counter `name` in `map`, which several counters can share.
+    pub fn new(map: Arc<TypedLogMap<(String, String), i64>>, name: &str) -> Self {
+        let slot = format!("{}-{}", map.client_id(), NEXT_SLOT.fetch_add(1, Ordering::Relaxed));
+        Self {
+            slot: (name.to_string(), slot),
+            name: name.to_string(),
+            map,
+            total: tokio::sync::Mutex::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds `delta`, which may be negative, to the counter.
+    pub async fn incr(&self, delta: i64) -> Result<(), Error> {
+        let mut total = self.total.lock().await;
+        let updated = total.wrapping_add(delta);
+        self.map
+            .write_unconditional(&self.slot, Json::encode_value(&updated)?)
+            .await?;
+        *total = updated;
+        Ok(())
+    }
+
+    /// The sum of every handle's increments, as far as the local cache has
+    /// synced; like [`TypedLogMap::get`], it trails recent increments,
+    /// this handle's included.
+    pub fn value(&self) -> i64 {
+        // No string sorts between a name and the name followed by NUL, so
+        // this covers exactly the slots of `name`.
+        let start = (self.name.clone(), String::new());
+        let end = (format!("{}\0", self.name), String::new());
+        self.map
+            .range((Bound::Included(start), Bound::Excluded(end)))
+            .fold(0i64, |sum, (_, total)| sum.wrapping_add(total))
+    }
+}
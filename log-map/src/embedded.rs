@@ -0,0 +1,137 @@
+//! A log-server inside the client process, for tests.
+//!
+//! [`EmbeddedServer`] runs the real gRPC service on in-memory SQLite and
+//! hands out connections over in-memory pipes, so tests need no port, no
+//! external process and no cleanup. Needs the `embedded` feature.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), log_map::Error> {
+//! use log_map::LogMap;
+//! use log_map::embedded::EmbeddedServer;
+//!
+//! let server = EmbeddedServer::start().await?;
+//! let writer: LogMap = server.connect().await?;
+//! let reader: LogMap = server.connect().await?;
+//! writer.insert(1, "one".to_string()).await?;
+//! assert_eq!(reader.get_consistent(1).await?, Some("one".to_string()));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::sync::Arc;
+
+use hyper_util::rt::TokioIo;
+use log_server::storage::Storage;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc;
+use tonic::transport::{Channel, Endpoint, Uri};
+
+use crate::codec::Codec;
+use crate::error::Error;
+use crate::map::TypedLogMap;
+
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// A log-server reachable only from this process.
+///
+/// It runs until the last clone of it and the last map connected to it
+/// are dropped. Clones share the server.
+#[derive(Clone)]
+pub struct EmbeddedServer {
+    storage: Arc<Storage>,
+    connections: mpsc::Sender<io::Result<DuplexStream>>,
+}
+
+impl EmbeddedServer {
+    /// Starts a server with fresh in-memory storage.
+    pub async fn start() -> Result<Self, Error> {
+        let pool = log_server::db::init_pool("sqlite::memory:")
+            .await
+            .map_err(|e| Error::Internal(format!("in-memory database: {}", e)))?;
+        Ok(Self::with_storage(Arc::new(Storage::new(pool))))
+    }
+
+    /// Starts a server backed by `storage`, e.g. one a test prepared.
+    pub fn with_storage(storage: Arc<Storage>) -> Self {
+        let (connections, accepted) = mpsc::channel(16);
+        let service = log_server::grpc::create_server(Arc::clone(&storage));
+        let incoming = futures_util::stream::unfold(accepted, |mut accepted| async move {
+            accepted.recv().await.map(|connection| (connection, accepted))
+        });
+        tokio::spawn(async move {
+            let _served = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await;
+            #[cfg(feature = "tracing")]
+            if let Err(e) = _served {
+                tracing::warn!(error = %e, "embedded server stopped");
+            }
+        });
+        Self { storage, connections }
+    }
+
+    /// Storage the server writes to.
+    pub fn storage(&self) -> &Arc<Storage> {
+        &self.storage
+    }
+
+    /// A channel to the server, for [`LogMapBuilder::with_channel`](crate::LogMapBuilder::with_channel)
+    /// or a raw gRPC client. It reconnects on demand like a TCP channel.
+    pub async fn channel(&self) -> Result<Channel, Error> {
+        let connections = self.connections.clone();
+        let channel = Endpoint::from_static("http://embedded.invalid")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let connections = connections.clone();
+                async move {
+                    let (client, server) = tokio::io::duplex(PIPE_CAPACITY);
+                    connections
+                        .send(Ok(server))
+                        .await
+                        .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "embedded server stopped"))?;
+                    Ok::<_, io::Error>(TokioIo::new(client))
+                }
+            }))
+            .await?;
+        Ok(channel)
+    }
+
+    /// Connects a map, like [`TypedLogMap::connect`].
+    pub async fn connect<K, V, C>(&self) -> Result<TypedLogMap<K, V, C>, Error>
+    where
+        K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+        V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        C: Codec,
+    {
+        TypedLogMap::with_channel(self.channel().await?).await
+    }
+
+    /// Connects a map in `namespace`, like [`TypedLogMap::connect_namespace`].
+    pub async fn connect_namespace<K, V, C>(&self, namespace: &str) -> Result<TypedLogMap<K, V, C>, Error>
+    where
+        K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+        V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        C: Codec,
+    {
+        TypedLogMap::builder()
+            .namespace(namespace)
+            .with_channel(self.channel().await?)
+            .await
+    }
+}
+
+impl<K, V, C> TypedLogMap<K, V, C>
+where
+    K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    C: Codec,
+{
+    /// A map on an [`EmbeddedServer`] of its own, which stops with the
+    /// map. Start the server yourself to connect several maps to it.
+    pub async fn in_memory() -> Result<Self, Error> {
+        EmbeddedServer::start().await?.connect().await
+    }
+}
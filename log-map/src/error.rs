@@ -13,9 +13,61 @@ pub enum Error {
     #[error("write conflict after {0} retries")]
     Conflict(usize),
 
+    #[error("write did not complete within {0:?}")]
+    Timeout(std::time::Duration),
+
     #[error("connection closed")]
     ConnectionClosed,
 
+    #[error("no server address given")]
+    NoServers,
+
+    #[error("write response for request {got} while waiting for {expected}")]
+    UnexpectedResponse { expected: u64, got: u64 },
+
+    #[error("could not encode or decode a key or value: {0}")]
+    Codec(String),
+
+    #[error("value of {size} bytes is over the server's limit of {limit} bytes")]
+    ValueTooLarge { size: u64, limit: u64 },
+
+    #[error("server does not support {0}")]
+    Unsupported(&'static str),
+
+    #[error("token must be non-empty printable ASCII")]
+    InvalidToken,
+
+    #[error("invalid namespace '{0}': must be non-empty and must not contain ':'")]
+    InvalidNamespace(String),
+
+    #[error("watcher fell behind and missed {0} changes")]
+    Lagged(u64),
+
+    #[error("cache did not catch up with ordinal {0} in time")]
+    SyncTimeout(u64),
+
+    #[error("lease on lock '{0}' was lost")]
+    LeaseLost(String),
+
+    #[error("REST gateway request failed: {0}")]
+    Http(String),
+
     #[error("internal error: {0}")]
     Internal(String),
 }
+
+impl Error {
+    /// Whether the server couldn't be reached or the connection to it
+    /// broke, rather than the server answering with an error.
+    pub(crate) fn is_connection_error(&self) -> bool {
+        match self {
+            Error::Transport(_) | Error::ConnectionClosed => true,
+            // A connection that broke mid-call shows up as `Unknown`.
+            Error::Status(status) => {
+                status.code() == tonic::Code::Unavailable
+                    || std::error::Error::source(status).is_some_and(|source| source.is::<tonic::transport::Error>())
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Moving a map to the next server when the one in use goes away.
+
+use std::sync::Mutex;
+
+use tokio::sync::watch;
+use tonic::transport::{Channel, Endpoint};
+
+use crate::error::Error;
+use crate::protocol::{self, Client, Extra};
+
+/// The servers a map may use, in order of preference, and the one it
+/// uses now. Writes and the background sync both go to that one; when
+/// either finds it gone, they move on together.
+pub(crate) struct Servers {
+    endpoints: Vec<Endpoint>,
+    extra: Extra,
+    /// Index in `endpoints` of the server in use, and a client for it.
+    current: Mutex<(usize, Client)>,
+    /// The index of the server in use, for the sync to notice a failover
+    /// the writes started.
+    moved: watch::Sender<usize>,
+    /// Held while looking for the next server, so a failure seen by
+    /// several callers moves on once.
+    switching: tokio::sync::Mutex<()>,
+}
+
+impl Servers {
+    /// Connects to the first of `endpoints` that accepts a connection.
+    pub(crate) async fn connect(endpoints: Vec<Endpoint>, extra: Extra) -> Result<Self, Error> {
+        let mut last_error = Error::NoServers;
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            match endpoint.connect().await {
+                Ok(channel) => {
+                    let client = protocol::client(channel, extra.clone());
+                    return Ok(Self {
+                        current: Mutex::new((index, client)),
+                        moved: watch::Sender::new(index),
+                        endpoints,
+                        extra,
+                        switching: tokio::sync::Mutex::new(()),
+                    });
+                }
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(server = %endpoint.uri(), error = %e, "server unreachable, trying the next");
+                    last_error = e.into();
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Just `channel`, with nowhere to fail over to.
+    pub(crate) fn single(channel: Channel, extra: Extra) -> Self {
+        Self {
+            current: Mutex::new((0, protocol::client(channel, extra.clone()))),
+            moved: watch::Sender::new(0),
+            endpoints: Vec::new(),
+            extra,
+            switching: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The server in use, by index, and a client for it.
+    pub(crate) fn current(&self) -> (usize, Client) {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Changes with every failover.
+    pub(crate) fn moved(&self) -> watch::Receiver<usize> {
+        self.moved.subscribe()
+    }
+
+    /// Whether there is another server to move to.
+    pub(crate) fn can_fail_over(&self) -> bool {
+        self.endpoints.len() > 1
+    }
+
+    /// Moves on from server `failed` to the next one, in order and
+    /// wrapping around, that accepts a connection. Does nothing if another
+    /// caller moved on already, or if none does.
+    pub(crate) async fn fail_over(&self, failed: usize) {
+        if !self.can_fail_over() {
+            return;
+        }
+        let _switching = self.switching.lock().await;
+        if self.current.lock().unwrap().0 != failed {
+            return;
+        }
+        let count = self.endpoints.len();
+        for index in (1..=count).map(|offset| (failed + offset) % count) {
+            let endpoint = &self.endpoints[index];
+            match endpoint.connect().await {
+                Ok(channel) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        from = %self.endpoints[failed].uri(),
+                        to = %endpoint.uri(),
+                        "failing over"
+                    );
+                    *self.current.lock().unwrap() = (index, protocol::client(channel, self.extra.clone()));
+                    self.moved.send_replace(index);
+                    return;
+                }
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(server = %endpoint.uri(), error = %_e, "server unreachable");
+                }
+            }
+        }
+    }
+}
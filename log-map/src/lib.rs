@@ -2,14 +2,26 @@
 //!
 //! `log-map` provides a `Map<i64, String>` implementation that stores all data
 //! through the log-server's gRPC API. It uses optimistic concurrency control
-//! with automatic retry on conflicts.
+//! with automatic retry on conflicts. [`TypedLogMap`] does the same for any
+//! serde-serializable keys and values.
 //!
 //! # Features
 //!
 //! - Distributed key-value storage with automatic sync
-//! - Optimistic concurrency control with exponential backoff
-//! - Background subscription to keep local cache updated
+//! - Optimistic concurrency control with exponential backoff, tunable with
+//!   a [`RetryPolicy`]
+//! - Multi-key transactions that commit together or not at all
+//! - Leased locks with fencing tokens, see [`lock`]
+//! - [`Counter`]s that many clients add to without conflicts
+//! - Background subscription to keep local cache updated, reconnecting
+//!   with backoff when it drops
+//! - Failover to the next of several servers, see [`ServerList`]
 //! - Key prefix isolation (`map:`) to avoid collisions
+//! - Protocol negotiation, so newer clients degrade gracefully on older servers
+//! - With the `embedded` feature, an in-process log-server for hermetic
+//!   tests, see [`embedded`]
+//! - With the `rest` feature, a blocking client of the server's REST
+//!   gateway for where the gRPC stack is too heavy, see [`rest`]
 //!
 //! # Example
 //!
@@ -28,10 +40,34 @@
 //! }
 //! ```
 
+mod builder;
 mod cache;
+mod chunk;
+mod coalesce;
+mod codec;
+mod counter;
+#[cfg(feature = "embedded")]
+pub mod embedded;
 mod error;
+mod failover;
+pub mod lock;
 mod map;
+mod protocol;
+#[cfg(feature = "rest")]
+pub mod rest;
+mod retry;
 mod sync;
+mod transaction;
 
+pub use builder::{ConnectConfig, LogMapBuilder};
+#[cfg(feature = "tls")]
+pub use builder::TlsConfig;
+pub use cache::{Cache, Entry};
+pub use codec::{Codec, Json, Plain};
+pub use counter::Counter;
 pub use error::Error;
-pub use map::{LogMap, ServerAddr};
+pub use map::{Change, LogMap, ServerAddr, ServerList, TypedLogMap};
+pub use protocol::ServerInfo;
+pub use retry::RetryPolicy;
+pub use sync::Health;
+pub use transaction::Transaction;
@@ -0,0 +1,227 @@
+//! Named locks held under a lease, for coordinating workers through the log.
+//!
+//! A lock is a key in the `lock` namespace whose value names the holder.
+//! It is written with a TTL of one lease, so a holder that crashes loses
+//! the lock once the lease lapses, and renewed by a heartbeat while the
+//! [`LockGuard`] is alive. Claims, renewals and releases are conditional
+//! writes, so two clients never both see themselves as the holder.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), log_map::Error> {
+//! use std::time::Duration;
+//! use log_map::lock::Mutex;
+//!
+//! let locks = Mutex::connect("localhost:50051").await?;
+//! let guard = locks.acquire("matrix-row-7", Duration::from_secs(10)).await?;
+//! // Pass guard.fencing_token() along with writes to shared resources.
+//! guard.release().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A lease is a promise about time, and a paused process can outlive it
+//! without noticing. Services that act on behalf of a holder should
+//! remember the highest [fencing token](LockGuard::fencing_token) they have
+//! seen and refuse lower ones. Needs the `conditional-writes` and `ttl`
+//! server features.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use log_server_types::features;
+use tokio::task::JoinHandle;
+
+use crate::cache::{expires_after, now_millis};
+use crate::codec::Plain;
+use crate::error::Error;
+use crate::map::{ServerList, TypedLogMap};
+
+/// The namespace [`Mutex::connect`] keeps locks in.
+pub const NAMESPACE: &str = "lock";
+
+/// How long to wait before trying again when a claim lost a race and the
+/// current lease is not known yet.
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Tells the holders of one client apart.
+static NEXT_HOLDER: AtomicU64 = AtomicU64::new(1);
+
+type LockMap = TypedLogMap<String, String, Plain>;
+
+/// Hands out named locks, see the [module documentation](self).
+pub struct Mutex {
+    map: Arc<LockMap>,
+}
+
+impl Mutex {
+    /// Connects to a log-server, keeping locks in the [`NAMESPACE`]
+    /// namespace.
+    pub async fn connect(addr: impl Into<ServerList>) -> Result<Self, Error> {
+        Ok(Self::new(LockMap::connect_namespace(addr, NAMESPACE).await?))
+    }
+
+    /// Uses `map` for the locks, e.g. one built with a token or TLS.
+    /// Clients only contend for a lock when they use the same namespace.
+    pub fn new(map: TypedLogMap<String, String, Plain>) -> Self {
+        Self { map: Arc::new(map) }
+    }
+
+    /// Takes the lock `name`, waiting while someone else holds it, until
+    /// their lease lapses at the latest.
+    ///
+    /// The lease lasts `lease` and is renewed every third of it until the
+    /// guard is released or dropped.
+    pub async fn acquire(&self, name: &str, lease: Duration) -> Result<LockGuard, Error> {
+        loop {
+            // Subscribed before trying, so a release in between wakes us.
+            let changes = self.map.watch(name.to_string());
+            tokio::pin!(changes);
+            if let Some(guard) = self.try_acquire(name, lease).await? {
+                return Ok(guard);
+            }
+
+            let wait = match self.map.entry(name.to_string()) {
+                Some(entry) if entry.expires_at > 0 => {
+                    Duration::from_millis(entry.expires_at.saturating_sub(now_millis()).max(0) as u64)
+                }
+                _ => RETRY_DELAY,
+            };
+            let _ = tokio::time::timeout(wait, changes.next()).await;
+        }
+    }
+
+    /// Takes the lock `name` if nobody holds it, like
+    /// [`acquire`](Self::acquire), or returns `None` right away.
+    pub async fn try_acquire(&self, name: &str, lease: Duration) -> Result<Option<LockGuard>, Error> {
+        if !self.map.server_info().supports(features::TTL) {
+            return Err(Error::Unsupported(features::TTL));
+        }
+        let holder = format!(
+            "{}/{}",
+            self.map.client_id(),
+            NEXT_HOLDER.fetch_add(1, Ordering::Relaxed)
+        );
+        let expires_at = expires_after(lease);
+        let claimed = self
+            .map
+            .write_encoded_if(name.to_string(), holder.clone().into_bytes(), expires_at, |current| {
+                current.is_none()
+            })
+            .await;
+        let token = match claimed {
+            Ok(Ok(ordinal)) => ordinal,
+            Ok(Err(_)) | Err(Error::Conflict(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // Renewals and the release check the cache for our claim.
+        self.map.catch_up(token).await?;
+
+        let held = Arc::new(AtomicBool::new(true));
+        let expires_at = Arc::new(AtomicI64::new(expires_at));
+        let heartbeat = tokio::spawn(heartbeat(
+            Arc::clone(&self.map),
+            name.to_string(),
+            holder.clone(),
+            lease,
+            Arc::clone(&held),
+            Arc::clone(&expires_at),
+        ));
+        Ok(Some(LockGuard {
+            map: Arc::clone(&self.map),
+            name: name.to_string(),
+            holder,
+            token,
+            held,
+            expires_at,
+            heartbeat,
+        }))
+    }
+}
+
+/// A held lock. Dropping it stops the renewals, so the lock is free once
+/// the lease lapses; [`release`](Self::release) frees it right away.
+#[must_use = "the lock is given up when the guard is dropped"]
+pub struct LockGuard {
+    map: Arc<LockMap>,
+    name: String,
+    holder: String,
+    token: u64,
+    held: Arc<AtomicBool>,
+    expires_at: Arc<AtomicI64>,
+    heartbeat: JoinHandle<()>,
+}
+
+impl LockGuard {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The log ordinal the lock was claimed at. Every later claim of any
+    /// lock gets a higher one.
+    pub fn fencing_token(&self) -> u64 {
+        self.token
+    }
+
+    /// Whether the lease is still ours: `false` once a renewal found
+    /// someone else holding the lock, or the lease lapsed without one.
+    pub fn is_held(&self) -> bool {
+        self.held.load(Ordering::SeqCst) && self.expires_at.load(Ordering::SeqCst) > now_millis()
+    }
+
+    /// Gives the lock up. Fails with [`Error::LeaseLost`] if it had
+    /// already passed to someone else.
+    pub async fn release(self) -> Result<(), Error> {
+        self.heartbeat.abort();
+        let holder = self.holder.clone();
+        let released = self
+            .map
+            .write_encoded_if(self.name.clone(), Vec::new(), 0, |current| current == Some(&holder))
+            .await?;
+        self.held.store(false, Ordering::SeqCst);
+        released.map(|_| ()).map_err(|_| Error::LeaseLost(self.name.clone()))
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+    }
+}
+
+/// Renews the lease every third of `lease` for as long as it is ours.
+async fn heartbeat(
+    map: Arc<LockMap>,
+    name: String,
+    holder: String,
+    lease: Duration,
+    held: Arc<AtomicBool>,
+    expires_at: Arc<AtomicI64>,
+) {
+    let mut ticker = tokio::time::interval(lease / 3);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let renewed_until = expires_after(lease);
+        let renewed = map
+            .write_encoded_if(name.clone(), holder.clone().into_bytes(), renewed_until, |current| {
+                current == Some(&holder)
+            })
+            .await;
+        match renewed {
+            Ok(Ok(_)) => expires_at.store(renewed_until, Ordering::SeqCst),
+            Ok(Err(_)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(lock = %name, "lease lost");
+                held.store(false, Ordering::SeqCst);
+                return;
+            }
+            // Retried on the next tick; the lease may lapse meanwhile.
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(lock = %name, error = %_e, "lease renewal failed");
+            }
+        }
+    }
+}
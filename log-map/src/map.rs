@@ -1,40 +1,93 @@
 //! Distributed map implementation with optimistic concurrency control.
 
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use futures_util::{StreamExt, stream};
-use log_server_types::kv::WriteRequest;
-use log_server_types::kv::kv_server_client::KvServerClient;
+use futures_util::{Stream, StreamExt, stream};
+use log_server_types::features;
+use log_server_types::kv::{
+    GetHistoryRequest, SubscribeRequest, TransactionRequest, TransactionWrite, WriteRejection, WriteRequest,
+    WriteResponse,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::{broadcast, watch};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::task::JoinHandle;
 use tonic::transport::{Channel, Endpoint};
 
-use crate::cache::Cache;
+use crate::builder::{ConnectConfig, LogMapBuilder};
+use crate::cache::{self, Cache, Entry};
+use crate::chunk::{self, Manifest, Manifests};
+use crate::coalesce;
+use crate::codec::{Codec, Json, Plain};
 use crate::error::Error;
-use crate::sync::SyncTask;
+use crate::failover::Servers;
+use crate::protocol::{self, Client, Extra, ServerInfo};
+use crate::retry::RetryPolicy;
+use crate::sync::{Health, SyncTask};
+use crate::transaction::Transaction;
 
-const MAP_PREFIX: &str = "map:";
-const MAX_RETRIES: usize = 5;
+/// Namespace of [`TypedLogMap::connect`]; its keys start with `map:`.
+pub(crate) const DEFAULT_NAMESPACE: &str = "map";
+/// How often `back_off` checks the sync progress.
+const SYNC_POLL: Duration = Duration::from_millis(5);
+/// How long [`TypedLogMap::get_consistent`] waits for the sync task.
+const CONSISTENT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Changes buffered per watcher before it starts missing some.
+const WATCH_CAPACITY: usize = 1024;
+
+/// The original map of `i64` keys to `String` values, stored in the
+/// [`Plain`] format.
+pub type LogMap = TypedLogMap<i64, String, Plain>;
 
 /// A distributed key-value map backed by the log-server.
 ///
-/// `LogMap` stores `i64` keys with `String` values through the log-server's
-/// gRPC API. All mutations go through the log's append-only storage with
-/// optimistic concurrency control.
+/// `TypedLogMap` stores serde-serializable keys and values through the
+/// log-server's gRPC API, encoded with the codec `C` ([`Json`] unless
+/// chosen otherwise). All mutations go through the log's append-only
+/// storage with optimistic concurrency control. [`LogMap`] is the
+/// `i64`/`String` map most callers use.
 ///
 /// # Conflict Resolution
 ///
-/// When a write is rejected by the server, `LogMap` automatically:
-/// 1. Syncs the latest state from the server
-/// 2. Retries the write with updated `latest_known` ordinal
-/// 3. Uses exponential backoff (100ms starting, doubles each retry)
-/// 4. Gives up after 5 retries
+/// Plain writes ([`insert`](Self::insert), [`remove`](Self::remove),
+/// [`insert_with_ttl`](Self::insert_with_ttl) and
+/// [`insert_batch`](Self::insert_batch)) are unconditional: the last one to
+/// reach the server wins, whatever this map has synced so far.
+///
+/// Only [`insert_if_absent`](Self::insert_if_absent) and
+/// [`compare_and_swap`](Self::compare_and_swap) conflict, when their key
+/// was written after the `latest_known` ordinal they carry; writes to other
+/// keys never get in the way. When one is rejected, `LogMap` automatically:
+/// 1. Waits for the sync to apply the key's newer record
+/// 2. Checks the newer value again, and returns it if the check fails
+/// 3. Otherwise retries with the updated `latest_known` ordinal
+/// 4. Uses exponential backoff (100ms starting, doubles each retry)
+/// 5. Gives up after 5 retries with [`Error::Conflict`]
+///
+/// The delays, the number of retries and an overall deadline can be set
+/// with a [`RetryPolicy`]. Conditional writes need a server with the
+/// `conditional-writes` feature and fail with [`Error::Unsupported`]
+/// otherwise.
 ///
 /// # Key Encoding
 ///
-/// Keys are encoded as `"map:{i64}"` in the log to avoid collisions with
-/// other data using the same log-server.
+/// Keys are encoded as `"map:{key}"` in the log, with the key as the codec
+/// encodes it, to avoid collisions with other data using the same
+/// log-server. Records the codec can't decode are skipped.
+///
+/// # Large Values
+///
+/// Values over the chunk size, 1 MiB or the server's `max_value_size` if
+/// lower, are written in pieces under `<namespace>~chunks:` first; the
+/// key's record then points at them. Reads, watchers, the history and
+/// replays see the whole value. Pieces of replaced values are removed by
+/// the writer that replaced them. See [`LogMapBuilder::chunk_size`].
 ///
 /// # Example
 ///
@@ -54,160 +107,1146 @@ const MAX_RETRIES: usize = 5;
 ///     Ok(())
 /// }
 /// ```
-pub struct LogMap {
-    inner: Arc<LogMapInner>,
+///
+/// With structured values:
+///
+/// ```no_run
+/// use log_map::TypedLogMap;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Clone, Serialize, Deserialize)]
+/// struct Progress {
+///     done: u32,
+///     total: u32,
+/// }
+///
+/// # async fn example() -> Result<(), log_map::Error> {
+/// let map: TypedLogMap<String, Progress> = TypedLogMap::connect("localhost:50051").await?;
+/// map.insert("job-1".to_string(), Progress { done: 3, total: 10 }).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedLogMap<K, V, C = Json> {
+    inner: Arc<LogMapInner<K, V>>,
+    codec: PhantomData<C>,
 }
 
-struct LogMapInner {
-    cache: Arc<Cache>,
-    client: tokio::sync::Mutex<KvServerClient<Channel>>,
+struct LogMapInner<K, V> {
+    cache: Arc<Cache<K, V>>,
+    /// The servers to write to and, without a replica, to read from.
+    servers: Arc<Servers>,
+    /// Index of the server the client is for, and a client for writes;
+    /// locked for the length of a write.
+    writer: tokio::sync::Mutex<(usize, Client)>,
+    /// Snapshot and subscriptions, from the preferred replica.
+    replica: Option<Client>,
+    /// `<namespace>:`, prepended to every encoded key.
+    prefix: String,
+    /// `<namespace>~chunks:`, prepended to the keys of value pieces.
+    chunk_prefix: String,
+    /// Values over this size are written in pieces; `None` if the map
+    /// doesn't chunk values.
+    chunk_size: Option<usize>,
+    /// Kept up to date by the sync task.
+    manifests: Arc<Manifests>,
+    server_info: ServerInfo,
+    retry: RetryPolicy,
+    client_id: String,
+    worker_label: std::sync::RwLock<String>,
     next_ordinal: AtomicU64,
     latest_known: Arc<AtomicU64>,
-    last_sync: Arc<AtomicU64>,
+    /// Highest ordinal assigned to one of this map's accepted writes.
+    last_write: AtomicU64,
+    /// Every change the sync task applies, for [`TypedLogMap::watch`].
+    changes: broadcast::Sender<Change<K, V>>,
+    /// Published by the sync task, for [`TypedLogMap::health`].
+    health: watch::Receiver<Health>,
     _sync_handle: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
 }
 
-impl LogMap {
+impl<K, V> Drop for LogMapInner<K, V> {
+    /// Stops the sync task, which would otherwise keep reconnecting.
+    fn drop(&mut self) {
+        if let Ok(handle) = self._sync_handle.try_lock()
+            && let Some(handle) = handle.as_ref()
+        {
+            handle.abort();
+        }
+    }
+}
+
+impl<K, V, C> TypedLogMap<K, V, C>
+where
+    K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    C: Codec,
+{
     /// Connects to a log-server and creates a new `LogMap` instance.
     ///
-    /// This spawns a background task that subscribes to log updates and
-    /// keeps the local cache synchronized.
+    /// This negotiates the protocol version with the server, then spawns a
+    /// background task that subscribes to log updates and keeps the local
+    /// cache synchronized.
     ///
     /// # Arguments
     ///
-    /// * `addr` - Server address (e.g., `"localhost:50051"`)
-    pub async fn connect(addr: impl Into<ServerAddr>) -> Result<Self, Error> {
-        let server_addr = addr.into();
-        let endpoint = Endpoint::from_shared(format!("http://{}", server_addr.0))?;
-        let channel = endpoint.connect().await?;
-        let client = KvServerClient::new(channel);
+    /// * `addr` - Server address (e.g., `"localhost:50051"`), or several
+    ///   to fail over between, see [`ServerList`]
+    pub async fn connect(addr: impl Into<ServerList>) -> Result<Self, Error> {
+        Self::builder().connect(addr).await
+    }
+
+    /// Connects like [`connect`](Self::connect), with TLS, a bearer token
+    /// and the namespace as configured in `config`.
+    pub async fn connect_with_config(addr: impl Into<ServerList>, config: ConnectConfig) -> Result<Self, Error> {
+        Self::builder().config(config).connect(addr).await
+    }
+
+    /// Connects like [`connect`](Self::connect), keeping this map's keys
+    /// apart from those of other applications on the same server.
+    ///
+    /// Keys are stored as `<namespace>:<key>`, and on servers with the
+    /// `prefix-subscribe` feature the map only downloads its own
+    /// namespace. `connect` uses the namespace `map`. Namespaces must be
+    /// non-empty and must not contain `:`.
+    pub async fn connect_namespace(addr: impl Into<ServerList>, namespace: &str) -> Result<Self, Error> {
+        Self::builder().namespace(namespace).connect(addr).await
+    }
+
+    /// Starts configuring a `LogMap`, e.g. with extra request metadata or
+    /// interceptors. See [`LogMapBuilder`].
+    pub fn builder() -> LogMapBuilder<K, V, C> {
+        LogMapBuilder::default()
+    }
+
+    /// Creates a `LogMap` on top of an already established channel.
+    ///
+    /// This is the hook for custom transports, e.g. the in-memory
+    /// simulation link in `log-server-test`.
+    pub async fn with_channel(channel: Channel) -> Result<Self, Error> {
+        Self::builder().with_channel(channel).await
+    }
+
+    /// Connects to the first of `endpoints` that answers.
+    pub(crate) async fn open(
+        endpoints: Vec<Endpoint>,
+        replica: Option<Endpoint>,
+        extra: Extra,
+        namespace: &str,
+        retry: RetryPolicy,
+        chunk_size: usize,
+    ) -> Result<Self, Error> {
+        let prefix = key_prefix(namespace)?;
+        let servers = Servers::connect(endpoints, extra.clone()).await?;
+
+        let mut read_channel = None;
+        if let Some(replica) = replica {
+            match replica.connect().await {
+                Ok(channel) => read_channel = Some(channel),
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(replica = %replica.uri(), error = %_e, "replica unreachable, reading from the primary");
+                }
+            }
+        }
+
+        Self::open_prefixed(servers, read_channel, extra, prefix, retry, chunk_size).await
+    }
+
+    /// `read_channel` serves the snapshot and subscriptions when given.
+    pub(crate) async fn open_channel(
+        channel: Channel,
+        read_channel: Option<Channel>,
+        extra: Extra,
+        namespace: &str,
+        retry: RetryPolicy,
+        chunk_size: usize,
+    ) -> Result<Self, Error> {
+        let prefix = key_prefix(namespace)?;
+        let servers = Servers::single(channel, extra.clone());
+        Self::open_prefixed(servers, read_channel, extra, prefix, retry, chunk_size).await
+    }
+
+    async fn open_prefixed(
+        servers: Servers,
+        read_channel: Option<Channel>,
+        extra: Extra,
+        prefix: String,
+        retry: RetryPolicy,
+        chunk_size: usize,
+    ) -> Result<Self, Error> {
+        let replica = read_channel.map(|read_channel| protocol::client(read_channel, extra));
+        let (server, mut client) = servers.current();
+        let server_info = protocol::negotiate(&mut client).await?;
+        let servers = Arc::new(servers);
+        // Pieces are read back with GetHistory, and must fit the server's limit.
+        let chunk_size = match server_info.max_value_size {
+            0 => chunk_size,
+            limit => chunk_size.min(usize::try_from(limit).unwrap_or(usize::MAX)),
+        };
+        let chunk_size = (chunk_size > 0 && server_info.supports(features::HISTORY)).then_some(chunk_size);
+        let chunk_prefix = chunk::chunk_prefix(&prefix);
+        let manifests = Arc::new(Manifests::default());
 
         let cache = Arc::new(Cache::new());
         let next_ordinal = AtomicU64::new(1);
         let latest_known = Arc::new(AtomicU64::new(0));
         let last_sync = Arc::new(AtomicU64::new(0));
+        let (changes, _) = broadcast::channel(WATCH_CAPACITY);
+        let (health_tx, health) = watch::channel(Health::Connecting);
 
         let inner = Arc::new(LogMapInner {
             cache: Arc::clone(&cache),
-            client: tokio::sync::Mutex::new(client),
+            servers: Arc::clone(&servers),
+            writer: tokio::sync::Mutex::new((server, client.clone())),
+            replica: replica.clone(),
+            prefix: prefix.clone(),
+            chunk_prefix: chunk_prefix.clone(),
+            chunk_size,
+            manifests: Arc::clone(&manifests),
+            server_info,
+            retry,
+            client_id: new_client_id(),
+            worker_label: std::sync::RwLock::new(String::new()),
             next_ordinal,
             latest_known: Arc::clone(&latest_known),
-            last_sync: Arc::clone(&last_sync),
+            last_write: AtomicU64::new(0),
+            changes: changes.clone(),
+            health,
             _sync_handle: Arc::new(tokio::sync::Mutex::new(None)),
         });
 
-        let sync_task = SyncTask::new(
-            inner.client.lock().await.clone(),
+        let sync_task = SyncTask::<K, V, C>::new(
+            replica.unwrap_or(client),
             cache,
             last_sync,
             latest_known,
-        );
+            changes,
+            prefix,
+            health_tx,
+        )
+        .with_chunks(chunk_prefix, manifests)
+        .with_chunked_snapshots(inner.server_info.supports(features::CHUNKED_SNAPSHOTS));
+        // A replica is read from for good; the primary is left with the writes.
+        let sync_task = match &inner.replica {
+            Some(_) => sync_task,
+            None => sync_task.with_failover(servers),
+        };
 
-        let sync_handle = tokio::spawn(async move {
-            if let Err(e) = sync_task.run().await {
-                eprintln!("Sync task error: {:?}", e);
-            }
-        });
+        let sync_handle = tokio::spawn(sync_task.run());
 
         *inner._sync_handle.lock().await = Some(sync_handle);
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            codec: PhantomData,
+        })
+    }
+
+    /// The namespace this map's keys live in, `map` unless chosen with
+    /// [`connect_namespace`](Self::connect_namespace).
+    pub fn namespace(&self) -> &str {
+        self.inner.prefix.trim_end_matches(':')
+    }
+
+    /// Whether the background sync is following the log.
+    ///
+    /// After a dropped subscription the map keeps reconnecting with backoff
+    /// and reports [`Health::Reconnecting`] meanwhile; reads are served
+    /// from a stale cache until it is [`Health::Connected`] again.
+    pub fn health(&self) -> Health {
+        self.inner.health.borrow().clone()
+    }
+
+    /// Streams every change of [`health`](Self::health), starting with the
+    /// current state.
+    pub fn health_changes(&self) -> impl Stream<Item = Health> + Send + 'static {
+        let receiver = self.inner.health.clone();
+        stream::unfold((receiver, true), |(mut receiver, first)| async move {
+            if !first {
+                receiver.changed().await.ok()?;
+            }
+            let health = receiver.borrow_and_update().clone();
+            Some((health, (receiver, false)))
+        })
+    }
+
+    /// Protocol version and features negotiated with the server on connect.
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.inner.server_info
+    }
+
+    /// Identifies this instance's writes in the log, see `Record::client_id`.
+    pub fn client_id(&self) -> &str {
+        &self.inner.client_id
+    }
+
+    /// Labels subsequent writes with `label`, e.g. a worker or role name.
+    pub fn set_worker_label(&self, label: impl Into<String>) {
+        *self.inner.worker_label.write().unwrap() = label.into();
     }
 
     /// Gets the value for a key from the local cache.
-    pub async fn get(&self, key: i64) -> Result<Option<String>, Error> {
+    pub async fn get(&self, key: K) -> Result<Option<V>, Error> {
         Ok(self.inner.cache.get(&key))
     }
 
+    /// Like [`get`](Self::get), but first waits until the cache has caught
+    /// up with every write this map made, so a value just inserted is
+    /// seen. Waits for this map's writes only, not other clients'.
+    ///
+    /// Fails with [`Error::SyncTimeout`] if the sync task doesn't get
+    /// there within 5 seconds, e.g. while reconnecting.
+    pub async fn get_consistent(&self, key: K) -> Result<Option<V>, Error> {
+        self.catch_up(self.inner.last_write.load(Ordering::SeqCst)).await?;
+        self.get(key).await
+    }
+
+    /// Waits until the cache has applied every record up to `ordinal`, at
+    /// most 5 seconds.
+    pub(crate) async fn catch_up(&self, ordinal: u64) -> Result<(), Error> {
+        let deadline = tokio::time::Instant::now() + CONSISTENT_READ_TIMEOUT;
+        while self.inner.latest_known.load(Ordering::SeqCst) < ordinal {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::SyncTimeout(ordinal));
+            }
+            tokio::time::sleep(SYNC_POLL).await;
+        }
+        Ok(())
+    }
+
+    /// Gets the value for a key together with the time it was written.
+    pub fn entry(&self, key: K) -> Option<Entry<V>> {
+        self.inner.cache.entry(&key)
+    }
+
+    /// Current entries written at or after `timestamp` (Unix milliseconds),
+    /// in key order.
+    ///
+    /// Answered from the local cache, so keys removed since then are not
+    /// reported and values loaded from a snapshot (timestamp 0) only show
+    /// up for `timestamp <= 0`. Use [`replay_since`](Self::replay_since)
+    /// for the full history.
+    pub fn entries_since(&self, timestamp: i64) -> Vec<(K, Entry<V>)> {
+        self.inner.cache.entries_since(timestamp)
+    }
+
+    /// Streams every change to the map written at or after `timestamp`
+    /// (Unix milliseconds), removals included, in log order.
+    ///
+    /// The stream follows the log like the background sync does and does
+    /// not end on its own. Servers without the `timestamp-subscribe`
+    /// feature replay from the start of the log and the records are
+    /// filtered here instead.
+    pub async fn replay_since(
+        &self,
+        timestamp: i64,
+    ) -> Result<impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static, Error> {
+        let mut client = self.reader();
+        let prefix = self.inner.prefix.clone();
+        let request = SubscribeRequest {
+            start_ordinal: 0,
+            start_timestamp: timestamp,
+            key_prefix: prefix.clone(),
+            ..Default::default()
+        };
+        let records = client.subscribe(request).await?.into_inner();
+        let chunk_prefix = self.inner.chunk_prefix.clone();
+
+        Ok(records.filter_map(move |result| {
+            let (client, prefix, chunk_prefix) = (client.clone(), prefix.clone(), chunk_prefix.clone());
+            async move {
+                match result {
+                    Ok(record) if record.timestamp >= timestamp => {
+                        Change::resolve::<C>(&client, &chunk_prefix, record, &prefix).await.transpose()
+                    }
+                    Ok(_) => None,
+                    Err(status) => Some(Err(Error::from(status))),
+                }
+            }
+        }))
+    }
+
+    /// Every write of `key` the server still holds, oldest first, removals
+    /// included. Queries the server's log, not the cache.
+    ///
+    /// Records replaced before the last compaction are gone, so a compacted
+    /// key's history starts with its value at that point, and chunked
+    /// values are left out once replaced, as their pieces are removed.
+    /// Needs a server with the `history` feature.
+    pub async fn history(&self, key: K) -> Result<Vec<Change<K, V>>, Error> {
+        self.query_history(&key, 0, 0).await
+    }
+
+    /// The value `key` had once the log reached `ordinal`, i.e. that of its
+    /// latest write at or before `ordinal`; `None` if it was absent or
+    /// removed then. Like [`history`](Self::history), it can't see past
+    /// the last compaction.
+    pub async fn get_at(&self, key: K, ordinal: u64) -> Result<Option<V>, Error> {
+        if ordinal == 0 {
+            return Ok(None);
+        }
+        let mut changes = self.query_history(&key, ordinal, 1).await?;
+        Ok(changes.pop().and_then(|change| change.value))
+    }
+
+    async fn query_history(&self, key: &K, until_ordinal: u64, limit: u32) -> Result<Vec<Change<K, V>>, Error> {
+        if !self.inner.server_info.supports(features::HISTORY) {
+            return Err(Error::Unsupported(features::HISTORY));
+        }
+        let request = GetHistoryRequest {
+            key: self.record_key(key)?,
+            until_ordinal,
+            limit,
+        };
+        let reader = self.reader();
+        let response = reader.clone().get_history(request).await?.into_inner();
+        let mut changes = Vec::with_capacity(response.records.len());
+        for record in response.records {
+            let change = Change::resolve::<C>(&reader, &self.inner.chunk_prefix, record, &self.inner.prefix).await?;
+            changes.extend(change);
+        }
+        Ok(changes)
+    }
+
+    /// Streams changes to `key` as the background sync applies them, so
+    /// callers can await an update instead of polling the cache.
+    ///
+    /// Only changes made after the call are reported; the current value is
+    /// in the cache already. A watcher that falls more than 1024 changes
+    /// behind gets [`Error::Lagged`] and continues with newer changes. The
+    /// stream ends when the map is dropped.
+    pub fn watch(&self, key: K) -> impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static {
+        self.watch_where(move |change| change.key == key)
+    }
+
+    /// Like [`watch`](Self::watch), for every key whose text form starts
+    /// with `prefix`: strings as they are, numbers in decimal, anything
+    /// else as JSON. An empty prefix watches the whole map.
+    pub fn watch_prefix(
+        &self,
+        prefix: impl Into<String>,
+    ) -> impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static {
+        let prefix = prefix.into();
+        self.watch_where(move |change| key_text(&change.key).starts_with(&prefix))
+    }
+
+    /// Like [`watch`](Self::watch), for keys that change faster than the
+    /// caller wants to hear about: at most one change every `interval`,
+    /// the latest one, so intermediate values are skipped.
+    ///
+    /// The first change after a quiet interval comes right away. Changes
+    /// are collected in the background while the caller is busy, so a
+    /// slow caller doesn't get [`Error::Lagged`] either.
+    pub fn watch_coalesced(
+        &self,
+        key: K,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static {
+        coalesce::coalesce(self.inner.changes.subscribe(), move |change| change.key == key, interval)
+    }
+
+    /// [`watch_prefix`](Self::watch_prefix) coalesced like
+    /// [`watch_coalesced`](Self::watch_coalesced): every `interval`, the
+    /// latest change of each key that changed, in log order.
+    pub fn watch_prefix_coalesced(
+        &self,
+        prefix: impl Into<String>,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static {
+        let prefix = prefix.into();
+        let matches = move |change: &Change<K, V>| key_text(&change.key).starts_with(&prefix);
+        coalesce::coalesce(self.inner.changes.subscribe(), matches, interval)
+    }
+
+    fn watch_where(
+        &self,
+        matches: impl Fn(&Change<K, V>) -> bool + Send + 'static,
+    ) -> impl Stream<Item = Result<Change<K, V>, Error>> + Send + 'static {
+        let receiver = self.inner.changes.subscribe();
+        stream::unfold((receiver, matches), |(mut receiver, matches)| async move {
+            loop {
+                let item = match receiver.recv().await {
+                    Ok(change) if matches(&change) => Ok(change),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => Err(Error::Lagged(missed)),
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((item, (receiver, matches)));
+            }
+        })
+    }
+
     /// Inserts a key-value pair into the map.
     ///
     /// This writes to the log-server with optimistic concurrency control.
-    /// On conflict, it will retry up to 5 times with exponential backoff.
-    pub async fn insert(&self, key: i64, value: String) -> Result<(), Error> {
-        let mut retries = 0;
-        let mut delay = Duration::from_millis(100);
+    /// On conflict, it retries as the map's [`RetryPolicy`] says, by
+    /// default up to 5 times with exponential backoff.
+    pub async fn insert(&self, key: K, value: V) -> Result<(), Error> {
+        let key = self.record_key(&key)?;
+        self.write_with_retry(key, C::encode_value(&value)?, 0).await
+    }
 
-        loop {
-            let ordinal = self.inner.next_ordinal.fetch_add(1, Ordering::SeqCst);
-            let latest_known = self.inner.latest_known.load(Ordering::SeqCst);
+    /// Inserts a key-value pair that expires after `ttl`.
+    ///
+    /// Once lapsed, the entry is no longer returned by `get`,
+    /// `contains_key` or iteration, and the server writes a tombstone for
+    /// it shortly after, which watchers see as a removal. Overwriting the
+    /// key first, with or without a TTL, replaces the expiry. Expiry is
+    /// judged by the local and the server clock, so keep them in sync.
+    /// Needs a server with the `ttl` feature.
+    pub async fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Result<(), Error> {
+        if !self.inner.server_info.supports(features::TTL) {
+            return Err(Error::Unsupported(features::TTL));
+        }
+        let key = self.record_key(&key)?;
+        let expires_at = cache::expires_after(ttl);
+        self.write_with_retry(key, C::encode_value(&value)?, expires_at).await
+    }
 
-            let mut request = WriteRequest::default();
-            request.ordinal = ordinal;
-            request.key = format!("{}{}", MAP_PREFIX, key);
-            request.value = value.clone().into_bytes();
-            request.latest_known = latest_known;
+    /// Inserts many key-value pairs, pipelined over one write stream.
+    ///
+    /// Returns one result per entry, in input order. Writes the server
+    /// rejects are retried one by one like [`insert`](Self::insert) does;
+    /// the outer error means the stream itself failed. Servers without the
+    /// `batch-writes` feature get the entries one at a time.
+    pub async fn insert_batch(&self, entries: Vec<(K, V)>) -> Result<Vec<Result<(), Error>>, Error> {
+        let mut results: Vec<Result<(), Error>> = Vec::with_capacity(entries.len());
+        let mut writes = Vec::with_capacity(entries.len());
+        for (key, value) in &entries {
+            let encoded = self.record_key(key).and_then(|key| Ok((key, C::encode_value(value)?)));
+            match encoded {
+                Ok((key, value)) => {
+                    writes.push((results.len(), key, value));
+                    results.push(Ok(()));
+                }
+                Err(e) => results.push(Err(e)),
+            }
+        }
 
-            let mut client = self.inner.client.lock().await;
-            let request_stream = stream::once(async { request });
-            let mut response_stream = client.write(request_stream).await?.into_inner();
-            let response = response_stream
-                .next()
-                .await
-                .ok_or(Error::ConnectionClosed)??;
-            drop(client);
+        if !self.inner.server_info.supports(features::BATCH_WRITES) {
+            for (index, key, value) in writes {
+                results[index] = self.write_with_retry(key, value, 0).await;
+            }
+            return Ok(results);
+        }
 
-            if response.accepted {
-                return Ok(());
+        let mut chunked = Vec::with_capacity(writes.len());
+        for (index, key, value) in writes {
+            match self.chunk_value(value).await {
+                Ok(value) => chunked.push((index, key, value)),
+                Err(e) => results[index] = Err(e),
             }
+        }
+        let writes = chunked;
 
-            retries += 1;
-            if retries >= MAX_RETRIES {
-                return Err(Error::Conflict(retries));
+        let latest_known = self.inner.latest_known.load(Ordering::SeqCst);
+        let replaced: Vec<Option<Manifest>> = writes.iter().map(|(_, key, _)| self.replaced_manifest(key)).collect();
+        let first_ordinal = self
+            .inner
+            .next_ordinal
+            .fetch_add(writes.len() as u64, Ordering::SeqCst);
+        let worker_label = self.inner.worker_label.read().unwrap().clone();
+        let requests: Vec<WriteRequest> = writes
+            .iter()
+            .zip(first_ordinal..)
+            .map(|((_, key, value), ordinal)| WriteRequest {
+                ordinal,
+                key: key.clone(),
+                value: value.clone(),
+                latest_known,
+                client_id: self.inner.client_id.clone(),
+                worker_label: worker_label.clone(),
+                if_unchanged: false,
+                expires_at: 0,
+            })
+            .collect();
+
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+        let mut answered = vec![None; writes.len()];
+        let mut writer = self.writer().await;
+        let streamed = async {
+            let mut responses = writer.1.write(stream::iter(requests)).await?.into_inner();
+            let mut position = 0;
+            while let Some(response) = responses.next().await {
+                let response = response?;
+                // Old servers leave request_ordinal at 0 and answer in order.
+                let slot = match response.request_ordinal {
+                    0 => position,
+                    ordinal => ordinal
+                        .checked_sub(first_ordinal)
+                        .map(|slot| slot as usize)
+                        .filter(|slot| *slot < answered.len())
+                        .ok_or(Error::UnexpectedResponse {
+                            expected: first_ordinal + position as u64,
+                            got: ordinal,
+                        })?,
+                };
+                if response.accepted {
+                    self.inner.last_write.fetch_max(response.assigned_ordinal, Ordering::SeqCst);
+                }
+                answered[slot] = Some(response.accepted);
+                position += 1;
             }
+            Ok(())
+        }
+        .await;
+        let server = writer.0;
+        drop(writer);
+        // Writes left unanswered by a server that went away are sent again
+        // to the next one.
+        let failed_over = match self.check_server(server, streamed).await {
+            Ok(()) => false,
+            Err(e) if e.is_connection_error() && self.inner.servers.can_fail_over() => true,
+            Err(e) => return Err(e),
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            writes = writes.len(),
+            accepted = answered.iter().filter(|a| **a == Some(true)).count(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "batch written"
+        );
 
-            self.sync_now().await?;
-            tokio::time::sleep(delay).await;
-            delay *= 2;
+        for (((index, key, value), accepted), replaced) in writes.into_iter().zip(answered).zip(replaced) {
+            results[index] = match accepted {
+                Some(true) => {
+                    self.remove_chunks(replaced).await;
+                    Ok(())
+                }
+                Some(false) => self.write_with_retry(key, value, 0).await,
+                None if failed_over => self.write_with_retry(key, value, 0).await,
+                None => Err(Error::ConnectionClosed),
+            };
         }
+        Ok(results)
     }
 
     /// Removes a key from the map by writing a tombstone.
     ///
     /// This writes an empty value to the log-server, which is interpreted
     /// as a deletion by the sync task.
-    pub async fn remove(&self, key: i64) -> Result<(), Error> {
-        let mut retries = 0;
-        let mut delay = Duration::from_millis(100);
+    pub async fn remove(&self, key: K) -> Result<(), Error> {
+        let key = self.record_key(&key)?;
+        self.write_with_retry(key, Vec::new(), 0).await
+    }
 
-        loop {
-            let ordinal = self.inner.next_ordinal.fetch_add(1, Ordering::SeqCst);
+    /// Inserts `value` unless `key` already has a value.
+    ///
+    /// Unlike checking [`contains_key`](Self::contains_key) first, this is
+    /// atomic: the server rejects the write if `key` was written since this
+    /// map last synced, and the check runs again on the newer value. Returns
+    /// `Ok(Err(current))` with the value found instead of overwriting it.
+    /// Needs a server with the `conditional-writes` feature.
+    pub async fn insert_if_absent(&self, key: K, value: V) -> Result<Result<(), V>, Error> {
+        let result = self.write_if(key, value, |current| current.is_none()).await?;
+        Ok(result.map_err(|current| current.expect("the check only fails on a present value")))
+    }
+
+    /// Replaces the value of `key` with `new` if it is currently `expected`,
+    /// atomically like [`insert_if_absent`](Self::insert_if_absent).
+    ///
+    /// Returns `Ok(Err(current))` with the value found, `None` if the key
+    /// is absent, when it is not `expected`.
+    pub async fn compare_and_swap(&self, key: K, expected: V, new: V) -> Result<Result<(), Option<V>>, Error>
+    where
+        V: PartialEq,
+    {
+        self.write_if(key, new, |current| current == Some(&expected)).await
+    }
+
+    /// Writes `value` if `check` accepts the current value. A conflict
+    /// means `key` itself changed, so after waiting for the sync to catch
+    /// up the check runs again.
+    async fn write_if(
+        &self,
+        key: K,
+        value: V,
+        check: impl Fn(Option<&V>) -> bool,
+    ) -> Result<Result<(), Option<V>>, Error> {
+        let value = C::encode_value(&value)?;
+        Ok(self.write_encoded_if(key, value, 0, check).await?.map(|_| ()))
+    }
+
+    /// [`write_if`](Self::write_if) for an encoded value, empty for a
+    /// tombstone, that expires at `expires_at` unless that is 0. Returns
+    /// the ordinal the record was written at.
+    pub(crate) async fn write_encoded_if(
+        &self,
+        key: K,
+        value: Vec<u8>,
+        expires_at: i64,
+        check: impl Fn(Option<&V>) -> bool,
+    ) -> Result<Result<u64, Option<V>>, Error> {
+        if !self.inner.server_info.supports(features::CONDITIONAL_WRITES) {
+            return Err(Error::Unsupported(features::CONDITIONAL_WRITES));
+        }
+        let encoded_key = self.record_key(&key)?;
+        let value = self.chunk_value(value).await?;
+        let (key, encoded_key, value, check) = (&key, &encoded_key, &value, &check);
+
+        let result = self.with_retry(|_attempt| async move {
+            // Loaded before reading the cache, which then reflects at least
+            // every record up to `latest_known`.
             let latest_known = self.inner.latest_known.load(Ordering::SeqCst);
+            let current = self.inner.cache.get(key);
+            if !check(current.as_ref()) {
+                return Ok(Attempt::Done(Err(current)));
+            }
+            let replaced = self.replaced_manifest(encoded_key);
+
+            let request = WriteRequest {
+                ordinal: self.inner.next_ordinal.fetch_add(1, Ordering::SeqCst),
+                key: encoded_key.clone(),
+                value: value.clone(),
+                latest_known,
+                client_id: self.inner.client_id.clone(),
+                worker_label: self.inner.worker_label.read().unwrap().clone(),
+                if_unchanged: true,
+                expires_at,
+            };
+            let response = self.send_write(request).await?;
+            if response.accepted {
+                return Ok(Attempt::Done(Ok((response.assigned_ordinal, replaced))));
+            }
+            if response.rejection() == WriteRejection::ValueTooLarge {
+                return Err(self.too_large(value.len()));
+            }
 
-            let mut request = WriteRequest::default();
-            request.ordinal = ordinal;
-            request.key = format!("{}{}", MAP_PREFIX, key);
-            request.value = Vec::new();
-            request.latest_known = latest_known;
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                key = %encoded_key,
+                latest_known,
+                key_ordinal = response.key_ordinal,
+                attempt = _attempt,
+                "conditional write conflict"
+            );
+            Ok(Attempt::Rejected(response.key_ordinal))
+        })
+        .await;
 
-            let mut client = self.inner.client.lock().await;
-            let request_stream = stream::once(async { request });
-            let mut response_stream = client.write(request_stream).await?.into_inner();
-            let response = response_stream
-                .next()
+        match result {
+            Ok(Ok((ordinal, replaced))) => {
+                self.remove_chunks(replaced).await;
+                Ok(Ok(ordinal))
+            }
+            Ok(Err(current)) => {
+                self.remove_chunks(Manifest::parse(value)).await;
+                Ok(Err(current))
+            }
+            Err(e) => Err(self.abandon_chunks(value, e).await),
+        }
+    }
+
+    /// Runs `attempt`, with the 1-based attempt number, until it is done,
+    /// retrying rejections and enforcing the deadline of the map's
+    /// [`RetryPolicy`].
+    async fn with_retry<T, F>(&self, attempt: impl Fn(usize) -> F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<Attempt<T>, Error>>,
+    {
+        let policy = &self.inner.retry;
+        let attempts = async {
+            let mut retries = 0;
+            loop {
+                match attempt(retries + 1).await {
+                    Ok(Attempt::Done(value)) => return Ok(value),
+                    Ok(Attempt::Rejected(key_ordinal)) => {
+                        retries += 1;
+                        if retries >= policy.max_retries {
+                            return Err(Error::Conflict(retries));
+                        }
+                        self.back_off(key_ordinal, policy.delay(retries)).await;
+                    }
+                    // The write went to a server that is gone; the map has
+                    // moved on to the next one.
+                    Err(e) if e.is_connection_error() && self.inner.servers.can_fail_over() => {
+                        retries += 1;
+                        if retries >= policy.max_retries {
+                            return Err(e);
+                        }
+                        self.back_off(0, policy.delay(retries)).await;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        match policy.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, attempts)
                 .await
-                .ok_or(Error::ConnectionClosed)??;
-            drop(client);
+                .map_err(|_| Error::Timeout(timeout))?,
+            None => attempts.await,
+        }
+    }
+
+    /// Waits before retrying a rejected write: until the sync task has
+    /// applied the key's newer record `key_ordinal`, at most `delay`.
+    /// Rejections that don't name one (0: old servers, database errors)
+    /// wait the full `delay`.
+    async fn back_off(&self, key_ordinal: u64, delay: Duration) {
+        if key_ordinal == 0 {
+            tokio::time::sleep(delay).await;
+            return;
+        }
+        let deadline = tokio::time::Instant::now() + delay;
+        while self.inner.latest_known.load(Ordering::SeqCst) < key_ordinal
+            && tokio::time::Instant::now() < deadline
+        {
+            tokio::time::sleep(SYNC_POLL).await;
+        }
+    }
+
+    /// The log key of `key`: the namespace prefix and the encoded key.
+    pub(crate) fn record_key(&self, key: &K) -> Result<String, Error> {
+        Ok(format!("{}{}", self.inner.prefix, C::encode_key(key)?))
+    }
+
+    /// Writes an encoded record over whatever `key` holds, retrying
+    /// rejections with exponential backoff; only servers without
+    /// `conditional-writes` reject such writes, when any record is newer
+    /// than `latest_known`. `expires_at` is 0 for records that don't expire.
+    /// Values over the chunk size are written in pieces.
+    async fn write_with_retry(&self, key: String, value: Vec<u8>, expires_at: i64) -> Result<(), Error> {
+        let value = self.chunk_value(value).await?;
+        let (key, value) = (&key, &value);
+        let result = self.with_retry(|_attempt| async move {
+            let ordinal = self.inner.next_ordinal.fetch_add(1, Ordering::SeqCst);
+            let latest_known = self.inner.latest_known.load(Ordering::SeqCst);
+            let replaced = self.replaced_manifest(key);
+
+            let request = WriteRequest {
+                ordinal,
+                key: key.clone(),
+                value: value.clone(),
+                latest_known,
+                client_id: self.inner.client_id.clone(),
+                worker_label: self.inner.worker_label.read().unwrap().clone(),
+                // Last writer wins: `latest_known` trails this map's own
+                // writes, so checking it would conflict with them.
+                if_unchanged: false,
+                expires_at,
+            };
+
+            #[cfg(feature = "tracing")]
+            let started = std::time::Instant::now();
+            let response = self.send_write(request).await?;
+
+            #[cfg(feature = "tracing")]
+            let latency_ms = started.elapsed().as_millis() as u64;
 
             if response.accepted {
-                return Ok(());
+                #[cfg(feature = "tracing")]
+                tracing::debug!(key = %key, ordinal = response.assigned_ordinal, latency_ms, "write accepted");
+                return Ok(Attempt::Done(replaced));
             }
+            if response.rejection() == WriteRejection::ValueTooLarge {
+                return Err(self.too_large(value.len()));
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                key = %key,
+                latest_known,
+                latest_ordinal = response.assigned_ordinal,
+                key_ordinal = response.key_ordinal,
+                attempt = _attempt,
+                latency_ms,
+                "write conflict"
+            );
+            Ok(Attempt::Rejected(response.key_ordinal))
+        })
+        .await;
 
-            retries += 1;
-            if retries >= MAX_RETRIES {
-                return Err(Error::Conflict(retries));
+        match result {
+            Ok(replaced) => {
+                self.remove_chunks(replaced).await;
+                Ok(())
             }
+            Err(e) => Err(self.abandon_chunks(value, e).await),
+        }
+    }
 
-            self.sync_now().await?;
-            tokio::time::sleep(delay).await;
-            delay *= 2;
+    /// Writes an encoded record over whatever `key` holds, for keys no
+    /// other client writes. Returns the ordinal it was written at.
+    pub(crate) async fn write_unconditional(&self, key: &K, value: Vec<u8>) -> Result<u64, Error> {
+        let size = value.len();
+        let response = self.send_write(self.raw_request(self.record_key(key)?, value)).await?;
+        if response.rejection() == WriteRejection::ValueTooLarge {
+            return Err(self.too_large(size));
+        }
+        if !response.accepted {
+            return Err(Error::Internal(format!("write rejected: {}", response.error)));
+        }
+        Ok(response.assigned_ordinal)
+    }
+
+    /// An unconditional write of `value` to the log key `key`.
+    fn raw_request(&self, key: String, value: Vec<u8>) -> WriteRequest {
+        WriteRequest {
+            ordinal: self.inner.next_ordinal.fetch_add(1, Ordering::SeqCst),
+            key,
+            value,
+            latest_known: self.inner.latest_known.load(Ordering::SeqCst),
+            client_id: self.inner.client_id.clone(),
+            worker_label: self.inner.worker_label.read().unwrap().clone(),
+            if_unchanged: false,
+            expires_at: 0,
+        }
+    }
+
+    /// Writes `requests` over one stream, failing if any is rejected.
+    async fn write_all(&self, requests: Vec<WriteRequest>) -> Result<(), Error> {
+        let expected = requests.len();
+        let mut writer = self.writer().await;
+        let written = async {
+            let mut responses = writer.1.write(stream::iter(requests)).await?.into_inner();
+            let mut answered = 0;
+            while let Some(response) = responses.next().await {
+                let response = response?;
+                if !response.accepted {
+                    return Err(Error::Internal(format!("write rejected: {}", response.error)));
+                }
+                answered += 1;
+            }
+            if answered < expected {
+                return Err(Error::ConnectionClosed);
+            }
+            Ok(())
+        }
+        .await;
+        self.check_server(writer.0, written).await
+    }
+
+    /// `value` as the map writes it: as it is, or if it is over the chunk
+    /// size, the manifest of the pieces it was just written in.
+    async fn chunk_value(&self, value: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let Some(chunk_size) = self.inner.chunk_size.filter(|chunk_size| value.len() > *chunk_size) else {
+            return Ok(value);
+        };
+        let id = format!(
+            "{}.{}",
+            self.inner.client_id,
+            self.inner.next_ordinal.fetch_add(1, Ordering::SeqCst)
+        );
+        let manifest = Manifest::new(id, value.len(), chunk_size);
+        let requests = manifest
+            .chunk_keys(&self.inner.chunk_prefix)
+            .zip(value.chunks(chunk_size))
+            .map(|(key, piece)| self.raw_request(key, piece.to_vec()))
+            .collect();
+        if let Err(e) = self.write_all(requests).await {
+            self.remove_chunks(Some(manifest)).await;
+            return Err(e);
+        }
+        Ok(manifest.encode())
+    }
+
+    /// The manifest of the chunked value the map last synced for the log
+    /// key `key`, whose pieces a write of `key` leaves unused.
+    fn replaced_manifest(&self, key: &str) -> Option<Manifest> {
+        self.inner.manifests.lock().unwrap().get(key).cloned()
+    }
+
+    /// Tombstones the pieces of a chunked value that was replaced or never
+    /// written. Failing only leaves the pieces behind, so errors are
+    /// ignored.
+    async fn remove_chunks(&self, manifest: Option<Manifest>) {
+        let Some(manifest) = manifest else {
+            return;
+        };
+        let requests = manifest
+            .chunk_keys(&self.inner.chunk_prefix)
+            .map(|key| self.raw_request(key, Vec::new()))
+            .collect();
+        let _ = self.write_all(requests).await;
+    }
+
+    /// Passes on the error of a failed write of `value`, first removing
+    /// its pieces if it was chunked and certainly not written. After a
+    /// timeout or a broken stream it may have been, so they stay.
+    async fn abandon_chunks(&self, value: &[u8], error: Error) -> Error {
+        if matches!(error, Error::Conflict(_) | Error::ValueTooLarge { .. }) {
+            self.remove_chunks(Manifest::parse(value)).await;
+        }
+        error
+    }
+
+    /// The error for a value of `size` bytes the server turned down.
+    fn too_large(&self, size: usize) -> Error {
+        Error::ValueTooLarge {
+            size: size as u64,
+            limit: self.inner.server_info.max_value_size,
+        }
+    }
+
+    /// Starts a [`Transaction`]: writes to several keys that are committed
+    /// together or not at all.
+    ///
+    /// ```no_run
+    /// # async fn example(map: log_map::LogMap) -> Result<(), log_map::Error> {
+    /// map.transaction().insert(1, "a".to_string()).remove(2).commit().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transaction(&self) -> Transaction<'_, K, V, C> {
+        Transaction::new(self)
+    }
+
+    /// Commits encoded writes as one transaction, retrying conflicts like
+    /// [`write_with_retry`](Self::write_with_retry).
+    pub(crate) async fn commit_transaction(&self, writes: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+        if !self.inner.server_info.supports(features::TRANSACTIONS) {
+            return Err(Error::Unsupported(features::TRANSACTIONS));
+        }
+        let mut chunked: Vec<TransactionWrite> = Vec::with_capacity(writes.len());
+        for (key, value) in writes {
+            match self.chunk_value(value).await {
+                Ok(value) => chunked.push(TransactionWrite { key, value }),
+                Err(e) => {
+                    for write in chunked {
+                        self.remove_chunks(Manifest::parse(&write.value)).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let writes = &chunked;
+
+        let result = self.with_retry(|_attempt| async move {
+            let latest_known = self.inner.latest_known.load(Ordering::SeqCst);
+            let replaced: Vec<Manifest> = writes
+                .iter()
+                .filter_map(|write| self.replaced_manifest(&write.key))
+                .collect();
+            let request = TransactionRequest {
+                writes: writes.clone(),
+                latest_known,
+                client_id: self.inner.client_id.clone(),
+                worker_label: self.inner.worker_label.read().unwrap().clone(),
+            };
+            let mut writer = self.writer().await;
+            let response = writer.1.transaction(request).await.map_err(Error::from);
+            let response = self.check_server(writer.0, response).await?.into_inner();
+            drop(writer);
+            if response.accepted {
+                let last_ordinal = response.first_ordinal + writes.len() as u64 - 1;
+                self.inner.last_write.fetch_max(last_ordinal, Ordering::SeqCst);
+                return Ok(Attempt::Done(replaced));
+            }
+            if response.rejection() == WriteRejection::ValueTooLarge {
+                let largest = writes.iter().map(|write| write.value.len()).max().unwrap_or(0);
+                return Err(self.too_large(largest));
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                writes = writes.len(),
+                key_ordinal = response.key_ordinal,
+                attempt = _attempt,
+                "transaction conflict"
+            );
+            Ok(Attempt::Rejected(response.key_ordinal))
+        })
+        .await;
+
+        match result {
+            Ok(replaced) => {
+                for manifest in replaced {
+                    self.remove_chunks(Some(manifest)).await;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                let mut error = e;
+                for write in writes {
+                    error = self.abandon_chunks(&write.value, error).await;
+                }
+                Err(error)
+            }
         }
     }
 
+    /// Sends a single write and returns the response that answers it.
+    async fn send_write(&self, request: WriteRequest) -> Result<WriteResponse, Error> {
+        let ordinal = request.ordinal;
+        let mut writer = self.writer().await;
+        let response = async {
+            let request_stream = stream::once(async { request });
+            let mut response_stream = writer.1.write(request_stream).await?.into_inner();
+            Ok(response_stream.next().await.ok_or(Error::ConnectionClosed)??)
+        }
+        .await;
+        let response = self.check_server(writer.0, response).await?;
+        drop(writer);
+
+        // 0 comes from servers that predate correlation; they answer in
+        // request order, which with one request per stream is trivially ours.
+        if response.request_ordinal != 0 && response.request_ordinal != ordinal {
+            return Err(Error::UnexpectedResponse {
+                expected: ordinal,
+                got: response.request_ordinal,
+            });
+        }
+        if response.accepted {
+            self.inner.last_write.fetch_max(response.assigned_ordinal, Ordering::SeqCst);
+        }
+        Ok(response)
+    }
+
+    /// A client for reads: the preferred replica, or the server in use.
+    fn reader(&self) -> Client {
+        match &self.inner.replica {
+            Some(replica) => replica.clone(),
+            None => self.inner.servers.current().1,
+        }
+    }
+
+    /// Locks the map's writes, with a client for the server in use.
+    async fn writer(&self) -> tokio::sync::MutexGuard<'_, (usize, Client)> {
+        let mut writer = self.inner.writer.lock().await;
+        let (server, client) = self.inner.servers.current();
+        if writer.0 != server {
+            *writer = (server, client);
+        }
+        writer
+    }
+
+    /// Passes `result` on, first failing over if it says server `server`
+    /// is gone, so the next call goes to the next server.
+    async fn check_server<T>(&self, server: usize, result: Result<T, Error>) -> Result<T, Error> {
+        if let Err(e) = &result
+            && e.is_connection_error()
+        {
+            self.inner.servers.fail_over(server).await;
+        }
+        result
+    }
+
     /// Checks if the map contains a key.
-    pub fn contains_key(&self, key: i64) -> bool {
+    pub fn contains_key(&self, key: K) -> bool {
         self.inner.cache.contains_key(&key)
     }
 
+    /// The keys in the local cache, in order.
+    pub fn keys(&self) -> Vec<K> {
+        self.inner.cache.keys()
+    }
+
+    /// Iterates over a copy of the local cache, in key order. Changes
+    /// applied while iterating are not seen.
+    pub fn iter(&self) -> std::vec::IntoIter<(K, V)> {
+        self.inner.cache.entries().into_iter()
+    }
+
+    /// Like [`iter`](Self::iter), for the keys in `range`, e.g.
+    /// `map.range(10..20)`.
+    pub fn range(&self, range: impl RangeBounds<K>) -> std::vec::IntoIter<(K, V)> {
+        self.inner.cache.range(range).into_iter()
+    }
+
     /// Returns the number of entries in the local cache.
     pub fn len(&self) -> usize {
         self.inner.cache.len()
@@ -224,6 +1263,94 @@ impl LogMap {
     }
 }
 
+/// One write to the map, as returned by [`TypedLogMap::replay_since`] and
+/// [`TypedLogMap::watch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<K = i64, V = String> {
+    pub key: K,
+    /// The new value, `None` if the key was removed.
+    pub value: Option<V>,
+    pub ordinal: u64,
+    /// Unix milliseconds, assigned by the server.
+    pub timestamp: i64,
+}
+
+impl<K: DeserializeOwned, V: DeserializeOwned> Change<K, V> {
+    /// [`from_record`](Self::from_record) with a chunked value put back
+    /// together; `None` as well if its pieces are gone.
+    async fn resolve<C: Codec>(
+        client: &Client,
+        chunk_prefix: &str,
+        mut record: log_server_types::kv::Record,
+        prefix: &str,
+    ) -> Result<Option<Self>, Error> {
+        if !record.key.starts_with(prefix) {
+            return Ok(None);
+        }
+        match chunk::reassemble(client, chunk_prefix, std::mem::take(&mut record.value)).await? {
+            Some(value) => record.value = value,
+            None => return Ok(None),
+        }
+        Ok(Self::from_record::<C>(record, prefix))
+    }
+
+    /// Skips records outside the map's namespace and undecodable values.
+    fn from_record<C: Codec>(record: log_server_types::kv::Record, prefix: &str) -> Option<Self> {
+        let key = C::decode_key(record.key.strip_prefix(prefix)?).ok()?;
+        let value = match record.value.is_empty() {
+            true => None,
+            false => Some(C::decode_value(&record.value).ok()?),
+        };
+        Some(Self {
+            key,
+            value,
+            ordinal: record.ordinal,
+            timestamp: record.timestamp,
+        })
+    }
+}
+
+/// Checks `namespace` and turns it into the prefix of its log keys.
+pub(crate) fn key_prefix(namespace: &str) -> Result<String, Error> {
+    if namespace.is_empty() || namespace.contains(':') {
+        return Err(Error::InvalidNamespace(namespace.to_string()));
+    }
+    Ok(format!("{}:", namespace))
+}
+
+/// The text [`TypedLogMap::watch_prefix`] matches a key against.
+fn key_text<K: Serialize>(key: &K) -> String {
+    match serde_json::to_value(key) {
+        Ok(serde_json::Value::String(text)) => text,
+        Ok(other) => other.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// What one attempt of a write got back, see [`TypedLogMap::with_retry`].
+enum Attempt<T> {
+    Done(T),
+    /// The server rejected it because a key changed at `key_ordinal`,
+    /// 0 if it didn't say.
+    Rejected(u64),
+}
+
+/// Returns an id that is unique across processes and across `LogMap`s in
+/// this process: `<pid>-<start time in µs>-<sequence>`, all hex.
+fn new_client_id() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    format!(
+        "{:x}-{:x}-{:x}",
+        std::process::id(),
+        started,
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
 /// Server address wrapper for type-safe connection.
 #[derive(Clone)]
 pub struct ServerAddr(pub String);
@@ -239,3 +1366,52 @@ impl From<&str> for ServerAddr {
         Self(s.to_string())
     }
 }
+
+/// One or more servers for [`TypedLogMap::connect`], in order of
+/// preference, e.g. a primary and its standby.
+///
+/// The map connects to the first that answers. When the server in use
+/// goes away, writes and the background sync move on to the next one,
+/// wrapping around to the first, and the cache is rebuilt from that
+/// server's log.
+///
+/// ```no_run
+/// use log_map::LogMap;
+///
+/// # async fn example() -> Result<(), log_map::Error> {
+/// let map = LogMap::connect(["primary:50051", "standby:50051"]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ServerList(pub Vec<ServerAddr>);
+
+impl From<ServerAddr> for ServerList {
+    fn from(addr: ServerAddr) -> Self {
+        Self(vec![addr])
+    }
+}
+
+impl From<String> for ServerList {
+    fn from(s: String) -> Self {
+        Self(vec![s.into()])
+    }
+}
+
+impl From<&str> for ServerList {
+    fn from(s: &str) -> Self {
+        Self(vec![s.into()])
+    }
+}
+
+impl<A: Into<ServerAddr>> From<Vec<A>> for ServerList {
+    fn from(addrs: Vec<A>) -> Self {
+        Self(addrs.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<A: Into<ServerAddr>, const N: usize> From<[A; N]> for ServerList {
+    fn from(addrs: [A; N]) -> Self {
+        Self(addrs.into_iter().map(Into::into).collect())
+    }
+}
@@ -0,0 +1,142 @@
+//! Protocol version negotiation and the client handshake.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use log_server_types::PROTOCOL_VERSION;
+use log_server_types::kv::GetServerInfoRequest;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use log_server_types::metadata::Handshake;
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status};
+
+use crate::error::Error;
+
+/// Capabilities sent in the handshake, for the server's logs. The server
+/// serves every client the same way, so nothing depends on this list.
+const CAPABILITIES: &[&str] = &[];
+
+/// gRPC client that sends the handshake metadata with every call.
+pub(crate) type Client = KvServerClient<InterceptedService<Channel, Interceptors>>;
+
+pub(crate) fn client(channel: Channel, extra: Extra) -> Client {
+    let name = concat!("log-map/", env!("CARGO_PKG_VERSION"));
+    let interceptors = Interceptors {
+        handshake: Handshake::new(name, CAPABILITIES),
+        extra,
+    };
+    KvServerClient::with_interceptor(channel, interceptors)
+}
+
+/// Caller-supplied metadata and interceptors, see
+/// [`LogMapBuilder`](crate::LogMapBuilder).
+#[derive(Clone, Default)]
+pub(crate) struct Extra {
+    pub(crate) metadata: MetadataMap,
+    pub(crate) interceptors: Vec<Arc<Mutex<dyn Interceptor + Send>>>,
+}
+
+/// The handshake followed by the caller's additions, so they can override
+/// the handshake keys if they really need to.
+#[derive(Clone)]
+pub(crate) struct Interceptors {
+    handshake: Handshake,
+    extra: Extra,
+}
+
+impl Interceptor for Interceptors {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let mut request = self.handshake.call(request)?;
+
+        let metadata = request.metadata_mut();
+        for entry in self.extra.metadata.iter() {
+            match entry {
+                tonic::metadata::KeyAndValueRef::Ascii(key, value) => {
+                    metadata.insert(key.clone(), value.clone());
+                }
+                tonic::metadata::KeyAndValueRef::Binary(key, value) => {
+                    metadata.insert_bin(key.clone(), value.clone());
+                }
+            }
+        }
+
+        for interceptor in &self.extra.interceptors {
+            request = interceptor.lock().unwrap().call(request)?;
+        }
+        Ok(request)
+    }
+}
+
+/// What the connected server reported about itself.
+///
+/// Servers that predate `GetServerInfo` show up as protocol version 0 with
+/// no features, so callers only need to check [`ServerInfo::supports`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub protocol_version: u32,
+    pub server_version: String,
+    /// Largest value a write may carry, in bytes; 0 if the server has no
+    /// limit or doesn't say.
+    pub max_value_size: u64,
+    features: HashSet<String>,
+}
+
+impl ServerInfo {
+    fn legacy() -> Self {
+        Self {
+            protocol_version: 0,
+            server_version: String::from("unknown"),
+            max_value_size: 0,
+            features: HashSet::new(),
+        }
+    }
+
+    /// Returns `true` if the server advertises `feature`, one of the names
+    /// in `log_server_types::features`.
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+
+    /// Features advertised by the server, in no particular order.
+    pub fn features(&self) -> impl Iterator<Item = &str> {
+        self.features.iter().map(String::as_str)
+    }
+}
+
+/// Asks the server which protocol version and features it supports.
+///
+/// An `Unimplemented` answer means an older server; that is not an error,
+/// the map just sticks to the original protocol.
+pub(crate) async fn negotiate(client: &mut Client) -> Result<ServerInfo, Error> {
+    let request = GetServerInfoRequest {
+        protocol_version: PROTOCOL_VERSION,
+    };
+
+    let info = match client.get_server_info(request).await {
+        Ok(response) => {
+            let info = response.into_inner();
+            ServerInfo {
+                protocol_version: info.protocol_version,
+                server_version: info.server_version,
+                max_value_size: info.max_value_size,
+                features: info.features.into_iter().collect(),
+            }
+        }
+        Err(status) if status.code() == Code::Unimplemented => ServerInfo::legacy(),
+        Err(status) => return Err(status.into()),
+    };
+
+    #[cfg(feature = "tracing")]
+    if info.protocol_version < PROTOCOL_VERSION {
+        tracing::warn!(
+            server = info.protocol_version,
+            client = PROTOCOL_VERSION,
+            "older server protocol, newer features are disabled"
+        );
+    }
+
+    Ok(info)
+}
@@ -0,0 +1,191 @@
+//! Blocking fallback client for the server's REST gateway (`rest_addr`),
+//! for places where tokio and tonic are too heavy.
+//!
+//! One HTTP/1.1 request per call over a plain `std` TCP connection: no
+//! runtime, no cache, no sync and no retries. Keys and values are stored
+//! like [`LogMap`](crate::LogMap)'s, so both see the same entries, except
+//! that values [`LogMap`](crate::LogMap) split into chunks read back as
+//! their manifest.
+//!
+//! ```no_run
+//! let client = log_map::rest::RestClient::new("127.0.0.1:8081");
+//! client.insert(1, "one")?;
+//! assert_eq!(client.get(1)?, Some("one".to_string()));
+//! # Ok::<(), log_map::Error>(())
+//! ```
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::codec::{Codec, Plain};
+use crate::error::Error;
+use crate::map::{DEFAULT_NAMESPACE, key_prefix};
+
+/// How long a call may take to connect, and then to send or receive.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A client of the REST gateway, with `i64` keys and `String` values.
+#[derive(Debug, Clone)]
+pub struct RestClient {
+    addr: String,
+    prefix: String,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+impl RestClient {
+    /// A client of the gateway at `addr` (`host:port`), for the `map`
+    /// namespace. Nothing is sent until the first call.
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            prefix: format!("{}:", DEFAULT_NAMESPACE),
+            token: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Uses the keys of `namespace`, like
+    /// [`LogMap::connect_namespace`](crate::TypedLogMap::connect_namespace).
+    pub fn namespace(mut self, namespace: &str) -> Result<Self, Error> {
+        self.prefix = key_prefix(namespace)?;
+        Ok(self)
+    }
+
+    /// Sends `authorization: Bearer <token>` with every call.
+    pub fn token(mut self, token: &str) -> Result<Self, Error> {
+        if token.is_empty() || !token.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(Error::InvalidToken);
+        }
+        self.token = Some(token.to_string());
+        Ok(self)
+    }
+
+    /// Bounds connecting, sending and receiving, each; 10 seconds unless
+    /// set.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The server's current value for `key`.
+    pub fn get(&self, key: i64) -> Result<Option<String>, Error> {
+        let (status, body) = self.call("GET", key, None)?;
+        match status {
+            200 => match body.get("value").and_then(Value::as_str) {
+                Some(value) => Plain::decode_value(value.as_bytes()).map(Some),
+                None => Err(Error::Http(format!("no value in response: {}", body))),
+            },
+            404 => Ok(None),
+            _ => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Writes `value` to `key`. Unconditional, like
+    /// [`LogMap::insert`](crate::TypedLogMap::insert).
+    pub fn insert(&self, key: i64, value: &str) -> Result<(), Error> {
+        let value = String::from_utf8(Plain::encode_value(&value)?).map_err(|e| Error::Codec(e.to_string()))?;
+        if value.is_empty() {
+            // An empty value is a tombstone in the log.
+            return Err(Error::Codec("empty values can't be stored".to_string()));
+        }
+        match self.call("PUT", key, Some(json!({ "value": value })))? {
+            (200, _) => Ok(()),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Removes `key`.
+    pub fn remove(&self, key: i64) -> Result<(), Error> {
+        match self.call("DELETE", key, None)? {
+            (200, _) => Ok(()),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Sends one request for `key` and returns the status and JSON body.
+    fn call(&self, method: &str, key: i64, body: Option<Value>) -> Result<(u16, Value), Error> {
+        let key = format!("{}{}", self.prefix, Plain::encode_key(&key)?);
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut request = format!(
+            "{} /v1/keys/{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n",
+            method,
+            encode_path_segment(&key),
+            self.addr,
+            body.len()
+        );
+        if let Some(token) = &self.token {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        let mut stream = self.connect()?;
+        stream.write_all(request.as_bytes()).map_err(http_error)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).map_err(http_error)?;
+        parse_response(&response)
+    }
+
+    fn connect(&self) -> Result<TcpStream, Error> {
+        let mut last = None;
+        for addr in self.addr.to_socket_addrs().map_err(http_error)? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout)).map_err(http_error)?;
+                    stream.set_write_timeout(Some(self.timeout)).map_err(http_error)?;
+                    return Ok(stream);
+                }
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.map_or(Error::NoServers, http_error))
+    }
+}
+
+fn http_error(err: std::io::Error) -> Error {
+    Error::Http(err.to_string())
+}
+
+fn status_error(status: u16, body: &Value) -> Error {
+    let message = body.get("error").and_then(Value::as_str).unwrap_or("no details");
+    Error::Http(format!("status {}: {}", status, message))
+}
+
+/// Splits a `Connection: close` response into its status and JSON body.
+fn parse_response(response: &[u8]) -> Result<(u16, Value), Error> {
+    let text = std::str::from_utf8(response).map_err(|e| Error::Http(e.to_string()))?;
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| Error::Http("truncated response".to_string()))?;
+    let status = head
+        .strip_prefix("HTTP/1.1 ")
+        .and_then(|rest| rest.get(..3))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| Error::Http(format!("bad status line in '{}'", head.lines().next().unwrap_or(""))))?;
+    if head.lines().any(|line| line.eq_ignore_ascii_case("transfer-encoding: chunked")) {
+        return Err(Error::Http("chunked responses are not supported".to_string()));
+    }
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(body).map_err(|e| Error::Http(format!("bad response body: {}", e)))?
+    };
+    Ok((status, body))
+}
+
+/// Percent-encodes everything but unreserved characters and `:`.
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
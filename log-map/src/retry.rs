@@ -0,0 +1,76 @@
+//! How writes retry after a conflict.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// How a map retries writes the server rejected because their key changed
+/// since the map last synced, set with
+/// [`LogMapBuilder::retry_policy`](crate::LogMapBuilder::retry_policy) or
+/// [`ConnectConfig::retry`](crate::ConnectConfig::retry).
+///
+/// Between attempts the map waits until its sync has caught up with the
+/// newer record, at most the current delay: `base_delay`, doubling with
+/// every rejection up to `max_delay`. The defaults give up after 5
+/// rejections, waiting 100 ms, 200 ms, 400 ms and 800 ms, without jitter
+/// or a deadline.
+///
+/// ```
+/// use std::time::Duration;
+/// use log_map::RetryPolicy;
+///
+/// let policy = RetryPolicy {
+///     max_retries: 10,
+///     jitter: 0.5,
+///     timeout: Some(Duration::from_secs(2)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Rejections a write takes before failing with
+    /// [`Error::Conflict`](crate::Error::Conflict).
+    pub max_retries: usize,
+    /// Longest wait after the first rejection.
+    pub base_delay: Duration,
+    /// Longest wait after any rejection.
+    pub max_delay: Duration,
+    /// Share of each wait, from 0.0 to 1.0, taken off at random, so
+    /// clients that conflicted together don't retry in lockstep.
+    pub jitter: f64,
+    /// Deadline for a whole write, retries and waits included, after
+    /// which it fails with [`Error::Timeout`](crate::Error::Timeout). A
+    /// write that times out may still have been applied.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.0,
+            timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Longest wait after the `rejections`-th rejection.
+    pub(crate) fn delay(&self, rejections: usize) -> Duration {
+        let doublings = rejections.saturating_sub(1).min(31) as u32;
+        let delay = self.base_delay.saturating_mul(1 << doublings).min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - jitter * random_fraction())
+    }
+}
+
+/// A number in `[0, 1)`, random enough to spread retries.
+fn random_fraction() -> f64 {
+    // Every `RandomState` is keyed differently.
+    (RandomState::new().hash_one(()) >> 11) as f64 / (1u64 << 53) as f64
+}
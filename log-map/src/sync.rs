@@ -1,175 +1,394 @@
 //! Background synchronization task for keeping the cache updated.
 
+use std::collections::{BTreeSet, HashMap};
+use std::marker::PhantomData;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
-use futures_util::StreamExt;
-use log_server_types::kv::kv_server_client::KvServerClient;
+use futures_util::stream::BoxStream;
+use futures_util::{FutureExt, StreamExt, future, stream};
 use log_server_types::kv::{GetSnapshotRequest, Record, SubscribeRequest};
-use tonic::transport::Channel;
+use log_snapshot_format::Decoder;
+use serde::de::DeserializeOwned;
+use tokio::sync::{broadcast, watch};
 
 use crate::Error;
 use crate::cache::Cache;
+use crate::chunk::{self, Manifest, Manifests};
+use crate::codec::Codec;
+use crate::failover::Servers;
+use crate::map::Change;
+use crate::protocol::Client;
 
-const MAP_PREFIX: &str = "map:";
-const BMAP_MAGIC: &[u8; 4] = b"BMAP";
+/// Bytes handed to the decoder at a time.
+const SNAPSHOT_CHUNK: usize = 64 * 1024;
+/// Snapshot entries collected before taking the cache lock.
+const SNAPSHOT_BATCH: usize = 1024;
+/// A snapshot payload as it arrives, in one or more pieces.
+type SnapshotData = BoxStream<'static, Result<Vec<u8>, Error>>;
+/// First wait before reconnecting; doubles per failed attempt.
+const RECONNECT_MIN: Duration = Duration::from_millis(100);
+/// Longest wait between reconnection attempts.
+const RECONNECT_MAX: Duration = Duration::from_secs(10);
 
-pub struct SnapshotLoader;
-
-impl SnapshotLoader {
-    pub fn load_from_bytes(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
-        if data.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        if data.len() < 12 {
-            return Err("Data too short".to_string());
-        }
-
-        if &data[0..4] != BMAP_MAGIC {
-            return Err("Invalid magic".to_string());
-        }
-
-        let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-        if version != 1 {
-            return Err(format!("Invalid version: {}", version));
-        }
-
-        let count = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
-        let mut result = Vec::with_capacity(count);
-        let mut offset = 12;
-
-        for _ in 0..count {
-            if offset + 2 > data.len() {
-                return Err("Data truncated (key length)".to_string());
-            }
-            let key_len = u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
-            offset += 2;
-
-            if offset + key_len > data.len() {
-                return Err("Data truncated (key)".to_string());
-            }
-            let key = String::from_utf8_lossy(&data[offset..offset + key_len]).to_string();
-            offset += key_len;
-
-            if offset + 4 > data.len() {
-                return Err("Data truncated (value length)".to_string());
-            }
-            let value_len = u32::from_le_bytes([
-                data[offset],
-                data[offset + 1],
-                data[offset + 2],
-                data[offset + 3],
-            ]) as usize;
-            offset += 4;
-
-            if offset + value_len > data.len() {
-                return Err("Data truncated (value)".to_string());
-            }
-            let value = data[offset..offset + value_len].to_vec();
-            offset += value_len;
-
-            result.push((key, value));
-        }
-
-        Ok(result)
-    }
+/// State of the background sync, see [`TypedLogMap::health`](crate::TypedLogMap::health).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// Loading the first snapshot; the cache may be incomplete.
+    Connecting,
+    /// Subscribed; the cache follows the log.
+    Connected,
+    /// The subscription broke and `attempt` reconnections have failed so
+    /// far. The cache, and the `latest_known` of writes, are stale until
+    /// it is back.
+    Reconnecting { attempt: u32, error: String },
 }
 
-pub struct SyncTask {
-    client: KvServerClient<Channel>,
-    cache: Arc<Cache>,
+pub struct SyncTask<K, V, C> {
+    client: Client,
+    /// Where to go when the server goes away; `None` to stay with `client`.
+    servers: Option<Arc<Servers>>,
+    /// Index in `servers` of the server `client` is for.
+    server: usize,
+    /// Set after moving to another server, whose log the cache is rebuilt
+    /// from.
+    resync: bool,
+    cache: Arc<Cache<K, V>>,
     last_sync: Arc<AtomicU64>,
     latest_known: Arc<AtomicU64>,
+    changes: broadcast::Sender<Change<K, V>>,
+    /// `<namespace>:`; other keys are skipped, and filtered out by the
+    /// server when it supports `prefix-subscribe`.
+    prefix: String,
+    /// `<namespace>~chunks:`, where the pieces of chunked values are.
+    chunk_prefix: String,
+    /// Manifests of the chunked values in the cache, by log key.
+    manifests: Arc<Manifests>,
+    health: watch::Sender<Health>,
+    /// Whether the server has `StreamSnapshot`.
+    chunked_snapshots: bool,
+    codec: PhantomData<C>,
 }
 
-impl SyncTask {
+impl<K, V, C> SyncTask<K, V, C>
+where
+    K: DeserializeOwned + Ord + Clone,
+    V: DeserializeOwned + Clone,
+    C: Codec,
+{
     pub fn new(
-        client: KvServerClient<Channel>,
-        cache: Arc<Cache>,
+        client: Client,
+        cache: Arc<Cache<K, V>>,
         last_sync: Arc<AtomicU64>,
         latest_known: Arc<AtomicU64>,
+        changes: broadcast::Sender<Change<K, V>>,
+        prefix: String,
+        health: watch::Sender<Health>,
     ) -> Self {
         Self {
             client,
+            servers: None,
+            server: 0,
+            resync: false,
             cache,
             last_sync,
             latest_known,
+            changes,
+            chunk_prefix: chunk::chunk_prefix(&prefix),
+            prefix,
+            manifests: Arc::default(),
+            health,
+            chunked_snapshots: false,
+            codec: PhantomData,
         }
     }
 
-    pub async fn initialize_with_snapshot(
-        client: &KvServerClient<Channel>,
-        cache: &Arc<Cache>,
-    ) -> Result<u64, Error> {
-        let mut client_clone = client.clone();
-        let response = client_clone
-            .get_snapshot(GetSnapshotRequest::default())
-            .await?
-            .into_inner();
-
-        println!("latest snapshot ordinal: {}", response.snapshot_ordinal);
-        if response.snapshot_ordinal > 0 && !response.snapshot_data.is_empty() {
-            println!("log-map: loading from snapshot...");
-            let records = SnapshotLoader::load_from_bytes(&response.snapshot_data)
-                .map_err(|e| Error::Internal(e.to_string()))?;
-            println!("log-map: received {} records", records.len());
-
-            let parsed: Vec<(i64, String)> = records
-                .into_iter()
-                .filter_map(|(key, value)| {
-                    key.strip_prefix(MAP_PREFIX)
-                        .and_then(|k| k.parse::<i64>().ok())
-                        .map(|k| (k, String::from_utf8_lossy(&value).to_string()))
-                        .filter(|(_, v)| !v.is_empty())
-                })
-                .collect();
-
-            cache.insert_all(parsed);
+    /// Reads the pieces of chunked values from under `chunk_prefix` and
+    /// keeps `manifests` up to date for the map's writes.
+    pub fn with_chunks(mut self, chunk_prefix: String, manifests: Arc<Manifests>) -> Self {
+        self.chunk_prefix = chunk_prefix;
+        self.manifests = manifests;
+        self
+    }
+
+    /// Follows the map's writes from server to server, see [`Servers`].
+    pub fn with_failover(mut self, servers: Arc<Servers>) -> Self {
+        (self.server, self.client) = servers.current();
+        self.servers = Some(servers);
+        self
+    }
+
+    /// Fetches snapshots with `StreamSnapshot`; only for servers that
+    /// advertise `chunked-snapshots`.
+    pub fn with_chunked_snapshots(mut self, enabled: bool) -> Self {
+        self.chunked_snapshots = enabled;
+        self
+    }
+
+    /// Fetches the latest snapshot: its ordinal, 0 if there is none, and
+    /// its payload in pieces. Streamed when the server supports
+    /// `chunked-snapshots`, so large maps stay under the message size limit.
+    async fn fetch_snapshot(&mut self) -> Result<(u64, SnapshotData), Error> {
+        let request = GetSnapshotRequest {
+            key_prefix: self.prefix.clone(),
+        };
+        if !self.chunked_snapshots {
+            let response = self.client.get_snapshot(request).await?.into_inner();
+            let data = stream::once(future::ready(Ok(response.snapshot_data)));
+            return Ok((response.snapshot_ordinal, data.boxed()));
         }
 
-        Ok(response.snapshot_ordinal)
+        let mut chunks = self.client.stream_snapshot(request).await?.into_inner();
+        let Some(first) = chunks.message().await? else {
+            return Ok((0, stream::empty().boxed()));
+        };
+        let rest = chunks.map(|chunk| Ok(chunk?.data));
+        let data = stream::once(future::ready(Ok(first.data))).chain(rest);
+        Ok((first.snapshot_ordinal, data.boxed()))
     }
 
-    pub async fn run(mut self) -> Result<(), Error> {
-        println!("starting syncing...");
-        loop {
-            println!("initializing with snapshot...");
-            let from = Self::initialize_with_snapshot(&self.client, &self.cache).await?;
-            self.last_sync.store(from, Ordering::SeqCst);
+    /// Decodes a snapshot into the cache as its pieces arrive and returns
+    /// its ordinal.
+    ///
+    /// When the cache already holds entries, e.g. after a reconnect, keys
+    /// missing from the snapshot are removed as well.
+    async fn load_snapshot(&self, ordinal: u64, mut data: SnapshotData) -> Result<u64, Error> {
+        let mut decoder = Decoder::new();
+        let mut batch = Vec::with_capacity(SNAPSHOT_BATCH);
+        let mut loaded = (!self.cache.is_empty()).then(BTreeSet::new);
+        let mut bytes = 0;
+        // Their pieces are fetched once the snapshot is decoded.
+        let mut chunked = Vec::new();
+
+        while let Some(piece) = data.next().await {
+            let piece = piece?;
+            bytes += piece.len();
+            for chunk in piece.chunks(SNAPSHOT_CHUNK) {
+                decoder
+                    .feed(chunk, |key, value| {
+                        if let Some(manifest) = Manifest::parse(&value) {
+                            chunked.push((key, manifest, value));
+                        } else if let Some(entry) = parse_entry::<K, V, C>(&key, &value, &self.prefix) {
+                            if let Some(loaded) = &mut loaded {
+                                loaded.insert(entry.0.clone());
+                            }
+                            batch.push(entry);
+                        }
+                    })
+                    .map_err(|e| Error::Internal(e.to_string()))?;
+                if batch.len() >= SNAPSHOT_BATCH {
+                    self.cache.insert_all(std::mem::take(&mut batch));
+                }
+            }
+        }
+        if bytes == 0 {
+            return Ok(ordinal);
+        }
+
+        #[cfg(feature = "tracing")]
+        let records = decoder.records();
+        decoder.finish().map_err(|e| Error::Internal(e.to_string()))?;
 
-            let mut request = SubscribeRequest::default();
-            request.start_ordinal = from;
+        let mut manifests = HashMap::with_capacity(chunked.len());
+        for (key, manifest, value) in chunked {
+            let Some(value) = chunk::reassemble(&self.client, &self.chunk_prefix, value).await? else {
+                continue;
+            };
+            if let Some(entry) = parse_entry::<K, V, C>(&key, &value, &self.prefix) {
+                if let Some(loaded) = &mut loaded {
+                    loaded.insert(entry.0.clone());
+                }
+                batch.push(entry);
+                manifests.insert(key, manifest);
+            }
+        }
+        *self.manifests.lock().unwrap() = manifests;
+        self.cache.insert_all(batch);
+        if let Some(loaded) = loaded {
+            self.cache.retain(|key| loaded.contains(key));
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(ordinal, records, bytes, "loaded snapshot");
 
-            let mut stream = self.client.subscribe(request).await?.into_inner();
+        Ok(ordinal)
+    }
 
-            while let Some(result) = stream.next().await {
-                match result {
-                    Ok(record) => {
-                        self.process_record(record);
-                    }
-                    Err(e) => {
-                        return Err(Error::from(e));
-                    }
+    /// Keeps the cache in sync until the map is dropped, reconnecting
+    /// with exponential backoff whenever the subscription breaks.
+    pub async fn run(mut self) {
+        let mut attempt = 0;
+        loop {
+            let (error, gone) = match self.sync().await {
+                Ok(()) => (Error::Internal("subscription closed by the server".to_string()), true),
+                Err(e) => {
+                    let gone = e.is_connection_error();
+                    (e, gone)
                 }
+            };
+            if gone && let Some(servers) = &self.servers {
+                servers.fail_over(self.server).await;
             }
+            if matches!(*self.health.borrow(), Health::Connected) {
+                attempt = 0;
+            }
+            attempt += 1;
+            #[cfg(feature = "tracing")]
+            tracing::warn!(attempt, error = %error, "sync interrupted, reconnecting");
+            self.health.send_replace(Health::Reconnecting {
+                attempt,
+                error: error.to_string(),
+            });
+
+            let delay = RECONNECT_MIN.saturating_mul(1 << (attempt - 1).min(16)).min(RECONNECT_MAX);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Catches up and follows the log until the subscription ends.
+    ///
+    /// The snapshot is requested again every time, but only loaded when it is
+    /// newer than what the cache has seen, e.g. because the records in
+    /// between were compacted away; otherwise the subscription resumes
+    /// from `last_sync`. Watchers are not told about changes a snapshot
+    /// skips over.
+    ///
+    /// After a failover the ordinals seen so far belong to another log, so
+    /// the cache is emptied and rebuilt from the new server's snapshot and
+    /// the records after it, or its whole log if it has none. Watchers see
+    /// those changes again.
+    async fn sync(&mut self) -> Result<(), Error> {
+        let moved = self.servers.as_ref().map(|servers| servers.moved());
+        if let Some(servers) = &self.servers {
+            let (server, client) = servers.current();
+            if server != self.server {
+                (self.server, self.client) = (server, client);
+                self.resync = true;
+            }
+        }
+
+        let (snapshot_ordinal, data) = self.fetch_snapshot().await?;
+        let last_sync = self.last_sync.load(Ordering::SeqCst);
+        let from = if self.resync {
+            self.cache.retain(|_| false);
+            self.manifests.lock().unwrap().clear();
+            let from = self.load_snapshot(snapshot_ordinal, data).await?;
+            self.last_sync.store(from, Ordering::SeqCst);
+            self.latest_known.store(from, Ordering::SeqCst);
+            self.resync = false;
+            from
+        } else if snapshot_ordinal > last_sync {
+            self.load_snapshot(snapshot_ordinal, data).await?
+        } else {
+            last_sync
+        };
+        self.last_sync.fetch_max(from, Ordering::SeqCst);
+        self.latest_known.fetch_max(from, Ordering::SeqCst);
+
+        let request = SubscribeRequest {
+            start_ordinal: from,
+            key_prefix: self.prefix.clone(),
+            ..Default::default()
+        };
+
+        // Ends early when the writes fail over, to follow them.
+        let moved = match moved {
+            Some(mut moved) => async move { moved.changed().await }.boxed(),
+            None => future::pending().boxed(),
+        };
+        let mut stream = self.client.subscribe(request).await?.into_inner().take_until(moved);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(ordinal = from, "subscribed");
+        self.health.send_replace(Health::Connected);
+
+        while let Some(result) = stream.next().await {
+            self.process_record(result?).await?;
         }
+        if stream.is_stopped() {
+            return Err(Error::Internal("failed over to another server".to_string()));
+        }
+        Ok(())
     }
 
-    fn process_record(&self, record: Record) {
-        if let Some(key) = record.key.strip_prefix(MAP_PREFIX) {
-            if let Ok(parsed_key) = key.parse::<i64>() {
-                self.last_sync.fetch_max(record.ordinal, Ordering::SeqCst);
-                self.latest_known
-                    .fetch_max(record.ordinal, Ordering::SeqCst);
-
-                if record.value.is_empty() {
-                    self.cache.remove(&parsed_key);
-                } else {
-                    let value = String::from_utf8_lossy(&record.value).to_string();
-                    self.cache.insert(parsed_key, value);
+    /// Applies `record`. Fails only if the pieces of a chunked value can't
+    /// be fetched, before anything is applied.
+    async fn process_record(&self, mut record: Record) -> Result<(), Error> {
+        let Some(parsed_key) = record
+            .key
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|key| C::decode_key::<K>(key).ok())
+        else {
+            return Ok(());
+        };
+
+        let manifest = Manifest::parse(&record.value);
+        let mut unresolved = false;
+        if manifest.is_some() {
+            match chunk::reassemble(&self.client, &self.chunk_prefix, std::mem::take(&mut record.value)).await? {
+                Some(value) => record.value = value,
+                None => unresolved = true,
+            }
+        }
+        {
+            let mut manifests = self.manifests.lock().unwrap();
+            match manifest {
+                Some(manifest) => manifests.insert(record.key.clone(), manifest),
+                None => manifests.remove(&record.key),
+            };
+        }
+
+        let value = if unresolved {
+            // Its pieces are gone, so a newer record of the key follows.
+            #[cfg(feature = "tracing")]
+            tracing::debug!(key = %record.key, ordinal = record.ordinal, "replaced chunked value skipped");
+            None
+        } else if record.value.is_empty() {
+            self.cache.remove(&parsed_key);
+            Some(None)
+        } else {
+            match C::decode_value::<V>(&record.value) {
+                Ok(value) => {
+                    self.cache
+                        .insert_expiring(parsed_key.clone(), value.clone(), record.timestamp, record.expires_at);
+                    Some(Some(value))
+                }
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(key = %record.key, ordinal = record.ordinal, error = %_e, "undecodable value skipped");
+                    None
                 }
             }
+        };
+
+        // Only after the cache update, so whoever reads `latest_known` finds
+        // the record applied; conditional writes rely on that.
+        self.last_sync.fetch_max(record.ordinal, Ordering::SeqCst);
+        self.latest_known
+            .fetch_max(record.ordinal, Ordering::SeqCst);
+
+        if let Some(value) = value {
+            // Sending fails only when nobody is watching.
+            let _ = self.changes.send(Change {
+                key: parsed_key,
+                value,
+                ordinal: record.ordinal,
+                timestamp: record.timestamp,
+            });
         }
+        Ok(())
+    }
+}
+
+/// Converts a snapshot entry into a cache entry, skipping foreign keys,
+/// tombstones and anything the codec can't decode.
+fn parse_entry<K: DeserializeOwned, V: DeserializeOwned, C: Codec>(
+    key: &str,
+    value: &[u8],
+    prefix: &str,
+) -> Option<(K, V)> {
+    let key = C::decode_key(key.strip_prefix(prefix)?).ok()?;
+    if value.is_empty() {
+        return None;
     }
+    Some((key, C::decode_value(value).ok()?))
 }
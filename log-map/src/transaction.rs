@@ -0,0 +1,73 @@
+//! Writes to several keys that are committed together.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::codec::{Codec, Json};
+use crate::error::Error;
+use crate::map::TypedLogMap;
+
+/// A set of writes built with [`TypedLogMap::transaction`], sent to the
+/// server as one `Transaction` call by [`commit`](Self::commit).
+///
+/// The server rejects the whole transaction if any of its keys changed
+/// since the map last synced; like single writes, it is then retried once
+/// the sync has caught up, up to 5 times. Later writes of the same key
+/// win. Needs the `transactions` server feature.
+#[must_use = "a transaction does nothing until committed"]
+pub struct Transaction<'a, K, V, C = Json> {
+    map: &'a TypedLogMap<K, V, C>,
+    writes: Vec<(String, Vec<u8>)>,
+    /// The first key or value that failed to encode, reported by `commit`.
+    error: Option<Error>,
+}
+
+impl<'a, K, V, C> Transaction<'a, K, V, C>
+where
+    K: Serialize + DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    C: Codec,
+{
+    pub(crate) fn new(map: &'a TypedLogMap<K, V, C>) -> Self {
+        Self {
+            map,
+            writes: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Sets `key` to `value` when the transaction commits.
+    pub fn insert(mut self, key: K, value: V) -> Self {
+        match C::encode_value(&value) {
+            Ok(value) => self.push(&key, value),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Removes `key` when the transaction commits.
+    pub fn remove(mut self, key: K) -> Self {
+        self.push(&key, Vec::new());
+        self
+    }
+
+    fn push(&mut self, key: &K, value: Vec<u8>) {
+        match self.map.record_key(key) {
+            Ok(key) => self.writes.push((key, value)),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+    }
+
+    /// Sends every write at once. On success they are all in the log, at
+    /// consecutive ordinals; on error none of them is.
+    pub async fn commit(self) -> Result<(), Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.map.commit_transaction(self.writes).await
+    }
+}
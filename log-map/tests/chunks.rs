@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use log_map::LogMap;
+use log_server::storage::KeyFilter;
+use log_server_test::TestServer;
+
+fn large_value(len: usize) -> String {
+    (0..len).map(|i| char::from(b'a' + (i % 26) as u8)).collect()
+}
+
+async fn wait_for(map: &LogMap, key: i64, value: &str) {
+    for _ in 0..100 {
+        if map.get(key).await.unwrap().as_deref() == Some(value) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("key {} never reached the cache", key);
+}
+
+#[tokio::test]
+async fn test_large_values_round_trip_in_chunks() {
+    let server = TestServer::spawn().await;
+    let writer = LogMap::builder().chunk_size(1000).connect(server.addr().to_string()).await.unwrap();
+    let reader = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let value = large_value(4500);
+
+    writer.insert(1, value.clone()).await.unwrap();
+    assert_eq!(writer.get_consistent(1).await.unwrap(), Some(value.clone()));
+    wait_for(&reader, 1, &value).await;
+    let record = server.storage().history("map:1", 0, 0).await.unwrap().pop().unwrap();
+    assert!(record.value.len() < 1000);
+
+    let history = reader.history(1).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].value, Some(value.clone()));
+    assert_eq!(reader.get_at(1, history[0].ordinal).await.unwrap(), Some(value.clone()));
+
+    let results = writer
+        .insert_batch(vec![(2, value.clone()), (3, "small".to_string())])
+        .await
+        .unwrap();
+    assert!(results.iter().all(Result::is_ok));
+    writer.transaction().insert(4, value.clone()).remove(3).commit().await.unwrap();
+    assert_eq!(writer.insert_if_absent(5, value.clone()).await.unwrap(), Ok(()));
+    for key in [2, 4, 5] {
+        assert_eq!(writer.get_consistent(key).await.unwrap(), Some(value.clone()));
+        wait_for(&reader, key, &value).await;
+    }
+    assert_eq!(reader.get(3).await.unwrap(), None);
+
+    let late = LogMap::connect(server.addr().to_string()).await.unwrap();
+    wait_for(&late, 5, &value).await;
+    assert_eq!(late.keys(), vec![1, 2, 4, 5]);
+}
+
+#[tokio::test]
+async fn test_replaced_chunks_are_removed() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::builder().chunk_size(1000).connect(server.addr().to_string()).await.unwrap();
+
+    map.insert(1, large_value(4500)).await.unwrap();
+    map.get_consistent(1).await.unwrap();
+    map.insert(1, "small".to_string()).await.unwrap();
+    assert_eq!(map.get_consistent(1).await.unwrap(), Some("small".to_string()));
+
+    let filter = KeyFilter {
+        prefix: "map~chunks:".to_string(),
+        ..Default::default()
+    };
+    let chunks: Vec<_> = server.storage().subscribe_from(0, filter).take(10).collect().await;
+    let (written, removed) = chunks.split_at(5);
+    assert!(written.iter().all(|record| !record.value.is_empty()));
+    assert!(removed.iter().all(|record| record.value.is_empty()));
+
+    // Its pieces are gone, so a map reading the log later skips the
+    // replaced value.
+    let late = LogMap::connect(server.addr().to_string()).await.unwrap();
+    wait_for(&late, 1, "small").await;
+    let history = late.history(1).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].value, Some("small".to_string()));
+}
+
+#[tokio::test]
+async fn test_values_over_the_server_limit() {
+    let server = TestServer::spawn().await;
+    server.storage().set_max_value_size(100);
+    let unchunked = LogMap::builder().chunk_size(0).connect(server.addr().to_string()).await.unwrap();
+    let chunked = LogMap::connect(server.addr().to_string()).await.unwrap();
+    assert_eq!(chunked.server_info().max_value_size, 100);
+
+    let result = unchunked.insert(1, large_value(200)).await;
+    assert!(matches!(result, Err(log_map::Error::ValueTooLarge { size: 200, limit: 100 })));
+    let result = unchunked.transaction().insert(1, large_value(150)).commit().await;
+    assert!(matches!(result, Err(log_map::Error::ValueTooLarge { size: 150, limit: 100 })));
+
+    // Chunked at the server's limit instead.
+    chunked.insert(1, large_value(200)).await.unwrap();
+    assert_eq!(chunked.get_consistent(1).await.unwrap(), Some(large_value(200)));
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log_map::{Counter, TypedLogMap};
+use log_server_test::TestServer;
+
+async fn wait_for_value(counter: &Counter, expected: i64) {
+    for _ in 0..100 {
+        if counter.value() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{} stuck at {}, expected {}", counter.name(), counter.value(), expected);
+}
+
+#[tokio::test]
+async fn test_concurrent_increments_add_up() {
+    let server = TestServer::spawn().await;
+    let mut workers = Vec::new();
+    for _ in 0..4 {
+        let counter = Arc::new(Counter::connect(server.addr().to_string(), "done").await.unwrap());
+        workers.push(tokio::spawn(async move {
+            let increments = (0..25).map(|_| counter.incr(1));
+            futures_util::future::try_join_all(increments).await.unwrap();
+            counter
+        }));
+    }
+    let counters = futures_util::future::join_all(workers).await;
+    let counter = counters.into_iter().next().unwrap().unwrap();
+    wait_for_value(&counter, 100).await;
+
+    counter.incr(-10).await.unwrap();
+    wait_for_value(&counter, 90).await;
+}
+
+#[tokio::test]
+async fn test_counters_sharing_a_map_are_separate() {
+    let server = TestServer::spawn().await;
+    let map = Arc::new(
+        TypedLogMap::connect_namespace(server.addr().to_string(), Counter::NAMESPACE)
+            .await
+            .unwrap(),
+    );
+    let a = Counter::new(Arc::clone(&map), "a");
+    let ab = Counter::new(Arc::clone(&map), "a\u{1}b");
+    let b = Counter::new(map, "b");
+
+    a.incr(2).await.unwrap();
+    ab.incr(5).await.unwrap();
+    b.incr(3).await.unwrap();
+    wait_for_value(&b, 3).await;
+    wait_for_value(&ab, 5).await;
+    wait_for_value(&a, 2).await;
+
+    // A fresh handle starts its own slot and sees the total so far.
+    let fresh = Counter::connect(server.addr().to_string(), "a").await.unwrap();
+    wait_for_value(&fresh, 2).await;
+    fresh.incr(1).await.unwrap();
+    wait_for_value(&a, 3).await;
+}
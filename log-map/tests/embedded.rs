@@ -0,0 +1,38 @@
+use log_map::embedded::EmbeddedServer;
+use log_map::{LogMap, Plain, TypedLogMap};
+
+#[tokio::test]
+async fn test_maps_on_an_embedded_server_share_writes() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let writer: LogMap = server.connect().await.unwrap();
+    let reader: LogMap = server.connect().await.unwrap();
+    let other: LogMap = server.connect_namespace("other").await.unwrap();
+
+    writer.insert(1, "one".to_string()).await.unwrap();
+    assert_eq!(reader.get_consistent(1).await.unwrap(), Some("one".to_string()));
+    assert_eq!(other.get_consistent(1).await.unwrap(), None);
+
+    other.insert(1, "uno".to_string()).await.unwrap();
+    assert_eq!(reader.get_consistent(1).await.unwrap(), Some("one".to_string()));
+}
+
+#[tokio::test]
+async fn test_in_memory_maps_are_separate() {
+    let first: TypedLogMap<String, String, Plain> = TypedLogMap::in_memory().await.unwrap();
+    let second: TypedLogMap<String, String, Plain> = TypedLogMap::in_memory().await.unwrap();
+
+    first.insert("key".to_string(), "first".to_string()).await.unwrap();
+    assert_eq!(first.get_consistent("key".to_string()).await.unwrap(), Some("first".to_string()));
+    assert_eq!(second.get_consistent("key".to_string()).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_server_runs_while_a_map_is_connected() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let map: LogMap = server.connect().await.unwrap();
+    drop(server);
+
+    map.insert(1, "one".to_string()).await.unwrap();
+    map.remove(1).await.unwrap();
+    assert_eq!(map.get_consistent(1).await.unwrap(), None);
+}
@@ -0,0 +1,96 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log_map::{Health, LogMap};
+use log_server_test::TestServer;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Relays connections to a server until cut, which the client sees as
+/// the server dying: open connections break and new ones are refused.
+struct Cable {
+    addr: SocketAddr,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Cable {
+    async fn to(target: SocketAddr) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let tasks = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::clone(&tasks);
+        let accept = tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                connections.lock().unwrap().push(tokio::spawn(async move {
+                    if let Ok(mut server) = TcpStream::connect(target).await {
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                    }
+                }));
+            }
+        });
+        tasks.lock().unwrap().push(accept);
+        Self { addr, tasks }
+    }
+
+    fn cut(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+async fn wait_until(mut done: impl FnMut() -> bool) {
+    for _ in 0..250 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("condition not reached in time");
+}
+
+#[tokio::test]
+async fn test_connect_skips_unreachable_servers() {
+    let server = TestServer::spawn().await;
+    let unreachable = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+    let map = LogMap::connect(vec![unreachable.to_string(), server.addr().to_string()])
+        .await
+        .unwrap();
+    map.insert(1, "one".to_string()).await.unwrap();
+    assert_eq!(map.get_consistent(1).await.unwrap(), Some("one".to_string()));
+
+    let result = LogMap::connect(vec![unreachable.to_string()]).await;
+    assert!(matches!(result, Err(log_map::Error::Transport(_))));
+    let result = LogMap::connect(Vec::<String>::new()).await;
+    assert!(matches!(result, Err(log_map::Error::NoServers)));
+}
+
+#[tokio::test]
+async fn test_fails_over_to_the_standby() {
+    let primary = TestServer::spawn().await;
+    let standby = TestServer::spawn().await;
+    let cable = Cable::to(primary.addr()).await;
+    let servers = [cable.addr.to_string(), standby.addr().to_string()];
+
+    let writer = LogMap::connect(servers.clone()).await.unwrap();
+    let reader = LogMap::connect(servers).await.unwrap();
+    writer.insert(1, "primary only".to_string()).await.unwrap();
+    let on_standby = LogMap::connect(standby.addr().to_string()).await.unwrap();
+    on_standby.insert(2, "standby".to_string()).await.unwrap();
+    wait_until(|| reader.contains_key(1)).await;
+
+    cable.cut();
+
+    // The write fails on the primary and goes to the standby instead.
+    writer.insert(3, "after".to_string()).await.unwrap();
+    assert_eq!(writer.get_consistent(3).await.unwrap(), Some("after".to_string()));
+
+    // Both caches now follow the standby's log.
+    for map in [&writer, &reader] {
+        wait_until(|| map.keys() == vec![2, 3]).await;
+        wait_until(|| map.health() == Health::Connected).await;
+    }
+    assert_eq!(reader.get(2).await.unwrap(), Some("standby".to_string()));
+}
@@ -0,0 +1,194 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::StreamExt;
+use log_map::{Change, LogMap};
+use log_server_test::TestServer;
+
+fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+async fn wait_for(map: &LogMap, key: i64) {
+    for _ in 0..50 {
+        if map.contains_key(key) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("key {} never reached the cache", key);
+}
+
+#[tokio::test]
+async fn test_queries_since_timestamp() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    map.insert(1, "old".to_string()).await.unwrap();
+    wait_for(&map, 1).await;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let since = now_millis();
+
+    map.insert(2, "new".to_string()).await.unwrap();
+    map.remove(1).await.unwrap();
+    wait_for(&map, 2).await;
+
+    let entry = map.entry(2).unwrap();
+    assert!(entry.timestamp >= since);
+    let recent = map.entries_since(since);
+    assert_eq!(recent, vec![(2, entry)]);
+
+    let changes: Vec<Change> = map
+        .replay_since(since)
+        .await
+        .unwrap()
+        .take(2)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    let changes: Vec<_> = changes.into_iter().map(|c| (c.key, c.value)).collect();
+    assert_eq!(changes, vec![(2, Some("new".to_string())), (1, None)]);
+}
+
+#[tokio::test]
+async fn test_history_and_reads_at_an_ordinal() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let other = LogMap::connect_namespace(server.addr().to_string(), "other").await.unwrap();
+
+    map.insert(1, "first".to_string()).await.unwrap();
+    other.insert(1, "elsewhere".to_string()).await.unwrap();
+    map.insert(2, "unrelated".to_string()).await.unwrap();
+    map.insert(1, "second".to_string()).await.unwrap();
+    map.remove(1).await.unwrap();
+
+    let history = map.history(1).await.unwrap();
+    let versions: Vec<_> = history.iter().map(|c| (c.ordinal, c.value.clone())).collect();
+    assert_eq!(
+        versions,
+        vec![(1, Some("first".to_string())), (4, Some("second".to_string())), (5, None)]
+    );
+    assert!(history.iter().all(|c| c.key == 1 && c.timestamp > 0));
+
+    assert_eq!(map.get_at(1, 0).await.unwrap(), None);
+    assert_eq!(map.get_at(1, 1).await.unwrap(), Some("first".to_string()));
+    assert_eq!(map.get_at(1, 3).await.unwrap(), Some("first".to_string()));
+    assert_eq!(map.get_at(1, 4).await.unwrap(), Some("second".to_string()));
+    assert_eq!(map.get_at(1, 5).await.unwrap(), None);
+    assert_eq!(map.get_at(2, 2).await.unwrap(), None);
+    assert!(map.history(3).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_watch_reports_changes() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    let key = map.watch(7);
+    let prefix = map.watch_prefix("1");
+    tokio::pin!(key, prefix);
+
+    map.insert(2, "other".to_string()).await.unwrap();
+    map.insert(7, "seven".to_string()).await.unwrap();
+    map.insert(12, "twelve".to_string()).await.unwrap();
+    map.remove(7).await.unwrap();
+
+    let next = |change: Option<Result<Change, log_map::Error>>| {
+        let change = change.unwrap().unwrap();
+        (change.key, change.value)
+    };
+    let timeout = Duration::from_secs(5);
+    assert_eq!(next(tokio::time::timeout(timeout, key.next()).await.unwrap()), (7, Some("seven".to_string())));
+    assert_eq!(next(tokio::time::timeout(timeout, key.next()).await.unwrap()), (7, None));
+    assert_eq!(next(tokio::time::timeout(timeout, prefix.next()).await.unwrap()), (12, Some("twelve".to_string())));
+}
+
+#[tokio::test]
+async fn test_coalesced_watch_reports_the_latest_change_per_interval() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    let interval = Duration::from_millis(300);
+    let key = map.watch_coalesced(1, interval);
+    let prefix = map.watch_prefix_coalesced("1", interval);
+    tokio::pin!(key, prefix);
+
+    for (k, value) in [(1, "a"), (12, "x"), (1, "b"), (2, "other"), (12, "y"), (1, "c")] {
+        map.insert(k, value.to_string()).await.unwrap();
+    }
+    map.get_consistent(1).await.unwrap();
+    // The cache is ahead of the watchers by a hair.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let next = |change: Option<Result<Change, log_map::Error>>| {
+        let change = change.unwrap().unwrap();
+        (change.key, change.value)
+    };
+    let timeout = Duration::from_secs(5);
+    // Nothing was polled while the writes went by; only the latest is left.
+    assert_eq!(next(tokio::time::timeout(timeout, key.next()).await.unwrap()), (1, Some("c".to_string())));
+    let emitted = tokio::time::Instant::now();
+    assert_eq!(next(tokio::time::timeout(timeout, prefix.next()).await.unwrap()), (12, Some("y".to_string())));
+    assert_eq!(next(tokio::time::timeout(timeout, prefix.next()).await.unwrap()), (1, Some("c".to_string())));
+
+    // The next change waits out the interval.
+    map.remove(1).await.unwrap();
+    map.get_consistent(1).await.unwrap();
+    assert_eq!(next(tokio::time::timeout(timeout, key.next()).await.unwrap()), (1, None));
+    assert!(emitted.elapsed() >= interval - Duration::from_millis(20));
+    assert_eq!(next(tokio::time::timeout(timeout, prefix.next()).await.unwrap()), (1, None));
+    assert!(tokio::time::timeout(interval * 2, key.next()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_iterates_in_key_order() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    for key in [30, -5, 10, 20] {
+        map.insert(key, format!("v{}", key)).await.unwrap();
+        wait_for(&map, key).await;
+    }
+
+    assert_eq!(map.keys(), vec![-5, 10, 20, 30]);
+    assert_eq!(map.iter().map(|(key, _)| key).collect::<Vec<_>>(), vec![-5, 10, 20, 30]);
+    assert_eq!(
+        map.range(10..30).collect::<Vec<_>>(),
+        vec![(10, "v10".to_string()), (20, "v20".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn test_get_consistent_sees_own_writes() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    for i in 0..20 {
+        map.insert(i, format!("v{}", i)).await.unwrap();
+        assert_eq!(map.get_consistent(i).await.unwrap(), Some(format!("v{}", i)));
+    }
+    map.remove(3).await.unwrap();
+    assert_eq!(map.get_consistent(3).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_entries_lapse_after_their_ttl() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let changes = map.watch(1);
+    tokio::pin!(changes);
+
+    map.insert_with_ttl(1, "brief".to_string(), Duration::from_millis(300)).await.unwrap();
+    map.insert_with_ttl(2, "lasting".to_string(), Duration::from_secs(60)).await.unwrap();
+    wait_for(&map, 1).await;
+    wait_for(&map, 2).await;
+    assert!(map.entry(1).unwrap().expires_at > now_millis());
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(!map.contains_key(1));
+    assert_eq!(map.keys(), vec![2]);
+
+    // The test server has no expiry loop; sweep by hand.
+    assert_eq!(server.storage().expire().await.unwrap(), 1);
+    let changes: Vec<_> = changes.take(2).map(Result::unwrap).map(|c| c.value).collect().await;
+    assert_eq!(changes, vec![Some("brief".to_string()), None]);
+}
@@ -0,0 +1,226 @@
+//! Randomized histories against several `LogMap` clients, checked against
+//! a sequential model of the log.
+//!
+//! The log itself defines the total order of accepted writes. A history is
+//! accepted when:
+//!
+//! - every acknowledged write appears in the log exactly once and every
+//!   write rejected with a conflict does not appear at all;
+//! - each client's writes appear in the order the client issued them;
+//! - every `get` returned the key's value at some prefix of the log, and
+//!   successive reads by one client never go back to an earlier prefix;
+//! - once quiet, every client's view equals the model's final state.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use log_map::{Error, LogMap};
+use log_server_test::sim::SimServer;
+use log_server_types::kv::SubscribeRequest;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use proptest::prelude::*;
+
+const KEYS: i64 = 4;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(i64),
+    Remove(i64),
+    Get(i64),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (0..KEYS).prop_map(Op::Insert),
+        (0..KEYS).prop_map(Op::Remove),
+        (0..KEYS).prop_map(Op::Get),
+    ]
+}
+
+/// What one client did, in program order.
+#[derive(Debug, Default)]
+struct ClientHistory {
+    /// Values (unique per write) of writes the server acknowledged.
+    acked: Vec<String>,
+    /// Values of writes rejected after exhausting conflict retries.
+    rejected: Vec<String>,
+    /// `(key, observed value)` for every get.
+    reads: Vec<(i64, Option<String>)>,
+}
+
+/// One accepted write as recorded in the log.
+#[derive(Debug, Clone)]
+struct LogEntry {
+    key: i64,
+    /// Empty for removes; otherwise the unique value from [`tag`].
+    value: String,
+}
+
+/// Inserts write a unique value so each log entry can be traced back to the
+/// operation that produced it. Removes are empty values and are matched by
+/// key and program order instead.
+fn tag(client: usize, op: usize) -> String {
+    format!("c{}-{}", client, op)
+}
+
+async fn run_client(map: LogMap, client: usize, ops: Vec<Op>) -> (LogMap, ClientHistory, Vec<(i64, Option<String>)>) {
+    let mut history = ClientHistory::default();
+    let mut writes = Vec::new();
+
+    for (i, op) in ops.into_iter().enumerate() {
+        match op {
+            Op::Insert(key) => {
+                let value = tag(client, i);
+                match map.insert(key, value.clone()).await {
+                    Ok(()) => history.acked.push(value.clone()),
+                    Err(Error::Conflict(_)) => history.rejected.push(value.clone()),
+                    Err(e) => panic!("unexpected insert error: {}", e),
+                }
+                writes.push((key, Some(value)));
+            }
+            Op::Remove(key) => match map.remove(key).await {
+                Ok(()) => writes.push((key, None)),
+                Err(Error::Conflict(_)) => {}
+                Err(e) => panic!("unexpected remove error: {}", e),
+            },
+            Op::Get(key) => history.reads.push((key, map.get(key).await.unwrap())),
+        }
+    }
+
+    (map, history, writes)
+}
+
+/// Reads the whole log through a raw subscription.
+async fn read_log(server: &SimServer) -> Vec<LogEntry> {
+    let mut client = KvServerClient::new(server.channel().await.unwrap());
+    let mut stream = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let mut log = Vec::new();
+    while let Ok(Some(record)) = tokio::time::timeout(Duration::from_millis(300), stream.next()).await {
+        let record = record.unwrap();
+        let key = record.key.strip_prefix("map:").unwrap().parse().unwrap();
+        let value = String::from_utf8(record.value).unwrap();
+        log.push(LogEntry { key, value });
+    }
+    log
+}
+
+fn state_after(log: &[LogEntry], prefix: usize) -> HashMap<i64, String> {
+    let mut state = HashMap::new();
+    for entry in &log[..prefix] {
+        if entry.value.is_empty() {
+            state.remove(&entry.key);
+        } else {
+            state.insert(entry.key, entry.value.clone());
+        }
+    }
+    state
+}
+
+fn check_history(log: &[LogEntry], histories: &[ClientHistory], client_writes: &[Vec<(i64, Option<String>)>]) {
+    // Acknowledged inserts appear exactly once, rejected ones never.
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for entry in log.iter().filter(|e| !e.value.is_empty()) {
+        *seen.entry(entry.value.as_str()).or_default() += 1;
+    }
+    for history in histories {
+        for value in &history.acked {
+            assert_eq!(seen.get(value.as_str()), Some(&1), "acked write {} not logged once", value);
+        }
+        for value in &history.rejected {
+            assert!(!seen.contains_key(value.as_str()), "rejected write {} was logged", value);
+        }
+    }
+
+    // Per-client program order: a client's accepted writes form a
+    // subsequence of the log.
+    for (client, writes) in client_writes.iter().enumerate() {
+        let accepted: Vec<_> = writes
+            .iter()
+            .filter(|(_, v)| v.as_ref().is_none_or(|v| seen.contains_key(v.as_str())))
+            .collect();
+        let mut pos = 0;
+        for (key, value) in accepted {
+            let found = log[pos..].iter().position(|e| match value {
+                Some(v) => &e.value == v,
+                None => e.key == *key && e.value.is_empty(),
+            });
+            let found = found.unwrap_or_else(|| panic!("client {} write {:?} out of order", client, value));
+            pos += found + 1;
+        }
+    }
+
+    // Reads are explained by a non-decreasing sequence of log prefixes.
+    let states: Vec<_> = (0..=log.len()).map(|p| state_after(log, p)).collect();
+    for (client, history) in histories.iter().enumerate() {
+        let mut min_prefix = 0;
+        for (key, observed) in &history.reads {
+            let found = (min_prefix..states.len()).find(|&p| states[p].get(key) == observed.as_ref());
+            min_prefix = found.unwrap_or_else(|| {
+                panic!(
+                    "client {} read {:?} for key {} which no log prefix >= {} explains",
+                    client, observed, key, min_prefix
+                )
+            });
+        }
+    }
+}
+
+async fn converged(map: &LogMap, expected: &BTreeMap<i64, String>) -> bool {
+    for _ in 0..50 {
+        let mut actual = BTreeMap::new();
+        for key in 0..KEYS {
+            if let Some(value) = map.get(key).await.unwrap() {
+                actual.insert(key, value);
+            }
+        }
+        if &actual == expected {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(12))]
+
+    #[test]
+    fn test_concurrent_histories_are_linearizable(
+        programs in prop::collection::vec(prop::collection::vec(op(), 1..12), 2..4)
+    ) {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let server = SimServer::spawn().await;
+
+            let mut tasks = Vec::new();
+            for (client, ops) in programs.into_iter().enumerate() {
+                let map = LogMap::with_channel(server.channel().await.unwrap()).await.unwrap();
+                tasks.push(tokio::spawn(run_client(map, client, ops)));
+            }
+
+            let mut maps = Vec::new();
+            let mut histories = Vec::new();
+            let mut writes = Vec::new();
+            for task in tasks {
+                let (map, history, client_writes) = task.await.unwrap();
+                maps.push(map);
+                histories.push(history);
+                writes.push(client_writes);
+            }
+
+            let log = read_log(&server).await;
+            check_history(&log, &histories, &writes);
+
+            let expected: BTreeMap<_, _> = state_after(&log, log.len()).into_iter().collect();
+            for (client, map) in maps.iter().enumerate() {
+                assert!(converged(map, &expected).await, "client {} did not converge", client);
+            }
+        });
+    }
+}
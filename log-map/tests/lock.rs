@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use log_map::lock::{Mutex, NAMESPACE};
+use log_map::{Error, Plain, TypedLogMap};
+use log_server_test::TestServer;
+
+#[tokio::test]
+async fn test_lock_is_exclusive_until_released() {
+    let server = TestServer::spawn().await;
+    let a = Mutex::connect(server.addr().to_string()).await.unwrap();
+    let b = Mutex::connect(server.addr().to_string()).await.unwrap();
+
+    let guard = a.acquire("job", Duration::from_secs(10)).await.unwrap();
+    assert!(guard.is_held());
+    assert!(b.try_acquire("job", Duration::from_secs(10)).await.unwrap().is_none());
+    // Other names are independent.
+    let other = b.try_acquire("other", Duration::from_secs(10)).await.unwrap().unwrap();
+    assert!(other.fencing_token() > guard.fencing_token());
+
+    let token = guard.fencing_token();
+    let waiter = tokio::spawn(async move { b.acquire("job", Duration::from_secs(10)).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!waiter.is_finished());
+    guard.release().await.unwrap();
+
+    let next = tokio::time::timeout(Duration::from_secs(2), waiter).await.unwrap().unwrap().unwrap();
+    assert!(next.fencing_token() > token);
+}
+
+#[tokio::test]
+async fn test_heartbeat_keeps_the_lease_and_expired_leases_are_taken_over() {
+    let server = TestServer::spawn().await;
+    let a = Mutex::connect(server.addr().to_string()).await.unwrap();
+    let b = Mutex::connect(server.addr().to_string()).await.unwrap();
+    let lease = Duration::from_millis(300);
+
+    let guard = a.acquire("job", lease).await.unwrap();
+    tokio::time::sleep(lease * 3).await;
+    assert!(guard.is_held());
+    assert!(b.try_acquire("job", lease).await.unwrap().is_none());
+
+    // Dropping the guard stops the heartbeat without releasing; the lease
+    // then lapses and the lock is up for grabs without the server's sweep.
+    let token = guard.fencing_token();
+    drop(guard);
+    let taken = tokio::time::timeout(Duration::from_secs(2), b.acquire("job", lease)).await.unwrap().unwrap();
+    assert!(taken.fencing_token() > token);
+
+    assert!(a.try_acquire("job", lease).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_release_reports_a_lost_lease() {
+    let server = TestServer::spawn().await;
+    let locks = Mutex::connect(server.addr().to_string()).await.unwrap();
+    let guard = locks.acquire("job", Duration::from_secs(10)).await.unwrap();
+
+    // Someone ignoring the protocol overwrites the lock.
+    let raw = TypedLogMap::<String, String, Plain>::connect_namespace(server.addr().to_string(), NAMESPACE)
+        .await
+        .unwrap();
+    raw.insert("job".to_string(), "intruder".to_string()).await.unwrap();
+
+    assert!(matches!(guard.release().await, Err(Error::LeaseLost(name)) if name == "job"));
+}
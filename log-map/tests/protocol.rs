@@ -0,0 +1,456 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures_util::{Stream, StreamExt};
+use log_map::{ConnectConfig, LogMap, RetryPolicy};
+use log_server_test::TestServer;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use log_server_types::kv::kv_server_server::{KvServer, KvServerServer};
+use log_server_types::kv::{
+    CompactRequest, CompactResponse, GetHistoryRequest, GetHistoryResponse, GetServerInfoRequest, GetSnapshotRequest,
+    GetSnapshotResponse, Record, ServerInfo, SnapshotChunk, StatsRequest, StatsResponse, SubscribeRequest,
+    TransactionRequest, TransactionResponse, WriteRequest, WriteResponse,
+};
+use log_server_types::{PROTOCOL_VERSION, features};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+type Stub<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// A server from before `GetServerInfo` existed.
+struct LegacyServer;
+
+#[tonic::async_trait]
+impl KvServer for LegacyServer {
+    type SubscribeStream = Stub<Record>;
+    type WriteStream = Stub<WriteResponse>;
+    type StreamSnapshotStream = Stub<SnapshotChunk>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        Ok(Response::new(Box::pin(futures_util::stream::pending())))
+    }
+
+    async fn write(
+        &self,
+        _request: Request<Streaming<WriteRequest>>,
+    ) -> Result<Response<Self::WriteStream>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn get_snapshot(
+        &self,
+        _request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn get_server_info(
+        &self,
+        _request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        Err(Status::unimplemented("unknown method GetServerInfo"))
+    }
+
+    async fn compact(
+        &self,
+        _request: Request<CompactRequest>,
+    ) -> Result<Response<CompactResponse>, Status> {
+        Err(Status::unimplemented("unknown method Compact"))
+    }
+
+    async fn transaction(
+        &self,
+        _request: Request<TransactionRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        Err(Status::unimplemented("unknown method Transaction"))
+    }
+
+    async fn get_history(
+        &self,
+        _request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        Err(Status::unimplemented("unknown method GetHistory"))
+    }
+
+    async fn stream_snapshot(
+        &self,
+        _request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<Self::StreamSnapshotStream>, Status> {
+        Err(Status::unimplemented("unknown method StreamSnapshot"))
+    }
+}
+
+#[tokio::test]
+async fn test_negotiates_with_current_server() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    let info = map.server_info();
+    assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+    assert!(info.supports(features::STATS));
+    assert!(!info.supports("no-such-feature"));
+}
+
+#[tokio::test]
+async fn test_degrades_on_legacy_server() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(KvServerServer::new(LegacyServer))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let map = LogMap::connect(addr.to_string()).await.unwrap();
+
+    assert_eq!(map.server_info().protocol_version, 0);
+    assert_eq!(map.server_info().features().count(), 0);
+}
+
+#[tokio::test]
+async fn test_writes_carry_client_identity() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let other = LogMap::connect(server.addr().to_string()).await.unwrap();
+    assert_ne!(map.client_id(), other.client_id());
+
+    map.set_worker_label("worker-a");
+    map.insert(1, "one".to_string()).await.unwrap();
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let mut records = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner();
+    let record = records.next().await.unwrap().unwrap();
+
+    assert_eq!(record.client_id, map.client_id());
+    assert_eq!(record.worker_label, "worker-a");
+}
+
+/// Rejects any call without the gateway headers `LogMap::builder` adds.
+fn require_gateway_headers(request: Request<()>) -> Result<Request<()>, Status> {
+    let metadata = request.metadata();
+    if metadata.get("x-tenant-id").is_none_or(|tenant| tenant != "acme") {
+        return Err(Status::permission_denied("unknown tenant"));
+    }
+    match metadata.get("authorization") {
+        Some(token) if token == "Bearer secret" => Ok(request),
+        _ => Err(Status::unauthenticated("missing or wrong token")),
+    }
+}
+
+async fn spawn_gateway() -> std::net::SocketAddr {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Arc::new(log_server::storage::Storage::new(pool));
+    let service = KvServerServer::with_interceptor(
+        log_server::grpc::KvServiceImpl::new(storage),
+        require_gateway_headers,
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    addr
+}
+
+#[tokio::test]
+async fn test_builder_metadata_and_interceptors_reach_every_call() {
+    let addr = spawn_gateway().await;
+
+    let err = LogMap::connect(addr.to_string()).await.err().unwrap();
+    assert!(matches!(err, log_map::Error::Status(status) if status.code() == Code::PermissionDenied));
+
+    let mut metadata = MetadataMap::new();
+    metadata.insert("x-tenant-id", "acme".parse().unwrap());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&calls);
+    let map = LogMap::builder()
+        .metadata(metadata)
+        .interceptor(move |mut request: Request<()>| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            request
+                .metadata_mut()
+                .insert("authorization", "Bearer secret".parse().unwrap());
+            Ok(request)
+        })
+        .connect(addr.to_string())
+        .await
+        .unwrap();
+
+    map.insert(1, "one".to_string()).await.unwrap();
+    for _ in 0..50 {
+        if map.get(1).await.unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(map.get(1).await.unwrap(), Some("one".to_string()));
+    // Handshake, snapshot, subscribe and write.
+    assert!(calls.load(Ordering::Relaxed) >= 4);
+}
+
+#[tokio::test]
+async fn test_connect_with_token() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Arc::new(log_server::storage::Storage::new(pool));
+    let auth = tonic::service::InterceptorLayer::new(log_server::auth::BearerToken::new("s3cr3t"));
+    let service = log_server::grpc::create_server_with_layer(storage, auth);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let wrong = ConnectConfig {
+        token: Some("guess".to_string()),
+        ..Default::default()
+    };
+    let err = LogMap::connect_with_config(addr.to_string(), wrong).await.err().unwrap();
+    assert!(matches!(err, log_map::Error::Status(status) if status.code() == Code::Unauthenticated));
+
+    let config = ConnectConfig {
+        token: Some("s3cr3t".to_string()),
+        ..Default::default()
+    };
+    let map = LogMap::connect_with_config(addr.to_string(), config).await.unwrap();
+    map.insert(1, "one".to_string()).await.unwrap();
+
+    let invalid = ConnectConfig {
+        token: Some("new\nline".to_string()),
+        ..Default::default()
+    };
+    let err = LogMap::connect_with_config(addr.to_string(), invalid).await.err().unwrap();
+    assert!(matches!(err, log_map::Error::InvalidToken));
+}
+
+#[tokio::test]
+async fn test_prefer_replica_reads_locally_and_writes_to_primary() {
+    let primary = TestServer::spawn().await;
+    let replica = TestServer::spawn().await;
+    replica.storage().append("map:7".to_string(), b"from replica".to_vec()).await.unwrap();
+
+    let map = LogMap::builder()
+        .prefer_replica(replica.addr().to_string())
+        .connect(primary.addr().to_string())
+        .await
+        .unwrap();
+
+    for _ in 0..50 {
+        if map.contains_key(7) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(map.get(7).await.unwrap(), Some("from replica".to_string()));
+
+    map.insert(1, "one".to_string()).await.unwrap();
+    assert_eq!(primary.storage().stats().await.unwrap().record_count, 1);
+    assert_eq!(replica.storage().stats().await.unwrap().record_count, 1);
+}
+
+#[tokio::test]
+async fn test_prefer_replica_falls_back_to_primary() {
+    let primary = TestServer::spawn().await;
+    primary.storage().append("map:7".to_string(), b"from primary".to_vec()).await.unwrap();
+    let replica = TestServer::spawn().await;
+    let replica_addr = replica.addr();
+    replica.shutdown().await;
+
+    let map = LogMap::builder()
+        .prefer_replica(replica_addr.to_string())
+        .connect(primary.addr().to_string())
+        .await
+        .unwrap();
+
+    for _ in 0..50 {
+        if map.contains_key(7) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(map.get(7).await.unwrap(), Some("from primary".to_string()));
+}
+
+#[tokio::test]
+async fn test_insert_batch_uses_one_stream() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    let entries = (1..=20).map(|key| (key, format!("value-{}", key))).collect();
+    let results = map.insert_batch(entries).await.unwrap();
+    assert_eq!(results.len(), 20);
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(server.storage().stats().await.unwrap().record_count, 20);
+
+    for _ in 0..50 {
+        if map.len() == 20 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(map.get(20).await.unwrap(), Some("value-20".to_string()));
+}
+
+#[tokio::test]
+async fn test_insert_batch_on_legacy_server_falls_back() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(KvServerServer::new(LegacyServer))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    let map = LogMap::connect(addr.to_string()).await.unwrap();
+
+    // The stub rejects every Write call, so each entry fails on its own.
+    let results = map.insert_batch(vec![(1, "a".to_string()), (2, "b".to_string())]).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| matches!(r, Err(log_map::Error::Status(s)) if s.code() == Code::Unimplemented)));
+}
+
+#[tokio::test]
+async fn test_conditional_writes() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let other = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    assert_eq!(map.insert_if_absent(1, "first".to_string()).await.unwrap(), Ok(()));
+    // `other` may not have synced "first" yet; the server catches that.
+    assert_eq!(
+        other.insert_if_absent(1, "second".to_string()).await.unwrap(),
+        Err("first".to_string())
+    );
+
+    assert_eq!(
+        other.compare_and_swap(1, "nope".to_string(), "third".to_string()).await.unwrap(),
+        Err(Some("first".to_string()))
+    );
+    assert_eq!(
+        other.compare_and_swap(1, "first".to_string(), "third".to_string()).await.unwrap(),
+        Ok(())
+    );
+    assert_eq!(
+        map.compare_and_swap(2, "first".to_string(), "third".to_string()).await.unwrap(),
+        Err(None)
+    );
+}
+
+#[tokio::test]
+async fn test_conditional_writes_need_server_support() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(KvServerServer::new(LegacyServer))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let map = LogMap::connect(addr.to_string()).await.unwrap();
+    let result = map.insert_if_absent(1, "one".to_string()).await;
+    assert!(matches!(result, Err(log_map::Error::Unsupported(_))));
+    let result = map.history(1).await;
+    assert!(matches!(result, Err(log_map::Error::Unsupported(features::HISTORY))));
+}
+
+#[tokio::test]
+async fn test_transaction_writes_every_key() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let other = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    map.insert(2, "stale".to_string()).await.unwrap();
+    // `other` may not have seen key 2 yet; a conflict is retried.
+    other
+        .transaction()
+        .insert(1, "result".to_string())
+        .insert(3, "1".to_string())
+        .remove(2)
+        .commit()
+        .await
+        .unwrap();
+
+    assert_eq!(other.get_consistent(1).await.unwrap(), Some("result".to_string()));
+    assert_eq!(other.get(2).await.unwrap(), None);
+    assert_eq!(other.get(3).await.unwrap(), Some("1".to_string()));
+}
+
+#[tokio::test]
+async fn test_writers_of_different_keys_never_conflict() {
+    let server = TestServer::spawn().await;
+    let first = LogMap::connect(server.addr().to_string()).await.unwrap();
+    let second = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    // Neither map waits for the other's writes to sync.
+    tokio::join!(
+        async {
+            for i in 0..20 {
+                first.insert(1, i.to_string()).await.unwrap();
+            }
+        },
+        async {
+            for i in 0..20 {
+                second.insert(2, i.to_string()).await.unwrap();
+            }
+        },
+    );
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let stale = WriteRequest {
+        key: "map:1".to_string(),
+        value: b"stale".to_vec(),
+        latest_known: 1,
+        if_unchanged: true,
+        ..Default::default()
+    };
+    let response = client
+        .write(tokio_stream::once(stale))
+        .await
+        .unwrap()
+        .into_inner()
+        .next()
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!response.accepted);
+    assert!(response.key_ordinal > 1);
+}
+
+#[tokio::test]
+async fn test_back_to_back_inserts_to_one_key_never_conflict() {
+    let server = TestServer::spawn().await;
+    // No retries, so a conflict would surface as an error.
+    let policy = RetryPolicy {
+        max_retries: 1,
+        ..Default::default()
+    };
+    let map = LogMap::builder().retry_policy(policy).connect(server.addr().to_string()).await.unwrap();
+
+    let (first, second) = tokio::join!(map.insert(1, "a".to_string()), map.insert(1, "b".to_string()));
+    first.unwrap();
+    second.unwrap();
+    for i in 0..20 {
+        map.insert(1, i.to_string()).await.unwrap();
+    }
+    map.remove(1).await.unwrap();
+    map.insert(1, "last".to_string()).await.unwrap();
+    assert_eq!(map.get_consistent(1).await.unwrap(), Some("last".to_string()));
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use log_map::LogMap;
+use log_map::rest::RestClient;
+use log_server_test::TestServer;
+use tokio::net::TcpListener;
+
+async fn spawn_gateway(server: &TestServer, token: Option<&'static str>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(log_server::rest::serve_on(listener, Arc::clone(server.storage()), token));
+    addr.to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rest_client_shares_entries_with_log_map() {
+    let server = TestServer::spawn().await;
+    let addr = spawn_gateway(&server, None).await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    map.insert(1, "from grpc".to_string()).await.unwrap();
+
+    let client = RestClient::new(addr);
+    let read = tokio::task::spawn_blocking(move || {
+        assert_eq!(client.get(1).unwrap(), Some("from grpc".to_string()));
+        assert_eq!(client.get(2).unwrap(), None);
+        client.insert(2, "from rest").unwrap();
+        client.insert(3, "gone soon").unwrap();
+        client.remove(3).unwrap();
+        assert_eq!(client.get(3).unwrap(), None);
+        client.get(2).unwrap()
+    });
+    assert_eq!(read.await.unwrap(), Some("from rest".to_string()));
+
+    for _ in 0..50 {
+        if map.contains_key(2) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(map.get(2).await.unwrap(), Some("from rest".to_string()));
+    assert!(!map.contains_key(3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rest_client_namespaces_and_tokens() {
+    let server = TestServer::spawn().await;
+    let addr = spawn_gateway(&server, Some("s3cr3t")).await;
+
+    let anonymous = RestClient::new(addr.clone());
+    let client = RestClient::new(addr).namespace("jobs").unwrap().token("s3cr3t").unwrap();
+    tokio::task::spawn_blocking(move || {
+        assert!(matches!(anonymous.get(1), Err(log_map::Error::Http(_))));
+        client.insert(1, "open").unwrap();
+        assert_eq!(client.get(1).unwrap(), Some("open".to_string()));
+    })
+    .await
+    .unwrap();
+    let records = server.storage().history("jobs:1", 0, 0).await.unwrap();
+    assert_eq!(records.len(), 1);
+
+    assert!(matches!(RestClient::new("x").token(""), Err(log_map::Error::InvalidToken)));
+    assert!(matches!(RestClient::new("x").namespace("a:b"), Err(log_map::Error::InvalidNamespace(_))));
+}
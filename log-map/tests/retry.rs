@@ -0,0 +1,162 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use futures_util::{Stream, StreamExt};
+use log_map::{ConnectConfig, LogMap, RetryPolicy};
+use log_server_types::PROTOCOL_VERSION;
+use log_server_types::kv::kv_server_server::{KvServer, KvServerServer};
+use log_server_types::kv::{
+    CompactRequest, CompactResponse, GetHistoryRequest, GetHistoryResponse, GetServerInfoRequest, GetSnapshotRequest,
+    GetSnapshotResponse, Record, ServerInfo, SnapshotChunk, StatsRequest, StatsResponse, SubscribeRequest,
+    TransactionRequest, TransactionResponse, WriteRequest, WriteResponse,
+};
+use tonic::{Request, Response, Status, Streaming};
+
+type Stub<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// A server that turns down every write as if its key had just changed.
+#[derive(Clone, Default)]
+struct RejectingServer {
+    writes: Arc<AtomicUsize>,
+}
+
+#[tonic::async_trait]
+impl KvServer for RejectingServer {
+    type SubscribeStream = Stub<Record>;
+    type WriteStream = Stub<WriteResponse>;
+    type StreamSnapshotStream = Stub<SnapshotChunk>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        Ok(Response::new(Box::pin(futures_util::stream::pending())))
+    }
+
+    async fn write(
+        &self,
+        request: Request<Streaming<WriteRequest>>,
+    ) -> Result<Response<Self::WriteStream>, Status> {
+        let writes = Arc::clone(&self.writes);
+        let responses = request.into_inner().map(move |request| {
+            writes.fetch_add(1, Ordering::SeqCst);
+            Ok(WriteResponse {
+                accepted: false,
+                request_ordinal: request?.ordinal,
+                ..Default::default()
+            })
+        });
+        Ok(Response::new(Box::pin(responses)))
+    }
+
+    async fn get_snapshot(
+        &self,
+        _request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn get_server_info(
+        &self,
+        _request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        Ok(Response::new(ServerInfo {
+            protocol_version: PROTOCOL_VERSION,
+            features: Vec::new(),
+            server_version: "rejecting".to_string(),
+            max_value_size: 0,
+        }))
+    }
+
+    async fn compact(
+        &self,
+        _request: Request<CompactRequest>,
+    ) -> Result<Response<CompactResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn transaction(
+        &self,
+        _request: Request<TransactionRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn get_history(
+        &self,
+        _request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+
+    async fn stream_snapshot(
+        &self,
+        _request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<Self::StreamSnapshotStream>, Status> {
+        Err(Status::unimplemented("not part of this test"))
+    }
+}
+
+async fn spawn_rejecting() -> (String, Arc<AtomicUsize>) {
+    let server = RejectingServer::default();
+    let writes = Arc::clone(&server.writes);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(KvServerServer::new(server))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    (addr.to_string(), writes)
+}
+
+#[tokio::test]
+async fn test_retry_policy_limits_attempts() {
+    let (addr, writes) = spawn_rejecting().await;
+    let policy = RetryPolicy {
+        max_retries: 3,
+        base_delay: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let map = LogMap::builder().retry_policy(policy).connect(addr).await.unwrap();
+
+    let result = map.insert(1, "one".to_string()).await;
+    assert!(matches!(result, Err(log_map::Error::Conflict(3))));
+    assert_eq!(writes.load(Ordering::SeqCst), 3);
+
+    let result = map.remove(1).await;
+    assert!(matches!(result, Err(log_map::Error::Conflict(3))));
+    assert_eq!(writes.load(Ordering::SeqCst), 6);
+}
+
+#[tokio::test]
+async fn test_retry_policy_caps_delays_and_enforces_the_deadline() {
+    let (addr, writes) = spawn_rejecting().await;
+    let config = ConnectConfig {
+        retry: RetryPolicy {
+            max_retries: usize::MAX,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(20),
+            jitter: 0.5,
+            timeout: Some(Duration::from_millis(300)),
+        },
+        ..Default::default()
+    };
+    let map = LogMap::connect_with_config(addr, config).await.unwrap();
+
+    let started = Instant::now();
+    let result = map.insert(1, "one".to_string()).await;
+    assert!(matches!(result, Err(log_map::Error::Timeout(timeout)) if timeout == Duration::from_millis(300)));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    // Uncapped doubling would have waited past the deadline after 5 tries.
+    assert!(writes.load(Ordering::SeqCst) > 10);
+}
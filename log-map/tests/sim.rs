@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use log_map::{Health, LogMap};
+use log_server_test::sim::SimServer;
+
+async fn eventually<F: Fn() -> bool>(check: F) -> bool {
+    for _ in 0..100 {
+        if check() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_sync_between_clients_over_sim_link() {
+    let server = SimServer::spawn().await;
+    let a = LogMap::with_channel(server.channel().await.unwrap()).await.unwrap();
+    let b = LogMap::with_channel(server.channel().await.unwrap()).await.unwrap();
+
+    server.link().set_latency(Duration::from_millis(5));
+    a.insert(7, "seven".to_string()).await.unwrap();
+
+    assert!(eventually(|| b.contains_key(7)).await);
+    assert_eq!(b.get(7).await.unwrap(), Some("seven".to_string()));
+}
+
+#[tokio::test]
+async fn test_write_fails_while_link_is_down() {
+    let server = SimServer::spawn().await;
+    let map = LogMap::with_channel(server.channel().await.unwrap()).await.unwrap();
+    map.insert(1, "before".to_string()).await.unwrap();
+
+    server.link().refuse_connections(true);
+    server.link().drop_connections();
+    assert!(map.insert(2, "during".to_string()).await.is_err());
+
+    server.link().refuse_connections(false);
+    map.insert(3, "after".to_string()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_sync_reconnects_after_the_link_drops() {
+    let server = SimServer::spawn().await;
+    let reader = LogMap::with_channel(server.channel().await.unwrap()).await.unwrap();
+    assert!(eventually(|| reader.health() == Health::Connected).await);
+
+    server.link().refuse_connections(true);
+    server.link().drop_connections();
+    assert!(eventually(|| matches!(reader.health(), Health::Reconnecting { .. })).await);
+
+    // Written behind the reader's back; it must show up after the reconnect.
+    server.storage().append("map:5".to_string(), b"missed".to_vec()).await.unwrap();
+    server.link().refuse_connections(false);
+
+    assert!(eventually(|| reader.contains_key(5)).await);
+    assert_eq!(reader.health(), Health::Connected);
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use log_map::{LogMap, TypedLogMap};
+use log_server_test::TestServer;
+use log_server_types::kv::SubscribeRequest;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Progress {
+    done: u32,
+    total: u32,
+}
+
+async fn wait_until(mut ready: impl FnMut() -> bool) {
+    for _ in 0..50 {
+        if ready() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("condition never became true");
+}
+
+#[tokio::test]
+async fn test_structured_values_round_trip() {
+    let server = TestServer::spawn().await;
+    let map: TypedLogMap<String, Progress> = TypedLogMap::connect(server.addr().to_string()).await.unwrap();
+    let plain = LogMap::connect(server.addr().to_string()).await.unwrap();
+
+    let progress = Progress { done: 3, total: 10 };
+    map.insert("job-1".to_string(), progress.clone()).await.unwrap();
+    plain.insert(1, "hello".to_string()).await.unwrap();
+    wait_until(|| map.contains_key("job-1".to_string()) && plain.contains_key(1)).await;
+
+    assert_eq!(map.get("job-1".to_string()).await.unwrap(), Some(progress));
+    // Neither map decodes the other's records.
+    assert_eq!(map.len(), 1);
+    assert_eq!(plain.len(), 1);
+
+    map.remove("job-1".to_string()).await.unwrap();
+    wait_until(|| !map.contains_key("job-1".to_string())).await;
+}
+
+#[tokio::test]
+async fn test_log_map_keeps_raw_string_format() {
+    let server = TestServer::spawn().await;
+    let map = LogMap::connect(server.addr().to_string()).await.unwrap();
+    map.insert(7, "\"quoted\" text".to_string()).await.unwrap();
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let record = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner()
+        .next()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.key, "map:7");
+    assert_eq!(record.value, b"\"quoted\" text");
+
+    wait_until(|| map.contains_key(7)).await;
+    assert_eq!(map.get(7).await.unwrap(), Some("\"quoted\" text".to_string()));
+}
+
+#[tokio::test]
+async fn test_namespaces_share_a_server() {
+    let server = TestServer::spawn().await;
+    let jobs: TypedLogMap<i64, String> = TypedLogMap::connect_namespace(server.addr().to_string(), "jobs")
+        .await
+        .unwrap();
+    let plain = LogMap::connect(server.addr().to_string()).await.unwrap();
+    assert_eq!(jobs.namespace(), "jobs");
+    assert_eq!(plain.namespace(), "map");
+
+    jobs.insert(1, "queued".to_string()).await.unwrap();
+    plain.insert(1, "hello".to_string()).await.unwrap();
+    plain.insert(2, "world".to_string()).await.unwrap();
+    wait_until(|| jobs.contains_key(1) && plain.len() == 2).await;
+
+    assert_eq!(jobs.get(1).await.unwrap(), Some("queued".to_string()));
+    assert_eq!(plain.get(1).await.unwrap(), Some("hello".to_string()));
+    assert_eq!(jobs.len(), 1);
+
+    let invalid = TypedLogMap::<i64, String>::connect_namespace(server.addr().to_string(), "a:b").await;
+    assert!(matches!(invalid, Err(log_map::Error::InvalidNamespace(_))));
+}
@@ -0,0 +1,142 @@
+//! Test support for code that talks to a log-server.
+//!
+//! [`TestServer`] runs a real gRPC log-server on a random local port with
+//! in-memory SQLite storage, so integration tests can exercise clients
+//! end-to-end without any external setup.
+//!
+//! For tests that need control over the network, [`sim::SimServer`] serves
+//! the same service over in-memory pipes with injectable latency and
+//! connection drops.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use log_server_test::TestServer;
+//!
+//! # async fn example() {
+//! let server = TestServer::spawn().await;
+//! // Point a client at `server.url()` (gRPC) or `server.addr()` (LogMap).
+//! println!("test server at {}", server.url());
+//! server.shutdown().await;
+//! # }
+//! ```
+
+pub mod sim;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log_server::grpc::KvServiceImpl;
+use log_server::storage::Storage;
+use log_server_types::kv::kv_server_server::KvServerServer;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// A log-server running inside the test process.
+///
+/// The server stops when [`TestServer::shutdown`] is awaited or the value is
+/// dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    storage: Arc<Storage>,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Starts a server with fresh in-memory storage on `127.0.0.1:0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database or the listening socket cannot be created.
+    pub async fn spawn() -> Self {
+        let pool = log_server::db::init_pool("sqlite::memory:")
+            .await
+            .expect("failed to create in-memory database");
+        Self::spawn_with_storage(Arc::new(Storage::new(pool))).await
+    }
+
+    /// Starts a server backed by the given storage.
+    ///
+    /// Useful when a test needs snapshots or wants to inspect storage
+    /// directly.
+    pub async fn spawn_with_storage(storage: Arc<Storage>) -> Self {
+        let service = log_server::grpc::create_server(Arc::clone(&storage));
+        Self::serve(storage, service).await
+    }
+
+    /// Starts a server with in-memory storage whose responses are disturbed
+    /// by `faults`.
+    #[cfg(feature = "chaos")]
+    pub async fn spawn_with_faults(faults: log_server::chaos::FaultInjector) -> Self {
+        let pool = log_server::db::init_pool("sqlite::memory:")
+            .await
+            .expect("failed to create in-memory database");
+        let storage = Arc::new(Storage::new(pool));
+        let service = log_server::grpc::create_server_with_faults(Arc::clone(&storage), faults);
+        Self::serve(storage, service).await
+    }
+
+    async fn serve(storage: Arc<Storage>, service: KvServerServer<KvServiceImpl>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind test server");
+        let addr = listener.local_addr().expect("listener has no local address");
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        let handle = tokio::spawn(async move {
+            let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, async {
+                    let _ = shutdown_rx.await;
+                })
+                .await
+            {
+                eprintln!("test server error: {}", e);
+            }
+        });
+
+        Self {
+            addr,
+            storage,
+            shutdown: Some(shutdown_tx),
+            handle: Some(handle),
+        }
+    }
+
+    /// Address the server listens on, e.g. `127.0.0.1:40123`.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Endpoint URL suitable for `KvServerClient::connect`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Storage the server writes to.
+    pub fn storage(&self) -> &Arc<Storage> {
+        &self.storage
+    }
+
+    /// Stops the server and waits for it to finish.
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.await;
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+    }
+}
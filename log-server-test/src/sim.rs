@@ -0,0 +1,191 @@
+//! In-process transport for deterministic simulation tests.
+//!
+//! [`SimServer`] serves the KV service over in-memory duplex pipes instead
+//! of TCP. Every client connection is relayed through a [`SimLink`], which
+//! tests use to add latency or cut all open connections at a chosen point,
+//! so scenarios such as conflict storms or sync gaps run fast and without
+//! timing-dependent sockets.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper_util::rt::TokioIo;
+use log_server::storage::Storage;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tonic::transport::{Channel, Endpoint, Uri};
+
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Fault controls shared by all connections of a [`SimServer`].
+#[derive(Clone)]
+pub struct SimLink {
+    latency: Arc<Mutex<Duration>>,
+    /// Bumped by [`SimLink::drop_connections`]; relays exit when it changes.
+    generation: Arc<watch::Sender<u64>>,
+    refuse: Arc<Mutex<bool>>,
+}
+
+impl SimLink {
+    fn new() -> Self {
+        Self {
+            latency: Arc::new(Mutex::new(Duration::ZERO)),
+            generation: Arc::new(watch::channel(0).0),
+            refuse: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    /// Delays every chunk of data by `latency` in both directions.
+    pub fn set_latency(&self, latency: Duration) {
+        *self.latency.lock().unwrap() = latency;
+    }
+
+    /// Closes every open connection; clients see a broken stream.
+    pub fn drop_connections(&self) {
+        self.generation.send_modify(|g| *g += 1);
+    }
+
+    /// Makes new connection attempts fail until reset to `false`.
+    pub fn refuse_connections(&self, refuse: bool) {
+        *self.refuse.lock().unwrap() = refuse;
+    }
+
+    fn latency(&self) -> Duration {
+        *self.latency.lock().unwrap()
+    }
+}
+
+/// A log-server reachable only through in-memory connections.
+pub struct SimServer {
+    storage: Arc<Storage>,
+    link: SimLink,
+    connections: mpsc::Sender<Result<DuplexStream, io::Error>>,
+    handle: JoinHandle<()>,
+}
+
+impl SimServer {
+    /// Starts a server with fresh in-memory storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the in-memory database cannot be created.
+    pub async fn spawn() -> Self {
+        let pool = log_server::db::init_pool("sqlite::memory:")
+            .await
+            .expect("failed to create in-memory database");
+        Self::spawn_with_storage(Arc::new(Storage::new(pool))).await
+    }
+
+    /// Starts a server backed by the given storage.
+    pub async fn spawn_with_storage(storage: Arc<Storage>) -> Self {
+        let (tx, rx) = mpsc::channel(16);
+        let service = log_server::grpc::create_server(Arc::clone(&storage));
+
+        let handle = tokio::spawn(async move {
+            let incoming = tokio_stream::wrappers::ReceiverStream::new(rx);
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await
+            {
+                eprintln!("sim server error: {}", e);
+            }
+        });
+
+        Self {
+            storage,
+            link: SimLink::new(),
+            connections: tx,
+            handle,
+        }
+    }
+
+    /// Fault controls for this server's connections.
+    pub fn link(&self) -> &SimLink {
+        &self.link
+    }
+
+    /// Storage the server writes to.
+    pub fn storage(&self) -> &Arc<Storage> {
+        &self.storage
+    }
+
+    /// Opens a client channel to the server.
+    ///
+    /// The channel reconnects through the link on demand, so connections
+    /// cut by [`SimLink::drop_connections`] are re-established on the next
+    /// request unless the link refuses them.
+    pub async fn channel(&self) -> Result<Channel, tonic::transport::Error> {
+        let link = self.link.clone();
+        let connections = self.connections.clone();
+
+        Endpoint::from_static("http://sim.invalid")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let link = link.clone();
+                let connections = connections.clone();
+                async move {
+                    if *link.refuse.lock().unwrap() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionRefused,
+                            "sim link refuses connections",
+                        ));
+                    }
+
+                    let (client, client_relay) = tokio::io::duplex(PIPE_CAPACITY);
+                    let (server, server_relay) = tokio::io::duplex(PIPE_CAPACITY);
+                    connections
+                        .send(Ok(server))
+                        .await
+                        .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "sim server stopped"))?;
+                    spawn_relay(link, client_relay, server_relay);
+
+                    Ok::<_, io::Error>(TokioIo::new(client))
+                }
+            }))
+            .await
+    }
+}
+
+impl Drop for SimServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Pumps bytes between the two pipe ends until either closes or the link
+/// drops all connections.
+fn spawn_relay(link: SimLink, client: DuplexStream, server: DuplexStream) {
+    let (client_read, client_write) = tokio::io::split(client);
+    let (server_read, server_write) = tokio::io::split(server);
+
+    tokio::spawn(relay(link.clone(), client_read, server_write));
+    tokio::spawn(relay(link, server_read, client_write));
+}
+
+async fn relay(link: SimLink, mut from: ReadHalf<DuplexStream>, mut to: WriteHalf<DuplexStream>) {
+    let mut generation = link.generation.subscribe();
+    generation.mark_unchanged();
+    let mut buf = vec![0u8; PIPE_CAPACITY];
+
+    loop {
+        let n = tokio::select! {
+            read = from.read(&mut buf) => match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            },
+            _ = generation.changed() => break,
+        };
+
+        let latency = link.latency();
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        if to.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+    }
+
+    let _ = to.shutdown().await;
+}
@@ -0,0 +1,161 @@
+//! Helpers for reading the log through the raw gRPC API.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use futures_util::StreamExt;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use log_server_types::kv::{GetSnapshotRequest, Record, StatsRequest, SubscribeRequest};
+use log_server_types::metadata::Handshake;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Channel, Endpoint};
+
+pub type Client = KvServerClient<InterceptedService<Channel, Handshake>>;
+
+/// Connects to `addr` (`host:port`), identifying as logctl in the handshake.
+pub async fn connect(addr: &str) -> Result<Client, tonic::transport::Error> {
+    let channel = Endpoint::from_shared(format!("http://{}", addr))?
+        .connect()
+        .await?;
+    let handshake = Handshake::new(concat!("logctl/", env!("CARGO_PKG_VERSION")), &[]);
+    Ok(KvServerClient::with_interceptor(channel, handshake))
+}
+
+/// Returns the ordinal of the newest record, or 0 for an empty log.
+pub async fn latest_ordinal(client: &mut Client) -> Result<u64, tonic::Status> {
+    Ok(client.stats(StatsRequest {}).await?.into_inner().latest_ordinal)
+}
+
+/// Reads records with ordinals in `(from, to]`.
+///
+/// Subscribe streams never end on their own, so the read stops once the
+/// record at `to` has been seen.
+pub async fn read_range(client: &mut Client, from: u64, to: u64) -> Result<Vec<Record>, tonic::Status> {
+    let mut records = Vec::new();
+    if to <= from {
+        return Ok(records);
+    }
+
+    let mut stream = client
+        .subscribe(SubscribeRequest { start_ordinal: from, ..Default::default() })
+        .await?
+        .into_inner();
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        let ordinal = record.ordinal;
+        if ordinal > to {
+            break;
+        }
+        records.push(record);
+        if ordinal >= to {
+            break;
+        }
+    }
+
+    Ok(records)
+}
+
+/// Downloads the latest snapshot: its ordinal and payload, empty if there
+/// is none. Streamed in chunks unless the server predates `StreamSnapshot`.
+pub async fn snapshot(client: &mut Client) -> Result<(u64, Vec<u8>), tonic::Status> {
+    let mut chunks = match client.stream_snapshot(GetSnapshotRequest::default()).await {
+        Ok(response) => response.into_inner(),
+        Err(status) if status.code() == tonic::Code::Unimplemented => {
+            let snapshot = client.get_snapshot(GetSnapshotRequest::default()).await?.into_inner();
+            return Ok((snapshot.snapshot_ordinal, snapshot.snapshot_data));
+        }
+        Err(status) => return Err(status),
+    };
+
+    let mut ordinal = 0;
+    let mut data = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        ordinal = chunk.snapshot_ordinal;
+        data.extend_from_slice(&chunk.data);
+    }
+    Ok((ordinal, data))
+}
+
+/// Replays records into the latest value per key; empty values delete.
+pub fn fold_latest(records: Vec<Record>) -> BTreeMap<String, Record> {
+    let mut state = BTreeMap::new();
+    for record in records {
+        if record.value.is_empty() {
+            state.remove(&record.key);
+        } else {
+            state.insert(record.key.clone(), record);
+        }
+    }
+    state
+}
+
+/// Streams every record after `from` whose key starts with `key_prefix` to
+/// `on_record` until the server closes the subscription.
+///
+/// Servers without `prefix-subscribe` send every key, so callers still
+/// check the prefix.
+pub async fn follow(
+    client: &mut Client,
+    from: u64,
+    key_prefix: &str,
+    mut on_record: impl FnMut(&Record),
+) -> Result<(), tonic::Status> {
+    let request = SubscribeRequest {
+        start_ordinal: from,
+        key_prefix: key_prefix.to_string(),
+        ..Default::default()
+    };
+    let mut stream = client
+        .subscribe(request)
+        .await?
+        .into_inner();
+
+    while let Some(record) = stream.next().await {
+        on_record(&record?);
+    }
+
+    Ok(())
+}
+
+/// How records are printed by `scan` and `tail`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `ordinal key=value`, aligned for humans.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("unknown format '{}', expected text or json", other)),
+        }
+    }
+}
+
+/// Renders a record in the requested format.
+///
+/// Values are printed lossily as UTF-8; the log itself does not constrain
+/// them, but every writer in this repo stores text.
+pub fn format_record(record: &Record, format: OutputFormat) -> String {
+    let value = String::from_utf8_lossy(&record.value);
+    match format {
+        OutputFormat::Text => format!("{:>8} {}={}", record.ordinal, record.key, value),
+        OutputFormat::Json => serde_json::json!({
+            "ordinal": record.ordinal,
+            "key": record.key,
+            "value": value,
+            "timestamp": record.timestamp,
+            "client_id": record.client_id,
+            "worker_label": record.worker_label,
+        })
+        .to_string(),
+    }
+}
@@ -0,0 +1,230 @@
+//! `logctl` — inspect and poke a log-server from the command line.
+//!
+//! Keys are raw log keys, so LogMap entries are addressed as `map:<i64>`.
+
+mod log;
+
+use std::env;
+
+use futures_util::stream;
+use log_server_types::kv::{CompactRequest, GetHistoryRequest, Record, StatsRequest, WriteRequest};
+
+use crate::log::{Client, OutputFormat};
+
+const DEFAULT_ADDR: &str = "localhost:50051";
+const DEFAULT_TAIL: u64 = 20;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let addr = match flag_value(&args, "--addr") {
+        Some(addr) => addr.to_string(),
+        None => env::var("LOGCTL_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.to_string()),
+    };
+    remove_flag(&mut args, "--addr", true);
+
+    let Some(command) = args.first().cloned() else {
+        usage();
+    };
+
+    let format: OutputFormat = flag_value(&args, "--format").map_or(Ok(OutputFormat::Text), str::parse)?;
+    let prefix = flag_value(&args, "--prefix").unwrap_or("");
+
+    let mut client = log::connect(&addr).await?;
+
+    match command.as_str() {
+        "get" => {
+            let key = positional(&args, 1, "key");
+            let latest = log::latest_ordinal(&mut client).await?;
+            let records = log::read_range(&mut client, 0, latest).await?;
+            match log::fold_latest(records).get(key) {
+                Some(record) => println!("{}", String::from_utf8_lossy(&record.value)),
+                None => {
+                    eprintln!("{}: not found", key);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "put" => {
+            let key = positional(&args, 1, "key");
+            let value = positional(&args, 2, "value");
+            let ordinal = write(&mut client, key, value.as_bytes().to_vec()).await?;
+            println!("written at ordinal {}", ordinal);
+        }
+        "delete" => {
+            let key = positional(&args, 1, "key");
+            let ordinal = write(&mut client, key, Vec::new()).await?;
+            println!("tombstone written at ordinal {}", ordinal);
+        }
+        "history" => {
+            let key = positional(&args, 1, "key");
+            let request = GetHistoryRequest {
+                key: key.to_string(),
+                until_ordinal: flag_value(&args, "--until-ordinal").map_or(Ok(0), str::parse)?,
+                limit: flag_value(&args, "-n").map_or(Ok(0), str::parse)?,
+            };
+            let history = client.get_history(request).await?.into_inner();
+            for record in &history.records {
+                println!("{}", log::format_record(record, format));
+            }
+        }
+        "scan" => {
+            let latest = log::latest_ordinal(&mut client).await?;
+            let records = log::read_range(&mut client, 0, latest).await?;
+            for record in log::fold_latest(records).values() {
+                if record.key.starts_with(prefix) {
+                    println!("{}", log::format_record(record, format));
+                }
+            }
+        }
+        "tail" => {
+            let count: u64 = flag_value(&args, "-n").map_or(Ok(DEFAULT_TAIL), str::parse)?;
+            let latest = log::latest_ordinal(&mut client).await?;
+            // Subscriptions start after the given ordinal, so an inclusive
+            // --from-ordinal N becomes a subscription from N - 1.
+            let from = match flag_value(&args, "--from-ordinal") {
+                Some(ordinal) => ordinal.parse::<u64>()?.saturating_sub(1),
+                None => latest.saturating_sub(count),
+            };
+
+            let print = |record: &Record| {
+                if record.key.starts_with(prefix) {
+                    println!("{}", log::format_record(record, format));
+                }
+            };
+
+            if has_flag(&args, "--follow") {
+                log::follow(&mut client, from, prefix, print).await?;
+            } else {
+                log::read_range(&mut client, from, latest).await?.iter().for_each(print);
+            }
+        }
+        "snapshot" => {
+            let (ordinal, data) = log::snapshot(&mut client).await?;
+            if data.is_empty() {
+                println!("no snapshot available");
+                return Ok(());
+            }
+            println!("snapshot at ordinal {} ({} bytes)", ordinal, data.len());
+            if let Some(path) = flag_value(&args, "--out") {
+                std::fs::write(path, &data)?;
+                println!("written to {}", path);
+            }
+        }
+        "compact" => {
+            let compaction = client.compact(CompactRequest {}).await?.into_inner();
+            println!(
+                "removed {} records up to ordinal {}",
+                compaction.records_removed, compaction.cutoff_ordinal
+            );
+        }
+        "stats" => {
+            let stats = client.stats(StatsRequest {}).await?.into_inner();
+            println!("latest ordinal:   {}", stats.latest_ordinal);
+            println!("records:          {}", stats.record_count);
+            println!("distinct keys:    {}", stats.key_count);
+            println!("snapshot ordinal: {}", stats.snapshot_ordinal);
+            if !stats.durability.is_empty() {
+                println!("durability:       {}", stats.durability);
+            }
+            if let Some(maintenance) = &stats.maintenance {
+                println!("maintenance:      {}", maintenance.window);
+                if !maintenance.running.is_empty() {
+                    println!(
+                        "  running {} ({}/{} done)",
+                        maintenance.running, maintenance.tasks_done, maintenance.tasks_total
+                    );
+                }
+                for result in &maintenance.last_results {
+                    println!("  last run: {}", result);
+                }
+            }
+            for replica in &stats.replicas {
+                println!(
+                    "replica {} ({}): ordinal {}, lag {}{}",
+                    replica.name,
+                    if replica.region.is_empty() { "-" } else { &replica.region },
+                    replica.latest_ordinal,
+                    replica.lag,
+                    if replica.alarm { "  ALARM" } else { "" }
+                );
+            }
+        }
+        _ => usage(),
+    }
+
+    Ok(())
+}
+
+/// Appends a record, using the current log head as `latest_known`.
+async fn write(client: &mut Client, key: &str, value: Vec<u8>) -> Result<u64, Box<dyn std::error::Error>> {
+    let latest_known = log::latest_ordinal(client).await?;
+    let request = WriteRequest {
+        ordinal: 0,
+        key: key.to_string(),
+        value,
+        latest_known,
+        client_id: format!("logctl-{:x}", std::process::id()),
+        worker_label: String::new(),
+        if_unchanged: false,
+        expires_at: 0,
+    };
+
+    let mut responses = client.write(stream::iter(vec![request])).await?.into_inner();
+    let response = responses
+        .message()
+        .await?
+        .ok_or("server closed the write stream")?;
+    if !response.accepted {
+        return Err(format!("write rejected: {}", response.error).into());
+    }
+    Ok(response.assigned_ordinal)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: logctl [--addr <host:port>] <command> [args...]");
+    eprintln!("Commands:");
+    eprintln!("  get <key>                 - Print the current value of a key");
+    eprintln!("  put <key> <value>         - Append a record");
+    eprintln!("  delete <key>              - Append a tombstone");
+    eprintln!("  history <key> [-n <count>] [--until-ordinal <n>]");
+    eprintln!("                            - Print the records of a key, oldest first");
+    eprintln!("  scan [--prefix <p>]       - Print the latest value of every key");
+    eprintln!("  tail [-n <count>] [--from-ordinal <n>] [--prefix <p>] [--follow]");
+    eprintln!("                            - Print the newest records, optionally streaming new ones");
+    eprintln!("  snapshot [--out <file>]   - Show or download the latest snapshot");
+    eprintln!("  stats                     - Show log counters");
+    eprintln!("  compact                   - Drop overwritten records and tombstones now");
+    eprintln!("history, scan and tail accept --format text|json (one JSON object per line).");
+    eprintln!("The address defaults to $LOGCTL_ADDR or {}.", DEFAULT_ADDR);
+    std::process::exit(2);
+}
+
+fn positional<'a>(args: &'a [String], index: usize, name: &str) -> &'a str {
+    match args.get(index) {
+        Some(value) if !value.starts_with("--") => value,
+        _ => {
+            eprintln!("missing <{}>", name);
+            usage();
+        }
+    }
+}
+
+fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+fn remove_flag(args: &mut Vec<String>, name: &str, takes_value: bool) {
+    if let Some(i) = args.iter().position(|a| a == name) {
+        let end = if takes_value { (i + 2).min(args.len()) } else { i + 1 };
+        args.drain(i..end);
+    }
+}
@@ -0,0 +1,63 @@
+//! Adaptive sleep policy for worker loops.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// Shortest pause between task attempts while most tasks are still open.
+const MIN_POLL: Duration = Duration::from_millis(5);
+/// Longest pause between task attempts when few tasks remain.
+const MAX_POLL: Duration = Duration::from_millis(100);
+/// First pause after a write conflict; doubles on every further conflict.
+const CONFLICT_BASE: Duration = Duration::from_millis(50);
+/// Upper bound for conflict backoff.
+const CONFLICT_MAX: Duration = Duration::from_secs(2);
+
+/// Decides how long a worker sleeps between task attempts.
+///
+/// While many tasks are open the worker polls quickly; as the matrix fills
+/// up the pause grows towards [`MAX_POLL`] because most random picks will
+/// hit computed cells. Consecutive write conflicts switch to exponential
+/// backoff. Every delay is jittered by ±50% so workers started together
+/// drift apart instead of retrying in lockstep.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    conflicts: u32,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a successful write, leaving conflict backoff.
+    pub fn on_success(&mut self) {
+        self.conflicts = 0;
+    }
+
+    /// Records a rejected write.
+    pub fn on_conflict(&mut self) {
+        self.conflicts = self.conflicts.saturating_add(1);
+    }
+
+    /// Returns the jittered pause before the next attempt.
+    pub fn delay(&self, remaining: usize, total: usize) -> Duration {
+        jitter(self.base_delay(remaining, total))
+    }
+
+    fn base_delay(&self, remaining: usize, total: usize) -> Duration {
+        if self.conflicts > 0 {
+            let factor = 1u32 << (self.conflicts - 1).min(16);
+            return CONFLICT_BASE.saturating_mul(factor).min(CONFLICT_MAX);
+        }
+
+        if total == 0 {
+            return MAX_POLL;
+        }
+        let done = 1.0 - remaining as f64 / total as f64;
+        MIN_POLL + (MAX_POLL - MIN_POLL).mul_f64(done.clamp(0.0, 1.0))
+    }
+}
+
+fn jitter(delay: Duration) -> Duration {
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..1.5))
+}
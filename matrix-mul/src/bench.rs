@@ -0,0 +1,93 @@
+//! Scaling benchmark against an embedded log-server.
+//!
+//! Only compiled with the `bench` feature (on by default). Each run starts a
+//! fresh in-memory server, loads the same seeded matrices and multiplies
+//! them with a given number of worker tasks, so runs differ only in the
+//! amount of coordination between workers.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log_server_test::TestServer;
+use tokio::task::JoinHandle;
+
+use crate::{generate, Error, MatrixMul, WorkStats};
+
+const VALUE_RANGE: std::ops::Range<f64> = 0.0..10.0;
+
+/// Result of multiplying once with a fixed number of workers.
+#[derive(Debug, Clone)]
+pub struct BenchRun {
+    pub workers: usize,
+    pub elapsed: Duration,
+    /// Sum of [`WorkStats`] over all workers.
+    pub stats: WorkStats,
+}
+
+/// Runs the multiplication with 1, 2, 4, ... up to `max_workers` workers.
+pub async fn run_scaling(
+    m: usize,
+    n: usize,
+    p: usize,
+    max_workers: usize,
+    seed: u64,
+) -> Result<Vec<BenchRun>, Error> {
+    let mut runs = Vec::new();
+    let mut workers = 1;
+    while workers <= max_workers.max(1) {
+        runs.push(run_once(m, n, p, workers, seed).await?);
+        workers *= 2;
+    }
+    Ok(runs)
+}
+
+/// Runs one multiplication with `workers` concurrent worker tasks.
+pub async fn run_once(
+    m: usize,
+    n: usize,
+    p: usize,
+    workers: usize,
+    seed: u64,
+) -> Result<BenchRun, Error> {
+    let server = TestServer::spawn().await;
+    let addr = server.addr().to_string();
+
+    let mut loader = MatrixMul::connect(addr.clone()).await?;
+    let (a, b) = generate::random_pair(m, n, p, VALUE_RANGE, seed);
+    loader.load_matrices(a, b).await?;
+    loader.start().await?;
+
+    let mut handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let mut mm = MatrixMul::connect(addr.clone()).await?;
+        mm.set_size(m, n, p);
+        handles.push(mm);
+    }
+
+    let started = Instant::now();
+    let tasks: Vec<JoinHandle<Result<WorkStats, Error>>> = handles
+        .into_iter()
+        .map(|mm| {
+            let mm = Arc::new(mm);
+            tokio::spawn(async move { mm.work().await })
+        })
+        .collect();
+
+    let mut stats = WorkStats::default();
+    for task in tasks {
+        let worker = task
+            .await
+            .map_err(|e| Error::Bench(format!("worker panicked: {}", e)))??;
+        stats.tasks_computed += worker.tasks_computed;
+        stats.conflicts += worker.conflicts;
+        stats.failures += worker.failures;
+    }
+    let elapsed = started.elapsed();
+
+    server.shutdown().await;
+    Ok(BenchRun {
+        workers,
+        elapsed,
+        stats,
+    })
+}
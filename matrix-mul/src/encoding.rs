@@ -0,0 +1,210 @@
+//! How matrix elements are written to the map.
+//!
+//! Every stored value is a block of rows: a matrix row is a block of one
+//! row, a result element one of one element. The [`Job`](crate::Job)
+//! descriptor names the encoding, so workers read a job the way it was
+//! loaded.
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::Error;
+use crate::matrix_mul::parse_row;
+
+/// Version byte of the binary layout.
+const BINARY_V1: u8 = 1;
+/// Version byte and column count.
+const BINARY_HEADER: usize = 5;
+
+/// Element encoding of a job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Rows as comma-separated decimals, joined by `;`, see
+    /// [`parse_row`](crate::parse_row). Readable with any tool, and what
+    /// logs from before the binary encoding hold.
+    Decimal,
+    /// Base64 of a version byte (1), the column count as a little-endian
+    /// u32 and the elements as little-endian f64s, row-major. Exact for
+    /// every f64 and about half the size of decimal text for random
+    /// values.
+    #[default]
+    Binary,
+}
+
+impl Encoding {
+    /// The name stored in the job descriptor.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Decimal => "decimal",
+            Encoding::Binary => "binary",
+        }
+    }
+
+    pub fn encode_block(&self, block: &[Vec<f64>]) -> String {
+        match self {
+            Encoding::Decimal => block
+                .iter()
+                .map(|row| row.iter().map(f64::to_string).collect::<Vec<_>>().join(","))
+                .collect::<Vec<_>>()
+                .join(";"),
+            Encoding::Binary => {
+                let cols = block.first().map_or(0, Vec::len);
+                let mut bytes = Vec::with_capacity(BINARY_HEADER + block.len() * cols * 8);
+                bytes.push(BINARY_V1);
+                bytes.extend_from_slice(&(cols as u32).to_le_bytes());
+                for value in block.iter().flatten() {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                STANDARD.encode(bytes)
+            }
+        }
+    }
+
+    pub fn decode_block(&self, value: &str) -> Result<Vec<Vec<f64>>, Error> {
+        match self {
+            Encoding::Decimal => value.split(';').map(parse_row).collect(),
+            Encoding::Binary => {
+                let bytes = STANDARD
+                    .decode(value)
+                    .map_err(|_| Error::MalformedBlock("not base64"))?;
+                if bytes.len() < BINARY_HEADER {
+                    return Err(Error::MalformedBlock("truncated header"));
+                }
+                if bytes[0] != BINARY_V1 {
+                    return Err(Error::MalformedBlock("unknown version"));
+                }
+                let cols = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+                let data = &bytes[BINARY_HEADER..];
+                if cols == 0 {
+                    return if data.is_empty() {
+                        Ok(Vec::new())
+                    } else {
+                        Err(Error::MalformedBlock("elements without columns"))
+                    };
+                }
+                if data.len() % (cols * 8) != 0 {
+                    return Err(Error::MalformedBlock("partial row"));
+                }
+                let elements = data.chunks_exact(8).map(|chunk| {
+                    f64::from_le_bytes(chunk.try_into().expect("chunks_exact yields 8 bytes"))
+                });
+                let elements: Vec<f64> = elements.collect();
+                Ok(elements.chunks(cols).map(<[f64]>::to_vec).collect())
+            }
+        }
+    }
+
+    pub(crate) fn encode_row(&self, row: &[f64]) -> String {
+        self.encode_block(&[row.to_vec()])
+    }
+
+    pub(crate) fn decode_row(&self, value: &str) -> Result<Vec<f64>, Error> {
+        let mut block = self.decode_block(value)?;
+        match block.len() {
+            1 => Ok(block.remove(0)),
+            _ => Err(Error::MalformedBlock("expected one row")),
+        }
+    }
+
+    pub(crate) fn encode_element(&self, value: f64) -> String {
+        self.encode_block(&[vec![value]])
+    }
+
+    pub(crate) fn decode_element(&self, value: &str) -> Result<f64, Error> {
+        match self.decode_row(value)?.as_slice() {
+            [element] => Ok(*element),
+            _ => Err(Error::MalformedBlock("expected one element")),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "decimal" => Ok(Encoding::Decimal),
+            "binary" => Ok(Encoding::Binary),
+            other => Err(format!("unknown encoding '{}', expected decimal or binary", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary block made of `header` and `elements`, as base64.
+    fn binary(header: &[u8], elements: &[f64]) -> String {
+        let mut bytes = header.to_vec();
+        for value in elements {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        STANDARD.encode(bytes)
+    }
+
+    fn malformed(value: &str) -> &'static str {
+        match Encoding::Binary.decode_block(value) {
+            Err(Error::MalformedBlock(reason)) => reason,
+            other => panic!("expected a malformed block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_binary_round_trips_every_bit_pattern() {
+        let subnormal = f64::MIN_POSITIVE / 2.0;
+        assert!(subnormal.is_subnormal());
+        let block = vec![
+            vec![f64::NAN, -0.0, subnormal],
+            vec![f64::INFINITY, f64::NEG_INFINITY, 0.1],
+        ];
+        let decoded = Encoding::Binary.decode_block(&Encoding::Binary.encode_block(&block)).unwrap();
+        let bits = |block: &[Vec<f64>]| block.iter().flatten().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&decoded), bits(&block));
+        assert_eq!(decoded[1].len(), 3);
+
+        let nan = Encoding::Binary.decode_element(&Encoding::Binary.encode_element(f64::NAN)).unwrap();
+        assert!(nan.is_nan());
+        let zero = Encoding::Binary.decode_element(&Encoding::Binary.encode_element(-0.0)).unwrap();
+        assert!(zero == 0.0 && zero.is_sign_negative());
+        let row = Encoding::Binary.decode_row(&Encoding::Binary.encode_row(&[subnormal])).unwrap();
+        assert_eq!(row[0].to_bits(), subnormal.to_bits());
+    }
+
+    #[test]
+    fn test_binary_empty_block() {
+        assert_eq!(Encoding::Binary.decode_block(&Encoding::Binary.encode_block(&[])).unwrap(), Vec::<Vec<f64>>::new());
+    }
+
+    #[test]
+    fn test_malformed_binary_blocks_are_rejected() {
+        assert_eq!(malformed("not base64!"), "not base64");
+        assert_eq!(malformed(&STANDARD.encode([BINARY_V1, 1, 0])), "truncated header");
+        assert_eq!(malformed(&binary(&[2, 1, 0, 0, 0], &[1.0])), "unknown version");
+        assert_eq!(malformed(&binary(&[BINARY_V1, 2, 0, 0, 0], &[1.0, 2.0, 3.0])), "partial row");
+        let mut torn = STANDARD.decode(binary(&[BINARY_V1, 1, 0, 0, 0], &[1.0])).unwrap();
+        torn.pop();
+        assert_eq!(malformed(&STANDARD.encode(torn)), "partial row");
+        assert_eq!(malformed(&binary(&[BINARY_V1, 0, 0, 0, 0], &[1.0])), "elements without columns");
+    }
+
+    #[test]
+    fn test_decimal_reads_text_from_before_the_binary_encoding() {
+        assert_eq!(
+            Encoding::Decimal.decode_block("1,2.5,-3;4,5,6").unwrap(),
+            vec![vec![1.0, 2.5, -3.0], vec![4.0, 5.0, 6.0]]
+        );
+        assert_eq!(Encoding::Decimal.decode_element("42").unwrap(), 42.0);
+        let block = vec![vec![0.1, -0.0], vec![1e300, 5e-324]];
+        assert_eq!(Encoding::Decimal.decode_block(&Encoding::Decimal.encode_block(&block)).unwrap(), block);
+    }
+}
@@ -16,6 +16,38 @@ pub enum Error {
     #[error("missing matrix data at key {0}")]
     MissingMatrixData(i64),
 
+    #[error("malformed binary block: {0}")]
+    MalformedBlock(&'static str),
+
+    #[error("block ({0}, {1}) does not fit the matrix")]
+    TileShape(usize, usize),
+
+    #[error("no job loaded: run `load` first, or set the sizes")]
+    NoJob,
+
+    #[error("undecodable job descriptor: {0}")]
+    InvalidJob(String),
+
+    #[error("job has unsupported element encoding {0:?}")]
+    UnsupportedEncoding(String),
+
+    #[error("stored {stored} does not match expected {local}")]
+    JobMismatch { stored: Box<crate::Job>, local: Box<crate::Job> },
+
     #[error("timeout waiting for completion")]
     Timeout,
+
+    #[error("tasks failed too often and were abandoned: {0:?}")]
+    PoisonedTasks(Vec<(usize, usize)>),
+
+    #[error("worker registry is full")]
+    RegistryFull,
+
+    #[cfg(feature = "bench")]
+    #[error("benchmark error: {0}")]
+    Bench(String),
+
+    #[cfg(feature = "reference-check")]
+    #[error("result does not match reference: {0}")]
+    ReferenceMismatch(crate::reference::MismatchReport),
 }
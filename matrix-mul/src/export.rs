@@ -0,0 +1,57 @@
+//! Serialization of result matrices for consumption by other tools.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Output format for exported matrices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One row per line, elements separated by commas.
+    Csv,
+    /// A JSON array of row arrays.
+    Json,
+}
+
+impl Format {
+    /// Guesses the format from a file extension, defaulting to CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Csv,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            other => Err(format!("unknown format '{}', expected csv or json", other)),
+        }
+    }
+}
+
+/// Writes `matrix` to `out` in the given format.
+pub fn write_matrix<W: Write>(out: &mut W, matrix: &[Vec<f64>], format: Format) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            for row in matrix {
+                let line = row
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                writeln!(out, "{}", line)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer(&mut *out, matrix)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
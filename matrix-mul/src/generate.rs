@@ -0,0 +1,51 @@
+//! Test matrix generators used by the `load` CLI mode.
+
+use std::ops::Range;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Builds a `rows`×`cols` matrix filled with 1, 2, 3, ... in row-major order.
+pub fn sequential_matrix(rows: usize, cols: usize) -> Vec<Vec<f64>> {
+    let mut matrix = vec![vec![0.0; cols]; rows];
+    let mut value = 1.0;
+    for row in &mut matrix {
+        for elem in row {
+            *elem = value;
+            value += 1.0;
+        }
+    }
+    matrix
+}
+
+/// Builds a `rows`×`cols` matrix of uniformly distributed values in `range`.
+///
+/// The same `rng` state always yields the same matrix, so generating A and
+/// B from one seeded [`StdRng`] makes the whole job reproducible.
+pub fn random_matrix(rows: usize, cols: usize, range: Range<f64>, rng: &mut StdRng) -> Vec<Vec<f64>> {
+    (0..rows)
+        .map(|_| (0..cols).map(|_| rng.gen_range(range.clone())).collect())
+        .collect()
+}
+
+/// Generates a reproducible pair of random matrices A (m×n) and B (n×p).
+pub fn random_pair(
+    m: usize,
+    n: usize,
+    p: usize,
+    range: Range<f64>,
+    seed: u64,
+) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let a = random_matrix(m, n, range.clone(), &mut rng);
+    let b = random_matrix(n, p, range, &mut rng);
+    (a, b)
+}
+
+/// Parses a `lo..hi` range as accepted by `--range`.
+pub fn parse_range(s: &str) -> Option<Range<f64>> {
+    let (lo, hi) = s.split_once("..")?;
+    let lo: f64 = lo.trim().parse().ok()?;
+    let hi: f64 = hi.trim().parse().ok()?;
+    (lo < hi).then_some(lo..hi)
+}
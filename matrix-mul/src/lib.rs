@@ -10,6 +10,34 @@
 //! - **Matrix B rows**: keys -(m+1), -(m+2), ... (row j at key -(m+j+1))
 //! - **Start signal**: key 0 (write "start" to begin computation)
 //! - **Results**: keys 1, 2, 3, ... (element C[i][j] at key i*p+j+1)
+//! - **Failure counters**: keys 2^49 + i*p+j, attempts that failed for C[i][j]
+//! - **Worker registry**: keys 2^48 .. 2^48+1023, one `hostname,pid,start_ms`
+//!   record per live worker
+//! - **Job descriptor**: key 2^50, JSON with the job `id`, the sizes `m`,
+//!   `n`, `p`, `tile` and the element `encoding`; see [`Job`]
+//! - **Task claims**: `claim:<i>:<j>` in the `lock` namespace while a worker
+//!   computes C[i][j], see [`Strategy::Claimed`]
+//!
+//! These keys are in the `map` namespace for a handle from
+//! [`MatrixMul::connect`]. Jobs from [`MatrixMul::create_job`] use the same
+//! layout in a namespace `job-<id>` each, and are registered in the
+//! [`JOBS_NAMESPACE`] namespace as `<id>` → `open` or `done`; their task
+//! claims are named `claim:<id>:<i>:<j>`.
+//!
+//! [`MatrixMul::load_matrices_tiled`] stores A and B as b×b blocks at the
+//! negative keys instead, and each task, result key and failure counter
+//! then stands for a b×b block of C rather than one element.
+//!
+//! Rows, blocks and result elements are written in the job's [`Encoding`]:
+//! base64 of little-endian f64s behind a version header by default, or
+//! comma-separated decimals.
+//!
+//! # Cargo Features
+//!
+//! - `bench` (default): the [`bench`] module, a scaling benchmark that runs
+//!   workers against an embedded log-server.
+//! - `reference-check`: `get_result` recomputes small products locally and
+//!   returns `Error::ReferenceMismatch` if the distributed result differs.
 //!
 //! # Example
 //!
@@ -25,17 +53,35 @@
 //!
 //!     mm.load_matrices(a, b).await?;
 //!     mm.start().await?;
-//!     mm.wait_for_completion(2, 2).await?;
+//!     mm.wait_for_completion().await?;
 //!
-//!     let result = mm.get_result(2, 2).await?;
+//!     let result = mm.get_result().await?;
 //!     assert_eq!(result, vec![vec![19.0, 22.0], vec![43.0, 50.0]]);
 //!
 //!     Ok(())
 //! }
 //! ```
 
+mod backoff;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod encoding;
 mod error;
+pub mod export;
+pub mod generate;
 mod matrix_mul;
+#[cfg(feature = "reference-check")]
+pub mod reference;
+mod tile;
+mod worker;
 
+pub use encoding::Encoding;
 pub use error::Error;
-pub use matrix_mul::MatrixMul;
+pub use matrix_mul::{
+    DEFAULT_CLAIM_LEASE, DEFAULT_MAX_TASK_ATTEMPTS, Dimensions, Job, JobId, JOBS_NAMESPACE, MatrixMul, Progress, Strategy, WorkStats,
+};
+pub use worker::WorkerInfo;
+
+// Exposed for the fuzz targets in `fuzz/`, not part of the supported API.
+#[doc(hidden)]
+pub use matrix_mul::parse_row;
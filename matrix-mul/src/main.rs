@@ -1,4 +1,17 @@
 use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use matrix_mul::export::{self, Format};
+use matrix_mul::{generate, MatrixMul};
+
+/// Matrices with more elements than this are not echoed by `load`.
+const PRINT_LIMIT: usize = 64;
+const DEFAULT_RANGE: Range<f64> = 0.0..10.0;
+const SYNC_GRACE: Duration = Duration::from_millis(500);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -10,13 +23,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap_or_else(|| "localhost:50051".to_string());
     let mode = args.get(2).cloned().unwrap_or_else(|| "client".to_string());
 
-    let mut mm = matrix_mul::MatrixMul::connect(addr.clone()).await?;
-
-    let m: usize = args.get(3).unwrap_or(&"2".to_string()).parse()?;
-    let n: usize = args.get(4).unwrap_or(&"2".to_string()).parse()?;
-    let p: usize = args.get(5).unwrap_or(&"2".to_string()).parse()?;
+    #[cfg(feature = "bench")]
+    if mode == "bench" {
+        return run_bench(&args).await;
+    }
 
-    mm.set_size(m, n, p);
+    let mut mm = match flag_value(&args, "--job") {
+        Some(id) => MatrixMul::connect_job(addr.clone(), &id.parse()?).await?,
+        None => MatrixMul::connect(addr.clone()).await?,
+    };
 
     match mode.as_str() {
         "load" => {
@@ -24,37 +39,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let n: usize = args.get(4).unwrap_or(&"2".to_string()).parse()?;
             let p: usize = args.get(5).unwrap_or(&"2".to_string()).parse()?;
 
-            let mut a = vec![vec![0.0; n]; m];
-            let mut b = vec![vec![0.0; p]; n];
+            let (a, b) = if has_flag(&args, "--random") {
+                let seed = match flag_value(&args, "--seed") {
+                    Some(s) => s.parse()?,
+                    None => rand::random(),
+                };
+                let range = match flag_value(&args, "--range") {
+                    Some(s) => generate::parse_range(s)
+                        .ok_or_else(|| format!("invalid --range '{}', expected lo..hi", s))?,
+                    None => DEFAULT_RANGE,
+                };
+                println!(
+                    "Generating random matrices (seed {}, range {}..{})",
+                    seed, range.start, range.end
+                );
+                generate::random_pair(m, n, p, range, seed)
+            } else {
+                (
+                    generate::sequential_matrix(m, n),
+                    generate::sequential_matrix(n, p),
+                )
+            };
 
-            let mut val_a = 1.0;
-            for row in &mut a {
-                for elem in row {
-                    *elem = val_a;
-                    val_a += 1.0;
+            println!("Loading {}x{} matrix A and {}x{} matrix B", m, n, n, p);
+            if m * n + n * p <= PRINT_LIMIT {
+                println!("Matrix A:");
+                for row in &a {
+                    println!("  {:?}", row);
                 }
-            }
-
-            let mut val_b = 1.0;
-            for row in &mut b {
-                for elem in row {
-                    *elem = val_b;
-                    val_b += 1.0;
+                println!("Matrix B:");
+                for row in &b {
+                    println!("  {:?}", row);
                 }
             }
-
-            let mut mm = mm;
-            println!("Loading {}x{} matrix A and {}x{} matrix B", m, n, n, p);
-            println!("Matrix A:");
-            for row in &a {
-                println!("  {:?}", row);
+            if let Some(encoding) = flag_value(&args, "--encoding") {
+                mm.set_encoding(encoding.parse()?);
             }
-            println!("Matrix B:");
-            for row in &b {
-                println!("  {:?}", row);
+            if has_flag(&args, "--new-job") {
+                mm = mm.create_job().await?;
+            }
+            match flag_value(&args, "--tile") {
+                Some(tile) => mm.load_matrices_tiled(a, b, tile.parse()?).await?,
+                None => mm.load_matrices(a, b).await?,
+            }
+            match mm.job_id() {
+                Some(id) => println!("Matrices loaded as job {}. Run 'start --job {}' to begin computation.", id, id),
+                None => println!("Matrices loaded. Run 'start' to begin computation."),
             }
-            mm.load_matrices(a, b).await?;
-            println!("Matrices loaded. Run 'start' to begin computation.");
         }
         "start" => {
             println!("Starting computation...");
@@ -65,21 +96,79 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let worker_id = std::process::id();
             println!("Starting worker (PID: {})...", worker_id);
             println!("Connecting to {}...", addr);
-            mm.work().await?;
+            let strategy = match flag_value(&args, "--strategy") {
+                Some(s) => s.parse::<matrix_mul::Strategy>()?,
+                None => matrix_mul::Strategy::default(),
+            };
+            let work = async {
+                if has_flag(&args, "--jobs") {
+                    mm.work_on_open_jobs(strategy).await
+                } else {
+                    mm.work_with_strategy(strategy).await
+                }
+            };
+            tokio::select! {
+                result = work => {
+                    let stats = result?;
+                    println!(
+                        "Computed {} tasks ({} conflicts, {} failures)",
+                        stats.tasks_computed, stats.conflicts, stats.failures
+                    );
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    println!("Interrupted, unregistering worker...");
+                    mm.unregister_worker().await?;
+                    return Ok(());
+                }
+            }
             println!("Worker (PID: {}) done!", worker_id);
         }
+        "workers" => {
+            // The map syncs in the background; give it a moment to catch up.
+            tokio::time::sleep(SYNC_GRACE).await;
+            let workers = mm.list_workers().await?;
+            println!("{} registered worker(s)", workers.len());
+            for worker in workers {
+                println!("  {}", worker);
+            }
+        }
+        "jobs" => {
+            tokio::time::sleep(SYNC_GRACE).await;
+            let jobs = mm.open_jobs();
+            println!("{} open job(s)", jobs.len());
+            for id in jobs {
+                println!("  {}", id);
+            }
+        }
         "result" => {
-            let m: usize = args.get(3).unwrap_or(&"2".to_string()).parse()?;
-            let p: usize = args.get(4).unwrap_or(&"2".to_string()).parse()?;
-
             println!("Waiting for completion...");
-            mm.wait_for_completion(m, p).await?;
+            mm.wait_for_completion().await?;
             println!("Retrieving result...");
-            let result = mm.get_result(m, p).await?;
+            let result = mm.get_result().await?;
+            let (m, p) = (result.len(), result.first().map_or(0, Vec::len));
 
-            println!("Result ({}x{}):", m, p);
-            for row in result {
-                println!("  {:?}", row);
+            let out = flag_value(&args, "--out");
+            let format = match flag_value(&args, "--format") {
+                Some(f) => Some(f.parse::<Format>()?),
+                None => out.map(|path| Format::from_path(Path::new(path))),
+            };
+
+            match (out, format) {
+                (Some(path), Some(format)) => {
+                    let mut file = BufWriter::new(File::create(path)?);
+                    export::write_matrix(&mut file, &result, format)?;
+                    file.flush()?;
+                    println!("Result ({}x{}) written to {}", m, p, path);
+                }
+                (None, Some(format)) => {
+                    export::write_matrix(&mut std::io::stdout().lock(), &result, format)?;
+                }
+                _ => {
+                    println!("Result ({}x{}):", m, p);
+                    for row in result {
+                        println!("  {:?}", row);
+                    }
+                }
             }
         }
         _ => {
@@ -87,12 +176,62 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             eprintln!("Usage: {} <addr> <mode> [args...]", args[0]);
             eprintln!("Modes:");
             eprintln!("  load <m> <n> <p>  - Load m×n and n×p matrices");
+            eprintln!("      [--random] [--seed <s>] [--range <lo..hi>] [--tile <b>] [--new-job]");
+            eprintln!("      [--encoding binary|decimal]");
             eprintln!("  start              - Start computation");
             eprintln!("  client             - Run worker (default)");
-            eprintln!("  result <m> <p>     - Get result matrix");
+            eprintln!("      [--strategy random|sequential|claimed] [--jobs]");
+            eprintln!("  workers            - List registered workers");
+            eprintln!("  jobs               - List open jobs");
+            eprintln!("  bench <m> <n> <p>  - Scaling benchmark on an embedded server");
+            eprintln!("      [--max-workers <n>] [--seed <s>]");
+            eprintln!("  result             - Get result matrix");
+            eprintln!("      [--out <file>] [--format csv|json]");
+            eprintln!("client and result take the sizes from the job stored by load.");
+            eprintln!("--job <id> works with a job from 'load --new-job'; 'client --jobs'");
+            eprintln!("works on every open job.");
             std::process::exit(1);
         }
     }
 
     Ok(())
 }
+
+/// Runs `bench` mode; `addr` is ignored because the server is embedded.
+#[cfg(feature = "bench")]
+async fn run_bench(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let m: usize = args.get(3).map_or(Ok(8), |s| s.parse())?;
+    let n: usize = args.get(4).map_or(Ok(8), |s| s.parse())?;
+    let p: usize = args.get(5).map_or(Ok(8), |s| s.parse())?;
+    let max_workers: usize = flag_value(args, "--max-workers").map_or(Ok(8), str::parse)?;
+    let seed: u64 = flag_value(args, "--seed").map_or(Ok(42), str::parse)?;
+
+    println!(
+        "Benchmarking {}x{} × {}x{} with up to {} workers (seed {})",
+        m, n, n, p, max_workers, seed
+    );
+    let runs = matrix_mul::bench::run_scaling(m, n, p, max_workers, seed).await?;
+
+    println!("{:>8} {:>12} {:>10} {:>10}", "workers", "wall_ms", "conflicts", "failures");
+    for run in runs {
+        println!(
+            "{:>8} {:>12.1} {:>10} {:>10}",
+            run.workers,
+            run.elapsed.as_secs_f64() * 1000.0,
+            run.stats.conflicts,
+            run.stats.failures
+        );
+    }
+    Ok(())
+}
+
+fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
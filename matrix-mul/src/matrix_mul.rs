@@ -1,40 +1,443 @@
 //! Distributed matrix multiplication implementation.
 
+use futures_util::StreamExt;
+use log_map::lock::LockGuard;
+use log_server_types::features;
 use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::backoff::Backoff;
+use crate::encoding::Encoding;
+use crate::tile;
+use crate::worker::{self, WorkerInfo, MAX_WORKERS};
 use crate::Error;
 
 const START_KEY: i64 = 0;
-const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Upper bound on waiting for a change before re-checking progress anyway.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// First key of the per-task failure counters; task `idx` lives at base + idx.
+const FAILURE_KEY_BASE: i64 = 1 << 49;
+/// Where `load_matrices` leaves the [`Job`] descriptor.
+const JOB_KEY: i64 = 1 << 50;
+/// Namespace of the job registry: job id to [`OPEN`] or [`DONE`].
+pub const JOBS_NAMESPACE: &str = "matrix-jobs";
+/// A registered job whose matrices are loaded and whose tasks are not
+/// all settled yet.
+const OPEN: &str = "open";
+const DONE: &str = "done";
+/// Default number of failed attempts after which a task is poisoned.
+pub const DEFAULT_MAX_TASK_ATTEMPTS: u32 = 3;
+/// How long a claimed task stays reserved for a worker that stops
+/// renewing its claim, e.g. because it crashed.
+pub const DEFAULT_CLAIM_LEASE: Duration = Duration::from_secs(10);
+
+/// How [`MatrixMul::work_with_strategy`] picks the next task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Random open cells. Workers often pick the same cell and all but
+    /// one of them compute it for nothing.
+    Random,
+    /// Open cells in row-major order. Cheap for a single worker; several
+    /// workers walk the same cells in lockstep.
+    Sequential,
+    /// Open cells in row-major order from a random start, each claimed
+    /// first with a [`log_map::lock`] named `claim:<i>:<j>` (with the job
+    /// id before `<i>` for a job of its own) held under
+    /// `lease`, so no two workers compute the same cell. Needs the
+    /// `conditional-writes` and `ttl` server features and falls back to
+    /// `Random` without them.
+    Claimed { lease: Duration },
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Strategy::Claimed {
+            lease: DEFAULT_CLAIM_LEASE,
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    /// `random`, `sequential` or `claimed`, with the default lease.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(Strategy::Random),
+            "sequential" => Ok(Strategy::Sequential),
+            "claimed" => Ok(Strategy::default()),
+            other => Err(format!(
+                "unknown strategy '{}', expected random, sequential or claimed",
+                other
+            )),
+        }
+    }
+}
+
+/// Sizes of the loaded matrices: A is m×n, B is n×p.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dimensions {
+    pub m: usize,
+    pub n: usize,
+    pub p: usize,
+    /// Block size of the tiled layout, 0 for one task per element.
+    pub tile: usize,
+}
+
+impl Dimensions {
+    /// Rows and columns of the task grid: one task per element of the
+    /// m×p result, or per block when tiled.
+    fn task_grid(&self) -> (usize, usize) {
+        match self.tile {
+            0 => (self.m, self.p),
+            tile => (tile::count(self.m, tile), tile::count(self.p, tile)),
+        }
+    }
+
+    fn a_tile_key(&self, ti: usize, tk: usize) -> i64 {
+        let kt = tile::count(self.n, self.tile);
+        -((ti * kt + tk) as i64 + 1)
+    }
+
+    fn b_tile_key(&self, tk: usize, tj: usize) -> i64 {
+        let (mt, kt, pt) = (
+            tile::count(self.m, self.tile),
+            tile::count(self.n, self.tile),
+            tile::count(self.p, self.tile),
+        );
+        -((mt * kt + tk * pt + tj) as i64 + 1)
+    }
+}
+
+/// Names a job created with [`MatrixMul::create_job`]. Its keys live in
+/// the namespace `job-<id>`, apart from every other job's.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct JobId(String);
+
+impl JobId {
+    fn generate() -> Self {
+        JobId(format!("{:016x}", rand::random::<u64>()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn namespace(&self) -> String {
+        format!("job-{}", self.0)
+    }
+
+    /// Whether this names a job; the default id stands for sizes set
+    /// locally with [`MatrixMul::set_size`].
+    fn is_named(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for JobId {
+    type Err = String;
+
+    /// Letters, digits, `-` and `_`, as printed by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("invalid job id '{}'", s));
+        }
+        Ok(JobId(s.to_string()))
+    }
+}
+
+/// Describes the loaded multiplication. `load_matrices` stores it in the
+/// map, so workers and readers configure themselves from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The job's [`JobId`], or a fresh one per `load_matrices` on a
+    /// handle from [`MatrixMul::connect`], to tell runs apart.
+    pub id: JobId,
+    pub dimensions: Dimensions,
+    /// How elements are written.
+    pub encoding: Encoding,
+}
+
+impl Job {
+    fn new(id: JobId, dimensions: Dimensions, encoding: Encoding) -> Self {
+        Job {
+            id,
+            dimensions,
+            encoding,
+        }
+    }
+
+    fn encode(&self) -> String {
+        serde_json::json!({
+            "id": self.id.as_str(),
+            "m": self.dimensions.m,
+            "n": self.dimensions.n,
+            "p": self.dimensions.p,
+            "tile": self.dimensions.tile,
+            "encoding": self.encoding.as_str(),
+        })
+        .to_string()
+    }
+
+    fn decode(value: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidJob(value.to_string());
+        let json: serde_json::Value = serde_json::from_str(value).map_err(|_| invalid())?;
+        let size = |name: &str| json[name].as_u64().map(|v| v as usize).ok_or_else(invalid);
+        let encoding = json["encoding"].as_str().ok_or_else(invalid)?;
+        Ok(Job {
+            id: JobId(json["id"].as_str().ok_or_else(invalid)?.to_string()),
+            dimensions: Dimensions {
+                m: size("m")?,
+                n: size("n")?,
+                p: size("p")?,
+                tile: size("tile")?,
+            },
+            encoding: encoding
+                .parse()
+                .map_err(|_| Error::UnsupportedEncoding(encoding.to_string()))?,
+        })
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Dimensions { m, n, p, tile } = self.dimensions;
+        if !self.id.is_named() {
+            write!(f, "{}x{} × {}x{}", m, n, n, p)?;
+        } else {
+            write!(f, "job {} ({}x{} × {}x{}", self.id, m, n, n, p)?;
+        }
+        if tile > 0 {
+            write!(f, " in {}x{} blocks", tile, tile)?;
+        }
+        if self.id.is_named() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Counters reported by [`MatrixMul::work`] when the worker finishes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkStats {
+    /// Result elements written by this worker.
+    pub tasks_computed: usize,
+    /// Writes that gave up after exhausting log-map conflict retries.
+    pub conflicts: usize,
+    /// Attempts that failed for any other reason.
+    pub failures: usize,
+}
+
+/// Snapshot of how far a multiplication has progressed, in tasks: result
+/// elements, or result tiles in the tiled layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub total: usize,
+    pub computed: usize,
+    /// Tasks `(i, j)` that failed too often and will not be retried.
+    pub poisoned: Vec<(usize, usize)>,
+}
+
+impl Progress {
+    /// Returns `true` once every task is either computed or poisoned.
+    pub fn is_settled(&self) -> bool {
+        self.total > 0 && self.computed + self.poisoned.len() == self.total
+    }
+}
 
 /// Distributed matrix multiplication coordinator.
 ///
 /// `MatrixMul` loads matrices into the log-map and coordinates
-/// distributed computation where clients pick random tasks.
+/// distributed computation where clients claim tasks, see [`Strategy`].
+///
+/// A handle from [`connect`](Self::connect) uses the fixed key layout of
+/// the `map` namespace, so only one multiplication runs there at a time.
+/// [`create_job`](Self::create_job) and [`connect_job`](Self::connect_job)
+/// give handles for jobs in namespaces of their own, which run side by
+/// side and are listed in the [`JOBS_NAMESPACE`] registry.
 pub struct MatrixMul {
+    server: Server,
+    /// Set for handles of a namespaced job.
+    job_id: Option<JobId>,
     map: log_map::LogMap,
-    m: usize,
-    n: usize,
-    p: usize,
+    claims: log_map::lock::Mutex,
+    jobs: log_map::TypedLogMap<String, String, log_map::Plain>,
+    /// The job loaded or sized here, checked against the stored one.
+    job: Option<Job>,
+    /// For the next `load_matrices`.
+    encoding: Encoding,
+    max_task_attempts: u32,
+    registration: Mutex<Option<WorkerInfo>>,
+}
+
+/// Where a handle's maps connect to.
+#[derive(Clone)]
+enum Server {
+    Addr(log_map::ServerList),
+    #[cfg(feature = "embedded")]
+    Embedded(log_map::embedded::EmbeddedServer),
+}
+
+impl Server {
+    async fn connect(&self) -> Result<log_map::LogMap, Error> {
+        let map = match self {
+            Server::Addr(addr) => log_map::LogMap::connect(addr.clone()).await?,
+            #[cfg(feature = "embedded")]
+            Server::Embedded(server) => server.connect().await?,
+        };
+        Ok(map)
+    }
+
+    async fn connect_namespace<K, V, C>(&self, namespace: &str) -> Result<log_map::TypedLogMap<K, V, C>, Error>
+    where
+        K: serde::Serialize + serde::de::DeserializeOwned + Ord + Clone + Send + Sync + 'static,
+        V: serde::Serialize + serde::de::DeserializeOwned + Clone + Send + Sync + 'static,
+        C: log_map::Codec,
+    {
+        let map = match self {
+            Server::Addr(addr) => log_map::TypedLogMap::connect_namespace(addr.clone(), namespace).await?,
+            #[cfg(feature = "embedded")]
+            Server::Embedded(server) => server.connect_namespace(namespace).await?,
+        };
+        Ok(map)
+    }
 }
 
 impl MatrixMul {
     /// Connects to a log-server and creates a new `MatrixMul` instance.
-    pub async fn connect(addr: impl Into<log_map::ServerAddr>) -> Result<Self, Error> {
-        let map = log_map::LogMap::connect(addr).await?;
+    pub async fn connect(addr: impl Into<log_map::ServerList>) -> Result<Self, Error> {
+        Self::open(Server::Addr(addr.into()), None).await
+    }
+
+    /// Connects for the job `job`, e.g. one another process created.
+    pub async fn connect_job(addr: impl Into<log_map::ServerList>, job: &JobId) -> Result<Self, Error> {
+        Self::open(Server::Addr(addr.into()), Some(job.clone())).await
+    }
+
+    /// Connects to a log-server running in this process, for tests. Jobs
+    /// created or worked on through the handle use the same server.
+    #[cfg(feature = "embedded")]
+    pub async fn connect_embedded(server: &log_map::embedded::EmbeddedServer) -> Result<Self, Error> {
+        Self::open(Server::Embedded(server.clone()), None).await
+    }
+
+    async fn open(server: Server, job_id: Option<JobId>) -> Result<Self, Error> {
+        let map = match &job_id {
+            Some(id) => server.connect_namespace(&id.namespace()).await?,
+            None => server.connect().await?,
+        };
+        let claims = log_map::lock::Mutex::new(server.connect_namespace(log_map::lock::NAMESPACE).await?);
+        let jobs = server.connect_namespace(JOBS_NAMESPACE).await?;
         Ok(Self {
+            server,
+            job_id,
             map,
-            m: 0,
-            n: 0,
-            p: 0,
+            claims,
+            jobs,
+            job: None,
+            encoding: Encoding::default(),
+            max_task_attempts: DEFAULT_MAX_TASK_ATTEMPTS,
+            registration: Mutex::new(None),
         })
     }
 
+    /// A handle for a new job with keys of its own, on the same server.
+    /// Load matrices through it; the job is registered as open once they
+    /// are stored.
+    pub async fn create_job(&self) -> Result<MatrixMul, Error> {
+        let mut mm = Self::open(self.server.clone(), Some(JobId::generate())).await?;
+        mm.max_task_attempts = self.max_task_attempts;
+        mm.encoding = self.encoding;
+        Ok(mm)
+    }
+
+    /// The job of a handle from [`create_job`](Self::create_job) or
+    /// [`connect_job`](Self::connect_job), `None` for the `map` namespace.
+    pub fn job_id(&self) -> Option<&JobId> {
+        self.job_id.as_ref()
+    }
+
+    /// Registered jobs whose tasks are not all settled, as far as this
+    /// handle has synced.
+    pub fn open_jobs(&self) -> Vec<JobId> {
+        self.jobs
+            .iter()
+            .filter(|(_, state)| state == OPEN)
+            .map(|(id, _)| JobId(id))
+            .collect()
+    }
+
+    /// Sets the sizes of the per-element layout, for logs without a
+    /// stored [`Job`]. With one, they must match it.
     pub fn set_size(&mut self, m: usize, n: usize, p: usize) {
-        self.m = m;
-        self.n = n;
-        self.p = p;
+        self.set_dimensions(Dimensions { m, n, p, tile: 0 });
+    }
+
+    /// Like [`set_size`](Self::set_size), for either layout.
+    pub fn set_dimensions(&mut self, dimensions: Dimensions) {
+        self.job = Some(Job::new(JobId::default(), dimensions, self.encoding));
+    }
+
+    /// Sets how the next `load_matrices` writes elements, [`Encoding::Binary`]
+    /// by default. Workers and readers follow the loaded job's encoding.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// The job to work on: the one stored by the last `load_matrices`, or
+    /// the sizes set here if none is.
+    ///
+    /// Fails with [`Error::JobMismatch`] if both exist and disagree, e.g.
+    /// when another job was loaded since, and with [`Error::NoJob`] if
+    /// neither does.
+    pub async fn job(&self) -> Result<Job, Error> {
+        let stored = match self.map.get(JOB_KEY).await? {
+            Some(value) => Some(Job::decode(&value)?),
+            None => None,
+        };
+        match (stored, &self.job) {
+            (Some(stored), Some(local))
+                if stored.dimensions != local.dimensions || (local.id.is_named() && local.id != stored.id) =>
+            {
+                Err(Error::JobMismatch {
+                    stored: Box::new(stored),
+                    local: Box::new(local.clone()),
+                })
+            }
+            (Some(stored), _) => Ok(stored),
+            (None, Some(local)) => Ok(local.clone()),
+            (None, None) => Err(Error::NoJob),
+        }
+    }
+
+    /// Stores the job descriptor and uses it, once this map sees it. Jobs
+    /// of their own are registered as open, too.
+    async fn store_job(&mut self, dimensions: Dimensions) -> Result<(), Error> {
+        let id = self.job_id.clone().unwrap_or_else(JobId::generate);
+        let job = Job::new(id, dimensions, self.encoding);
+        self.map.insert(JOB_KEY, job.encode()).await?;
+        self.map.get_consistent(JOB_KEY).await?;
+        if let Some(id) = &self.job_id {
+            self.jobs.insert(id.to_string(), OPEN.to_string()).await?;
+        }
+        self.job = Some(job);
+        Ok(())
+    }
+
+    /// Sets how many times a task may fail before it is poisoned.
+    ///
+    /// Write conflicts with other workers do not count as failures.
+    pub fn set_max_task_attempts(&mut self, attempts: u32) {
+        self.max_task_attempts = attempts.max(1);
     }
 
     /// Loads matrices A and B into the log-map.
@@ -44,39 +447,64 @@ impl MatrixMul {
     /// B rows stored at -(m+1), -(m+2), ..., -(m+n)
     pub async fn load_matrices(&mut self, a: Vec<Vec<f64>>, b: Vec<Vec<f64>>) -> Result<(), Error> {
         let m = a.len();
-        let n = a.get(0).map_or(0, |row| row.len());
+        let n = a.first().map_or(0, |row| row.len());
         let b_n = b.len();
-        let p = b.get(0).map_or(0, |row| row.len());
+        let p = b.first().map_or(0, |row| row.len());
 
         if n != b_n {
             return Err(Error::DimensionMismatch(m, n, b_n, p));
         }
 
-        self.m = m;
-        self.n = n;
-        self.p = p;
+        let encode = |row: Vec<f64>| self.encoding.encode_row(&row);
+        let rows_a = a.into_iter().enumerate().map(|(i, row)| (-(i as i64 + 1), encode(row)));
+        let rows_b = b
+            .into_iter()
+            .enumerate()
+            .map(|(j, row)| (-(m as i64 + j as i64 + 1), encode(row)));
 
-        for (i, row) in a.into_iter().enumerate() {
-            let key = -(i as i64 + 1);
-            let value = row
-                .iter()
-                .map(|v| v.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-            self.map.insert(key, value).await?;
+        for result in self.map.insert_batch(rows_a.chain(rows_b).collect()).await? {
+            result?;
         }
 
-        for (j, row) in b.into_iter().enumerate() {
-            let key = -(m as i64 + j as i64 + 1);
-            let value = row
-                .iter()
-                .map(|v| v.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-            self.map.insert(key, value).await?;
+        self.store_job(Dimensions { m, n, p, tile: 0 }).await
+    }
+
+    /// Loads A and B cut into `tile`×`tile` blocks, so that each task
+    /// computes a whole block of the result with one write.
+    ///
+    /// A's block (i, k) is stored at key -(1 + i·kt + k) and B's block
+    /// (k, j) at -(1 + mt·kt + k·pt + j), where mt, kt and pt count the
+    /// blocks along m, n and p. Result block (i, j) lands at i·pt + j + 1.
+    pub async fn load_matrices_tiled(&mut self, a: Vec<Vec<f64>>, b: Vec<Vec<f64>>, tile: usize) -> Result<(), Error> {
+        let m = a.len();
+        let n = a.first().map_or(0, |row| row.len());
+        let b_n = b.len();
+        let p = b.first().map_or(0, |row| row.len());
+
+        if n != b_n {
+            return Err(Error::DimensionMismatch(m, n, b_n, p));
         }
 
-        Ok(())
+        let tile = tile.max(1);
+        let d = Dimensions { m, n, p, tile };
+        let (mt, kt, pt) = (tile::count(m, tile), tile::count(n, tile), tile::count(p, tile));
+
+        let mut entries = Vec::with_capacity(mt * kt + kt * pt);
+        for ti in 0..mt {
+            for tk in 0..kt {
+                entries.push((d.a_tile_key(ti, tk), self.encoding.encode_block(&tile::extract(&a, ti, tk, tile))));
+            }
+        }
+        for tk in 0..kt {
+            for tj in 0..pt {
+                entries.push((d.b_tile_key(tk, tj), self.encoding.encode_block(&tile::extract(&b, tk, tj, tile))));
+            }
+        }
+
+        for result in self.map.insert_batch(entries).await? {
+            result?;
+        }
+        self.store_job(d).await
     }
 
     /// Signals the start of computation by writing "start" to key 0.
@@ -85,57 +513,284 @@ impl MatrixMul {
         Ok(())
     }
 
-    /// Runs the worker loop: pick random tasks and compute until complete.
-    pub async fn work(&self) -> Result<(), Error> {
-        let mut tasks_computed = 0;
+    /// Runs the worker loop with the default [`Strategy`], claiming tasks
+    /// and computing them until complete.
+    pub async fn work(&self) -> Result<WorkStats, Error> {
+        self.work_with_strategy(Strategy::default()).await
+    }
+
+    /// Runs the worker loop: pick tasks as `strategy` says and compute
+    /// until complete.
+    ///
+    /// The sizes come from the stored [`Job`](Self::job); a worker started
+    /// before the matrices are loaded waits for them.
+    ///
+    /// A task that fails [`set_max_task_attempts`](Self::set_max_task_attempts)
+    /// times is poisoned: no worker picks it again and the loop ends once all
+    /// remaining tasks are computed.
+    ///
+    /// Sleeps between attempts are adaptive: short while most tasks are open,
+    /// longer as the result fills up, and exponentially backed off while
+    /// writes keep conflicting with other workers.
+    ///
+    /// The worker registers itself (see [`MatrixMul::list_workers`]) for the
+    /// duration of the loop and unregisters when it returns.
+    pub async fn work_with_strategy(&self, strategy: Strategy) -> Result<WorkStats, Error> {
+        let info = self.register_worker().await?;
+        println!("Registered as worker {}", info);
+
+        let result = self.work_loop(strategy).await;
+        self.unregister_worker().await?;
+        result
+    }
+
+    /// Runs the worker loop on the job `job` with a handle of its own.
+    pub async fn work_on(&self, job: &JobId, strategy: Strategy) -> Result<WorkStats, Error> {
+        let mut mm = Self::open(self.server.clone(), Some(job.clone())).await?;
+        mm.max_task_attempts = self.max_task_attempts;
+        mm.work_with_strategy(strategy).await
+    }
+
+    /// Works on the [open jobs](Self::open_jobs) one after another until
+    /// none is left, waiting for one if there is none yet. Returns the
+    /// stats of all of them together.
+    pub async fn work_on_open_jobs(&self, strategy: Strategy) -> Result<WorkStats, Error> {
+        let changes = self.jobs.watch_prefix("");
+        tokio::pin!(changes);
+        let mut stats = WorkStats::default();
+        let mut worked = false;
         loop {
-            if self.is_complete()? {
-                println!("Work complete! Computed {} tasks", tasks_computed);
-                return Ok(());
+            let open = self.open_jobs();
+            if open.is_empty() {
+                if worked {
+                    return Ok(stats);
+                }
+                let _ = tokio::time::timeout(RECHECK_INTERVAL, changes.next()).await;
+                continue;
+            }
+            // Spread workers that start together over the jobs.
+            let job = &open[rand::thread_rng().gen_range(0..open.len())];
+            let done = self.work_on(job, strategy).await?;
+            stats.tasks_computed += done.tasks_computed;
+            stats.conflicts += done.conflicts;
+            stats.failures += done.failures;
+            worked = true;
+            // Our own write marking it done may not have synced yet.
+            self.jobs.get_consistent(job.to_string()).await?;
+        }
+    }
+
+    async fn work_loop(&self, mut strategy: Strategy) -> Result<WorkStats, Error> {
+        let server_info = self.map.server_info();
+        if matches!(strategy, Strategy::Claimed { .. })
+            && !(server_info.supports(features::CONDITIONAL_WRITES) && server_info.supports(features::TTL))
+        {
+            println!("Server can't hold claims, picking random tasks instead");
+            strategy = Strategy::Random;
+        }
+
+        let mut stats = WorkStats::default();
+        let mut backoff = Backoff::new();
+        let mut current = None;
+        // Next task index to look at, for the ordered strategies.
+        let mut cursor = match strategy {
+            Strategy::Claimed { .. } => rand::random::<u32>() as usize,
+            _ => 0,
+        };
+        loop {
+            let job = match self.job().await {
+                Ok(job) => job,
+                Err(Error::NoJob) => {
+                    tokio::time::sleep(RECHECK_INTERVAL).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if current.as_ref() != Some(&job.id) {
+                println!("Working on {}", job);
+                current = Some(job.id.clone());
+            }
+            let d = &job.dimensions;
+            let (rows, cols) = d.task_grid();
+            let total = rows * cols;
+
+            let progress = self.progress_of(d).await?;
+            if progress.is_settled() {
+                println!("Work complete! Computed {} tasks", stats.tasks_computed);
+                if !progress.poisoned.is_empty() {
+                    println!("Poisoned tasks: {:?}", progress.poisoned);
+                }
+                if let Some(id) = &self.job_id
+                    && self.jobs.get(id.to_string()).await?.as_deref() != Some(DONE)
+                {
+                    self.jobs.insert(id.to_string(), DONE.to_string()).await?;
+                }
+                return Ok(stats);
             }
+            let remaining = total - progress.computed - progress.poisoned.len();
 
-            let task_id = self.pick_random_task();
-            if let Some((i, j)) = task_id {
-                match self.try_compute_task(i, j).await {
-                    Ok(_) => {
-                        tasks_computed += 1;
+            let task = match strategy {
+                Strategy::Random => self.pick_random_task(d).await?.map(|task| (task, None)),
+                Strategy::Sequential => self.pick_next_task(d, &mut cursor).await?.map(|task| (task, None)),
+                Strategy::Claimed { lease } => self.claim_next_task(d, &mut cursor, lease).await?,
+            };
+            if let Some(((i, j), claim)) = task {
+                let result = self.try_compute_task(&job, i, j).await;
+                if let Some(claim) = claim
+                    && let Err(e) = claim.release().await
+                {
+                    println!("Releasing the claim on C[{}][{}] failed: {}", i, j, e);
+                }
+                match result {
+                    Ok(true) => {
+                        stats.tasks_computed += 1;
+                        backoff.on_success();
                         println!("Computed C[{}][{}]", i, j);
                     }
+                    Ok(false) => {
+                        backoff.on_conflict();
+                        println!("C[{}][{}] was computed by another worker", i, j);
+                    }
+                    Err(Error::LogMap(log_map::Error::Conflict(retries))) => {
+                        stats.conflicts += 1;
+                        backoff.on_conflict();
+                        println!("Conflict on C[{}][{}] after {} retries", i, j, retries);
+                    }
                     Err(e) => {
+                        stats.failures += 1;
                         println!("Failed to compute C[{}][{}]: {}", i, j, e);
+                        let attempts = self.record_failure(d, i, j).await?;
+                        if attempts >= self.max_task_attempts {
+                            println!("C[{}][{}] poisoned after {} attempts", i, j, attempts);
+                        }
                     }
                 }
             }
 
-            tokio::time::sleep(POLL_INTERVAL).await;
+            tokio::time::sleep(backoff.delay(remaining, total)).await;
         }
     }
 
-    /// Waits for the computation to complete (polls for all result keys).
-    pub async fn wait_for_completion(&self, m: usize, p: usize) -> Result<(), Error> {
-        let total = m * p;
-        let mut last_count = 0;
-        loop {
-            let mut count = 0;
-            for idx in 1..=total {
-                let key = idx as i64;
-                if self.map.contains_key(key) {
-                    count += 1;
-                }
+    /// Publishes this process in the worker registry.
+    ///
+    /// Slots are probed from a random offset so workers starting together
+    /// rarely pick the same one. Registering twice returns the existing entry.
+    pub async fn register_worker(&self) -> Result<WorkerInfo, Error> {
+        if let Some(info) = self.registration.lock().unwrap().clone() {
+            return Ok(info);
+        }
+
+        let offset = rand::thread_rng().gen_range(0..MAX_WORKERS);
+        let slot = (0..MAX_WORKERS)
+            .map(|i| (offset + i) % MAX_WORKERS)
+            .find(|&slot| !self.map.contains_key(worker::slot_key(slot)))
+            .ok_or(Error::RegistryFull)?;
+
+        let info = WorkerInfo::current(slot);
+        self.map.insert(info.key(), info.encode()).await?;
+        *self.registration.lock().unwrap() = Some(info.clone());
+        Ok(info)
+    }
+
+    /// Removes this process from the worker registry, if registered.
+    pub async fn unregister_worker(&self) -> Result<(), Error> {
+        let info = self.registration.lock().unwrap().take();
+        if let Some(info) = info {
+            self.map.remove(info.key()).await?;
+        }
+        Ok(())
+    }
+
+    /// Lists the workers currently registered for this server.
+    pub async fn list_workers(&self) -> Result<Vec<WorkerInfo>, Error> {
+        let mut workers = Vec::new();
+        for slot in 0..MAX_WORKERS {
+            let value = self.map.get(worker::slot_key(slot)).await?;
+            if let Some(info) = value.and_then(|v| WorkerInfo::decode(slot, &v)) {
+                workers.push(info);
             }
-            if count != last_count {
-                println!("Progress: {}/{} elements computed", count, total);
-                last_count = count;
+        }
+        Ok(workers)
+    }
+
+    /// Waits for the computation to complete, re-checking progress whenever
+    /// the map changes.
+    ///
+    /// Returns [`Error::PoisonedTasks`] if every task settled but some were
+    /// poisoned, since the result can then never be complete. Waits for
+    /// the matrices to be loaded, too.
+    pub async fn wait_for_completion(&self) -> Result<(), Error> {
+        let changes = self.map.watch_prefix("");
+        tokio::pin!(changes);
+        let mut last = Progress::default();
+        loop {
+            let progress = match self.job().await {
+                Ok(job) => self.progress_of(&job.dimensions).await?,
+                Err(Error::NoJob) => Progress::default(),
+                Err(e) => return Err(e),
+            };
+            if progress != last {
+                println!(
+                    "Progress: {}/{} tasks computed, {} poisoned",
+                    progress.computed,
+                    progress.total,
+                    progress.poisoned.len()
+                );
             }
-            if count == total {
+            if progress.total > 0 && progress.computed == progress.total {
                 return Ok(());
             }
-            tokio::time::sleep(POLL_INTERVAL).await;
+            if progress.is_settled() {
+                return Err(Error::PoisonedTasks(progress.poisoned));
+            }
+            last = progress;
+            // A closed stream means the sync task stopped; keep re-checking
+            // on the interval then.
+            if let Ok(None) = tokio::time::timeout(RECHECK_INTERVAL, changes.next()).await {
+                tokio::time::sleep(RECHECK_INTERVAL).await;
+            }
+        }
+    }
+
+    /// Reports computed and poisoned tasks of the current [`Job`](Self::job).
+    pub async fn progress(&self) -> Result<Progress, Error> {
+        self.progress_of(&self.job().await?.dimensions).await
+    }
+
+    async fn progress_of(&self, d: &Dimensions) -> Result<Progress, Error> {
+        let (rows, cols) = d.task_grid();
+        let mut progress = Progress {
+            total: rows * cols,
+            ..Progress::default()
+        };
+        for i in 0..rows {
+            for j in 0..cols {
+                if self.map.contains_key((i * cols + j + 1) as i64) {
+                    progress.computed += 1;
+                } else if self.is_poisoned(i * cols + j).await? {
+                    progress.poisoned.push((i, j));
+                }
+            }
         }
+        Ok(progress)
     }
 
-    /// Retrieves the complete result matrix.
-    pub async fn get_result(&self, m: usize, p: usize) -> Result<Vec<Vec<f64>>, Error> {
+    /// Retrieves the complete m×p result matrix of the current
+    /// [`Job`](Self::job).
+    pub async fn get_result(&self) -> Result<Vec<Vec<f64>>, Error> {
+        let job = self.job().await?;
+        let d = job.dimensions;
+        let (m, p) = (d.m, d.p);
+        if d.tile > 0 {
+            let (_, cols) = d.task_grid();
+            let result = self.read_tiled(&job, m, p, |ti, tj| (ti * cols + tj) as i64 + 1).await?;
+
+            #[cfg(feature = "reference-check")]
+            self.check_against_reference(&job, &result).await?;
+
+            return Ok(result);
+        }
+
         let mut result = vec![vec![0.0; p]; m];
         for i in 0..m {
             for j in 0..p {
@@ -145,66 +800,205 @@ impl MatrixMul {
                     .get(key)
                     .await?
                     .ok_or(Error::MissingMatrixData(key))?;
-                result[i][j] = value.parse()?;
+                result[i][j] = job.encoding.decode_element(&value)?;
             }
         }
+
+        #[cfg(feature = "reference-check")]
+        self.check_against_reference(&job, &result).await?;
+
         Ok(result)
     }
 
-    /// Checks if all result keys are present.
-    fn is_complete(&self) -> Result<bool, Error> {
-        let total = self.m * self.p;
-        if total == 0 {
-            return Ok(false);
+    /// Assembles a rows×cols matrix from the blocks at `key(ti, tj)`.
+    async fn read_tiled(
+        &self,
+        job: &Job,
+        rows: usize,
+        cols: usize,
+        key: impl Fn(usize, usize) -> i64,
+    ) -> Result<Vec<Vec<f64>>, Error> {
+        let d = &job.dimensions;
+        let mut matrix = vec![vec![0.0; cols]; rows];
+        for ti in 0..tile::count(rows, d.tile) {
+            for tj in 0..tile::count(cols, d.tile) {
+                let key = key(ti, tj);
+                let value = self.map.get(key).await?.ok_or(Error::MissingMatrixData(key))?;
+                tile::insert(&mut matrix, &job.encoding.decode_block(&value)?, ti, tj, d.tile)?;
+            }
         }
-        let mut count = 0;
-        for idx in 1..=total {
-            if self.map.contains_key(idx as i64) {
-                count += 1;
+        Ok(matrix)
+    }
+
+    /// Recomputes the product locally and diffs it against `result`.
+    ///
+    /// Skipped for products above [`REFERENCE_CHECK_LIMIT`](crate::reference::REFERENCE_CHECK_LIMIT).
+    #[cfg(feature = "reference-check")]
+    async fn check_against_reference(&self, job: &Job, result: &[Vec<f64>]) -> Result<(), Error> {
+        use crate::reference;
+
+        let d = &job.dimensions;
+        let (m, p) = (d.m, d.p);
+        if d.tile > 0 {
+            if m * d.n * p > reference::REFERENCE_CHECK_LIMIT {
+                return Ok(());
             }
+            let a = self.read_tiled(job, m, d.n, |ti, tk| d.a_tile_key(ti, tk)).await?;
+            let b = self.read_tiled(job, d.n, p, |tk, tj| d.b_tile_key(tk, tj)).await?;
+            let report = reference::compare(&reference::multiply(&a, &b), result);
+            return if report.is_ok() {
+                Ok(())
+            } else {
+                Err(Error::ReferenceMismatch(report))
+            };
         }
-        Ok(count == total)
+
+        let mut a = Vec::with_capacity(m);
+        for i in 0..m {
+            a.push(self.read_row(job.encoding, -(i as i64 + 1)).await?);
+        }
+        let n = a.first().map_or(0, |row| row.len());
+        if m * n * p > reference::REFERENCE_CHECK_LIMIT {
+            return Ok(());
+        }
+
+        let mut b = Vec::with_capacity(n);
+        for k in 0..n {
+            b.push(self.read_row(job.encoding, -(m as i64 + k as i64 + 1)).await?);
+        }
+
+        let report = reference::compare(&reference::multiply(&a, &b), result);
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(Error::ReferenceMismatch(report))
+        }
+    }
+
+    /// Reads and parses a stored matrix row.
+    #[cfg(feature = "reference-check")]
+    async fn read_row(&self, encoding: Encoding, key: i64) -> Result<Vec<f64>, Error> {
+        let value = self
+            .map
+            .get(key)
+            .await?
+            .ok_or(Error::MissingMatrixData(key))?;
+        encoding.decode_row(&value)
+    }
+
+    /// Increments the shared failure counter of task (i, j).
+    ///
+    /// Returns the new count. Concurrent failures on the same task may be
+    /// counted once, which only delays poisoning.
+    async fn record_failure(&self, d: &Dimensions, i: usize, j: usize) -> Result<u32, Error> {
+        let (_, cols) = d.task_grid();
+        let key = FAILURE_KEY_BASE + (i * cols + j) as i64;
+        let attempts = self.failure_count(i * cols + j).await? + 1;
+        self.map.insert(key, attempts.to_string()).await?;
+        Ok(attempts)
+    }
+
+    async fn failure_count(&self, idx: usize) -> Result<u32, Error> {
+        let key = FAILURE_KEY_BASE + idx as i64;
+        let value = self.map.get(key).await?;
+        Ok(value.and_then(|v| v.parse().ok()).unwrap_or(0))
     }
 
-    /// Picks a random task (i, j) that hasn't been computed yet.
-    fn pick_random_task(&self) -> Option<(usize, usize)> {
-        if self.m == 0 || self.p == 0 {
+    async fn is_poisoned(&self, idx: usize) -> Result<bool, Error> {
+        Ok(self.failure_count(idx).await? >= self.max_task_attempts)
+    }
+
+    /// Picks a random task (i, j) that is neither computed nor poisoned.
+    async fn pick_random_task(&self, d: &Dimensions) -> Result<Option<(usize, usize)>, Error> {
+        let (rows, cols) = d.task_grid();
+        if rows == 0 || cols == 0 {
             println!("none");
-            return None;
+            return Ok(None);
         }
 
-        let mut rng = rand::thread_rng();
-        let i = rng.gen_range(0..self.m);
-        let j = rng.gen_range(0..self.p);
-        let key = (i * self.p + j + 1) as i64;
+        let (i, j) = {
+            let mut rng = rand::thread_rng();
+            (rng.gen_range(0..rows), rng.gen_range(0..cols))
+        };
+        let key = (i * cols + j + 1) as i64;
 
-        if self.map.contains_key(key) {
-            None
+        if self.map.contains_key(key) || self.is_poisoned(i * cols + j).await? {
+            Ok(None)
         } else {
-            Some((i, j))
+            Ok(Some((i, j)))
+        }
+    }
+
+    /// The first task from `cursor` on, wrapping around, that is neither
+    /// computed nor poisoned. Moves `cursor` past it.
+    async fn pick_next_task(&self, d: &Dimensions, cursor: &mut usize) -> Result<Option<(usize, usize)>, Error> {
+        let (rows, cols) = d.task_grid();
+        let total = rows * cols;
+        for offset in 0..total {
+            let idx = (*cursor % total + offset) % total;
+            if !self.map.contains_key(idx as i64 + 1) && !self.is_poisoned(idx).await? {
+                *cursor = idx + 1;
+                return Ok(Some((idx / cols, idx % cols)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// `claim:<i>:<j>`, or `claim:<job>:<i>:<j>` for a job of its own,
+    /// since all jobs share the `lock` namespace.
+    fn claim_name(&self, i: usize, j: usize) -> String {
+        match &self.job_id {
+            Some(id) => format!("claim:{}:{}:{}", id, i, j),
+            None => format!("claim:{}:{}", i, j),
+        }
+    }
+
+    /// Like [`pick_next_task`](Self::pick_next_task), skipping tasks other
+    /// workers have claimed. The claim is held until released.
+    async fn claim_next_task(
+        &self,
+        d: &Dimensions,
+        cursor: &mut usize,
+        lease: Duration,
+    ) -> Result<Option<((usize, usize), Option<LockGuard>)>, Error> {
+        let (rows, cols) = d.task_grid();
+        let total = rows * cols;
+        for offset in 0..total {
+            let idx = (*cursor % total + offset) % total;
+            if self.map.contains_key(idx as i64 + 1) || self.is_poisoned(idx).await? {
+                continue;
+            }
+            let (i, j) = (idx / cols, idx % cols);
+            if let Some(claim) = self.claims.try_acquire(&self.claim_name(i, j), lease).await? {
+                *cursor = idx + 1;
+                return Ok(Some(((i, j), Some(claim))));
+            }
         }
+        Ok(None)
     }
 
-    /// Attempts to compute a single element C[i][j] and write it to the map.
-    async fn try_compute_task(&self, i: usize, j: usize) -> Result<(), Error> {
+    /// Attempts to compute a single element C[i][j], or block (i, j) when
+    /// tiled, and write it to the map.
+    ///
+    /// Returns `false` if another worker wrote it first.
+    async fn try_compute_task(&self, job: &Job, i: usize, j: usize) -> Result<bool, Error> {
+        let d = &job.dimensions;
+        if d.tile > 0 {
+            return self.try_compute_tile(job, i, j).await;
+        }
+
         let mut row_a = Vec::new();
         let mut col_b = Vec::new();
 
         let a_key = -(i as i64 + 1);
         if let Some(value) = self.map.get(a_key).await? {
-            row_a = value
-                .split(',')
-                .map(|s| s.parse::<f64>())
-                .collect::<Result<Vec<_>, _>>()?;
+            row_a = job.encoding.decode_row(&value)?;
         }
 
-        for k in 0..self.n {
-            let b_key = -(self.m as i64 + k as i64 + 1);
+        for k in 0..d.n {
+            let b_key = -(d.m as i64 + k as i64 + 1);
             if let Some(value) = self.map.get(b_key).await? {
-                let row: Vec<f64> = value
-                    .split(',')
-                    .map(|s| s.parse::<f64>())
-                    .collect::<Result<Vec<_>, _>>()?;
+                let row = job.encoding.decode_row(&value)?;
                 if let Some(&val) = row.get(j) {
                     col_b.push(val);
                 }
@@ -216,10 +1010,52 @@ impl MatrixMul {
             sum += row_a[k] * col_b[k];
         }
 
-        let key = (i * self.p + j + 1) as i64;
+        let key = (i * d.p + j + 1) as i64;
         println!("  Writing C[{}][{}] = {} to key {}", i, j, sum, key);
-        self.map.insert(key, sum.to_string()).await?;
+        self.write_result(key, job.encoding.encode_element(sum)).await
+    }
 
-        Ok(())
+    /// Computes result block (ti, tj) from A's block row ti and B's block
+    /// column tj.
+    async fn try_compute_tile(&self, job: &Job, ti: usize, tj: usize) -> Result<bool, Error> {
+        let d = &job.dimensions;
+        let rows = d.tile.min(d.m - ti * d.tile);
+        let cols = d.tile.min(d.p - tj * d.tile);
+        let mut block = vec![vec![0.0; cols]; rows];
+
+        for tk in 0..tile::count(d.n, d.tile) {
+            let a_key = d.a_tile_key(ti, tk);
+            let b_key = d.b_tile_key(tk, tj);
+            let a = self.map.get(a_key).await?.ok_or(Error::MissingMatrixData(a_key))?;
+            let b = self.map.get(b_key).await?.ok_or(Error::MissingMatrixData(b_key))?;
+            tile::multiply_add(&mut block, &job.encoding.decode_block(&a)?, &job.encoding.decode_block(&b)?);
+        }
+
+        let (_, cols) = d.task_grid();
+        let key = (ti * cols + tj + 1) as i64;
+        println!("  Writing block ({}, {}) to key {}", ti, tj, key);
+        self.write_result(key, job.encoding.encode_block(&block)).await
     }
+
+    /// Writes a result unless one is there already; `false` if it was.
+    async fn write_result(&self, key: i64, value: String) -> Result<bool, Error> {
+        match self.map.insert_if_absent(key, value.clone()).await {
+            Ok(result) => Ok(result.is_ok()),
+            // Older servers can't check; overwriting with the same sum is harmless.
+            Err(log_map::Error::Unsupported(_)) => {
+                self.map.insert(key, value).await?;
+                Ok(true)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Parses a comma-separated matrix row, as [`Encoding::Decimal`] writes
+/// them.
+pub fn parse_row(value: &str) -> Result<Vec<f64>, Error> {
+    Ok(value
+        .split(',')
+        .map(|s| s.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?)
 }
@@ -0,0 +1,100 @@
+//! Local reference multiplication used to validate distributed results.
+//!
+//! Only compiled with the `reference-check` feature. When enabled,
+//! [`MatrixMul::get_result`](crate::MatrixMul::get_result) recomputes small
+//! products naively and fails with a [`MismatchReport`] if any element of the
+//! distributed result disagrees.
+
+use std::fmt;
+
+/// Products with more than this many multiply-adds (m·n·p) are not checked.
+pub const REFERENCE_CHECK_LIMIT: usize = 1 << 20;
+
+const TOLERANCE: f64 = 1e-9;
+
+/// A single element that differs from the reference product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub row: usize,
+    pub col: usize,
+    pub expected: f64,
+    pub actual: f64,
+}
+
+/// Outcome of comparing a distributed result against the local reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MismatchReport {
+    /// Number of elements compared.
+    pub checked: usize,
+    /// Elements outside tolerance, in row-major order.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl MismatchReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} elements differ from the reference",
+            self.mismatches.len(),
+            self.checked
+        )?;
+        for m in self.mismatches.iter().take(10) {
+            write!(
+                f,
+                "; C[{}][{}] expected {} got {}",
+                m.row, m.col, m.expected, m.actual
+            )?;
+        }
+        if self.mismatches.len() > 10 {
+            write!(f, "; ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes A × B with the textbook triple loop.
+pub fn multiply(a: &[Vec<f64>], b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let p = b.first().map_or(0, |row| row.len());
+    a.iter()
+        .map(|row| {
+            (0..p)
+                .map(|j| {
+                    row.iter()
+                        .zip(b)
+                        .map(|(x, b_row)| x * b_row.get(j).copied().unwrap_or(0.0))
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Compares `actual` with `expected` element-wise.
+pub fn compare(expected: &[Vec<f64>], actual: &[Vec<f64>]) -> MismatchReport {
+    let mut report = MismatchReport {
+        checked: 0,
+        mismatches: Vec::new(),
+    };
+
+    for (i, (exp_row, act_row)) in expected.iter().zip(actual).enumerate() {
+        for (j, (&exp, &act)) in exp_row.iter().zip(act_row).enumerate() {
+            report.checked += 1;
+            if (exp - act).abs() > TOLERANCE * exp.abs().max(1.0) {
+                report.mismatches.push(Mismatch {
+                    row: i,
+                    col: j,
+                    expected: exp,
+                    actual: act,
+                });
+            }
+        }
+    }
+
+    report
+}
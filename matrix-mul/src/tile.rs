@@ -0,0 +1,44 @@
+//! Block layout for [`MatrixMul::load_matrices_tiled`](crate::MatrixMul::load_matrices_tiled).
+//!
+//! A matrix is cut into `tile`×`tile` blocks, row-major; blocks on the
+//! bottom and right edges are smaller when the size is not a multiple of
+//! `tile`. Blocks are stored in the job's
+//! [`Encoding`](crate::encoding::Encoding).
+
+use crate::Error;
+
+/// Number of tiles needed to cover `len` elements.
+pub(crate) fn count(len: usize, tile: usize) -> usize {
+    len.div_ceil(tile)
+}
+
+/// The block of `matrix` whose top-left element is at tile `(ti, tj)`.
+pub(crate) fn extract(matrix: &[Vec<f64>], ti: usize, tj: usize, tile: usize) -> Vec<Vec<f64>> {
+    let rows = matrix.iter().skip(ti * tile).take(tile);
+    rows.map(|row| row.iter().skip(tj * tile).take(tile).copied().collect())
+        .collect()
+}
+
+/// Copies `block` into `matrix` at tile `(ti, tj)`.
+pub(crate) fn insert(matrix: &mut [Vec<f64>], block: &[Vec<f64>], ti: usize, tj: usize, tile: usize) -> Result<(), Error> {
+    for (r, row) in block.iter().enumerate() {
+        let target = matrix
+            .get_mut(ti * tile + r)
+            .ok_or(Error::TileShape(ti, tj))?;
+        for (c, value) in row.iter().enumerate() {
+            *target.get_mut(tj * tile + c).ok_or(Error::TileShape(ti, tj))? = *value;
+        }
+    }
+    Ok(())
+}
+
+/// Adds `a`·`b` to `acc`, which must have `a.len()` rows of `b[0].len()`.
+pub(crate) fn multiply_add(acc: &mut [Vec<f64>], a: &[Vec<f64>], b: &[Vec<f64>]) {
+    for (acc_row, a_row) in acc.iter_mut().zip(a) {
+        for (a_ik, b_row) in a_row.iter().zip(b) {
+            for (acc_ij, b_kj) in acc_row.iter_mut().zip(b_row) {
+                *acc_ij += a_ik * b_kj;
+            }
+        }
+    }
+}
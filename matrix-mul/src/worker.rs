@@ -0,0 +1,78 @@
+//! Identity records that workers publish while they participate in a job.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// First key of the worker registry range.
+pub(crate) const WORKER_KEY_BASE: i64 = 1 << 48;
+/// Number of registry slots, i.e. the maximum number of live workers.
+pub(crate) const MAX_WORKERS: usize = 1024;
+
+/// A registered worker as stored in the map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerInfo {
+    /// Registry slot; the record lives at `WORKER_KEY_BASE + slot`.
+    pub slot: usize,
+    pub hostname: String,
+    pub pid: u32,
+    /// Registration time in milliseconds since the Unix epoch.
+    pub started_at: u64,
+}
+
+impl WorkerInfo {
+    /// Describes the current process.
+    pub(crate) fn current(slot: usize) -> Self {
+        let hostname = hostname::get()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "unknown".to_string());
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Self {
+            slot,
+            hostname,
+            pid: std::process::id(),
+            started_at,
+        }
+    }
+
+    pub(crate) fn key(&self) -> i64 {
+        slot_key(self.slot)
+    }
+
+    /// Encodes the record as `hostname,pid,started_at`.
+    pub(crate) fn encode(&self) -> String {
+        format!("{},{},{}", self.hostname, self.pid, self.started_at)
+    }
+
+    /// Parses a record written by [`WorkerInfo::encode`].
+    pub(crate) fn decode(slot: usize, value: &str) -> Option<Self> {
+        let mut parts = value.rsplitn(3, ',');
+        let started_at = parts.next()?.parse().ok()?;
+        let pid = parts.next()?.parse().ok()?;
+        let hostname = parts.next()?.to_string();
+
+        Some(Self {
+            slot,
+            hostname,
+            pid,
+            started_at,
+        })
+    }
+}
+
+impl fmt::Display for WorkerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} (pid {}, started at {})",
+            self.slot, self.hostname, self.pid, self.started_at
+        )
+    }
+}
+
+pub(crate) fn slot_key(slot: usize) -> i64 {
+    WORKER_KEY_BASE + slot as i64
+}
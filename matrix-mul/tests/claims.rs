@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use log_map::embedded::EmbeddedServer;
+use log_map::lock::{self, Mutex};
+use matrix_mul::{MatrixMul, Strategy, generate};
+
+/// Claims taken on each task of a `rows`×`cols` grid, in row-major order;
+/// releases are not counted.
+async fn claims_per_cell(server: &EmbeddedServer, rows: usize, cols: usize) -> Vec<usize> {
+    let mut claims = Vec::new();
+    for i in 0..rows {
+        for j in 0..cols {
+            // Lock names are JSON strings in the lock namespace.
+            let key = format!("{}:\"claim:{}:{}\"", lock::NAMESPACE, i, j);
+            let records = server.storage().history(&key, 0, 0).await.unwrap();
+            claims.push(records.iter().filter(|record| !record.value.is_empty()).count());
+        }
+    }
+    claims
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_claimed_workers_never_compute_the_same_cell() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut mm = MatrixMul::connect_embedded(&server).await.unwrap();
+    let (a, b) = (generate::sequential_matrix(5, 3), generate::sequential_matrix(3, 4));
+    mm.load_matrices(a, b).await.unwrap();
+    mm.start().await.unwrap();
+
+    let strategy = Strategy::Claimed {
+        lease: Duration::from_secs(10),
+    };
+    let mut workers = Vec::new();
+    for _ in 0..2 {
+        let worker = MatrixMul::connect_embedded(&server).await.unwrap();
+        workers.push(tokio::spawn(async move { worker.work_with_strategy(strategy).await.unwrap() }));
+    }
+    let mut computed = 0;
+    for worker in workers {
+        computed += worker.await.unwrap().tasks_computed;
+    }
+
+    assert_eq!(computed, 20);
+    assert!(claims_per_cell(&server, 5, 4).await.iter().all(|&claims| claims == 1));
+    assert_eq!(mm.get_result().await.unwrap()[4], vec![218.0, 260.0, 302.0, 344.0]);
+}
+
+#[tokio::test]
+async fn test_expired_claims_are_taken_over() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut mm = MatrixMul::connect_embedded(&server).await.unwrap();
+    mm.load_matrices(vec![vec![2.0]], vec![vec![3.0]]).await.unwrap();
+    mm.start().await.unwrap();
+
+    // A worker that claimed the only task and died: its lease is never
+    // renewed or released.
+    let locks = Mutex::new(server.connect_namespace(lock::NAMESPACE).await.unwrap());
+    let lease = Duration::from_millis(500);
+    let started = Instant::now();
+    let claim = locks.try_acquire("claim:0:0", lease).await.unwrap().unwrap();
+    drop(claim);
+
+    let stats = mm
+        .work_with_strategy(Strategy::Claimed {
+            lease: Duration::from_secs(10),
+        })
+        .await
+        .unwrap();
+    assert_eq!(stats.tasks_computed, 1);
+    assert!(started.elapsed() >= lease);
+    assert_eq!(claims_per_cell(&server, 1, 1).await, vec![2]);
+    assert_eq!(mm.get_result().await.unwrap(), vec![vec![6.0]]);
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use log_map::LogMap;
+use log_map::embedded::EmbeddedServer;
+use matrix_mul::{Dimensions, Encoding, Error, MatrixMul, Strategy, generate};
+
+/// Where `load_matrices` stores the job descriptor.
+const JOB_KEY: i64 = 1 << 50;
+
+/// A new handle, once its sync has seen a job descriptor.
+async fn connect_synced(server: &EmbeddedServer) -> MatrixMul {
+    let mm = MatrixMul::connect_embedded(server).await.unwrap();
+    for _ in 0..100 {
+        if !matches!(mm.job().await, Err(Error::NoJob)) {
+            return mm;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("no job descriptor synced");
+}
+
+#[tokio::test]
+async fn test_workers_and_readers_take_sizes_from_the_descriptor() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut loader = MatrixMul::connect_embedded(&server).await.unwrap();
+    loader
+        .load_matrices(generate::sequential_matrix(3, 2), generate::sequential_matrix(2, 4))
+        .await
+        .unwrap();
+    loader.start().await.unwrap();
+
+    // Neither handle is told the sizes.
+    let worker = connect_synced(&server).await;
+    let reader = connect_synced(&server).await;
+    let job = worker.job().await.unwrap();
+    assert_eq!(job.dimensions, Dimensions { m: 3, n: 2, p: 4, tile: 0 });
+    assert_eq!(job, loader.job().await.unwrap());
+
+    let stats = worker.work_with_strategy(Strategy::Sequential).await.unwrap();
+    assert_eq!(stats.tasks_computed, 12);
+    reader.wait_for_completion().await.unwrap();
+    assert_eq!(
+        reader.get_result().await.unwrap(),
+        vec![
+            vec![11.0, 14.0, 17.0, 20.0],
+            vec![23.0, 30.0, 37.0, 44.0],
+            vec![35.0, 46.0, 57.0, 68.0],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_mismatched_descriptor_is_rejected() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut loader = MatrixMul::connect_embedded(&server).await.unwrap();
+    loader
+        .load_matrices(generate::sequential_matrix(2, 2), generate::sequential_matrix(2, 2))
+        .await
+        .unwrap();
+
+    let mut stale = connect_synced(&server).await;
+    stale.set_size(3, 3, 3);
+    assert!(matches!(stale.job().await, Err(Error::JobMismatch { .. })));
+    assert!(matches!(stale.get_result().await, Err(Error::JobMismatch { .. })));
+    let result = stale.work_with_strategy(Strategy::Sequential).await;
+    assert!(matches!(result, Err(Error::JobMismatch { .. })));
+
+    // Matching sizes are fine.
+    stale.set_size(2, 2, 2);
+    assert_eq!(stale.job().await.unwrap(), loader.job().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_missing_or_undecodable_descriptor() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut mm = MatrixMul::connect_embedded(&server).await.unwrap();
+    assert!(matches!(mm.job().await, Err(Error::NoJob)));
+    assert!(matches!(mm.get_result().await, Err(Error::NoJob)));
+
+    // Logs from before the descriptor need the sizes set by hand.
+    mm.set_size(1, 2, 3);
+    assert_eq!(mm.job().await.unwrap().dimensions, Dimensions { m: 1, n: 2, p: 3, tile: 0 });
+
+    let map: LogMap = server.connect().await.unwrap();
+    map.insert(JOB_KEY, "not a job".to_string()).await.unwrap();
+    map.get_consistent(JOB_KEY).await.unwrap();
+    let fresh = connect_synced(&server).await;
+    assert!(matches!(fresh.job().await, Err(Error::InvalidJob(_))));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_two_jobs_run_side_by_side_on_one_server() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let coordinator = MatrixMul::connect_embedded(&server).await.unwrap();
+    let mut first = coordinator.create_job().await.unwrap();
+    first
+        .load_matrices(generate::sequential_matrix(3, 2), generate::sequential_matrix(2, 4))
+        .await
+        .unwrap();
+    let mut second = coordinator.create_job().await.unwrap();
+    second
+        .load_matrices(generate::sequential_matrix(2, 3), generate::sequential_matrix(3, 2))
+        .await
+        .unwrap();
+    let (first_id, second_id) = (first.job_id().unwrap().clone(), second.job_id().unwrap().clone());
+    assert_ne!(first_id, second_id);
+
+    let worker = MatrixMul::connect_embedded(&server).await.unwrap();
+    let (done_first, done_second) = tokio::join!(
+        worker.work_on(&first_id, Strategy::Sequential),
+        worker.work_on(&second_id, Strategy::Sequential)
+    );
+    assert_eq!(done_first.unwrap().tasks_computed, 12);
+    assert_eq!(done_second.unwrap().tasks_computed, 4);
+    first.wait_for_completion().await.unwrap();
+    second.wait_for_completion().await.unwrap();
+
+    assert_eq!(
+        first.get_result().await.unwrap(),
+        vec![
+            vec![11.0, 14.0, 17.0, 20.0],
+            vec![23.0, 30.0, 37.0, 44.0],
+            vec![35.0, 46.0, 57.0, 68.0],
+        ]
+    );
+    assert_eq!(second.get_result().await.unwrap(), vec![vec![22.0, 28.0], vec![49.0, 64.0]]);
+    // Neither job wrote to the default namespace.
+    assert!(matches!(coordinator.job().await, Err(Error::NoJob)));
+}
+
+#[tokio::test]
+async fn test_decimal_jobs_stay_readable() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut loader = MatrixMul::connect_embedded(&server).await.unwrap();
+    loader.set_encoding(Encoding::Decimal);
+    loader
+        .load_matrices(generate::sequential_matrix(2, 3), generate::sequential_matrix(3, 2))
+        .await
+        .unwrap();
+
+    // Workers and readers follow the descriptor, whatever their own setting.
+    let worker = connect_synced(&server).await;
+    assert_eq!(worker.job().await.unwrap().encoding, Encoding::Decimal);
+    worker.work_with_strategy(Strategy::Sequential).await.unwrap();
+    let reader = connect_synced(&server).await;
+    reader.wait_for_completion().await.unwrap();
+    assert_eq!(reader.get_result().await.unwrap(), vec![vec![22.0, 28.0], vec![49.0, 64.0]]);
+}
@@ -0,0 +1,25 @@
+use log_map::embedded::EmbeddedServer;
+use matrix_mul::{MatrixMul, Strategy, generate, reference};
+
+#[tokio::test]
+async fn test_tiled_round_trip_with_edge_tiles() {
+    let server = EmbeddedServer::start().await.unwrap();
+    let mut mm = MatrixMul::connect_embedded(&server).await.unwrap();
+    // None of 7, 5 and 4 is a multiple of the tile size, so the last
+    // block along each side is cut short.
+    let (a, b) = generate::random_pair(7, 5, 4, -10.0..10.0, 42);
+    mm.load_matrices_tiled(a.clone(), b.clone(), 3).await.unwrap();
+    mm.start().await.unwrap();
+
+    // Blocks of C: 3 along m, 2 along p.
+    assert_eq!(mm.progress().await.unwrap().total, 6);
+    let stats = mm.work_with_strategy(Strategy::Sequential).await.unwrap();
+    assert_eq!(stats.tasks_computed, 6);
+
+    let result = mm.get_result().await.unwrap();
+    assert_eq!(result.len(), 7);
+    assert!(result.iter().all(|row| row.len() == 4));
+    let report = reference::compare(&reference::multiply(&a, &b), &result);
+    assert_eq!(report.checked, 28);
+    assert!(report.is_ok(), "{}", report);
+}
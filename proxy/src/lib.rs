@@ -0,0 +1,258 @@
+//! A KV gRPC proxy that gives clients one stable endpoint.
+//!
+//! Writes, snapshots and stats go to the leader; subscriptions are spread
+//! over replicas round-robin and fall back to the leader when a replica is
+//! unreachable. Upstream channels connect lazily and reconnect on their
+//! own, so a restarted backend is picked up without restarting the proxy.
+//!
+//! Until the server supports replication, "replicas" are just additional
+//! servers that serve the same log, and the leader is fixed at startup.
+//!
+//! With a [`Topology`], replicas are named and `Stats` reports how far each
+//! one trails the leader, flagging those past their `lag_alarm`.
+
+pub mod topology;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use log_server_types::kv::kv_server_server::{KvServer, KvServerServer};
+use log_server_types::kv::{
+    CompactRequest, CompactResponse, GetHistoryRequest, GetHistoryResponse, GetServerInfoRequest, GetSnapshotRequest,
+    GetSnapshotResponse, Record, ReplicaStatus, ServerInfo, SnapshotChunk, StatsRequest, StatsResponse,
+    SubscribeRequest, TransactionRequest, TransactionResponse, WriteRequest, WriteResponse,
+};
+use tonic::metadata::{KeyAndValueRef, MetadataMap};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status, Streaming};
+
+pub use topology::{Follower, Topology};
+
+type Client = KvServerClient<Channel>;
+
+/// How long `Stats` waits for a replica before counting it unreachable.
+const REPLICA_STATS_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Proxy {
+    leader: Client,
+    replicas: Vec<Replica>,
+    next_replica: AtomicUsize,
+}
+
+struct Replica {
+    follower: Follower,
+    client: Client,
+}
+
+impl Proxy {
+    /// Creates a proxy for the given upstream URLs (`http://host:port`).
+    /// Replicas are named after their URL and have no lag alarm.
+    ///
+    /// No connection is made until the first request arrives.
+    pub fn new(leader: &str, replicas: &[String]) -> Result<Self, tonic::transport::Error> {
+        let followers: Vec<Follower> = replicas
+            .iter()
+            .map(|url| Follower {
+                name: url.clone(),
+                addr: url.clone(),
+                ..Default::default()
+            })
+            .collect();
+        Self::with_followers(leader, followers)
+    }
+
+    /// Creates a proxy for a topology whose addresses are `host:port`.
+    pub fn from_topology(topology: &Topology) -> Result<Self, tonic::transport::Error> {
+        let followers = topology
+            .followers
+            .iter()
+            .map(|follower| Follower {
+                addr: format!("http://{}", follower.addr),
+                ..follower.clone()
+            })
+            .collect();
+        Self::with_followers(&format!("http://{}", topology.leader), followers)
+    }
+
+    fn with_followers(leader: &str, followers: Vec<Follower>) -> Result<Self, tonic::transport::Error> {
+        let connect = |url: &str| -> Result<Client, tonic::transport::Error> {
+            Ok(KvServerClient::new(Endpoint::from_shared(url.to_string())?.connect_lazy()))
+        };
+
+        Ok(Self {
+            leader: connect(leader)?,
+            replicas: followers
+                .into_iter()
+                .map(|follower| {
+                    Ok(Replica {
+                        client: connect(&follower.addr)?,
+                        follower,
+                    })
+                })
+                .collect::<Result<_, tonic::transport::Error>>()?,
+            next_replica: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn into_server(self) -> KvServerServer<Self> {
+        KvServerServer::new(self)
+    }
+
+    fn pick_replica(&self) -> Option<Client> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+        let index = self.next_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+        Some(self.replicas[index].client.clone())
+    }
+
+    /// Lag of every replica behind `leader_ordinal`. Unreachable replicas
+    /// report ordinal 0 and always raise the alarm.
+    async fn replica_status(&self, leader_ordinal: u64) -> Vec<ReplicaStatus> {
+        let queries = self.replicas.iter().map(|replica| async move {
+            let mut client = replica.client.clone();
+            let latest = tokio::time::timeout(REPLICA_STATS_TIMEOUT, client.stats(StatsRequest {}))
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .map(|response| response.into_inner().latest_ordinal);
+
+            let follower = &replica.follower;
+            let latest_ordinal = latest.unwrap_or(0);
+            let lag = leader_ordinal.saturating_sub(latest_ordinal);
+            let alarm = latest.is_none() || follower.lag_alarm.is_some_and(|limit| lag > limit);
+            if alarm {
+                tracing::warn!(
+                    "replica {} alarm: {}",
+                    follower.name,
+                    if latest.is_none() { "unreachable".to_string() } else { format!("{} records behind", lag) }
+                );
+            }
+
+            ReplicaStatus {
+                name: follower.name.clone(),
+                region: follower.region.clone(),
+                latest_ordinal,
+                lag,
+                alarm,
+            }
+        });
+        futures_util::future::join_all(queries).await
+    }
+}
+
+/// Picks the client's handshake metadata (`x-log-*`) and bearer token out
+/// of a request, so the backend sees who it is really talking to.
+fn handshake(metadata: &MetadataMap) -> MetadataMap {
+    let mut forwarded = MetadataMap::new();
+    for entry in metadata.iter() {
+        if let KeyAndValueRef::Ascii(key, value) = entry {
+            if key.as_str().starts_with("x-log-") || key.as_str() == "authorization" {
+                forwarded.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    forwarded
+}
+
+/// Rebuilds a request for the upstream server, keeping the handshake.
+fn forward<T, U>(request: Request<T>, message: impl FnOnce(T) -> U) -> Request<U> {
+    let metadata = handshake(request.metadata());
+    Request::from_parts(metadata, Default::default(), message(request.into_inner()))
+}
+
+#[tonic::async_trait]
+impl KvServer for Proxy {
+    type SubscribeStream = Streaming<Record>;
+    type WriteStream = Streaming<WriteResponse>;
+    type StreamSnapshotStream = Streaming<SnapshotChunk>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let metadata = handshake(request.metadata());
+        let request = request.into_inner();
+        let upstream = || Request::from_parts(metadata.clone(), Default::default(), request.clone());
+
+        if let Some(mut replica) = self.pick_replica() {
+            match replica.subscribe(upstream()).await {
+                Err(status) if status.code() == Code::Unavailable => {
+                    tracing::warn!("replica unavailable, subscribing on the leader: {}", status);
+                }
+                result => return result,
+            }
+        }
+
+        self.leader.clone().subscribe(upstream()).await
+    }
+
+    async fn write(
+        &self,
+        request: Request<Streaming<WriteRequest>>,
+    ) -> Result<Response<Self::WriteStream>, Status> {
+        // The upstream call wants plain messages; a client-side error ends
+        // the forwarded stream the same way it ends the incoming one.
+        let request = forward(request, |stream| {
+            stream
+                .take_while(|request| futures_util::future::ready(request.is_ok()))
+                .filter_map(|request| futures_util::future::ready(request.ok()))
+        });
+
+        self.leader.clone().write(request).await
+    }
+
+    async fn get_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        self.leader.clone().get_snapshot(forward(request, |r| r)).await
+    }
+
+    async fn stats(
+        &self,
+        request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        let mut stats = self.leader.clone().stats(forward(request, |r| r)).await?;
+        let leader_ordinal = stats.get_ref().latest_ordinal;
+        stats.get_mut().replicas = self.replica_status(leader_ordinal).await;
+        Ok(stats)
+    }
+
+    async fn get_server_info(
+        &self,
+        request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        self.leader.clone().get_server_info(forward(request, |r| r)).await
+    }
+
+    async fn compact(
+        &self,
+        request: Request<CompactRequest>,
+    ) -> Result<Response<CompactResponse>, Status> {
+        self.leader.clone().compact(forward(request, |r| r)).await
+    }
+
+    async fn transaction(
+        &self,
+        request: Request<TransactionRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        self.leader.clone().transaction(forward(request, |r| r)).await
+    }
+
+    async fn get_history(
+        &self,
+        request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        self.leader.clone().get_history(forward(request, |r| r)).await
+    }
+
+    async fn stream_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<Self::StreamSnapshotStream>, Status> {
+        self.leader.clone().stream_snapshot(forward(request, |r| r)).await
+    }
+}
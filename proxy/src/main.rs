@@ -0,0 +1,64 @@
+use tonic::transport::Server;
+
+use log_proxy::{Proxy, Topology};
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:50050";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let topology = match (flag_value(&args, "--topology"), flag_value(&args, "--leader")) {
+        (Some(path), None) => Topology::load(std::path::Path::new(path))?,
+        (None, Some(leader)) => Topology {
+            leader: leader.to_string(),
+            followers: flag_values(&args, "--replica")
+                .map(|addr| log_proxy::Follower {
+                    name: addr.to_string(),
+                    addr: addr.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        },
+        _ => usage(),
+    };
+    let listen = flag_value(&args, "--listen").unwrap_or(DEFAULT_LISTEN);
+
+    let proxy = Proxy::from_topology(&topology)?;
+
+    let addr = listen.parse()?;
+    tracing::info!(
+        "proxying {} -> leader {}, {} replicas",
+        addr,
+        topology.leader,
+        topology.followers.len()
+    );
+    Server::builder()
+        .add_service(proxy.into_server())
+        .serve_with_shutdown(addr, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: log-proxy --leader <host:port> [--replica <host:port>]... [--listen <host:port>]");
+    eprintln!("       log-proxy --topology <file> [--listen <host:port>]");
+    eprintln!("Writes go to the leader, subscriptions are spread over the replicas.");
+    eprintln!("Listens on {} by default.", DEFAULT_LISTEN);
+    std::process::exit(2);
+}
+
+fn flag_value<'a>(args: &'a [String], name: &'a str) -> Option<&'a str> {
+    flag_values(args, name).next()
+}
+
+fn flag_values<'a>(args: &'a [String], name: &'a str) -> impl Iterator<Item = &'a str> {
+    args.windows(2)
+        .filter(move |pair| pair[0] == name)
+        .map(|pair| pair[1].as_str())
+}
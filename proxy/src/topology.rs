@@ -0,0 +1,138 @@
+//! Declarative description of a leader and its followers.
+//!
+//! The file is plain `key = value` lines like the server config; `#` starts
+//! a comment. Followers are named, and their settings can come in any
+//! order:
+//!
+//! ```text
+//! leader = 10.0.0.1:50051
+//!
+//! follower.eu-west = 10.1.0.1:50051
+//! follower.eu-west.region = eu
+//! follower.eu-west.lag_alarm = 1000
+//!
+//! follower.us-east = 10.2.0.1:50051
+//! ```
+//!
+//! `lag_alarm` is the number of records a follower may trail the leader by
+//! before `Stats` flags it; without it only unreachable followers are
+//! flagged.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    MissingLeader,
+    MissingAddress(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Error::MissingLeader => write!(f, "no `leader` configured"),
+            Error::MissingAddress(name) => write!(f, "follower '{}' has no address", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    /// `host:port` of the server taking writes.
+    pub leader: String,
+    /// Sorted by name.
+    pub followers: Vec<Follower>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Follower {
+    pub name: String,
+    /// `host:port`.
+    pub addr: String,
+    /// Free-form region label, reported in `Stats`.
+    pub region: String,
+    /// Records of lag that trigger an alarm, if any.
+    pub lag_alarm: Option<u64>,
+}
+
+impl Topology {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut leader = None;
+        let mut followers: BTreeMap<String, Follower> = BTreeMap::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parse_error = |message: String| Error::Parse {
+                line: index + 1,
+                message,
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| parse_error(format!("expected `key = value`, got '{}'", line)))?;
+            let (key, value) = (key.trim(), value.trim().to_string());
+
+            if key == "leader" {
+                leader = Some(value);
+                continue;
+            }
+
+            let Some(follower) = key.strip_prefix("follower.") else {
+                return Err(parse_error(format!("unknown key '{}'", key)));
+            };
+            let (name, setting) = match follower.split_once('.') {
+                Some((name, setting)) => (name, Some(setting)),
+                None => (follower, None),
+            };
+            if name.is_empty() {
+                return Err(parse_error(format!("follower without a name in '{}'", key)));
+            }
+
+            let entry = followers.entry(name.to_string()).or_insert_with(|| Follower {
+                name: name.to_string(),
+                ..Default::default()
+            });
+            match setting {
+                None => entry.addr = value,
+                Some("region") => entry.region = value,
+                Some("lag_alarm") => {
+                    entry.lag_alarm = Some(
+                        value
+                            .parse()
+                            .map_err(|_| parse_error(format!("invalid lag_alarm '{}'", value)))?,
+                    );
+                }
+                Some(other) => return Err(parse_error(format!("unknown follower setting '{}'", other))),
+            }
+        }
+
+        let followers: Vec<Follower> = followers.into_values().collect();
+        if let Some(follower) = followers.iter().find(|f| f.addr.is_empty()) {
+            return Err(Error::MissingAddress(follower.name.clone()));
+        }
+
+        Ok(Self {
+            leader: leader.ok_or(Error::MissingLeader)?,
+            followers,
+        })
+    }
+}
@@ -0,0 +1,71 @@
+use futures_util::StreamExt;
+use log_proxy::Proxy;
+use log_server_test::TestServer;
+use log_server_types::kv::{kv_server_client::KvServerClient, StatsRequest, SubscribeRequest, WriteRequest};
+use tokio::net::TcpListener;
+
+/// Serves `proxy` on a random local port and returns its URL.
+async fn serve(proxy: Proxy) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(proxy.into_server())
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    url
+}
+
+async fn first_key(client: &mut KvServerClient<tonic::transport::Channel>) -> String {
+    let mut stream = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner();
+    stream.next().await.unwrap().unwrap().key
+}
+
+#[tokio::test]
+async fn test_routes_writes_to_leader_and_subscribes_to_replica() {
+    let leader = TestServer::spawn().await;
+    let replica = TestServer::spawn().await;
+    replica.storage().append("map:replica".to_string(), b"1".to_vec()).await.unwrap();
+
+    let url = serve(Proxy::new(&leader.url(), &[replica.url()]).unwrap()).await;
+    let mut client = KvServerClient::connect(url).await.unwrap();
+
+    let request = WriteRequest {
+        ordinal: 0,
+        key: "map:leader".to_string(),
+        value: b"1".to_vec(),
+        latest_known: 0,
+        ..Default::default()
+    };
+    let mut responses = client
+        .write(tokio_stream::once(request))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(responses.next().await.unwrap().unwrap().accepted);
+
+    let stats = client.stats(StatsRequest {}).await.unwrap().into_inner();
+    assert_eq!(stats.latest_ordinal, 1);
+    assert_eq!(leader.storage().stats().await.unwrap().record_count, 1);
+
+    assert_eq!(first_key(&mut client).await, "map:replica");
+}
+
+#[tokio::test]
+async fn test_subscribe_falls_back_to_leader() {
+    let leader = TestServer::spawn().await;
+    leader.storage().append("map:leader".to_string(), b"1".to_vec()).await.unwrap();
+
+    let replica = TestServer::spawn().await;
+    let replica_url = replica.url();
+    replica.shutdown().await;
+
+    let url = serve(Proxy::new(&leader.url(), &[replica_url]).unwrap()).await;
+    let mut client = KvServerClient::connect(url).await.unwrap();
+
+    assert_eq!(first_key(&mut client).await, "map:leader");
+}
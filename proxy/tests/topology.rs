@@ -0,0 +1,78 @@
+use log_proxy::{Proxy, Topology};
+use log_server_test::TestServer;
+use log_server_types::kv::{kv_server_client::KvServerClient, StatsRequest};
+use tokio::net::TcpListener;
+
+#[test]
+fn test_parses_named_followers() {
+    let topology = Topology::parse(
+        "leader = 10.0.0.1:50051\n\
+         follower.us-east = 10.2.0.1:50051\n\
+         follower.eu-west.lag_alarm = 1000  # settings may come first\n\
+         follower.eu-west = 10.1.0.1:50051\n\
+         follower.eu-west.region = eu\n",
+    )
+    .unwrap();
+
+    assert_eq!(topology.leader, "10.0.0.1:50051");
+    let names: Vec<_> = topology.followers.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["eu-west", "us-east"]);
+    assert_eq!(topology.followers[0].region, "eu");
+    assert_eq!(topology.followers[0].lag_alarm, Some(1000));
+    assert_eq!(topology.followers[1].lag_alarm, None);
+
+    assert!(Topology::parse("follower.a = 10.1.0.1:50051").is_err());
+    assert!(Topology::parse("leader = x\nfollower.a.region = eu").is_err());
+    assert!(Topology::parse("leader = x\nfollower.a.colour = blue").is_err());
+}
+
+#[tokio::test]
+async fn test_stats_report_replica_lag() {
+    let leader = TestServer::spawn().await;
+    for key in ["map:1", "map:2", "map:3"] {
+        leader.storage().append(key.to_string(), b"1".to_vec()).await.unwrap();
+    }
+    let close = TestServer::spawn().await;
+    close.storage().append("map:1".to_string(), b"1".to_vec()).await.unwrap();
+    let far = TestServer::spawn().await;
+    let gone = TestServer::spawn().await;
+    let gone_addr = gone.addr();
+    gone.shutdown().await;
+
+    let topology = Topology::parse(&format!(
+        "leader = {}\n\
+         follower.close = {}\n\
+         follower.close.region = eu\n\
+         follower.close.lag_alarm = 5\n\
+         follower.far = {}\n\
+         follower.far.lag_alarm = 2\n\
+         follower.gone = {}\n",
+        leader.addr(),
+        close.addr(),
+        far.addr(),
+        gone_addr,
+    ))
+    .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(Proxy::from_topology(&topology).unwrap().into_server())
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let mut client = KvServerClient::connect(url).await.unwrap();
+    let stats = client.stats(StatsRequest {}).await.unwrap().into_inner();
+    assert_eq!(stats.latest_ordinal, 3);
+
+    let replicas: Vec<_> = stats
+        .replicas
+        .iter()
+        .map(|r| (r.name.as_str(), r.region.as_str(), r.lag, r.alarm))
+        .collect();
+    assert_eq!(
+        replicas,
+        [("close", "eu", 2, false), ("far", "", 3, true), ("gone", "", 3, true)]
+    );
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use log_server::models::ClientIdentity;
+use log_server::snapshot::Snapshot;
+use log_server::storage::Storage;
+use tokio::runtime::Runtime;
+
+async fn memory_storage() -> Arc<Storage> {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    Arc::new(Storage::new(pool))
+}
+
+fn bench_write(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let storage = rt.block_on(memory_storage());
+    let value = vec![b'x'; 64];
+
+    let mut group = c.benchmark_group("storage_write");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("sequential", |b| {
+        let mut key = 0u64;
+        b.to_async(&rt).iter(|| {
+            key += 1;
+            let storage = Arc::clone(&storage);
+            let value = value.clone();
+            async move {
+                storage
+                    .write(format!("map:{}", key), value, 0, &ClientIdentity::default())
+                    .await
+                    .unwrap()
+            }
+        });
+    });
+    group.finish();
+}
+
+/// N writers hammering the same small key set, timing until all land.
+fn bench_concurrent_writers(c: &mut Criterion) {
+    const WRITES_PER_WRITER: usize = 50;
+    let rt = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("storage_concurrent_writers");
+    for writers in [1usize, 4, 16] {
+        group.throughput(Throughput::Elements((writers * WRITES_PER_WRITER) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(writers), &writers, |b, &writers| {
+            b.to_async(&rt).iter_custom(|iters| async move {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let storage = memory_storage().await;
+                    let start = Instant::now();
+                    let tasks: Vec<_> = (0..writers)
+                        .map(|w| {
+                            let storage = Arc::clone(&storage);
+                            tokio::spawn(async move {
+                                let mut conflicts = 0usize;
+                                for i in 0..WRITES_PER_WRITER {
+                                    let key = format!("map:{}", (w + i) % 8);
+                                    if storage.write(key, vec![1; 16], 0, &ClientIdentity::default()).await.is_err() {
+                                        conflicts += 1;
+                                    }
+                                }
+                                conflicts
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                    total += start.elapsed();
+                }
+                total
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_snapshot(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = std::env::temp_dir().join(format!("log-server-bench-{}", std::process::id()));
+    let snapshot = Snapshot::new(dir.to_str().unwrap(), u64::MAX).unwrap();
+    let records: Vec<(String, Vec<u8>)> = (0..10_000)
+        .map(|i| (format!("map:{}", i), format!("value-{}", i).into_bytes()))
+        .collect();
+
+    let mut group = c.benchmark_group("snapshot");
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.bench_function("encode_binary", |b| {
+        b.to_async(&rt).iter(|| snapshot.save_binary(records.len() as u64, &records));
+    });
+    rt.block_on(snapshot.save_binary(records.len() as u64, &records)).unwrap();
+    group.bench_function("decode_binary", |b| {
+        b.to_async(&rt).iter(|| async { snapshot.load_binary().await.unwrap() });
+    });
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+criterion_group!(benches, bench_write, bench_concurrent_writers, bench_snapshot);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Live counters about what the server is doing, for the status page.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// How many conflicts [`Activity::recent_conflicts`] remembers.
+const RECENT_CONFLICTS: usize = 20;
+
+/// A rejected write, as shown on the status page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: String,
+    pub latest_ordinal: u64,
+    /// Unix millis.
+    pub at: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct Activity {
+    subscribers: Arc<AtomicUsize>,
+    conflicts: Mutex<VecDeque<Conflict>>,
+}
+
+impl Activity {
+    /// Number of open Subscribe streams.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.load(Ordering::Relaxed)
+    }
+
+    /// Counts a subscriber until the returned guard is dropped.
+    pub fn track_subscriber(&self) -> SubscriberGuard {
+        self.subscribers.fetch_add(1, Ordering::Relaxed);
+        SubscriberGuard(Arc::clone(&self.subscribers))
+    }
+
+    pub fn record_conflict(&self, key: &str, latest_ordinal: u64) {
+        let mut conflicts = self.conflicts.lock().unwrap();
+        if conflicts.len() == RECENT_CONFLICTS {
+            conflicts.pop_front();
+        }
+        conflicts.push_back(Conflict {
+            key: key.to_string(),
+            latest_ordinal,
+            at: chrono::Utc::now().timestamp_millis(),
+        });
+    }
+
+    /// The most recent conflicts, newest first.
+    pub fn recent_conflicts(&self) -> Vec<Conflict> {
+        self.conflicts.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+pub struct SubscriberGuard(Arc<AtomicUsize>);
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
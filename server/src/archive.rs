@@ -0,0 +1,240 @@
+//! Portable log archives (`.bmap2`) for `dump` and `restore`.
+//!
+//! Unlike `.bmap` snapshots, which hold only the latest value per key, an
+//! archive holds raw records with their ordinals and timestamps, so a
+//! restored log replays exactly like the original.
+//!
+//! # Format
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! header:
+//!   magic           4 bytes   "BMP2"
+//!   version         u32       1
+//!   from_ordinal    u64       records start after this ordinal
+//!   to_ordinal      u64       ordinal of the last record (0 if empty)
+//!   created_at      i64       unix millis when the dump was taken
+//!   record_count    u64
+//! record (repeated record_count times, in ordinal order):
+//!   ordinal         u64
+//!   timestamp       i64
+//!   key_len         u16
+//!   key             key_len bytes, UTF-8
+//!   value_len       u32
+//!   value           value_len bytes
+//! trailer:
+//!   checksum        u64       FNV-1a over every record, see `migrate`
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+use futures_util::TryStreamExt;
+use sqlx::SqlitePool;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::migrate::Checksum;
+
+const MAGIC: &[u8; 4] = b"BMP2";
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Sql(sqlx::Error),
+    InvalidMagic(String),
+    InvalidVersion(u32),
+    InvalidKey(u64),
+    ChecksumMismatch { expected: u64, actual: u64 },
+    /// Restoring would overwrite records already in the database.
+    Overlap { latest: u64, first: u64 },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        Error::Sql(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Sql(e) => write!(f, "Database error: {}", e),
+            Error::InvalidMagic(s) => write!(f, "Invalid magic: {}", s),
+            Error::InvalidVersion(v) => write!(f, "Invalid version: {}", v),
+            Error::InvalidKey(ord) => write!(f, "Record {} has a key that is not UTF-8", ord),
+            Error::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: archive says {:016x}, records hash to {:016x}",
+                expected, actual
+            ),
+            Error::Overlap { latest, first } => write!(
+                f,
+                "Database already holds ordinals up to {}, archive starts at {}",
+                latest, first
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Archive header, returned by [`dump`] and [`restore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub from_ordinal: u64,
+    pub to_ordinal: u64,
+    pub created_at: i64,
+    pub record_count: u64,
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} records, ordinals {}..={}",
+            self.record_count,
+            self.from_ordinal + 1,
+            self.to_ordinal
+        )
+    }
+}
+
+const HEADER_LEN: usize = 4 + 4 + 8 + 8 + 8 + 8;
+
+/// Writes every record with an ordinal above `from_ordinal` to `path`.
+pub async fn dump(pool: &SqlitePool, from_ordinal: u64, path: &Path) -> Result<Metadata, Error> {
+    let (to_ordinal, record_count): (Option<i64>, i64) =
+        sqlx::query_as("SELECT MAX(ordinal), COUNT(*) FROM records WHERE ordinal > ?")
+            .bind(from_ordinal as i64)
+            .fetch_one(pool)
+            .await?;
+
+    let metadata = Metadata {
+        from_ordinal,
+        to_ordinal: to_ordinal.unwrap_or(0) as u64,
+        created_at: chrono::Utc::now().timestamp_millis(),
+        record_count: record_count as u64,
+    };
+
+    let mut out = BufWriter::new(File::create(path).await?);
+    out.write_all(MAGIC).await?;
+    out.write_u32_le(VERSION).await?;
+    out.write_u64_le(metadata.from_ordinal).await?;
+    out.write_u64_le(metadata.to_ordinal).await?;
+    out.write_i64_le(metadata.created_at).await?;
+    out.write_u64_le(metadata.record_count).await?;
+
+    // Bounded by to_ordinal so writes landing mid-dump don't disagree with
+    // the header.
+    let mut rows = sqlx::query_as::<_, (i64, String, Option<Vec<u8>>, i64)>(
+        "SELECT ordinal, key, value, timestamp FROM records
+         WHERE ordinal > ? AND ordinal <= ? ORDER BY ordinal",
+    )
+    .bind(from_ordinal as i64)
+    .bind(metadata.to_ordinal as i64)
+    .fetch(pool);
+
+    let mut checksum = Checksum::new();
+    while let Some((ordinal, key, value, timestamp)) = rows.try_next().await? {
+        let value = value.unwrap_or_default();
+        checksum.record(ordinal, &key, &value, timestamp);
+
+        out.write_u64_le(ordinal as u64).await?;
+        out.write_i64_le(timestamp).await?;
+        out.write_u16_le(key.len() as u16).await?;
+        out.write_all(key.as_bytes()).await?;
+        out.write_u32_le(value.len() as u32).await?;
+        out.write_all(&value).await?;
+    }
+
+    out.write_u64_le(checksum.finish()).await?;
+    out.flush().await?;
+
+    Ok(metadata)
+}
+
+/// Loads an archive written by [`dump`] into `pool`.
+///
+/// The archive is verified before anything is inserted, and the whole
+/// restore runs in one transaction. Archives can be restored on top of an
+/// existing log as long as they start after its latest ordinal.
+pub async fn restore(pool: &SqlitePool, path: &Path) -> Result<Metadata, Error> {
+    let mut input = BufReader::new(File::open(path).await?);
+
+    let mut header = [0u8; HEADER_LEN];
+    input.read_exact(&mut header).await?;
+    if &header[0..4] != MAGIC {
+        return Err(Error::InvalidMagic(
+            String::from_utf8_lossy(&header[0..4]).to_string(),
+        ));
+    }
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if version != VERSION {
+        return Err(Error::InvalidVersion(version));
+    }
+    let field = |at: usize| <[u8; 8]>::try_from(&header[at..at + 8]).unwrap();
+    let metadata = Metadata {
+        from_ordinal: u64::from_le_bytes(field(8)),
+        to_ordinal: u64::from_le_bytes(field(16)),
+        created_at: i64::from_le_bytes(field(24)),
+        record_count: u64::from_le_bytes(field(32)),
+    };
+
+    let mut records = Vec::with_capacity(metadata.record_count.min(1 << 20) as usize);
+    let mut checksum = Checksum::new();
+    for _ in 0..metadata.record_count {
+        let ordinal = input.read_u64_le().await?;
+        let timestamp = input.read_i64_le().await?;
+        let mut key = vec![0u8; input.read_u16_le().await? as usize];
+        input.read_exact(&mut key).await?;
+        let key = String::from_utf8(key).map_err(|_| Error::InvalidKey(ordinal))?;
+        let mut value = vec![0u8; input.read_u32_le().await? as usize];
+        input.read_exact(&mut value).await?;
+
+        checksum.record(ordinal as i64, &key, &value, timestamp);
+        records.push((ordinal as i64, key, value, timestamp));
+    }
+
+    let expected = input.read_u64_le().await?;
+    let actual = checksum.finish();
+    if expected != actual {
+        return Err(Error::ChecksumMismatch { expected, actual });
+    }
+
+    let mut tx = pool.begin().await?;
+    let (latest,): (Option<i64>,) = sqlx::query_as("SELECT MAX(ordinal) FROM records")
+        .fetch_one(&mut *tx)
+        .await?;
+    let latest = latest.unwrap_or(0) as u64;
+    if let Some((first, ..)) = records.first() {
+        if *first as u64 <= latest {
+            return Err(Error::Overlap {
+                latest,
+                first: *first as u64,
+            });
+        }
+    }
+
+    for (ordinal, key, value, timestamp) in records {
+        sqlx::query("INSERT INTO records (ordinal, key, value, timestamp) VALUES (?, ?, ?, ?)")
+            .bind(ordinal)
+            .bind(key)
+            .bind(value)
+            .bind(timestamp)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(metadata)
+}
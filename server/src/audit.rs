@@ -0,0 +1,174 @@
+//! Offline consistency checks over the `records` table.
+//!
+//! `Storage::write` assigns ordinals itself and inserts with
+//! `ON CONFLICT(ordinal) DO UPDATE`, so a bad ordinal silently rewrites
+//! history instead of failing. The audit walks the table in ordinal order
+//! and reports anything that suggests that happened.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use futures_util::TryStreamExt;
+use sqlx::SqlitePool;
+
+/// A single problem found by [`audit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// Ordinals `from..=to` are missing from the log.
+    Gap { from: u64, to: u64 },
+    /// More than one row carries the same ordinal. Impossible with the
+    /// schema from `db::init_pool`, but databases copied by hand can lose
+    /// the primary key.
+    DuplicateOrdinal { ordinal: u64, copies: u64 },
+    /// The record is newer than the one after it, which is what an upsert
+    /// over an existing ordinal leaves behind.
+    Rewritten {
+        ordinal: u64,
+        timestamp: i64,
+        next_ordinal: u64,
+        next_timestamp: i64,
+    },
+    /// An empty value (delete) for a key that had no live value.
+    OrphanTombstone { ordinal: u64, key: String },
+}
+
+impl Issue {
+    /// A short, human-readable suggestion for fixing the issue.
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            Issue::Gap { .. } => {
+                "subscribers skip missing ordinals silently; if writes were acknowledged \
+                 for them, restore the records from the latest snapshot"
+            }
+            Issue::DuplicateOrdinal { .. } => {
+                "keep the row that matches the snapshot, delete the others and recreate \
+                 the table with `ordinal INTEGER PRIMARY KEY`"
+            }
+            Issue::Rewritten { .. } => {
+                "the original record was overwritten in place; compare with the snapshot \
+                 and re-append the lost value as a new record if it mattered"
+            }
+            Issue::OrphanTombstone { .. } => {
+                "harmless for readers; the record can be deleted to keep the log tidy"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Gap { from, to } if from == to => write!(f, "ordinal {} is missing", from),
+            Issue::Gap { from, to } => write!(f, "ordinals {}..={} are missing", from, to),
+            Issue::DuplicateOrdinal { ordinal, copies } => {
+                write!(f, "ordinal {} appears {} times", ordinal, copies)
+            }
+            Issue::Rewritten {
+                ordinal,
+                timestamp,
+                next_ordinal,
+                next_timestamp,
+            } => write!(
+                f,
+                "ordinal {} (written at {}) is newer than ordinal {} (written at {})",
+                ordinal, timestamp, next_ordinal, next_timestamp
+            ),
+            Issue::OrphanTombstone { ordinal, key } => {
+                write!(f, "ordinal {} deletes '{}', which has no live value", ordinal, key)
+            }
+        }
+    }
+}
+
+/// Result of an [`audit`] run.
+#[derive(Debug, Clone, Default)]
+pub struct AuditReport {
+    pub records_scanned: u64,
+    pub issues: Vec<Issue>,
+}
+
+impl AuditReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "scanned {} records, found {} issues",
+            self.records_scanned,
+            self.issues.len()
+        )?;
+        for issue in &self.issues {
+            writeln!(f, "- {}", issue)?;
+            writeln!(f, "  fix: {}", issue.suggestion())?;
+        }
+        Ok(())
+    }
+}
+
+/// Scans the whole `records` table and reports consistency issues.
+///
+/// Rows are streamed in ordinal order, so memory use grows with the number
+/// of distinct keys rather than the size of the log.
+pub async fn audit(pool: &SqlitePool) -> Result<AuditReport, sqlx::Error> {
+    let mut report = AuditReport::default();
+
+    let duplicates = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT ordinal, COUNT(*) FROM records GROUP BY ordinal HAVING COUNT(*) > 1 ORDER BY ordinal",
+    )
+    .fetch_all(pool)
+    .await?;
+    for (ordinal, copies) in duplicates {
+        report.issues.push(Issue::DuplicateOrdinal {
+            ordinal: ordinal as u64,
+            copies: copies as u64,
+        });
+    }
+
+    let mut rows = sqlx::query_as::<_, (i64, String, Option<Vec<u8>>, i64)>(
+        "SELECT ordinal, key, value, timestamp FROM records ORDER BY ordinal",
+    )
+    .fetch(pool);
+
+    let mut live: HashSet<String> = HashSet::new();
+    let mut previous: Option<(u64, i64)> = None;
+
+    while let Some((ordinal, key, value, timestamp)) = rows.try_next().await? {
+        let ordinal = ordinal as u64;
+        report.records_scanned += 1;
+
+        let previous_ordinal = previous.map_or(0, |(ord, _)| ord);
+        if ordinal > previous_ordinal + 1 {
+            report.issues.push(Issue::Gap {
+                from: previous_ordinal + 1,
+                to: ordinal - 1,
+            });
+        }
+
+        if let Some((prev_ordinal, prev_timestamp)) = previous {
+            if prev_ordinal != ordinal && prev_timestamp > timestamp {
+                report.issues.push(Issue::Rewritten {
+                    ordinal: prev_ordinal,
+                    timestamp: prev_timestamp,
+                    next_ordinal: ordinal,
+                    next_timestamp: timestamp,
+                });
+            }
+        }
+
+        if value.as_deref().is_none_or(<[u8]>::is_empty) {
+            if !live.remove(&key) {
+                report.issues.push(Issue::OrphanTombstone { ordinal, key });
+            }
+        } else {
+            live.insert(key);
+        }
+
+        previous = Some((ordinal, timestamp));
+    }
+
+    Ok(report)
+}
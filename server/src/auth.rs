@@ -0,0 +1,57 @@
+//! Static bearer-token authentication.
+//!
+//! ```text
+//! auth_token = s3cr3t
+//! ```
+//!
+//! With a token configured, every KV call must carry
+//! `authorization: Bearer <token>`; anything else is rejected with
+//! `UNAUTHENTICATED`. Pair it with TLS (`tls_cert`, `tls_key`) unless the
+//! network is trusted, or the token travels in the clear.
+
+use std::sync::Arc;
+
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// Checks the `authorization` header against a fixed token, see
+/// [`grpc::create_server_with_layer`](crate::grpc::create_server_with_layer).
+#[derive(Clone)]
+pub struct BearerToken {
+    expected: Arc<str>,
+}
+
+impl BearerToken {
+    pub fn new(token: &str) -> Self {
+        Self {
+            expected: format!("Bearer {}", token).into(),
+        }
+    }
+
+    /// Whether `presented`, an `authorization` header value, carries the
+    /// token.
+    pub fn accepts(&self, presented: &[u8]) -> bool {
+        constant_time_eq(presented, self.expected.as_bytes())
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .map(|value| value.as_bytes())
+            .unwrap_or_default();
+        if self.accepts(presented) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("missing or wrong token"))
+        }
+    }
+}
+
+/// Compares without returning early, so response times don't reveal how
+/// much of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
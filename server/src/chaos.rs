@@ -0,0 +1,82 @@
+//! Fault injection for resilience testing.
+//!
+//! Compiled only with the `chaos` feature. A [`FaultInjector`] is attached to
+//! a service via [`create_server_with_faults`](crate::grpc::create_server_with_faults)
+//! and can be reconfigured at any time, so a test can let a client settle
+//! and then switch faults on for just the phase under test. Decisions come
+//! from a seeded RNG, making failure patterns reproducible.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Fault probabilities and delays. The default injects nothing.
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    /// Probability that a write is answered with a rejection.
+    pub write_reject_rate: f64,
+    /// Delay added before each record delivered to a subscriber.
+    pub subscribe_delay: Duration,
+    /// Probability, per message, that a subscribe or write stream is
+    /// terminated with `UNAVAILABLE`.
+    pub stream_drop_rate: f64,
+}
+
+/// Shared, reconfigurable source of injected faults.
+#[derive(Clone)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<State>>,
+}
+
+struct State {
+    config: FaultConfig,
+    rng: StdRng,
+}
+
+impl FaultInjector {
+    /// Creates an injector with no faults enabled.
+    pub fn new(seed: u64) -> Self {
+        Self::with_config(seed, FaultConfig::default())
+    }
+
+    pub fn with_config(seed: u64, config: FaultConfig) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(State {
+                config,
+                rng: StdRng::seed_from_u64(seed),
+            })),
+        }
+    }
+
+    /// Replaces the active fault configuration.
+    pub fn set_config(&self, config: FaultConfig) {
+        self.inner.lock().unwrap().config = config;
+    }
+
+    /// Turns all faults off.
+    pub fn clear(&self) {
+        self.set_config(FaultConfig::default());
+    }
+
+    pub(crate) fn reject_write(&self) -> bool {
+        let mut state = self.inner.lock().unwrap();
+        let rate = state.config.write_reject_rate;
+        roll(&mut state.rng, rate)
+    }
+
+    pub(crate) fn drop_stream(&self) -> bool {
+        let mut state = self.inner.lock().unwrap();
+        let rate = state.config.stream_drop_rate;
+        roll(&mut state.rng, rate)
+    }
+
+    pub(crate) fn subscribe_delay(&self) -> Duration {
+        self.inner.lock().unwrap().config.subscribe_delay
+    }
+}
+
+fn roll(rng: &mut StdRng, rate: f64) -> bool {
+    rate > 0.0 && rng.gen_bool(rate.min(1.0))
+}
@@ -0,0 +1,40 @@
+//! Keeps the records table from growing forever.
+//!
+//! ```text
+//! compaction_interval = 3600
+//! compaction_retain = 10000
+//! ```
+//!
+//! Every `compaction_interval` seconds (never, unless set), records that
+//! were overwritten or removed are deleted up to a cutoff ordinal, see
+//! [`Storage::compact`]. The cutoff stays `compaction_retain` ordinals,
+//! and at least one, behind the head of the log, and behind the latest
+//! snapshot, so clients starting from a snapshot and subscribers replaying
+//! recent history see every change. A subscriber that is further behind than that can miss a
+//! removal and should resync from a snapshot. `Compact` runs it on demand.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::Storage;
+
+/// Ordinals behind the head left alone, unless configured.
+pub const DEFAULT_RETAIN: u64 = 10_000;
+
+/// Compacts the log every `interval`, starting one interval from now.
+/// Never returns.
+pub async fn schedule(storage: Arc<Storage>, interval: Duration) {
+    tracing::info!(
+        "compaction every {}s, retaining {} ordinals",
+        interval.as_secs(),
+        storage.compaction_retain()
+    );
+
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = storage.compact().await {
+            tracing::error!(error = %e, "compaction failed");
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Runtime settings that can be reloaded without restarting the server.
+//!
+//! The file is plain `key = value` lines; `#` starts a comment.
+//!
+//! ```text
+//! listen_addr = 0.0.0.0:50051
+//! database_url = sqlite:/var/lib/log-server/log.db
+//! snapshot_dir = /var/lib/log-server/snapshots
+//! log_level = debug
+//! log_target = file:/var/log/log-server.log
+//! log_format = json
+//! snapshot_interval = 500
+//! status_addr = 127.0.0.1:8080
+//! metrics_addr = 127.0.0.1:9100
+//! rest_addr = 127.0.0.1:8081
+//! durability = group:50
+//! maintenance_window = sat,sun 02:00-04:00
+//! maintenance_tasks = analyze, vacuum, snapshot
+//! catch_up_ratio = 8
+//! compaction_interval = 3600
+//! compaction_retain = 10000
+//! max_value_size = 1048576
+//! expiry_interval = 1
+//! tls_cert = /etc/log-server/cert.pem
+//! tls_key = /etc/log-server/key.pem
+//! auth_token = s3cr3t
+//! ```
+//!
+//! `listen_addr`, `database_url`, `snapshot_dir`, `log_target`,
+//! `log_format`, the rotation settings, `status_addr`, `metrics_addr`, `rest_addr`,
+//! `durability`, the maintenance settings, `compaction_interval`, `expiry_interval`, TLS and
+//! `auth_token` only take effect at startup.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::level_filters::LevelFilter;
+
+use crate::compaction;
+use crate::db::Durability;
+use crate::expiry;
+use crate::logging::{LogFormat, LogTarget};
+use crate::maintenance::{self, Task, Window};
+use crate::priority;
+use crate::storage;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Where to serve the gRPC API.
+    pub listen_addr: SocketAddr,
+    /// The log, e.g. `sqlite:log.db`.
+    pub database_url: String,
+    pub snapshot_dir: PathBuf,
+    pub log_level: LevelFilter,
+    pub log_target: LogTarget,
+    pub log_format: LogFormat,
+    pub log_rotate_bytes: u64,
+    pub log_rotate_keep: usize,
+    pub snapshot_interval: u64,
+    /// Where to serve the HTTP status page, if anywhere.
+    pub status_addr: Option<SocketAddr>,
+    /// Where to serve Prometheus metrics, if anywhere. See
+    /// [`metrics`](crate::metrics).
+    pub metrics_addr: Option<SocketAddr>,
+    /// Where to serve the REST/JSON gateway, if anywhere. See
+    /// [`rest`](crate::rest).
+    pub rest_addr: Option<SocketAddr>,
+    /// `full`, `group:<ms>` or `buffered`, see [`Durability`].
+    pub durability: Durability,
+    /// When to run [`maintenance_tasks`](Self::maintenance_tasks), if ever.
+    pub maintenance_window: Option<Window>,
+    pub maintenance_tasks: Vec<Task>,
+    /// Writes served per catch-up batch under load, 0 for no throttling.
+    /// See [`priority`](crate::priority).
+    pub catch_up_ratio: u32,
+    /// How often to compact the log, if ever. See
+    /// [`compaction`](crate::compaction).
+    pub compaction_interval: Option<Duration>,
+    /// Ordinals behind the head of the log that compaction leaves alone.
+    pub compaction_retain: u64,
+    /// Largest value a write may carry in bytes, 0 for no limit.
+    pub max_value_size: u64,
+    /// How often to tombstone lapsed entries, if ever. See
+    /// [`expiry`](crate::expiry).
+    pub expiry_interval: Option<Duration>,
+    /// PEM certificate chain and private key; both or neither. Needs the
+    /// `tls` feature.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Bearer token every call must present, see [`auth`](crate::auth).
+    pub auth_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 50051)),
+            database_url: "sqlite:log.db".to_string(),
+            snapshot_dir: PathBuf::from("./snapshots"),
+            log_level: LevelFilter::INFO,
+            log_target: LogTarget::Stdout,
+            log_format: LogFormat::Text,
+            log_rotate_bytes: 10 * 1024 * 1024,
+            log_rotate_keep: 5,
+            snapshot_interval: 100,
+            status_addr: None,
+            metrics_addr: None,
+            rest_addr: None,
+            durability: Durability::Full,
+            maintenance_window: None,
+            maintenance_tasks: vec![Task::Analyze, Task::Vacuum],
+            catch_up_ratio: priority::DEFAULT_CATCH_UP_RATIO,
+            compaction_interval: None,
+            compaction_retain: compaction::DEFAULT_RETAIN,
+            max_value_size: storage::DEFAULT_MAX_VALUE_SIZE,
+            expiry_interval: Some(expiry::DEFAULT_INTERVAL),
+            tls_cert: None,
+            tls_key: None,
+            auth_token: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a config file. Missing keys keep their defaults.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut config = Config::default();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parse_error = |message: String| Error::Parse {
+                line: index + 1,
+                message,
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| parse_error(format!("expected `key = value`, got '{}'", line)))?;
+            config.set(key.trim(), value.trim()).map_err(parse_error)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Sets one setting from its text form, as in a config file, e.g. for
+    /// a command-line override.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "listen_addr" => {
+                self.listen_addr = value
+                    .parse()
+                    .map_err(|_| format!("invalid listen_addr '{}'", value))?;
+            }
+            "database_url" => self.database_url = value.to_string(),
+            "snapshot_dir" => self.snapshot_dir = PathBuf::from(value),
+            "log_level" => {
+                self.log_level = value
+                    .parse()
+                    .map_err(|_| format!("invalid log level '{}'", value))?;
+            }
+            "log_target" => self.log_target = value.parse()?,
+            "log_format" => self.log_format = value.parse()?,
+            "log_rotate_bytes" => {
+                self.log_rotate_bytes = value
+                    .parse()
+                    .ok()
+                    .filter(|bytes| *bytes > 0)
+                    .ok_or_else(|| format!("invalid log_rotate_bytes '{}'", value))?;
+            }
+            "log_rotate_keep" => {
+                self.log_rotate_keep = value
+                    .parse()
+                    .map_err(|_| format!("invalid log_rotate_keep '{}'", value))?;
+            }
+            "snapshot_interval" => {
+                self.snapshot_interval = value
+                    .parse()
+                    .ok()
+                    .filter(|interval| *interval > 0)
+                    .ok_or_else(|| format!("invalid snapshot interval '{}'", value))?;
+            }
+            "status_addr" => {
+                self.status_addr = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid status_addr '{}'", value))?,
+                );
+            }
+            "metrics_addr" => {
+                self.metrics_addr = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid metrics_addr '{}'", value))?,
+                );
+            }
+            "rest_addr" => {
+                self.rest_addr = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid rest_addr '{}'", value))?,
+                );
+            }
+            "durability" => self.durability = value.parse()?,
+            "maintenance_window" => self.maintenance_window = Some(value.parse()?),
+            "maintenance_tasks" => self.maintenance_tasks = maintenance::parse_tasks(value)?,
+            "catch_up_ratio" => {
+                self.catch_up_ratio = value
+                    .parse()
+                    .map_err(|_| format!("invalid catch_up_ratio '{}'", value))?;
+            }
+            "compaction_interval" => {
+                let seconds: u64 = value
+                    .parse()
+                    .map_err(|_| format!("invalid compaction_interval '{}'", value))?;
+                self.compaction_interval = (seconds > 0).then(|| Duration::from_secs(seconds));
+            }
+            "compaction_retain" => {
+                self.compaction_retain = value
+                    .parse()
+                    .map_err(|_| format!("invalid compaction_retain '{}'", value))?;
+            }
+            "max_value_size" => {
+                self.max_value_size = value
+                    .parse()
+                    .map_err(|_| format!("invalid max_value_size '{}'", value))?;
+            }
+            "expiry_interval" => {
+                let seconds: u64 = value
+                    .parse()
+                    .map_err(|_| format!("invalid expiry_interval '{}'", value))?;
+                self.expiry_interval = (seconds > 0).then(|| Duration::from_secs(seconds));
+            }
+            "tls_cert" => self.tls_cert = Some(PathBuf::from(value)),
+            "tls_key" => self.tls_key = Some(PathBuf::from(value)),
+            "auth_token" => {
+                if value.is_empty() || !value.bytes().all(|b| b.is_ascii_graphic()) {
+                    return Err("auth_token must be non-empty printable ASCII".to_string());
+                }
+                self.auth_token = Some(value.to_string());
+            }
+            other => return Err(format!("unknown key '{}'", other)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Detaching from the terminal and pid-file management for init systems.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Forks into the background and starts a new session.
+///
+/// Must run before the tokio runtime starts: only the calling thread
+/// survives a fork. The working directory is kept so relative database and
+/// snapshot paths still resolve. Stdin is closed, and stdout/stderr go to
+/// `log_file` if given, `/dev/null` otherwise.
+pub fn daemonize(log_file: Option<&Path>) -> io::Result<()> {
+    // Open before forking so errors still reach the terminal.
+    let null = File::open("/dev/null")?;
+    let output = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+
+    // SAFETY: no other threads exist yet, so the child starts consistent.
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+
+    // SAFETY: plain syscalls on descriptors owned by this process.
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        for (from, to) in [
+            (null.as_raw_fd(), libc::STDIN_FILENO),
+            (output.as_raw_fd(), libc::STDOUT_FILENO),
+            (output.as_raw_fd(), libc::STDERR_FILENO),
+        ] {
+            if libc::dup2(from, to) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Holds a pid file for the lifetime of the process and removes it on drop.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        std::fs::write(path, format!("{}\n", std::process::id()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
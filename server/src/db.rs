@@ -1,11 +1,55 @@
 use sqlx::{
     migrate::MigrateDatabase,
-    sqlite::{Sqlite, SqlitePool},
+    sqlite::{Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqliteSynchronous},
     Pool,
 };
+use std::str::FromStr;
+use std::time::Duration;
 
 pub type DbPool = Pool<Sqlite>;
 
+/// When committed writes reach the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// fsync on every commit (SQLite's `synchronous = FULL`).
+    #[default]
+    Full,
+    /// WAL with `synchronous = NORMAL`, checkpointed (and so fsynced) every
+    /// interval. A crash can lose the writes of the last interval.
+    Group(Duration),
+    /// No fsync at all (`synchronous = OFF`); the OS decides when data hits
+    /// the disk.
+    Buffered,
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Durability::Full),
+            "buffered" => Ok(Durability::Buffered),
+            other => match other.strip_prefix("group:").map(str::parse::<u64>) {
+                Some(Ok(ms)) if ms > 0 => Ok(Durability::Group(Duration::from_millis(ms))),
+                _ => Err(format!(
+                    "unknown durability '{}', expected full, group:<ms> or buffered",
+                    other
+                )),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Durability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Durability::Full => write!(f, "full"),
+            Durability::Group(interval) => write!(f, "group:{}", interval.as_millis()),
+            Durability::Buffered => write!(f, "buffered"),
+        }
+    }
+}
+
 pub async fn ensure_database_file(url: &str) -> Result<(), sqlx::Error> {
     let exists = Sqlite::database_exists(url).await?;
 
@@ -17,8 +61,23 @@ pub async fn ensure_database_file(url: &str) -> Result<(), sqlx::Error> {
 }
 
 pub async fn init_pool(database_url: &str) -> Result<DbPool, sqlx::Error> {
+    init_pool_with(database_url, Durability::Full).await
+}
+
+/// Like [`init_pool`], with the SQLite pragmas for `durability`. For
+/// [`Durability::Group`] this also starts the periodic checkpoint task, so
+/// it has to run inside a tokio runtime.
+pub async fn init_pool_with(database_url: &str, durability: Durability) -> Result<DbPool, sqlx::Error> {
     ensure_database_file(database_url).await?;
-    let pool = SqlitePool::connect(database_url).await?;
+    let options = SqliteConnectOptions::from_str(database_url)?;
+    let options = match durability {
+        Durability::Full => options.synchronous(SqliteSynchronous::Full),
+        Durability::Group(_) => options
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal),
+        Durability::Buffered => options.synchronous(SqliteSynchronous::Off),
+    };
+    let pool = SqlitePool::connect_with(options).await?;
 
     sqlx::query(
         r#"
@@ -26,12 +85,67 @@ pub async fn init_pool(database_url: &str) -> Result<DbPool, sqlx::Error> {
             ordinal INTEGER PRIMARY KEY AUTOINCREMENT,
             key TEXT NOT NULL,
             value BLOB,
-            timestamp INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000)
+            timestamp INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
+            client_id TEXT NOT NULL DEFAULT '',
+            worker_label TEXT NOT NULL DEFAULT '',
+            expires_at INTEGER NOT NULL DEFAULT 0
         )
         "#,
     )
     .execute(&pool)
     .await?;
+    add_missing_columns(&pool).await?;
+    // Conditional writes look up the latest record of one key.
+    sqlx::query("CREATE INDEX IF NOT EXISTS records_key ON records (key, ordinal)")
+        .execute(&pool)
+        .await?;
+    // The expiry sweep only looks at records that can lapse.
+    sqlx::query("CREATE INDEX IF NOT EXISTS records_expiry ON records (expires_at) WHERE expires_at > 0")
+        .execute(&pool)
+        .await?;
+
+    if let Durability::Group(interval) = durability {
+        tokio::spawn(checkpoint_every(pool.clone(), interval));
+    }
 
     Ok(pool)
 }
+
+/// In WAL mode with `synchronous = NORMAL`, SQLite only fsyncs on
+/// checkpoints; running one per interval bounds what a crash can lose.
+async fn checkpoint_every(pool: DbPool, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if pool.is_closed() {
+            return;
+        }
+        if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(PASSIVE)").execute(&pool).await {
+            tracing::warn!("WAL checkpoint failed: {}", e);
+        }
+    }
+}
+
+/// Columns added after the first release, with their definitions.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("client_id", "TEXT NOT NULL DEFAULT ''"),
+    ("worker_label", "TEXT NOT NULL DEFAULT ''"),
+    ("expires_at", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+/// Brings databases created by older servers up to the current schema.
+async fn add_missing_columns(pool: &DbPool) -> Result<(), sqlx::Error> {
+    let existing: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('records')")
+        .fetch_all(pool)
+        .await?;
+
+    for (name, definition) in ADDED_COLUMNS {
+        if !existing.iter().any(|column| column == name) {
+            sqlx::query(&format!("ALTER TABLE records ADD COLUMN {} {}", name, definition))
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
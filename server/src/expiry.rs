@@ -0,0 +1,32 @@
+//! Removes entries written with an expiry once they lapse.
+//!
+//! ```text
+//! expiry_interval = 1
+//! ```
+//!
+//! Every `expiry_interval` seconds (1 unless set, 0 turns it off) a
+//! tombstone is written for each key whose latest record carries an
+//! `expires_at` in the past, see [`Storage::expire`]. Until then clients
+//! hide lapsed entries themselves, so the interval only bounds how long
+//! they stay in the log and when watchers hear about them.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::storage::Storage;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sweeps lapsed entries every `interval`. Never returns.
+pub async fn schedule(storage: Arc<Storage>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        match storage.expire().await {
+            Ok(0) => {}
+            Ok(removed) => tracing::debug!(removed, "expired entries"),
+            Err(e) => tracing::error!(error = %e, "expiry sweep failed"),
+        }
+    }
+}
@@ -1,46 +1,135 @@
-use crate::storage::{Storage, WriteError};
+use crate::handshake::ClientInfo;
+use crate::models::ClientIdentity;
+use crate::storage::{KeyFilter, Storage, WriteError};
 use futures_util::stream::{Stream, StreamExt};
-use log_server_types::kv::{kv_server_server::{KvServer, KvServerServer}, GetSnapshotRequest, GetSnapshotResponse, Record, SubscribeRequest, WriteRequest, WriteResponse};
+use log_server_types::kv::{kv_server_server::{KvServer, KvServerServer}, CompactRequest, CompactResponse, GetHistoryRequest, GetHistoryResponse, GetServerInfoRequest, GetSnapshotRequest, GetSnapshotResponse, MaintenanceStatus, Record, ServerInfo, SnapshotChunk, StatsRequest, StatsResponse, SubscribeRequest, TransactionRequest, TransactionResponse, WriteRejection, WriteRequest, WriteResponse};
+use log_server_types::{features, PROTOCOL_VERSION};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Instant;
+use tonic::codegen::{http, Service};
+use tonic::server::NamedService;
 use tonic::{Request, Response, Status};
+use tower::Layer;
+use tracing::Instrument;
 
 #[derive(Clone)]
 pub struct KvServiceImpl {
     storage: Arc<Storage>,
+    #[cfg(feature = "chaos")]
+    faults: Option<crate::chaos::FaultInjector>,
 }
 
 impl KvServiceImpl {
     pub fn new(storage: Arc<Storage>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            #[cfg(feature = "chaos")]
+            faults: None,
+        }
+    }
+
+    /// Creates a service whose responses are disturbed by `faults`.
+    #[cfg(feature = "chaos")]
+    pub fn with_faults(storage: Arc<Storage>, faults: crate::chaos::FaultInjector) -> Self {
+        Self {
+            storage,
+            faults: Some(faults),
+        }
+    }
+}
+
+/// Optional protocol features this server implements.
+const FEATURES: &[&str] = &[
+    features::BATCH_WRITES,
+    features::STATS,
+    features::TIMESTAMP_SUBSCRIBE,
+    features::CONDITIONAL_WRITES,
+    #[cfg(feature = "snapshots")]
+    features::COMPACT,
+    features::PREFIX_SUBSCRIBE,
+    features::KEY_SUBSCRIBE,
+    #[cfg(feature = "snapshots")]
+    features::CHUNKED_SNAPSHOTS,
+    features::TRANSACTIONS,
+    features::TTL,
+    features::HISTORY,
+];
+
+/// Bytes per `StreamSnapshot` message, well under tonic's 4 MiB limit.
+const SNAPSHOT_CHUNK: usize = 1024 * 1024;
+
+/// A stored record as sent to clients.
+fn proto_record(record: crate::models::Record) -> Record {
+    Record {
+        ordinal: record.ordinal,
+        key: record.key,
+        value: record.value,
+        timestamp: record.timestamp,
+        client_id: record.writer.client_id,
+        worker_label: record.writer.worker_label,
+        expires_at: record.expires_at,
     }
 }
 
 type SubscribeStream = Pin<Box<dyn Stream<Item = Result<Record, Status>> + Send>>;
 type WriteStream = Pin<Box<dyn Stream<Item = Result<WriteResponse, Status>> + Send>>;
+type SnapshotStream = Pin<Box<dyn Stream<Item = Result<SnapshotChunk, Status>> + Send>>;
 
 #[tonic::async_trait]
 impl KvServer for KvServiceImpl {
     type SubscribeStream = SubscribeStream;
     type WriteStream = WriteStream;
+    type StreamSnapshotStream = SnapshotStream;
 
+    #[tracing::instrument(level = "debug", skip_all, fields(peer = %peer(&request)))]
     async fn subscribe(
         &self,
         request: Request<SubscribeRequest>,
     ) -> Result<Response<Self::SubscribeStream>, Status> {
+        ClientInfo::from_metadata(request.metadata()).warn_if_outdated("Subscribe");
+        let peer = peer(&request);
         let req = request.into_inner();
-        let stream = self.storage.subscribe_from(req.start_ordinal);
+        let mut start_ordinal = req.start_ordinal;
+        if req.start_timestamp > 0 {
+            let from_time = self
+                .storage
+                .ordinal_before(req.start_timestamp)
+                .await
+                .map_err(|e| Status::internal(format!("Failed to resolve start timestamp: {}", e)))?;
+            start_ordinal = start_ordinal.max(from_time);
+        }
+        tracing::info!(
+            peer = %peer,
+            start_ordinal,
+            start_timestamp = req.start_timestamp,
+            key_prefix = %req.key_prefix,
+            keys = req.keys.len(),
+            "subscriber connected"
+        );
+        let filter = KeyFilter {
+            prefix: req.key_prefix,
+            keys: req.keys.into_iter().collect(),
+        };
+        let stream = self.storage.subscribe_from(start_ordinal, filter);
+        #[cfg(feature = "chaos")]
+        let faults = self.faults.clone();
 
         let output = async_stream::stream! {
             let mut db_stream = stream;
             while let Some(record) = db_stream.next().await {
-                let proto_record = Record {
-                    ordinal: record.ordinal,
-                    key: record.key,
-                    value: record.value,
-                    timestamp: record.timestamp,
-                };
-                yield Ok(proto_record);
+                #[cfg(feature = "chaos")]
+                if let Some(faults) = &faults {
+                    let delay = faults.subscribe_delay();
+                    if !delay.is_zero() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    if faults.drop_stream() {
+                        yield Err(Status::unavailable("injected fault: subscribe stream dropped"));
+                        break;
+                    }
+                }
+                yield Ok(proto_record(record));
             }
         };
 
@@ -51,40 +140,128 @@ impl KvServer for KvServiceImpl {
         &self,
         request: Request<tonic::Streaming<WriteRequest>>,
     ) -> Result<Response<Self::WriteStream>, Status> {
+        ClientInfo::from_metadata(request.metadata()).warn_if_outdated("Write");
+        let peer = peer(&request);
         let mut stream = request.into_inner();
+        // The stream outlives this call, so its writes get their own span.
+        let span = tracing::debug_span!("write_stream", peer = %peer);
 
         let storage = self.storage.clone();
+        #[cfg(feature = "chaos")]
+        let faults = self.faults.clone();
         let output = async_stream::stream! {
             while let Some(result) = stream.next().await {
+                #[cfg(feature = "chaos")]
+                if let Some(faults) = &faults {
+                    if faults.drop_stream() {
+                        yield Err(Status::unavailable("injected fault: write stream dropped"));
+                        break;
+                    }
+                    if faults.reject_write() {
+                        yield Ok(WriteResponse {
+                            accepted: false,
+                            error: "injected fault: write rejected".to_string(),
+                            assigned_ordinal: 0,
+                            request_ordinal: result.as_ref().map_or(0, |req| req.ordinal),
+                            key_ordinal: 0,
+                            rejection: WriteRejection::Unspecified.into(),
+                        });
+                        continue;
+                    }
+                }
+
                 match result {
                     Ok(req) => {
-                        match storage.write(req.ordinal, req.key, req.value, req.latest_known).await {
+                        let key = req.key.clone();
+                        let request_ordinal = req.ordinal;
+                        let latest_known = req.latest_known;
+                        let started = Instant::now();
+                        let writer = ClientIdentity {
+                            client_id: req.client_id,
+                            worker_label: req.worker_label,
+                        };
+                        let result = if req.expires_at != 0 {
+                            let unchanged_since = req.if_unchanged.then_some(latest_known);
+                            storage
+                                .write_expiring(req.key, req.value, req.expires_at, unchanged_since, &writer)
+                                .instrument(span.clone())
+                                .await
+                        } else if req.if_unchanged {
+                            storage
+                                .write_if_unchanged(req.key, req.value, latest_known, &writer)
+                                .instrument(span.clone())
+                                .await
+                        } else {
+                            storage
+                                .write(req.key, req.value, latest_known, &writer)
+                                .instrument(span.clone())
+                                .await
+                        };
+                        let latency_ms = started.elapsed().as_millis() as u64;
+
+                        match result {
                             Ok(ordinal) => {
+                                tracing::debug!(peer = %peer, key = %key, ordinal, latency_ms, "write accepted");
                                 yield Ok(WriteResponse {
                                     accepted: true,
                                     error: String::new(),
                                     assigned_ordinal: ordinal,
+                                    request_ordinal,
+                                    key_ordinal: ordinal,
+                                    rejection: WriteRejection::Unspecified.into(),
                                 });
                             }
-                            Err(WriteError::Conflict(latest)) => {
+                            Err(e @ WriteError::Conflict { latest_ordinal, key_ordinal }) => {
+                                tracing::warn!(
+                                    peer = %peer,
+                                    key = %key,
+                                    latest_ordinal,
+                                    key_ordinal,
+                                    latest_known,
+                                    latency_ms,
+                                    "write conflict"
+                                );
                                 yield Ok(WriteResponse {
                                     accepted: false,
-                                    error: format!("Conflict: latest ordinal is {}", latest),
-                                    assigned_ordinal: latest,
+                                    error: e.to_string(),
+                                    assigned_ordinal: latest_ordinal,
+                                    request_ordinal,
+                                    key_ordinal,
+                                    rejection: WriteRejection::Conflict.into(),
+                                });
+                            }
+                            Err(e @ WriteError::ValueTooLarge { size, limit }) => {
+                                tracing::warn!(peer = %peer, key = %key, size, limit, "value too large");
+                                yield Ok(WriteResponse {
+                                    accepted: false,
+                                    error: e.to_string(),
+                                    assigned_ordinal: 0,
+                                    request_ordinal,
+                                    key_ordinal: 0,
+                                    rejection: WriteRejection::ValueTooLarge.into(),
                                 });
                             }
                             Err(WriteError::Sql(e)) => {
+                                tracing::error!(peer = %peer, key = %key, error = %e, "write failed");
                                 yield Ok(WriteResponse {
                                     accepted: false,
                                     error: format!("Database error: {}", e),
                                     assigned_ordinal: 0,
+                                    request_ordinal,
+                                    key_ordinal: 0,
+                                    rejection: WriteRejection::Unspecified.into(),
                                 });
                             }
+                            #[cfg(feature = "snapshots")]
                             Err(WriteError::Snapshot(e)) => {
+                                tracing::error!(peer = %peer, key = %key, error = %e, "snapshot after write failed");
                                 yield Ok(WriteResponse {
                                     accepted: false,
                                     error: format!("Snapshot error: {}", e),
                                     assigned_ordinal: 0,
+                                    request_ordinal,
+                                    key_ordinal: 0,
+                                    rejection: WriteRejection::Unspecified.into(),
                                 });
                             }
                         }
@@ -102,9 +279,9 @@ impl KvServer for KvServiceImpl {
 
     async fn get_snapshot(
         &self,
-        _request: Request<GetSnapshotRequest>,
+        request: Request<GetSnapshotRequest>,
     ) -> Result<Response<GetSnapshotResponse>, Status> {
-        match self.storage.get_latest_snapshot().await {
+        match self.storage.get_latest_snapshot(&request.get_ref().key_prefix).await {
             Ok(Some((ordinal, data))) => {
                 Ok(Response::new(GetSnapshotResponse {
                     snapshot_ordinal: ordinal,
@@ -120,8 +297,271 @@ impl KvServer for KvServiceImpl {
             Err(e) => Err(Status::internal(format!("Failed to get snapshot: {}", e))),
         }
     }
+
+    async fn stream_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<Self::StreamSnapshotStream>, Status> {
+        let (ordinal, chunks) = match self
+            .storage
+            .stream_latest_snapshot(&request.get_ref().key_prefix, SNAPSHOT_CHUNK)
+            .await
+        {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return Ok(Response::new(Box::pin(futures_util::stream::empty()))),
+            Err(e) => return Err(Status::internal(format!("Failed to get snapshot: {}", e))),
+        };
+
+        let chunks = chunks.map(move |chunk| match chunk {
+            Ok(data) => Ok(SnapshotChunk {
+                snapshot_ordinal: ordinal,
+                data,
+            }),
+            Err(e) => Err(Status::internal(format!("Failed to read snapshot: {}", e))),
+        });
+        Ok(Response::new(Box::pin(chunks)))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        let stats = self
+            .storage
+            .stats()
+            .await
+            .map_err(|e| Status::internal(format!("Failed to read stats: {}", e)))?;
+
+        Ok(Response::new(StatsResponse {
+            latest_ordinal: stats.latest_ordinal,
+            record_count: stats.record_count,
+            key_count: stats.key_count,
+            snapshot_ordinal: stats.snapshot_ordinal,
+            replicas: Vec::new(),
+            durability: stats.durability.to_string(),
+            maintenance: maintenance_status(&self.storage),
+        }))
+    }
+
+    /// Needs the `snapshots` feature: without snapshots to load, a client
+    /// behind the cutoff could not catch up again.
+    async fn compact(
+        &self,
+        _request: Request<CompactRequest>,
+    ) -> Result<Response<CompactResponse>, Status> {
+        #[cfg(not(feature = "snapshots"))]
+        return Err(Status::unimplemented("compaction needs the snapshots feature"));
+
+        #[cfg(feature = "snapshots")]
+        {
+            let compaction = self
+                .storage
+                .compact()
+                .await
+                .map_err(|e| Status::internal(format!("Failed to compact: {}", e)))?;
+
+            Ok(Response::new(CompactResponse {
+                cutoff_ordinal: compaction.cutoff,
+                records_removed: compaction.removed,
+            }))
+        }
+    }
+
+    async fn get_history(
+        &self,
+        request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        let req = request.into_inner();
+        let records = self
+            .storage
+            .history(&req.key, req.until_ordinal, req.limit)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to read history: {}", e)))?;
+
+        Ok(Response::new(GetHistoryResponse {
+            records: records.into_iter().map(proto_record).collect(),
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(peer = %peer(&request)))]
+    async fn transaction(
+        &self,
+        request: Request<TransactionRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        ClientInfo::from_metadata(request.metadata()).warn_if_outdated("Transaction");
+        let peer = peer(&request);
+        let req = request.into_inner();
+        let writer = ClientIdentity {
+            client_id: req.client_id,
+            worker_label: req.worker_label,
+        };
+        let count = req.writes.len();
+        let writes = req.writes.into_iter().map(|w| (w.key, w.value)).collect();
+
+        match self.storage.write_transaction(writes, req.latest_known, &writer).await {
+            Ok(first_ordinal) => {
+                tracing::debug!(peer = %peer, writes = count, first_ordinal, "transaction committed");
+                Ok(Response::new(TransactionResponse {
+                    accepted: true,
+                    error: String::new(),
+                    first_ordinal,
+                    key_ordinal: 0,
+                    rejection: WriteRejection::Unspecified.into(),
+                }))
+            }
+            Err(e @ WriteError::Conflict { key_ordinal, .. }) => {
+                tracing::warn!(
+                    peer = %peer,
+                    writes = count,
+                    key_ordinal,
+                    latest_known = req.latest_known,
+                    "transaction conflict"
+                );
+                Ok(Response::new(TransactionResponse {
+                    accepted: false,
+                    error: e.to_string(),
+                    first_ordinal: 0,
+                    key_ordinal,
+                    rejection: WriteRejection::Conflict.into(),
+                }))
+            }
+            Err(e @ WriteError::ValueTooLarge { size, limit }) => {
+                tracing::warn!(peer = %peer, writes = count, size, limit, "transaction value too large");
+                Ok(Response::new(TransactionResponse {
+                    accepted: false,
+                    error: e.to_string(),
+                    first_ordinal: 0,
+                    key_ordinal: 0,
+                    rejection: WriteRejection::ValueTooLarge.into(),
+                }))
+            }
+            Err(e) => {
+                tracing::error!(peer = %peer, writes = count, error = %e, "transaction failed");
+                Err(Status::internal(format!("Transaction failed: {}", e)))
+            }
+        }
+    }
+
+    async fn get_server_info(
+        &self,
+        request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        let client_version = request.into_inner().protocol_version;
+        if client_version != PROTOCOL_VERSION {
+            tracing::info!(
+                "client speaks protocol {}, server speaks {}",
+                client_version,
+                PROTOCOL_VERSION
+            );
+        }
+
+        Ok(Response::new(ServerInfo {
+            protocol_version: PROTOCOL_VERSION,
+            features: FEATURES.iter().map(|f| f.to_string()).collect(),
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            max_value_size: self.storage.max_value_size(),
+        }))
+    }
+}
+
+fn maintenance_status(storage: &Storage) -> Option<MaintenanceStatus> {
+    let status = storage.maintenance();
+    let window = status.window()?;
+    let mut reply = MaintenanceStatus {
+        window: window.to_string(),
+        ..Default::default()
+    };
+    if let Some(progress) = status.running() {
+        reply.running = progress.task.to_string();
+        reply.tasks_done = progress.done as u32;
+        reply.tasks_total = progress.total as u32;
+    }
+    if let Some(run) = status.last_run() {
+        reply.last_started = run.started_at;
+        reply.last_finished = run.finished_at;
+        reply.last_results = run
+            .results
+            .iter()
+            .map(|result| format!("{}: {}", result.task, result.error.as_deref().unwrap_or("ok")))
+            .collect();
+    }
+    Some(reply)
+}
+
+/// Remote address for log fields, `unknown` for in-process transports.
+fn peer<T>(request: &Request<T>) -> String {
+    request
+        .remote_addr()
+        .map_or_else(|| "unknown".to_string(), |addr| addr.to_string())
 }
 
 pub fn create_server(storage: Arc<Storage>) -> KvServerServer<KvServiceImpl> {
     KvServerServer::new(KvServiceImpl::new(storage))
 }
+
+/// Like [`create_server`], with `layer` (auth, rate limiting, logging, ...)
+/// wrapped around the KV service only.
+///
+/// The result can be passed to `tonic::transport::Server::add_service`;
+/// use `Server::builder().layer(...)` instead for middleware that should
+/// see every service.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # fn example(storage: Arc<log_server::storage::Storage>) {
+/// use tonic::{Request, Status};
+///
+/// fn check_token(request: Request<()>) -> Result<Request<()>, Status> {
+///     match request.metadata().get("authorization") {
+///         Some(token) if token == "Bearer secret" => Ok(request),
+///         _ => Err(Status::unauthenticated("missing or wrong token")),
+///     }
+/// }
+///
+/// let service = log_server::grpc::create_server_with_layer(
+///     storage,
+///     tonic::service::InterceptorLayer::new(check_token),
+/// );
+/// tonic::transport::Server::builder().add_service(service);
+/// # }
+/// ```
+pub fn create_server_with_layer<L>(storage: Arc<Storage>, layer: L) -> Layered<L::Service>
+where
+    L: Layer<KvServerServer<KvServiceImpl>>,
+{
+    Layered(layer.layer(create_server(storage)))
+}
+
+/// The KV service behind user middleware, still routed under the KV
+/// service name.
+#[derive(Clone)]
+pub struct Layered<S>(S);
+
+impl<S> NamedService for Layered<S> {
+    const NAME: &'static str = <KvServerServer<KvServiceImpl> as NamedService>::NAME;
+}
+
+impl<S, B> Service<http::Request<B>> for Layered<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        self.0.call(request)
+    }
+}
+
+#[cfg(feature = "chaos")]
+pub fn create_server_with_faults(
+    storage: Arc<Storage>,
+    faults: crate::chaos::FaultInjector,
+) -> KvServerServer<KvServiceImpl> {
+    KvServerServer::new(KvServiceImpl::with_faults(storage, faults))
+}
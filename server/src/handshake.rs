@@ -0,0 +1,59 @@
+//! Client handshake metadata, see `log_server_types::metadata`.
+
+use std::collections::HashSet;
+
+use log_server_types::{metadata, PROTOCOL_VERSION};
+use tonic::metadata::MetadataMap;
+
+/// What a client said about itself in request metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// 0 for clients that send no handshake at all.
+    pub protocol_version: u32,
+    pub client: Option<String>,
+    /// Advertised capability names. Only logged: every client is served
+    /// the same way whatever it lists here.
+    pub capabilities: HashSet<String>,
+}
+
+impl ClientInfo {
+    /// Reads the handshake from request metadata. Missing or malformed
+    /// values fall back to what a legacy client would have sent.
+    pub fn from_metadata(map: &MetadataMap) -> Self {
+        let get = |key: &str| map.get(key).and_then(|value| value.to_str().ok());
+
+        Self {
+            protocol_version: get(metadata::PROTOCOL_VERSION)
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(0),
+            client: get(metadata::CLIENT).map(str::to_string),
+            capabilities: get(metadata::CAPABILITIES)
+                .map(|caps| {
+                    caps.split(',')
+                        .map(str::trim)
+                        .filter(|cap| !cap.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Logs a warning if the client is older than this server, to find
+    /// clients worth upgrading. Nothing is turned off for them; the request
+    /// is served as usual.
+    pub fn warn_if_outdated(&self, rpc: &str) {
+        if self.protocol_version < PROTOCOL_VERSION {
+            let mut capabilities: Vec<_> = self.capabilities.iter().map(String::as_str).collect();
+            capabilities.sort_unstable();
+            tracing::warn!(
+                "{} from {} speaking protocol {} (server {}, capabilities [{}]), consider upgrading it",
+                rpc,
+                self.client.as_deref().unwrap_or("unidentified client"),
+                self.protocol_version,
+                PROTOCOL_VERSION,
+                capabilities.join(", ")
+            );
+        }
+    }
+}
@@ -1,5 +1,30 @@
+#[cfg(not(feature = "sqlite"))]
+compile_error!("log-server needs a storage backend, enable the `sqlite` feature");
+
+pub mod activity;
+pub mod archive;
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod compaction;
+pub mod config;
+#[cfg(unix)]
+pub mod daemon;
 pub mod db;
+pub mod expiry;
 pub mod grpc;
+pub mod handshake;
+pub mod logging;
+pub mod maintenance;
+pub mod metrics;
+pub mod migrate;
 pub mod models;
+pub mod priority;
+#[cfg(feature = "rest-gateway")]
+pub mod rest;
+#[cfg(feature = "snapshots")]
 pub mod snapshot;
+#[cfg(feature = "status-page")]
+pub mod status;
 pub mod storage;
@@ -0,0 +1,350 @@
+//! Where the server's tracing output goes.
+//!
+//! Selected with `log_target` in the config file:
+//!
+//! - `stdout` (default)
+//! - `file:<path>`, rotated once it reaches `log_rotate_bytes`, keeping
+//!   `log_rotate_keep` old files as `<path>.1`, `<path>.2`, ...
+//! - `syslog`, datagrams to `/dev/log`, which journald also reads
+//!
+//! `log_format = json` writes one JSON object per event instead of text,
+//! with the event's fields (`ordinal`, `key`, `peer`, `latency_ms`, ...) as
+//! top-level members.
+//!
+//! The target and format are fixed at startup; only the level can be
+//! reloaded.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, MakeWriter};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, reload, Registry};
+
+use crate::config::Config;
+
+/// Handle for changing the log level of a running server.
+pub type LevelHandle = reload::Handle<LevelFilter, Registry>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogTarget {
+    Stdout,
+    File(PathBuf),
+    Syslog,
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(LogTarget::Stdout),
+            "syslog" | "journald" => Ok(LogTarget::Syslog),
+            other => match other.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(LogTarget::File(PathBuf::from(path))),
+                _ => Err(format!(
+                    "unknown log target '{}', expected stdout, syslog or file:<path>",
+                    other
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format '{}', expected text or json", other)),
+        }
+    }
+}
+
+/// Installs the global subscriber described by `config`.
+pub fn init(config: &Config) -> io::Result<LevelHandle> {
+    let (filter, handle) = reload::Layer::new(config.log_level);
+
+    let writer = match &config.log_target {
+        LogTarget::Stdout => BoxMakeWriter::new(io::stdout),
+        LogTarget::File(path) => BoxMakeWriter::new(RotatingFile::open(
+            path,
+            config.log_rotate_bytes,
+            config.log_rotate_keep,
+        )?),
+        #[cfg(unix)]
+        LogTarget::Syslog => BoxMakeWriter::new(Syslog::connect(Path::new(Syslog::DEFAULT_SOCKET))?),
+        #[cfg(not(unix))]
+        LogTarget::Syslog => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "syslog is only available on unix",
+            ))
+        }
+    };
+    let colored = config.log_target == LogTarget::Stdout;
+
+    let layer = match config.log_format {
+        LogFormat::Json => fmt::layer().event_format(JsonFormat).with_writer(writer).boxed(),
+        // syslog stamps messages itself and the ident already names us.
+        LogFormat::Text if config.log_target == LogTarget::Syslog => fmt::layer()
+            .with_ansi(false)
+            .without_time()
+            .with_target(false)
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Text => fmt::layer().with_ansi(colored).with_writer(writer).boxed(),
+    };
+
+    tracing_subscriber::registry().with(filter).with(layer).init();
+    Ok(handle)
+}
+
+/// Formats each event as a single-line JSON object.
+///
+/// ```text
+/// {"key":"map:1","latency_ms":3,"level":"WARN","message":"write conflict","peer":"127.0.0.1:50412",...}
+/// ```
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let meta = event.metadata();
+        let mut object = Map::new();
+        object.insert(
+            "timestamp".into(),
+            chrono::Utc::now()
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+                .into(),
+        );
+        object.insert("level".into(), meta.level().as_str().into());
+        object.insert("target".into(), meta.target().into());
+
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<Value> = scope.from_root().map(|span| span.name().into()).collect();
+            object.insert("spans".into(), spans.into());
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+/// Copies event fields into a JSON object, keeping numbers and booleans
+/// as JSON numbers and booleans.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0.insert(field.name().into(), value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
+
+/// A log file that is rotated by size.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    state: Mutex<(File, u64)>,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending. `keep` of 0 truncates instead of keeping
+    /// rotated files.
+    pub fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            keep,
+            state: Mutex::new((file, written)),
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> io::Result<File> {
+        if self.keep > 0 {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+    }
+
+    fn write_line(&self, buf: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let (file, written) = &mut *state;
+
+        if *written > 0 && *written + buf.len() as u64 > self.max_bytes {
+            *file = self.rotate()?;
+            *written = 0;
+        }
+
+        file.write_all(buf)?;
+        *written += buf.len() as u64;
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+    type Writer = RotatingFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RotatingFileWriter(self)
+    }
+}
+
+pub struct RotatingFileWriter<'a>(&'a RotatingFile);
+
+impl Write for RotatingFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The fmt layer hands over one complete event per call, so an event
+        // never straddles two files.
+        self.0.write_line(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.state.lock().unwrap().0.flush()
+    }
+}
+
+/// Sends each event as an RFC 3164 datagram to the local syslog socket.
+#[cfg(unix)]
+pub struct Syslog {
+    socket: std::os::unix::net::UnixDatagram,
+    header: String,
+}
+
+#[cfg(unix)]
+impl Syslog {
+    pub const DEFAULT_SOCKET: &'static str = "/dev/log";
+
+    /// Facility `daemon`, see RFC 3164 section 4.1.1.
+    const FACILITY: u8 = 3;
+
+    pub fn connect(socket_path: &Path) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(socket_path)?;
+        Ok(Self {
+            socket,
+            header: format!("log-server[{}]: ", std::process::id()),
+        })
+    }
+
+    fn severity(level: &tracing::Level) -> u8 {
+        match *level {
+            tracing::Level::ERROR => 3,
+            tracing::Level::WARN => 4,
+            tracing::Level::INFO => 6,
+            tracing::Level::DEBUG | tracing::Level::TRACE => 7,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = SyslogWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogWriter {
+            syslog: self,
+            severity: 6,
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        SyslogWriter {
+            syslog: self,
+            severity: Self::severity(meta.level()),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub struct SyslogWriter<'a> {
+    syslog: &'a Syslog,
+    severity: u8,
+}
+
+#[cfg(unix)]
+impl Write for SyslogWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let priority = Syslog::FACILITY * 8 + self.severity;
+        let line = buf.strip_suffix(b"\n").unwrap_or(buf);
+
+        let mut message = format!("<{}>{}", priority, self.syslog.header).into_bytes();
+        message.extend_from_slice(line);
+        self.syslog.socket.send(&message)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,19 +1,339 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tonic::transport::Server;
 
-use log_server::{db, grpc, storage};
+use log_server::config::Config;
+use log_server::logging::{self, LevelHandle};
+use log_server::{archive, audit, db, grpc, migrate, storage};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+/// Settings that can also be given as a flag or an environment variable,
+/// which take precedence over the config file in that order.
+const OVERRIDES: &[(&str, &str, &str)] = &[
+    ("--listen", "LOG_SERVER_LISTEN", "listen_addr"),
+    ("--db", "LOG_SERVER_DB", "database_url"),
+    ("--snapshot-dir", "LOG_SERVER_SNAPSHOT_DIR", "snapshot_dir"),
+    ("--snapshot-interval", "LOG_SERVER_SNAPSHOT_INTERVAL", "snapshot_interval"),
+];
 
-    let snapshot_dir = "./snapshots";
-    let pool = db::init_pool("sqlite:log.db").await?;
-    let storage = Arc::new(storage::Storage::with_snapshot(pool, snapshot_dir, 100)?);
-    let server = grpc::create_server(storage);
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    let addr = "127.0.0.1:50051".parse()?;
-    Server::builder().add_service(server).serve(addr).await?;
+    // Forking has to happen before the runtime spawns its worker threads.
+    #[cfg(unix)]
+    if has_flag(&args, "--daemon") {
+        log_server::daemon::daemonize(flag_value(&args, "--log-file").map(Path::new))?;
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args))
+}
+
+async fn run(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = flag_value(&args, "--config").map(PathBuf::from);
+    let mut config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    apply_overrides(&mut config, &args)?;
+
+    let level_handle = logging::init(&config)?;
+
+    match args.first().map(String::as_str).filter(|a| !a.starts_with("--")) {
+        None => {}
+        Some("audit") => {
+            let url = args.get(1).filter(|a| !a.starts_with("--")).unwrap_or(&config.database_url);
+            return run_audit(url).await;
+        }
+        Some("migrate") if args.len() == 3 => {
+            let summary = migrate::migrate(&args[1], &args[2]).await?;
+            println!("migrated and verified {}", summary);
+            return Ok(());
+        }
+        Some("dump") => {
+            let Some(path) = flag_value(&args, "--to") else {
+                usage();
+            };
+            let from_ordinal = flag_value(&args, "--from-ordinal").map_or(Ok(0), str::parse)?;
+            let pool = db::init_pool(&config.database_url).await?;
+            let metadata = archive::dump(&pool, from_ordinal, Path::new(path)).await?;
+            println!("dumped {} to {}", metadata, path);
+            return Ok(());
+        }
+        Some("restore") => {
+            let Some(path) = flag_value(&args, "--from") else {
+                usage();
+            };
+            let pool = db::init_pool(&config.database_url).await?;
+            let metadata = archive::restore(&pool, Path::new(path)).await?;
+            println!("restored {} from {}", metadata, path);
+            return Ok(());
+        }
+        Some(other) => {
+            eprintln!("Unknown command: {}", other);
+            usage();
+        }
+    }
+
+    #[cfg(unix)]
+    let _pid_file = flag_value(&args, "--pid-file")
+        .map(|path| log_server::daemon::PidFile::create(Path::new(path)))
+        .transpose()?;
+
+    let pool = db::init_pool_with(&config.database_url, config.durability).await?;
+    #[cfg(feature = "snapshots")]
+    let storage = storage::Storage::with_snapshot(pool, &config.snapshot_dir, config.snapshot_interval)?;
+    #[cfg(not(feature = "snapshots"))]
+    let storage = storage::Storage::new(pool);
+    let storage = Arc::new(storage.with_durability(config.durability));
+    #[cfg(feature = "snapshots")]
+    {
+        let recovery = storage.recover().await?;
+        if recovery.restored > 0 {
+            tracing::warn!(
+                "database ended before snapshot {}, restored {} keys from it",
+                recovery.snapshot_ordinal,
+                recovery.restored
+            );
+        }
+        tracing::info!(
+            snapshot_ordinal = recovery.snapshot_ordinal,
+            head = recovery.head,
+            "recovered {}, resuming after ordinal {}",
+            config.database_url,
+            recovery.head
+        );
+    }
+    storage.scheduler().set_ratio(config.catch_up_ratio);
+    storage.set_compaction_retain(config.compaction_retain);
+    storage.set_max_value_size(config.max_value_size);
+    tracing::info!("durability: {}", config.durability);
+    // Fail before serving anything rather than fall back to plaintext.
+    let mut builder = with_tls(Server::builder(), &config)?;
+    let router = match &config.auth_token {
+        Some(token) => {
+            tracing::info!("bearer token required");
+            let auth = tonic::service::InterceptorLayer::new(log_server::auth::BearerToken::new(token));
+            builder.add_service(grpc::create_server_with_layer(Arc::clone(&storage), auth))
+        }
+        None => builder.add_service(grpc::create_server(Arc::clone(&storage))),
+    };
+
+    if let Some(window) = config.maintenance_window.clone() {
+        tokio::spawn(log_server::maintenance::schedule(
+            Arc::clone(&storage),
+            window,
+            config.maintenance_tasks.clone(),
+        ));
+    }
+
+    if let Some(interval) = config.compaction_interval {
+        tokio::spawn(log_server::compaction::schedule(Arc::clone(&storage), interval));
+    }
+
+    if let Some(interval) = config.expiry_interval {
+        tokio::spawn(log_server::expiry::schedule(Arc::clone(&storage), interval));
+    }
+
+    if let Some(addr) = config.status_addr {
+        #[cfg(feature = "status-page")]
+        {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                if let Err(e) = log_server::status::serve(addr, storage).await {
+                    tracing::error!("status page failed: {}", e);
+                }
+            });
+        }
+        #[cfg(not(feature = "status-page"))]
+        tracing::warn!("status_addr = {} ignored, built without the status-page feature", addr);
+    }
+
+    if let Some(addr) = config.metrics_addr {
+        #[cfg(feature = "metrics")]
+        {
+            let storage = Arc::clone(&storage);
+            tokio::spawn(async move {
+                if let Err(e) = log_server::metrics::serve(addr, storage).await {
+                    tracing::error!("metrics endpoint failed: {}", e);
+                }
+            });
+        }
+        #[cfg(not(feature = "metrics"))]
+        tracing::warn!("metrics_addr = {} ignored, built without the metrics feature", addr);
+    }
+
+    if let Some(addr) = config.rest_addr {
+        #[cfg(feature = "rest-gateway")]
+        {
+            let storage = Arc::clone(&storage);
+            let token = config.auth_token.clone();
+            tokio::spawn(async move {
+                if let Err(e) = log_server::rest::serve(addr, storage, token.as_deref()).await {
+                    tracing::error!("REST gateway failed: {}", e);
+                }
+            });
+        }
+        #[cfg(not(feature = "rest-gateway"))]
+        tracing::warn!("rest_addr = {} ignored, built without the rest-gateway feature", addr);
+    }
+
+    #[cfg(unix)]
+    tokio::spawn(handle_signals(storage, config_path, args, level_handle));
+    #[cfg(not(unix))]
+    let _ = (config_path, level_handle);
+
+    tracing::info!("serving on {}", config.listen_addr);
+    router.serve_with_shutdown(config.listen_addr, shutdown_signal()).await?;
 
     Ok(())
 }
+
+/// Applies [`OVERRIDES`] given on the command line or in the environment.
+fn apply_overrides(config: &mut Config, args: &[String]) -> Result<(), String> {
+    for (flag, variable, key) in OVERRIDES {
+        let value = match flag_value(args, flag) {
+            Some(value) => value.to_string(),
+            None => match std::env::var(variable) {
+                Ok(value) => value,
+                Err(_) => continue,
+            },
+        };
+        config.set(key, &value).map_err(|e| format!("{} / {}: {}", flag, variable, e))?;
+    }
+    Ok(())
+}
+
+/// Turns on TLS when `tls_cert` and `tls_key` are configured.
+fn with_tls(builder: Server, config: &Config) -> Result<Server, Box<dyn std::error::Error>> {
+    match (&config.tls_cert, &config.tls_key) {
+        (None, None) => Ok(builder),
+        #[cfg(feature = "tls")]
+        (Some(cert), Some(key)) => {
+            use tonic::transport::{Identity, ServerTlsConfig};
+
+            let identity = Identity::from_pem(std::fs::read(cert)?, std::fs::read(key)?);
+            tracing::info!("TLS enabled with {}", cert.display());
+            Ok(builder.tls_config(ServerTlsConfig::new().identity(identity))?)
+        }
+        #[cfg(not(feature = "tls"))]
+        (Some(_), Some(_)) => Err("tls_cert and tls_key need a server built with the tls feature".into()),
+        _ => Err("tls_cert and tls_key must be set together".into()),
+    }
+}
+
+/// Resolves on ctrl-c, or SIGTERM on unix, so the pid file is cleaned up.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut term = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    tracing::info!("shutting down");
+}
+
+/// SIGHUP reloads the config file, SIGUSR1 writes a snapshot.
+#[cfg(unix)]
+async fn handle_signals(
+    storage: Arc<storage::Storage>,
+    config_path: Option<PathBuf>,
+    args: Vec<String>,
+    level_handle: LevelHandle,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).expect("failed to listen for SIGHUP");
+    let mut user1 = signal(SignalKind::user_defined1()).expect("failed to listen for SIGUSR1");
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                let Some(path) = &config_path else {
+                    tracing::warn!("SIGHUP received but no --config was given, nothing to reload");
+                    continue;
+                };
+                let config = Config::load(path).map_err(|e| e.to_string()).and_then(|mut config| {
+                    apply_overrides(&mut config, &args)?;
+                    Ok(config)
+                });
+                match config {
+                    Ok(config) => {
+                        if let Err(e) = level_handle.reload(config.log_level) {
+                            tracing::error!("failed to change log level: {}", e);
+                        }
+                        #[cfg(feature = "snapshots")]
+                        storage.set_snapshot_interval(config.snapshot_interval);
+                        storage.scheduler().set_ratio(config.catch_up_ratio);
+                        storage.set_compaction_retain(config.compaction_retain);
+                        storage.set_max_value_size(config.max_value_size);
+                        tracing::info!("reloaded {}: {:?}", path.display(), config);
+                    }
+                    Err(e) => tracing::error!("failed to reload {}: {}", path.display(), e),
+                }
+            }
+            _ = user1.recv() => {
+                #[cfg(feature = "snapshots")]
+                match storage.snapshot_now().await {
+                    Ok(()) => tracing::info!("snapshot written on SIGUSR1"),
+                    Err(e) => tracing::error!("SIGUSR1 snapshot failed: {}", e),
+                }
+                #[cfg(not(feature = "snapshots"))]
+                tracing::warn!("SIGUSR1 ignored, built without the snapshots feature");
+            }
+        }
+    }
+}
+
+/// Checks the database for ordinal gaps and rewrites, exiting non-zero when
+/// anything is found.
+async fn run_audit(url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::init_pool(url).await?;
+    let report = audit::audit(&pool).await?;
+    print!("{}", report);
+
+    if !report.is_clean() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: log-server [command] [options]");
+    eprintln!("Without a command, serves the log on 127.0.0.1:50051, recovering from the newest snapshot.");
+    eprintln!("Server options:");
+    eprintln!("  --config <file>     - Logging, snapshot, durability, maintenance and compaction settings, reloaded on SIGHUP");
+    eprintln!("  --listen <addr>     - Address to serve on ($LOG_SERVER_LISTEN)");
+    eprintln!("  --db <url>          - Database, sqlite:log.db by default ($LOG_SERVER_DB)");
+    eprintln!("  --snapshot-dir <dir>      - Where snapshots go, ./snapshots by default ($LOG_SERVER_SNAPSHOT_DIR)");
+    eprintln!("  --snapshot-interval <n>   - Ordinals between snapshots ($LOG_SERVER_SNAPSHOT_INTERVAL)");
+    eprintln!("  --daemon            - Detach from the terminal (unix)");
+    eprintln!("  --log-file <file>   - Where a daemon writes its output, /dev/null by default");
+    eprintln!("  --pid-file <file>   - Write the process id, removed on shutdown");
+    eprintln!("SIGUSR1 writes a snapshot immediately.");
+    eprintln!("Commands:");
+    eprintln!("  audit [database-url]                         - Check the log for gaps and rewrites");
+    eprintln!("  migrate <from-url> <to-url>                  - Copy the log into an empty database");
+    eprintln!("  dump --to <file> [--from-ordinal N] [--db url] - Write records after N to an archive");
+    eprintln!("  restore --from <file> [--db url]             - Append an archive to the log");
+    std::process::exit(2);
+}
+
+fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
@@ -0,0 +1,254 @@
+//! Housekeeping that runs inside a configured low-traffic window.
+//!
+//! ```text
+//! maintenance_window = sat,sun 02:00-04:00
+//! maintenance_tasks = analyze, vacuum, snapshot, compact
+//! ```
+//!
+//! The window is in UTC, `*` means every day, and it may wrap past
+//! midnight (`22:00-02:00`). The tasks run once per window, in the given
+//! order; progress and the last run's results are reported by `Stats`.
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, NaiveTime, Utc, Weekday};
+
+use crate::storage::Storage;
+
+/// How often the scheduler checks whether a window has opened.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// Rebuilds the database file to reclaim free pages.
+    Vacuum,
+    /// Refreshes the query planner statistics.
+    Analyze,
+    /// Writes a snapshot, as SIGUSR1 does.
+    Snapshot,
+    /// Compacts the log, see [`compaction`](crate::compaction).
+    Compact,
+}
+
+impl FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vacuum" => Ok(Task::Vacuum),
+            "analyze" => Ok(Task::Analyze),
+            "snapshot" => Ok(Task::Snapshot),
+            "compact" => Ok(Task::Compact),
+            other => Err(format!(
+                "unknown maintenance task '{}', expected vacuum, analyze, snapshot or compact",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Task {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Task::Vacuum => write!(f, "vacuum"),
+            Task::Analyze => write!(f, "analyze"),
+            Task::Snapshot => write!(f, "snapshot"),
+            Task::Compact => write!(f, "compact"),
+        }
+    }
+}
+
+/// Parses a comma-separated task list.
+pub fn parse_tasks(s: &str) -> Result<Vec<Task>, String> {
+    s.split(',').map(|task| task.trim().parse()).collect()
+}
+
+/// Recurring time range in UTC, on some or all days of the week.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Days the window opens on; empty means every day.
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    /// When the window that `at` falls into opened, or `None` if `at` is
+    /// outside every window.
+    pub fn opened_at(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let time = at.time();
+        let today = at.date_naive();
+        let opens_on = |date: chrono::NaiveDate| self.days.is_empty() || self.days.contains(&date.weekday());
+
+        let opened = if self.start <= self.end {
+            (opens_on(today) && time >= self.start && time < self.end).then_some(today)
+        } else if time >= self.start {
+            opens_on(today).then_some(today)
+        } else if time < self.end {
+            let yesterday = today.checked_sub_days(Days::new(1))?;
+            opens_on(yesterday).then_some(yesterday)
+        } else {
+            None
+        }?;
+        Some(opened.and_time(self.start).and_utc())
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid maintenance window '{}', expected e.g. `sat,sun 02:00-04:00`", s);
+        let (days, range) = s.trim().split_once(' ').ok_or_else(invalid)?;
+        let (start, end) = range.trim().split_once('-').ok_or_else(invalid)?;
+
+        let days = match days {
+            "*" => Vec::new(),
+            days => days
+                .split(',')
+                .map(|day| day.trim().parse::<Weekday>().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?,
+        };
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            return Err(invalid());
+        }
+
+        Ok(Self { days, start, end })
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.days.is_empty() {
+            write!(f, "*")?;
+        } else {
+            let days: Vec<_> = self.days.iter().map(|day| day.to_string().to_lowercase()).collect();
+            write!(f, "{}", days.join(","))?;
+        }
+        write!(f, " {}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// Outcome of one task in a [`Run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskResult {
+    pub task: Task,
+    pub error: Option<String>,
+}
+
+/// A completed maintenance run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// Unix millis.
+    pub started_at: i64,
+    /// Unix millis.
+    pub finished_at: i64,
+    pub results: Vec<TaskResult>,
+}
+
+/// The task being worked on, for `Stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub task: Task,
+    /// Tasks finished so far in this run.
+    pub done: usize,
+    pub total: usize,
+}
+
+/// What the scheduler is doing, kept on [`Storage`] like
+/// [`Activity`](crate::activity::Activity).
+#[derive(Debug, Default)]
+pub struct Status {
+    window: Mutex<Option<Window>>,
+    running: Mutex<Option<Progress>>,
+    last_run: Mutex<Option<Run>>,
+}
+
+impl Status {
+    /// The configured window, if the scheduler is running.
+    pub fn window(&self) -> Option<Window> {
+        self.window.lock().unwrap().clone()
+    }
+
+    pub fn running(&self) -> Option<Progress> {
+        self.running.lock().unwrap().clone()
+    }
+
+    pub fn last_run(&self) -> Option<Run> {
+        self.last_run.lock().unwrap().clone()
+    }
+}
+
+/// Runs `tasks` now, one after the other, recording progress and results
+/// in [`Storage::maintenance`]. A failing task does not stop the others.
+pub async fn run_tasks(storage: &Storage, tasks: &[Task]) -> Run {
+    let status = storage.maintenance();
+    let started_at = Utc::now().timestamp_millis();
+    let mut results = Vec::with_capacity(tasks.len());
+
+    for (done, &task) in tasks.iter().enumerate() {
+        *status.running.lock().unwrap() = Some(Progress {
+            task,
+            done,
+            total: tasks.len(),
+        });
+
+        let started = std::time::Instant::now();
+        let error = run_task(storage, task).await.err();
+        match &error {
+            None => tracing::info!(
+                task = %task,
+                latency_ms = started.elapsed().as_millis() as u64,
+                "maintenance task finished"
+            ),
+            Some(e) => tracing::error!(task = %task, error = %e, "maintenance task failed"),
+        }
+        results.push(TaskResult { task, error });
+    }
+
+    let run = Run {
+        started_at,
+        finished_at: Utc::now().timestamp_millis(),
+        results,
+    };
+    *status.running.lock().unwrap() = None;
+    *status.last_run.lock().unwrap() = Some(run.clone());
+    run
+}
+
+async fn run_task(storage: &Storage, task: Task) -> Result<(), String> {
+    match task {
+        Task::Vacuum => storage.vacuum().await.map_err(|e| e.to_string()),
+        Task::Analyze => storage.analyze().await.map_err(|e| e.to_string()),
+        #[cfg(feature = "snapshots")]
+        Task::Snapshot => storage.snapshot_now().await.map_err(|e| e.to_string()),
+        #[cfg(not(feature = "snapshots"))]
+        Task::Snapshot => Err(String::from("built without the snapshots feature")),
+        Task::Compact => storage.compact().await.map(|_| ()).map_err(|e| e.to_string()),
+    }
+}
+
+/// Runs `tasks` once every time `window` opens, including a window that is
+/// already open at startup. Never returns.
+pub async fn schedule(storage: Arc<Storage>, window: Window, tasks: Vec<Task>) {
+    tracing::info!("maintenance window {}, tasks {:?}", window, tasks);
+    *storage.maintenance().window.lock().unwrap() = Some(window.clone());
+
+    let mut last_window = None;
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let Some(opened) = window.opened_at(Utc::now()) else {
+            continue;
+        };
+        if last_window == Some(opened) {
+            continue;
+        }
+        last_window = Some(opened);
+        run_tasks(&storage, &tasks).await;
+    }
+}
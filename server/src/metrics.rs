@@ -0,0 +1,179 @@
+//! Counters for Prometheus.
+//!
+//! ```text
+//! metrics_addr = 127.0.0.1:9100
+//! ```
+//!
+//! With the `metrics` feature, `GET /metrics` on `metrics_addr` answers in
+//! the Prometheus text format: writes and conflicts as counters (rates are
+//! left to `rate()`), write and subscribe fan-out latency as histograms,
+//! and the subscriber count and log and snapshot sizes as gauges. The
+//! counters themselves are always kept; they cost an atomic add each.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::storage::Storage;
+
+/// Upper bounds of the latency buckets, in seconds.
+const BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    writes: AtomicU64,
+    conflicts: AtomicU64,
+    write_latency: Histogram,
+    fanout_latency: Histogram,
+}
+
+impl Metrics {
+    /// Counts an accepted write that took `latency` under the write lock.
+    pub fn record_write(&self, latency: Duration) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.write_latency.observe(latency);
+    }
+
+    pub fn record_conflict(&self) {
+        self.conflicts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Time from a record's commit until a live subscriber picked it up.
+    pub fn record_fanout(&self, latency: Duration) {
+        self.fanout_latency.observe(latency);
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    pub fn conflicts(&self) -> u64 {
+        self.conflicts.load(Ordering::Relaxed)
+    }
+}
+
+/// Cumulative buckets, as Prometheus wants them.
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, bucket.load(Ordering::Relaxed));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Every metric in the Prometheus text format. Gauges read from the
+/// database are left out if it can't be queried.
+pub async fn render(storage: &Storage) -> String {
+    let metrics = storage.metrics();
+    let mut out = String::new();
+
+    metric(&mut out, "log_server_writes_total", "counter", "Accepted writes.", metrics.writes());
+    metric(
+        &mut out,
+        "log_server_write_conflicts_total",
+        "counter",
+        "Writes rejected because the key changed.",
+        metrics.conflicts(),
+    );
+    metrics.write_latency.render(
+        &mut out,
+        "log_server_write_seconds",
+        "Time to commit a write, including waiting for the write lock.",
+    );
+    metrics.fanout_latency.render(
+        &mut out,
+        "log_server_subscribe_fanout_seconds",
+        "Time from commit until a live subscriber picks the record up.",
+    );
+    metric(
+        &mut out,
+        "log_server_subscribers",
+        "gauge",
+        "Open Subscribe streams.",
+        storage.activity().subscribers(),
+    );
+
+    if let Ok(stats) = storage.stats().await {
+        metric(&mut out, "log_server_latest_ordinal", "gauge", "Head of the log.", stats.latest_ordinal);
+        metric(&mut out, "log_server_records", "gauge", "Records in the log.", stats.record_count);
+        metric(&mut out, "log_server_keys", "gauge", "Distinct keys in the log.", stats.key_count);
+        metric(&mut out, "log_server_log_bytes", "gauge", "Size of the database.", stats.log_bytes);
+        metric(
+            &mut out,
+            "log_server_snapshot_ordinal",
+            "gauge",
+            "Ordinal of the latest snapshot.",
+            stats.snapshot_ordinal,
+        );
+        metric(
+            &mut out,
+            "log_server_snapshot_lag",
+            "gauge",
+            "Records written since the latest snapshot.",
+            stats.latest_ordinal.saturating_sub(stats.snapshot_ordinal),
+        );
+        metric(
+            &mut out,
+            "log_server_snapshot_bytes",
+            "gauge",
+            "Size of the latest binary snapshot.",
+            stats.snapshot_bytes,
+        );
+    }
+    out
+}
+
+/// Serves `/metrics` on `addr` until the future is dropped.
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: std::net::SocketAddr, storage: std::sync::Arc<Storage>) -> std::io::Result<()> {
+    use axum::extract::State;
+    use axum::routing::get;
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("metrics on http://{}/metrics", listener.local_addr()?);
+    let router = axum::Router::new()
+        .route(
+            "/metrics",
+            get(|State(storage): State<std::sync::Arc<Storage>>| async move {
+                (
+                    [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                    render(&storage).await,
+                )
+            }),
+        )
+        .with_state(storage);
+    axum::serve(listener, router).await
+}
@@ -0,0 +1,189 @@
+//! Copies the log from one database into another.
+//!
+//! Records are copied verbatim, ordinals and timestamps included, so
+//! subscribers and snapshots keep lining up after the switch. Only SQLite
+//! URLs are accepted until another storage backend exists.
+
+use std::fmt;
+
+use futures_util::TryStreamExt;
+use sqlx::SqlitePool;
+
+use crate::db;
+
+/// Rows inserted per destination transaction.
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug)]
+pub enum Error {
+    UnsupportedBackend(String),
+    DestinationNotEmpty(u64),
+    Sql(sqlx::Error),
+    /// The copy finished but the destination does not match the source.
+    Verification { source: Summary, destination: Summary },
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        Error::Sql(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedBackend(url) => {
+                write!(f, "Unsupported backend '{}': only sqlite: URLs are supported", url)
+            }
+            Error::DestinationNotEmpty(count) => {
+                write!(f, "Destination already holds {} records", count)
+            }
+            Error::Sql(e) => write!(f, "Database error: {}", e),
+            Error::Verification {
+                source,
+                destination,
+            } => write!(
+                f,
+                "Verification failed: source has {}, destination has {}",
+                source, destination
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Record count and checksum of a log, used to verify a migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub records: u64,
+    pub checksum: u64,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} records (checksum {:016x})", self.records, self.checksum)
+    }
+}
+
+/// Running FNV-1a hash over every field of every record, in ordinal order.
+///
+/// Also stored in `.bmap2` archives, so the byte layout fed to the hash must
+/// not change.
+pub(crate) struct Checksum(u64);
+
+impl Checksum {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    pub(crate) fn new() -> Self {
+        Self(Self::OFFSET)
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub(crate) fn record(&mut self, ordinal: i64, key: &str, value: &[u8], timestamp: i64) {
+        self.update(&ordinal.to_le_bytes());
+        // Length prefixes keep ("ab", "c") and ("a", "bc") apart.
+        self.update(&(key.len() as u64).to_le_bytes());
+        self.update(key.as_bytes());
+        self.update(&(value.len() as u64).to_le_bytes());
+        self.update(value);
+        self.update(&timestamp.to_le_bytes());
+    }
+}
+
+type Row = (i64, String, Option<Vec<u8>>, i64, String, String, i64);
+
+const SELECT_ALL: &str =
+    "SELECT ordinal, key, value, timestamp, client_id, worker_label, expires_at FROM records ORDER BY ordinal";
+
+/// Computes the record count and checksum of the log in `pool`.
+pub async fn summarize(pool: &SqlitePool) -> Result<Summary, sqlx::Error> {
+    let mut rows = sqlx::query_as::<_, Row>(SELECT_ALL).fetch(pool);
+    let mut checksum = Checksum::new();
+    let mut records = 0;
+
+    while let Some((ordinal, key, value, timestamp, _, _, _)) = rows.try_next().await? {
+        checksum.record(ordinal, &key, value.as_deref().unwrap_or_default(), timestamp);
+        records += 1;
+    }
+
+    Ok(Summary {
+        records,
+        checksum: checksum.finish(),
+    })
+}
+
+/// Copies every record from `from` into `to` and verifies the result.
+///
+/// The destination is created if needed and must not contain any records.
+/// Returns the summary both sides agreed on.
+pub async fn migrate(from: &str, to: &str) -> Result<Summary, Error> {
+    for url in [from, to] {
+        if !url.starts_with("sqlite:") {
+            return Err(Error::UnsupportedBackend(url.to_string()));
+        }
+    }
+
+    let source = db::init_pool(from).await?;
+    let destination = db::init_pool(to).await?;
+
+    let (existing,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM records")
+        .fetch_one(&destination)
+        .await?;
+    if existing > 0 {
+        return Err(Error::DestinationNotEmpty(existing as u64));
+    }
+
+    let mut rows = sqlx::query_as::<_, Row>(SELECT_ALL).fetch(&source);
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(row) = rows.try_next().await? {
+        batch.push(row);
+        if batch.len() == BATCH_SIZE {
+            insert_batch(&destination, &mut batch).await?;
+        }
+    }
+    insert_batch(&destination, &mut batch).await?;
+    drop(rows);
+
+    let source = summarize(&source).await?;
+    let destination = summarize(&destination).await?;
+    if source != destination {
+        return Err(Error::Verification {
+            source,
+            destination,
+        });
+    }
+
+    Ok(source)
+}
+
+async fn insert_batch(pool: &SqlitePool, batch: &mut Vec<Row>) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (ordinal, key, value, timestamp, client_id, worker_label, expires_at) in batch.drain(..) {
+        sqlx::query(
+            "INSERT INTO records (ordinal, key, value, timestamp, client_id, worker_label, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(ordinal)
+        .bind(key)
+        .bind(value)
+        .bind(timestamp)
+        .bind(client_id)
+        .bind(worker_label)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
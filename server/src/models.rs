@@ -6,6 +6,9 @@ pub struct Record {
     pub key: String,
     pub value: Vec<u8>,
     pub timestamp: i64,
+    pub writer: ClientIdentity,
+    /// Unix millis after which the value lapses, 0 for never.
+    pub expires_at: i64,
 }
 
 impl Record {
@@ -15,6 +18,16 @@ impl Record {
             key,
             value,
             timestamp: Utc::now().timestamp_millis(),
+            writer: ClientIdentity::default(),
+            expires_at: 0,
         }
     }
 }
+
+/// Who wrote a record, as sent in `WriteRequest`. Both fields are empty for
+/// anonymous writers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub client_id: String,
+    pub worker_label: String,
+}
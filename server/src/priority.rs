@@ -0,0 +1,98 @@
+//! Keeps live writes ahead of subscribers replaying a long history.
+//!
+//! Subscribers that are behind the head of the log ("catching up") ask the
+//! [`Scheduler`] before each batch. While writes are in flight, a catch-up
+//! batch only goes ahead after every `ratio` completed writes; when no write
+//! is waiting, catch-up runs at full speed. Subscribers at the head of the
+//! log are never throttled.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+/// Writes served per catch-up batch under load, unless configured.
+pub const DEFAULT_CATCH_UP_RATIO: u32 = 4;
+
+/// Upper bound on a single wait, so a catch-up batch is never starved
+/// completely by a write that hangs.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub struct Scheduler {
+    ratio: AtomicU32,
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    pending_writes: AtomicUsize,
+    writes_since_catch_up: AtomicU32,
+    write_done: Notify,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_CATCH_UP_RATIO)
+    }
+}
+
+impl Scheduler {
+    /// `ratio` writes per catch-up batch under load; 0 turns throttling off.
+    pub fn new(ratio: u32) -> Self {
+        Self {
+            ratio: AtomicU32::new(ratio),
+            inner: Arc::default(),
+        }
+    }
+
+    pub fn ratio(&self) -> u32 {
+        self.ratio.load(Ordering::Relaxed)
+    }
+
+    /// Changes the ratio of a running server, e.g. on config reload.
+    pub fn set_ratio(&self, ratio: u32) {
+        self.ratio.store(ratio, Ordering::Relaxed);
+    }
+
+    /// Marks a write as in flight until the guard is dropped.
+    pub fn write(&self) -> WriteGuard {
+        self.inner.pending_writes.fetch_add(1, Ordering::SeqCst);
+        WriteGuard(Arc::clone(&self.inner))
+    }
+
+    /// Waits until a catch-up batch may run.
+    pub async fn catch_up(&self) {
+        loop {
+            let done = self.inner.write_done.notified();
+            let ratio = self.ratio();
+            if ratio == 0 || self.inner.pending_writes.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            let served = self.inner.writes_since_catch_up.load(Ordering::SeqCst);
+            if served >= ratio
+                && self
+                    .inner
+                    .writes_since_catch_up
+                    .compare_exchange(served, 0, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                return;
+            }
+            if tokio::time::timeout(MAX_WAIT, done).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+pub struct WriteGuard(Arc<Inner>);
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        self.0.pending_writes.fetch_sub(1, Ordering::SeqCst);
+        self.0.writes_since_catch_up.fetch_add(1, Ordering::SeqCst);
+        self.0.write_done.notify_waiters();
+    }
+}
@@ -0,0 +1,135 @@
+//! Optional REST/JSON gateway to the log, for clients that can't carry a
+//! gRPC stack.
+//!
+//! ```text
+//! GET    /v1/keys/{key}   200 {"key", "value", "ordinal", "timestamp"}, 404 if absent
+//! PUT    /v1/keys/{key}   body {"value": "..."}, 200 {"ordinal"}
+//! DELETE /v1/keys/{key}   200 {"ordinal"} of the tombstone
+//! ```
+//!
+//! Keys are full log keys (`map:1`), percent-encoded in the path, and
+//! values UTF-8 text. Writes are unconditional and carry no TTL; reads
+//! see the newest record of the key, so chunked values come back as their
+//! manifest. With `auth_token` set, requests need the same
+//! `authorization: Bearer <token>` header as gRPC calls. Enabled with
+//! `rest_addr` in the config file.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::auth::BearerToken;
+use crate::models::{ClientIdentity, Record};
+use crate::storage::{Storage, WriteError};
+
+#[derive(Clone)]
+struct Gateway {
+    storage: Arc<Storage>,
+    token: Option<BearerToken>,
+}
+
+/// Serves the gateway on `addr` until the future is dropped.
+pub async fn serve(addr: SocketAddr, storage: Arc<Storage>, token: Option<&str>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("REST gateway on http://{}/v1/keys/", listener.local_addr()?);
+    serve_on(listener, storage, token).await
+}
+
+/// Serves the gateway on an already bound listener.
+pub async fn serve_on(listener: TcpListener, storage: Arc<Storage>, token: Option<&str>) -> std::io::Result<()> {
+    axum::serve(listener, router(storage, token)).await
+}
+
+pub fn router(storage: Arc<Storage>, token: Option<&str>) -> Router {
+    let gateway = Gateway {
+        storage,
+        token: token.map(BearerToken::new),
+    };
+    Router::new()
+        .route("/v1/keys/{key}", get(get_key).put(put_key).delete(delete_key))
+        .with_state(gateway)
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+impl Gateway {
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let presented = headers
+            .get("authorization")
+            .map(|value| value.as_bytes())
+            .unwrap_or_default();
+        token.accepts(presented)
+    }
+
+    async fn write(&self, key: String, value: Vec<u8>) -> Response {
+        match self.storage.write(key, value, 0, &ClientIdentity::default()).await {
+            Ok(ordinal) => Json(json!({ "ordinal": ordinal })).into_response(),
+            Err(WriteError::ValueTooLarge { size, limit }) => error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("value of {} bytes is over the limit of {} bytes", size, limit),
+            ),
+            Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+}
+
+async fn get_key(State(gateway): State<Gateway>, Path(key): Path<String>, headers: HeaderMap) -> Response {
+    if !gateway.authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED, "missing or wrong token");
+    }
+    let record = match gateway.storage.history(&key, 0, 1).await {
+        Ok(mut records) => records.pop(),
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let now = chrono::Utc::now().timestamp_millis();
+    // Tombstones and lapsed TTLs read as absent, as they do through gRPC.
+    let live = |record: &Record| {
+        !record.value.is_empty() && (record.expires_at == 0 || record.expires_at > now)
+    };
+    match record.filter(live) {
+        Some(record) => Json(json!({
+            "key": record.key,
+            "value": String::from_utf8_lossy(&record.value),
+            "ordinal": record.ordinal,
+            "timestamp": record.timestamp,
+        }))
+        .into_response(),
+        None => error(StatusCode::NOT_FOUND, "no such key"),
+    }
+}
+
+async fn put_key(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    if !gateway.authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED, "missing or wrong token");
+    }
+    match body.get("value").and_then(Value::as_str) {
+        // An empty value is a tombstone in the log.
+        Some("") => error(StatusCode::BAD_REQUEST, "empty value, use DELETE to remove a key"),
+        Some(value) => gateway.write(key, value.as_bytes().to_vec()).await,
+        None => error(StatusCode::BAD_REQUEST, "body must be {\"value\": \"...\"}"),
+    }
+}
+
+async fn delete_key(State(gateway): State<Gateway>, Path(key): Path<String>, headers: HeaderMap) -> Response {
+    if !gateway.authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED, "missing or wrong token");
+    }
+    gateway.write(key, Vec::new()).await
+}
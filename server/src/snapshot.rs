@@ -1,20 +1,23 @@
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use futures_util::Stream;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Pieces of a binary snapshot, see [`Snapshot::stream_latest`].
+pub type Chunks = Pin<Box<dyn Stream<Item = Result<Vec<u8>, Error>> + Send>>;
+
 #[derive(Debug)]
 struct SnapshotEntries {
     tmap: Option<PathBuf>,
     bmap: Option<PathBuf>,
 }
 
-const BMAP_MAGIC: &[u8; 4] = b"BMAP";
-const BMAP_VERSION: u32 = 1;
-
 #[derive(Debug)]
 pub enum Error {
     Io(std::io::Error),
-    InvalidMagic(String),
-    InvalidVersion(u32),
+    Format(log_snapshot_format::Error),
     InvalidOrdinal,
 }
 
@@ -24,12 +27,17 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<log_snapshot_format::Error> for Error {
+    fn from(err: log_snapshot_format::Error) -> Self {
+        Error::Format(err)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::Io(e) => write!(f, "IO error: {}", e),
-            Error::InvalidMagic(s) => write!(f, "Invalid magic: {}", s),
-            Error::InvalidVersion(v) => write!(f, "Invalid version: {}", v),
+            Error::Format(e) => write!(f, "Invalid snapshot: {}", e),
             Error::InvalidOrdinal => write!(f, "Invalid ordinal"),
         }
     }
@@ -39,19 +47,24 @@ impl std::error::Error for Error {}
 
 pub struct Snapshot {
     snapshot_dir: PathBuf,
-    snapshot_interval: u64,
+    snapshot_interval: AtomicU64,
     last_snapshot_ordinal: AtomicU64,
 }
 
 impl Snapshot {
-    pub fn new(dir: &str, interval: u64) -> Result<Self, Error> {
-        let snapshot_dir = PathBuf::from(dir);
+    /// Uses the snapshots in `dir`, creating it if needed. The interval
+    /// counts from the newest snapshot already there.
+    pub fn new(dir: impl AsRef<Path>, interval: u64) -> Result<Self, Error> {
+        let snapshot_dir = dir.as_ref().to_path_buf();
         std::fs::create_dir_all(&snapshot_dir)?;
-        Ok(Self {
+        let snapshot = Self {
             snapshot_dir,
-            snapshot_interval: interval,
+            snapshot_interval: AtomicU64::new(interval),
             last_snapshot_ordinal: AtomicU64::new(0),
-        })
+        };
+        let latest = snapshot.latest_ordinal()?;
+        snapshot.last_snapshot_ordinal.store(latest, Ordering::Relaxed);
+        Ok(snapshot)
     }
 
     pub fn should_snapshot(&self, current_ordinal: u64) -> bool {
@@ -59,7 +72,7 @@ impl Snapshot {
             return false;
         }
         let last = self.last_snapshot_ordinal.load(Ordering::Relaxed);
-        if current_ordinal - last >= self.snapshot_interval {
+        if current_ordinal.saturating_sub(last) >= self.snapshot_interval.load(Ordering::Relaxed) {
             self.last_snapshot_ordinal
                 .store(current_ordinal, Ordering::Relaxed);
             return true;
@@ -67,13 +80,28 @@ impl Snapshot {
         false
     }
 
+    pub fn interval(&self) -> u64 {
+        self.snapshot_interval.load(Ordering::Relaxed)
+    }
+
+    /// Changes how many ordinals pass between automatic snapshots.
+    pub fn set_interval(&self, interval: u64) {
+        self.snapshot_interval.store(interval, Ordering::Relaxed);
+    }
+
+    /// Ordinal at which the last snapshot was triggered, or of the newest
+    /// one on disk at startup.
+    pub fn last_snapshot_ordinal(&self) -> u64 {
+        self.last_snapshot_ordinal.load(Ordering::Relaxed)
+    }
+
     fn snapshot_path(&self, ordinal: u64, extension: &str) -> PathBuf {
         self.snapshot_dir
             .join(format!("snapshot_{}.{}", ordinal, extension))
     }
 
-    pub async fn save_text(&self, records: &[(String, Vec<u8>)]) -> Result<(), Error> {
-        let ordinal = records.len() as u64;
+    /// Writes `records`, the state of the map as of `ordinal`.
+    pub async fn save_text(&self, ordinal: u64, records: &[(String, Vec<u8>)]) -> Result<(), Error> {
         let path = self.snapshot_path(ordinal, "tmap");
         let mut content = String::new();
 
@@ -86,28 +114,11 @@ impl Snapshot {
         Ok(())
     }
 
-    pub async fn save_binary(&self, records: &[(String, Vec<u8>)]) -> Result<(), Error> {
-        let ordinal = records.len() as u64;
+    /// Binary counterpart of [`save_text`](Self::save_text), served by
+    /// `GetSnapshot`.
+    pub async fn save_binary(&self, ordinal: u64, records: &[(String, Vec<u8>)]) -> Result<(), Error> {
         let path = self.snapshot_path(ordinal, "bmap");
-
-        let mut buf = Vec::new();
-
-        buf.extend_from_slice(BMAP_MAGIC);
-        buf.extend_from_slice(&BMAP_VERSION.to_le_bytes());
-        buf.extend_from_slice(&(records.len() as u32).to_le_bytes());
-
-        for (key, value) in records {
-            let key_bytes = key.as_bytes();
-            let key_len = key_bytes.len() as u16;
-            buf.extend_from_slice(&key_len.to_le_bytes());
-            buf.extend_from_slice(key_bytes);
-
-            let value_len = value.len() as u32;
-            buf.extend_from_slice(&value_len.to_le_bytes());
-            buf.extend_from_slice(value);
-        }
-
-        tokio::fs::write(path, buf).await?;
+        tokio::fs::write(path, log_snapshot_format::encode(records)?).await?;
         Ok(())
     }
 
@@ -130,47 +141,10 @@ impl Snapshot {
     pub async fn load_binary(&self) -> Result<Vec<(String, Vec<u8>)>, Error> {
         let entries = self.read_snapshot_entries()?;
 
-        if let Some(path) = entries.bmap {
-            let data = tokio::fs::read(path).await?;
-
-            if &data[0..4] != BMAP_MAGIC {
-                return Err(Error::InvalidMagic(
-                    String::from_utf8_lossy(&data[0..4]).to_string(),
-                ));
-            }
-
-            let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-            if version != BMAP_VERSION {
-                return Err(Error::InvalidVersion(version));
-            }
-
-            let count = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
-            let mut result = Vec::with_capacity(count);
-            let mut offset = 12;
-
-            for _ in 0..count {
-                let key_len = u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
-                offset += 2;
-                let key = String::from_utf8_lossy(&data[offset..offset + key_len]).to_string();
-                offset += key_len;
-
-                let value_len = u32::from_le_bytes([
-                    data[offset],
-                    data[offset + 1],
-                    data[offset + 2],
-                    data[offset + 3],
-                ]) as usize;
-                offset += 4;
-                let value = data[offset..offset + value_len].to_vec();
-                offset += value_len;
-
-                result.push((key, value));
-            }
-
-            return Ok(result);
+        match entries.bmap {
+            Some(path) => Ok(log_snapshot_format::decode(&tokio::fs::read(path).await?)?),
+            None => Ok(Vec::new()),
         }
-
-        Ok(Vec::new())
     }
 
     fn read_snapshot_entries(&self) -> Result<SnapshotEntries, Error> {
@@ -178,32 +152,32 @@ impl Snapshot {
         let mut bmap = None;
         let mut max_ordinal = 0u64;
 
-        println!(
+        tracing::debug!(
             "Reading snapshot directory: '{}'",
             self.snapshot_dir.to_string_lossy()
         );
         for entry in std::fs::read_dir(&self.snapshot_dir)? {
-            println!("found file: {:#?}", entry);
+            tracing::trace!("found file: {:#?}", entry);
             let entry = entry?;
             let name = entry.file_name();
             let name_str = name.to_string_lossy();
 
             if let Some(rest) = name_str.strip_prefix("snapshot_") {
-                println!("matches snapshot_ prefix: {}", rest);
+                tracing::trace!("matches snapshot_ prefix: {}", rest);
                 if let Some(ordinal_str) = rest.split('_').next() {
                     let ordinal = PathBuf::from(&ordinal_str);
                     let ordinal = ordinal.file_stem().unwrap();
 
                     if let Ok(ordinal) = ordinal.to_string_lossy().parse::<u64>() {
                         if ordinal > max_ordinal {
-                            println!("ordinal > max_ordinal");
+                            tracing::trace!("ordinal > max_ordinal");
                             max_ordinal = ordinal;
                             tmap = None;
                             bmap = None;
                         }
 
                         if ordinal == max_ordinal {
-                            println!("ordinal == max_ordinal");
+                            tracing::trace!("ordinal == max_ordinal");
                             if name_str.ends_with(".tmap") {
                                 tmap = Some(entry.path());
                             } else if name_str.ends_with(".bmap") {
@@ -215,31 +189,33 @@ impl Snapshot {
             }
         }
 
-        println!("found {:#?} snapshot entry", bmap);
+        tracing::debug!("found {:?} snapshot entry", bmap);
 
         Ok(SnapshotEntries { tmap, bmap })
     }
 
+    /// Ordinal of the newest snapshot on disk, 0 if there is none.
+    pub fn latest_ordinal(&self) -> Result<u64, Error> {
+        match self.read_snapshot_entries()?.bmap {
+            Some(path) => self.extract_ordinal_from_path(&path),
+            None => Ok(0),
+        }
+    }
+
+    /// Size of the newest binary snapshot on disk, 0 if there is none.
+    pub fn latest_size(&self) -> Result<u64, Error> {
+        match self.read_snapshot_entries()?.bmap {
+            Some(path) => Ok(std::fs::metadata(path)?.len()),
+            None => Ok(0),
+        }
+    }
+
     pub async fn get_latest_snapshot(&self) -> Result<(u64, Option<Vec<u8>>), Error> {
         let entries = self.read_snapshot_entries()?;
 
         if let Some(path) = entries.bmap {
             let data = tokio::fs::read(&path).await?;
-
-            if data.len() < 8 {
-                return Err(Error::InvalidMagic("File too short".to_string()));
-            }
-
-            if &data[0..4] != BMAP_MAGIC {
-                return Err(Error::InvalidMagic(
-                    String::from_utf8_lossy(&data[0..4]).to_string(),
-                ));
-            }
-
-            let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-            if version != BMAP_VERSION {
-                return Err(Error::InvalidVersion(version));
-            }
+            log_snapshot_format::Header::parse(&data)?;
 
             let ordinal = self.extract_ordinal_from_path(&path)?;
             return Ok((ordinal, Some(data)));
@@ -248,8 +224,87 @@ impl Snapshot {
         Ok((0, None))
     }
 
-    fn extract_ordinal_from_path(&self, path: &PathBuf) -> Result<u64, Error> {
-        let filename = path.file_name().ok_or_else(|| Error::InvalidOrdinal)?;
+    /// The newest binary snapshot in pieces of at most `chunk` bytes, read
+    /// from its file as the stream is polled, with only the keys starting
+    /// with `key_prefix`. `None` if there is no snapshot.
+    ///
+    /// A prefix takes a first pass over the file to count the matching
+    /// records for the header; neither pass holds much more than a chunk in
+    /// memory.
+    pub async fn stream_latest(&self, key_prefix: &str, chunk: usize) -> Result<Option<(u64, Chunks)>, Error> {
+        let Some(path) = self.read_snapshot_entries()?.bmap else {
+            return Ok(None);
+        };
+        let ordinal = self.extract_ordinal_from_path(&path)?;
+        // An open file outlives its pruning.
+        let mut file = tokio::fs::File::open(&path).await?;
+        let mut header = [0; log_snapshot_format::HEADER_LEN];
+        file.read_exact(&mut header)
+            .await
+            .map_err(|_| log_snapshot_format::Error::Truncated("header"))?;
+        log_snapshot_format::Header::parse(&header)?;
+
+        if key_prefix.is_empty() {
+            let chunks = async_stream::try_stream! {
+                yield header.to_vec();
+                let mut buf = vec![0; chunk];
+                loop {
+                    let read = file.read(&mut buf).await?;
+                    if read == 0 {
+                        break;
+                    }
+                    yield buf[..read].to_vec();
+                }
+            };
+            return Ok(Some((ordinal, Box::pin(chunks))));
+        }
+
+        let prefix = key_prefix.to_string();
+        let mut count = 0u32;
+        let mut decoder = log_snapshot_format::Decoder::new();
+        let mut buf = vec![0; chunk];
+        file.seek(std::io::SeekFrom::Start(0)).await?;
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            decoder.feed(&buf[..read], |key, _| count += key.starts_with(&prefix) as u32)?;
+        }
+        decoder.finish()?;
+        file.seek(std::io::SeekFrom::Start(0)).await?;
+
+        let chunks = async_stream::try_stream! {
+            let mut out = log_snapshot_format::encode_header(count).to_vec();
+            let mut decoder = log_snapshot_format::Decoder::new();
+            let mut matching = Vec::new();
+            loop {
+                let read = file.read(&mut buf).await?;
+                if read == 0 {
+                    break;
+                }
+                decoder.feed(&buf[..read], |key, value| {
+                    if key.starts_with(&prefix) {
+                        matching.push((key, value));
+                    }
+                })?;
+                for (key, value) in matching.drain(..) {
+                    log_snapshot_format::encode_record(&mut out, &key, &value)?;
+                }
+                while out.len() >= chunk {
+                    let rest = out.split_off(chunk);
+                    yield std::mem::replace(&mut out, rest);
+                }
+            }
+            if !out.is_empty() {
+                yield out;
+            }
+        };
+        Ok(Some((ordinal, Box::pin(chunks))))
+    }
+
+    fn extract_ordinal_from_path(&self, path: &Path) -> Result<u64, Error> {
+        let filename = path.file_name().ok_or(Error::InvalidOrdinal)?;
 
         let name_str = filename.to_string_lossy();
         if let Some(rest) = name_str.strip_prefix("snapshot_") {
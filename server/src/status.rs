@@ -0,0 +1,163 @@
+//! Optional HTTP status page for operators.
+//!
+//! `GET /` renders a small HTML page, `GET /status.json` returns the same
+//! data for scripts. Enabled with `status_addr` in the config file.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::storage::Storage;
+
+/// Serves the status page on `addr` until the future is dropped.
+pub async fn serve(addr: SocketAddr, storage: Arc<Storage>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("status page on http://{}", listener.local_addr()?);
+    serve_on(listener, storage).await
+}
+
+/// Serves the status page on an already bound listener.
+pub async fn serve_on(listener: TcpListener, storage: Arc<Storage>) -> std::io::Result<()> {
+    axum::serve(listener, router(storage)).await
+}
+
+pub fn router(storage: Arc<Storage>) -> Router {
+    Router::new()
+        .route("/", get(page))
+        .route("/status.json", get(status_json))
+        .with_state(storage)
+}
+
+async fn collect(storage: &Storage) -> Value {
+    let (stats, error) = match storage.stats().await {
+        Ok(stats) => (stats, None),
+        Err(e) => (Default::default(), Some(e.to_string())),
+    };
+    let activity = storage.activity();
+    let maintenance = storage.maintenance();
+
+    json!({
+        "healthy": error.is_none(),
+        "error": error,
+        "latest_ordinal": stats.latest_ordinal,
+        "record_count": stats.record_count,
+        "key_count": stats.key_count,
+        "durability": stats.durability.to_string(),
+        "snapshot": {
+            "enabled": storage.snapshot_interval().is_some(),
+            "interval": storage.snapshot_interval(),
+            "last_ordinal": stats.snapshot_ordinal,
+        },
+        "maintenance": {
+            "window": maintenance.window().map(|w| w.to_string()),
+            "running": maintenance.running().map(|p| p.task.to_string()),
+            "last_run": maintenance.last_run().map(|run| json!({
+                "started_at": run.started_at,
+                "finished_at": run.finished_at,
+                "results": run.results.iter().map(|r| json!({
+                    "task": r.task.to_string(),
+                    "error": r.error,
+                })).collect::<Vec<_>>(),
+            })),
+        },
+        "subscribers": activity.subscribers(),
+        "recent_conflicts": activity.recent_conflicts().iter().map(|c| json!({
+            "key": c.key,
+            "latest_ordinal": c.latest_ordinal,
+            "at": c.at,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+async fn status_json(State(storage): State<Arc<Storage>>) -> Json<Value> {
+    Json(collect(&storage).await)
+}
+
+async fn page(State(storage): State<Arc<Storage>>) -> Html<String> {
+    let status = collect(&storage).await;
+    let snapshot = &status["snapshot"];
+
+    let mut conflicts = String::new();
+    for conflict in status["recent_conflicts"].as_array().into_iter().flatten() {
+        let at = chrono::DateTime::from_timestamp_millis(conflict["at"].as_i64().unwrap_or(0))
+            .map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default();
+        conflicts.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            at,
+            escape(conflict["key"].as_str().unwrap_or_default()),
+            conflict["latest_ordinal"]
+        ));
+    }
+    if conflicts.is_empty() {
+        conflicts.push_str("<tr><td colspan=\"3\">none</td></tr>");
+    }
+
+    let health = match status["error"].as_str() {
+        Some(error) => format!("<p class=\"bad\">database error: {}</p>", escape(error)),
+        None => String::from("<p class=\"ok\">healthy</p>"),
+    };
+    let snapshots = if snapshot["enabled"].as_bool().unwrap_or(false) {
+        format!(
+            "every {} ordinals, last at {}",
+            snapshot["interval"], snapshot["last_ordinal"]
+        )
+    } else {
+        String::from("disabled")
+    };
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="5">
+<title>log-server status</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+td, th {{ border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: left; }}
+.ok {{ color: green; }}
+.bad {{ color: red; }}
+</style>
+</head>
+<body>
+<h1>log-server {version}</h1>
+{health}
+<table>
+<tr><th>latest ordinal</th><td>{latest}</td></tr>
+<tr><th>records</th><td>{records}</td></tr>
+<tr><th>distinct keys</th><td>{keys}</td></tr>
+<tr><th>snapshots</th><td>{snapshots}</td></tr>
+<tr><th>durability</th><td>{durability}</td></tr>
+<tr><th>subscribers</th><td>{subscribers}</td></tr>
+</table>
+<h2>Recent conflicts</h2>
+<table>
+<tr><th>time</th><th>key</th><th>latest ordinal</th></tr>
+{conflicts}
+</table>
+</body>
+</html>
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+        latest = status["latest_ordinal"],
+        records = status["record_count"],
+        keys = status["key_count"],
+        durability = status["durability"].as_str().unwrap_or_default(),
+        subscribers = status["subscribers"],
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
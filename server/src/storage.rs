@@ -1,13 +1,47 @@
-use crate::models::Record;
+use crate::activity::Activity;
+use crate::compaction;
+use crate::db::Durability;
+use crate::maintenance;
+use crate::metrics::Metrics;
+use crate::models::{ClientIdentity, Record};
+use crate::priority::Scheduler;
+#[cfg(feature = "snapshots")]
 use crate::snapshot;
-use futures_util::{future::Map, stream::Stream};
+use futures_util::stream::{Stream, StreamExt};
 use sqlx::{Row, SqlitePool};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
     sync::{Arc, Mutex},
+    time::Instant,
 };
 use thiserror::Error;
+use tokio::sync::broadcast;
+
+/// Records fetched per subscriber query.
+const SUBSCRIBE_BATCH: usize = 100;
+/// Live records buffered per subscriber before it has to catch up from
+/// the database again.
+pub const LIVE_CAPACITY: usize = 1024;
+
+/// Largest value a write may carry unless configured otherwise: 1 MiB,
+/// which keeps records well under tonic's 4 MiB message limit.
+pub const DEFAULT_MAX_VALUE_SIZE: u64 = 1024 * 1024;
+
+/// Bytes read from the snapshot file at a time by
+/// [`Storage::get_latest_snapshot`].
+const SNAPSHOT_READ_CHUNK: usize = 64 * 1024;
+
+/// Pieces of a snapshot, see [`Storage::stream_latest_snapshot`].
+pub type SnapshotChunks = Pin<Box<dyn Stream<Item = Result<Vec<u8>, WriteError>> + Send>>;
+
+/// Writes a record at a chosen ordinal, replacing whatever held it.
+const INSERT_RECORD: &str = "INSERT INTO records (ordinal, key, value, timestamp, client_id, worker_label, expires_at)
+     VALUES (?, ?, ?, ?, ?, ?, ?)
+     ON CONFLICT(ordinal) DO UPDATE SET key = excluded.key, value = excluded.value, timestamp = excluded.timestamp,
+         client_id = excluded.client_id, worker_label = excluded.worker_label, expires_at = excluded.expires_at
+     RETURNING ordinal";
 
 pub struct InnerMapCache {
     cache: HashMap<String, i64>,
@@ -29,7 +63,7 @@ impl InnerMapCache {
 
         self.cache.insert(key, ordinal);
 
-        return Ok(());
+        Ok(())
     }
 
     fn new() -> Self {
@@ -44,6 +78,12 @@ pub struct MapCache {
     handle: Arc<Mutex<InnerMapCache>>,
 }
 
+impl Default for MapCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MapCache {
     pub fn new() -> Self {
         Self {
@@ -62,8 +102,23 @@ impl MapCache {
 
 pub struct Storage {
     pool: SqlitePool,
+    #[cfg(feature = "snapshots")]
     snapshot: Option<snapshot::Snapshot>,
     cache: MapCache,
+    /// Serializes ordinal assignment so concurrent writes cannot pick the
+    /// same ordinal and overwrite each other through the upsert.
+    write_lock: tokio::sync::Mutex<()>,
+    activity: Activity,
+    maintenance: maintenance::Status,
+    durability: Durability,
+    scheduler: Arc<Scheduler>,
+    compaction_retain: AtomicU64,
+    /// Largest value a write may carry, 0 for no limit.
+    max_value_size: AtomicU64,
+    metrics: Arc<Metrics>,
+    /// Every record written, in ordinal order and with its commit time,
+    /// for caught-up subscribers.
+    live: broadcast::Sender<(Record, Instant)>,
 }
 
 impl Storage {
@@ -71,24 +126,47 @@ impl Storage {
         Self {
             pool,
             cache: MapCache::new(),
+            #[cfg(feature = "snapshots")]
             snapshot: None,
+            write_lock: tokio::sync::Mutex::new(()),
+            activity: Activity::default(),
+            metrics: Arc::default(),
+            maintenance: maintenance::Status::default(),
+            durability: Durability::default(),
+            scheduler: Arc::default(),
+            compaction_retain: AtomicU64::new(compaction::DEFAULT_RETAIN),
+            max_value_size: AtomicU64::new(DEFAULT_MAX_VALUE_SIZE),
+            live: broadcast::channel(LIVE_CAPACITY).0,
         }
     }
 
+    #[cfg(feature = "snapshots")]
     pub fn with_snapshot(
         pool: SqlitePool,
-        snapshot_dir: &str,
+        snapshot_dir: impl AsRef<std::path::Path>,
         snapshot_interval: u64,
     ) -> Result<Self, snapshot::Error> {
         Ok(Self {
             pool,
             cache: MapCache::new(),
             snapshot: Some(snapshot::Snapshot::new(snapshot_dir, snapshot_interval)?),
+            write_lock: tokio::sync::Mutex::new(()),
+            activity: Activity::default(),
+            metrics: Arc::default(),
+            maintenance: maintenance::Status::default(),
+            durability: Durability::default(),
+            scheduler: Arc::default(),
+            compaction_retain: AtomicU64::new(compaction::DEFAULT_RETAIN),
+            max_value_size: AtomicU64::new(DEFAULT_MAX_VALUE_SIZE),
+            live: broadcast::channel(LIVE_CAPACITY).0,
         })
     }
 
     pub async fn append(&self, key: String, value: Vec<u8>) -> Result<u64, sqlx::Error> {
+        let _write = self.scheduler.write();
+        let started = Instant::now();
         let now = chrono::Utc::now().timestamp_millis();
+        let _guard = self.write_lock.lock().await;
         let result = sqlx::query(
             "INSERT INTO records (key, value, timestamp) VALUES (?, ?, ?) RETURNING ordinal",
         )
@@ -98,17 +176,83 @@ impl Storage {
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(result.get("ordinal"))
+        let ordinal: u64 = result.get("ordinal");
+        self.publish(Record {
+            ordinal,
+            key,
+            value,
+            timestamp: now,
+            writer: ClientIdentity::default(),
+            expires_at: 0,
+        });
+        self.metrics.record_write(started.elapsed());
+        Ok(ordinal)
     }
 
     pub async fn write(
         &self,
-        ordinal: u64,
+        key: String,
+        value: Vec<u8>,
+        _latest_known: u64,
+        writer: &ClientIdentity,
+    ) -> Result<u64, WriteError> {
+        self.write_record(key, value, None, 0, writer).await
+    }
+
+    /// Like [`write`](Self::write), but fails with [`WriteError::Conflict`]
+    /// if `key` has a record newer than `latest_known`. Records of other
+    /// keys never conflict.
+    pub async fn write_if_unchanged(
+        &self,
         key: String,
         value: Vec<u8>,
         latest_known: u64,
+        writer: &ClientIdentity,
+    ) -> Result<u64, WriteError> {
+        self.write_record(key, value, Some(latest_known), 0, writer).await
+    }
+
+    /// Writes a value that [`expire`](Self::expire) removes once the clock
+    /// passes `expires_at` (Unix millis), unless it was overwritten first.
+    /// With `unchanged_since`, this is [`write_if_unchanged`](Self::write_if_unchanged).
+    pub async fn write_expiring(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        expires_at: i64,
+        unchanged_since: Option<u64>,
+        writer: &ClientIdentity,
+    ) -> Result<u64, WriteError> {
+        self.write_record(key, value, unchanged_since, expires_at, writer).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(key = %key))]
+    async fn write_record(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        unchanged_since: Option<u64>,
+        expires_at: i64,
+        writer: &ClientIdentity,
     ) -> Result<u64, WriteError> {
+        self.check_value_size(&value)?;
+        let _write = self.scheduler.write();
+        let started = Instant::now();
         let now = chrono::Utc::now().timestamp_millis();
+        let guard = self.write_lock.lock().await;
+
+        if let Some(latest_known) = unchanged_since {
+            let key_ordinal: Option<i64> =
+                sqlx::query("SELECT MAX(ordinal) as max_ord FROM records WHERE key = ?")
+                    .bind(&key)
+                    .fetch_one(&self.pool)
+                    .await?
+                    .get("max_ord");
+            let key_ordinal = key_ordinal.unwrap_or(0) as u64;
+            if key_ordinal > latest_known {
+                return Err(self.conflict(&key, key_ordinal).await?);
+            }
+        }
 
         let latest_ordinal: Option<i64> =
             sqlx::query("SELECT MAX(ordinal) as max_ord FROM records")
@@ -120,25 +264,39 @@ impl Storage {
         let new_ordinal = latest_ordinal + 1;
 
         let update_result = self.cache.update(key.clone(), new_ordinal as i64).await;
-        if let Err(e) = update_result {
-            println!("conflict!: latest persisted - {latest_ordinal}, latest_known by client - {latest_known}");
-            return Err(WriteError::Conflict(latest_ordinal));
+        if update_result.is_err() {
+            self.activity.record_conflict(&key, latest_ordinal);
+            self.metrics.record_conflict();
+            return Err(WriteError::Conflict {
+                latest_ordinal,
+                key_ordinal: latest_ordinal,
+            });
         }
 
-        let result = sqlx::query(
-            "INSERT INTO records (ordinal, key, value, timestamp) VALUES (?, ?, ?, ?)
-             ON CONFLICT(ordinal) DO UPDATE SET key = excluded.key, value = excluded.value, timestamp = excluded.timestamp
-             RETURNING ordinal",
-        )
+        let result = sqlx::query(INSERT_RECORD)
         .bind(new_ordinal as i64)
         .bind(&key)
         .bind(&value)
         .bind(now)
+        .bind(&writer.client_id)
+        .bind(&writer.worker_label)
+        .bind(expires_at)
         .fetch_one(&self.pool)
         .await?;
 
         let written_ordinal = result.get("ordinal");
-
+        self.publish(Record {
+            ordinal: written_ordinal,
+            key,
+            value,
+            timestamp: now,
+            writer: writer.clone(),
+            expires_at,
+        });
+        drop(guard);
+        self.metrics.record_write(started.elapsed());
+
+        #[cfg(feature = "snapshots")]
         if let Some(ref snapshot) = self.snapshot {
             if snapshot.should_snapshot(written_ordinal) {
                 self.create_snapshot().await?;
@@ -148,69 +306,673 @@ impl Storage {
         Ok(written_ordinal)
     }
 
+    /// Writes every `(key, value)` of `writes` in one database transaction,
+    /// at consecutive ordinals, or none of them. Fails with
+    /// [`WriteError::Conflict`] if any of the keys has a record newer than
+    /// `latest_known`. Returns the ordinal of the first write, 0 if there
+    /// were none.
+    #[tracing::instrument(level = "debug", skip_all, fields(writes = writes.len()))]
+    pub async fn write_transaction(
+        &self,
+        writes: Vec<(String, Vec<u8>)>,
+        latest_known: u64,
+        writer: &ClientIdentity,
+    ) -> Result<u64, WriteError> {
+        if writes.is_empty() {
+            return Ok(0);
+        }
+        for (_, value) in &writes {
+            self.check_value_size(value)?;
+        }
+        let _write = self.scheduler.write();
+        let started = Instant::now();
+        let now = chrono::Utc::now().timestamp_millis();
+        let guard = self.write_lock.lock().await;
+
+        let keys: HashSet<&str> = writes.iter().map(|(key, _)| key.as_str()).collect();
+        for key in keys {
+            let key_ordinal: Option<i64> =
+                sqlx::query("SELECT MAX(ordinal) as max_ord FROM records WHERE key = ?")
+                    .bind(key)
+                    .fetch_one(&self.pool)
+                    .await?
+                    .get("max_ord");
+            let key_ordinal = key_ordinal.unwrap_or(0) as u64;
+            if key_ordinal > latest_known {
+                return Err(self.conflict(key, key_ordinal).await?);
+            }
+        }
+
+        let latest_ordinal: Option<i64> =
+            sqlx::query("SELECT MAX(ordinal) as max_ord FROM records")
+                .fetch_one(&self.pool)
+                .await?
+                .get("max_ord");
+        let first_ordinal = latest_ordinal.unwrap_or(0) as u64 + 1;
+
+        let mut tx = self.pool.begin().await?;
+        for ((key, value), ordinal) in writes.iter().zip(first_ordinal..) {
+            sqlx::query(INSERT_RECORD)
+                .bind(ordinal as i64)
+                .bind(key)
+                .bind(value)
+                .bind(now)
+                .bind(&writer.client_id)
+                .bind(&writer.worker_label)
+                .bind(0)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        let count = writes.len() as u64;
+        #[cfg(feature = "snapshots")]
+        let last_ordinal = first_ordinal + count - 1;
+        for ((key, value), ordinal) in writes.into_iter().zip(first_ordinal..) {
+            // Ordinals only grow under the write lock, so this can't fail.
+            let _ = self.cache.update(key.clone(), ordinal as i64).await;
+            self.publish(Record {
+                ordinal,
+                key,
+                value,
+                timestamp: now,
+                writer: writer.clone(),
+                expires_at: 0,
+            });
+        }
+        drop(guard);
+        let latency = started.elapsed();
+        for _ in 0..count {
+            self.metrics.record_write(latency);
+        }
+
+        #[cfg(feature = "snapshots")]
+        if let Some(ref snapshot) = self.snapshot {
+            if snapshot.should_snapshot(last_ordinal) {
+                self.create_snapshot().await?;
+            }
+        }
+
+        Ok(first_ordinal)
+    }
+
+    /// Writes a tombstone for every key whose latest record has lapsed,
+    /// see [`write_expiring`](Self::write_expiring). Returns how many.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn expire(&self) -> Result<u64, WriteError> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let lapsed: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT key, ordinal FROM records AS r
+             WHERE expires_at > 0 AND expires_at <= ?1 AND length(value) > 0
+               AND ordinal = (SELECT MAX(ordinal) FROM records WHERE key = r.key)",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let writer = ClientIdentity {
+            client_id: "log-server".to_string(),
+            worker_label: "expiry".to_string(),
+        };
+        let mut removed = 0;
+        for (key, ordinal) in lapsed {
+            match self.write_record(key, Vec::new(), Some(ordinal as u64), 0, &writer).await {
+                Ok(_) => removed += 1,
+                // Overwritten since the query; the new value stands.
+                Err(WriteError::Conflict { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Hands a committed record to live subscribers. Called with the write
+    /// lock held, so records go out in ordinal order.
+    fn publish(&self, record: Record) {
+        // No receivers just means nobody is subscribed.
+        let _ = self.live.send((record, Instant::now()));
+    }
+
+    /// Records a conflict on `key`, whose latest record is `key_ordinal`.
+    async fn conflict(&self, key: &str, key_ordinal: u64) -> Result<WriteError, sqlx::Error> {
+        let latest_ordinal: Option<i64> = sqlx::query("SELECT MAX(ordinal) as max_ord FROM records")
+            .fetch_one(&self.pool)
+            .await?
+            .get("max_ord");
+        let latest_ordinal = latest_ordinal.unwrap_or(0) as u64;
+        self.activity.record_conflict(key, latest_ordinal);
+        self.metrics.record_conflict();
+        Ok(WriteError::Conflict {
+            latest_ordinal,
+            key_ordinal,
+        })
+    }
+
+    /// Writes a snapshot immediately, regardless of the interval. Does
+    /// nothing when the storage was created without snapshots.
+    #[cfg(feature = "snapshots")]
+    pub async fn snapshot_now(&self) -> Result<(), snapshot::Error> {
+        self.create_snapshot().await
+    }
+
+    /// Changes the snapshot interval of a running storage.
+    #[cfg(feature = "snapshots")]
+    pub fn set_snapshot_interval(&self, interval: u64) {
+        if let Some(ref snapshot) = self.snapshot {
+            snapshot.set_interval(interval);
+        }
+    }
+
+    /// Current snapshot interval, or `None` if snapshots are disabled.
+    #[cfg(feature = "snapshots")]
+    pub fn snapshot_interval(&self) -> Option<u64> {
+        self.snapshot.as_ref().map(|s| s.interval())
+    }
+
+    /// Always `None`, the server was built without the `snapshots` feature.
+    #[cfg(not(feature = "snapshots"))]
+    pub fn snapshot_interval(&self) -> Option<u64> {
+        None
+    }
+
+    /// Records the policy the pool was opened with, see
+    /// [`db::init_pool_with`](crate::db::init_pool_with), for `Stats`.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// Subscriber and conflict counters for the status page.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn activity(&self) -> &Activity {
+        &self.activity
+    }
+
+    /// Balances live writes against subscribers catching up, see
+    /// [`priority`](crate::priority).
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Progress and last results of scheduled maintenance.
+    pub fn maintenance(&self) -> &maintenance::Status {
+        &self.maintenance
+    }
+
+    /// Ordinals behind the head of the log that compaction leaves alone.
+    pub fn compaction_retain(&self) -> u64 {
+        self.compaction_retain.load(Ordering::Relaxed)
+    }
+
+    /// Changes the retention of a running server, e.g. on config reload.
+    pub fn set_compaction_retain(&self, retain: u64) {
+        self.compaction_retain.store(retain, Ordering::Relaxed);
+    }
+
+    /// Largest value a write may carry, 0 for no limit.
+    pub fn max_value_size(&self) -> u64 {
+        self.max_value_size.load(Ordering::Relaxed)
+    }
+
+    /// Changes the value size limit of a running server, e.g. on config
+    /// reload. Records already written are not affected.
+    pub fn set_max_value_size(&self, limit: u64) {
+        self.max_value_size.store(limit, Ordering::Relaxed);
+    }
+
+    /// Fails with [`WriteError::ValueTooLarge`] if `value` is over the limit.
+    fn check_value_size(&self, value: &[u8]) -> Result<(), WriteError> {
+        let limit = self.max_value_size();
+        if limit != 0 && value.len() as u64 > limit {
+            return Err(WriteError::ValueTooLarge {
+                size: value.len() as u64,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Deletes every record at or below the cutoff ordinal that is not the
+    /// latest of its key, and tombstones at or below it. Reading the log
+    /// from any ordinal past the cutoff still yields the same map.
+    ///
+    /// The cutoff is [`compaction_retain`](Self::compaction_retain)
+    /// ordinals behind the head, and never past the latest snapshot when
+    /// snapshots are enabled. It stays below the head even with a
+    /// retention of 0: the head record is what the next write's ordinal
+    /// is counted from, so deleting a tombstone there would hand its
+    /// ordinal out again.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn compact(&self) -> Result<Compaction, WriteError> {
+        let started = std::time::Instant::now();
+        let latest: Option<i64> = sqlx::query("SELECT MAX(ordinal) as max_ord FROM records")
+            .fetch_one(&self.pool)
+            .await?
+            .get("max_ord");
+        let cutoff = (latest.unwrap_or(0) as u64).saturating_sub(self.compaction_retain().max(1));
+        #[cfg(feature = "snapshots")]
+        let cutoff = match self.snapshot {
+            Some(ref snapshot) => cutoff.min(snapshot.latest_ordinal()?),
+            None => cutoff,
+        };
+
+        let removed = sqlx::query(
+            "DELETE FROM records
+             WHERE ordinal <= ?1 AND (
+                 length(value) = 0 OR value IS NULL
+                 OR ordinal < (SELECT MAX(newer.ordinal) FROM records newer
+                               WHERE newer.key = records.key AND newer.ordinal <= ?1)
+             )",
+        )
+        .bind(cutoff as i64)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        tracing::info!(
+            cutoff,
+            removed,
+            latency_ms = started.elapsed().as_millis() as u64,
+            "log compacted"
+        );
+        Ok(Compaction { cutoff, removed })
+    }
+
+    /// Rebuilds the database file, reclaiming free pages.
+    pub async fn vacuum(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Refreshes the query planner's statistics.
+    pub async fn analyze(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("ANALYZE").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "snapshots")]
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn create_snapshot(&self) -> Result<(), snapshot::Error> {
         if let Some(ref snapshot) = self.snapshot {
+            let started = std::time::Instant::now();
+            let sql_error = |e| snapshot::Error::Io(std::io::Error::other(e));
+            let ordinal: Option<i64> = sqlx::query("SELECT MAX(ordinal) as max_ord FROM records")
+                .fetch_one(&self.pool)
+                .await
+                .map_err(sql_error)?
+                .get("max_ord");
+            let ordinal = ordinal.unwrap_or(0);
+            // The live value of every key, in every namespace, as of
+            // `ordinal`; removed keys are left out rather than stored as
+            // tombstones.
             let records = sqlx::query_as::<_, (String, Vec<u8>)>(
-                "SELECT key, value FROM records WHERE key LIKE 'map:%'",
+                "SELECT key, value FROM records
+                 WHERE ordinal IN (
+                     SELECT MAX(ordinal) FROM records WHERE ordinal <= ? GROUP BY key
+                 ) AND length(value) > 0
+                 ORDER BY key",
             )
+            .bind(ordinal)
             .fetch_all(&self.pool)
             .await
-            .map_err(|e| snapshot::Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
-
-            snapshot.save_text(&records).await?;
-            snapshot.save_binary(&records).await?;
+            .map_err(sql_error)?;
+
+            snapshot.save_text(ordinal as u64, &records).await?;
+            snapshot.save_binary(ordinal as u64, &records).await?;
+            tracing::info!(
+                ordinal,
+                keys = records.len(),
+                latency_ms = started.elapsed().as_millis() as u64,
+                "snapshot written"
+            );
         }
         Ok(())
     }
 
-    pub fn subscribe_from(&self, ordinal: u64) -> Pin<Box<dyn Stream<Item = Record> + Send>> {
+    /// Brings the database up to the newest snapshot on disk, for startup.
+    ///
+    /// Records after the snapshot are already in the database and are
+    /// served from there. If the database ends before the snapshot, e.g.
+    /// because it was lost or replaced by an older copy, its records are
+    /// replaced with the snapshot's entries, written at the ordinals just
+    /// before and up to the snapshot's, so new writes continue after it.
+    /// An empty snapshot leaves a single tombstone of the empty key at its
+    /// ordinal, which new writes are counted from.
+    #[cfg(feature = "snapshots")]
+    pub async fn recover(&self) -> Result<Recovery, WriteError> {
+        let Some(ref snapshot) = self.snapshot else {
+            return Ok(Recovery::default());
+        };
+        let _guard = self.write_lock.lock().await;
+        let snapshot_ordinal = snapshot.latest_ordinal()?;
+        let head: Option<i64> = sqlx::query("SELECT MAX(ordinal) as max_ord FROM records")
+            .fetch_one(&self.pool)
+            .await?
+            .get("max_ord");
+        let head = head.unwrap_or(0) as u64;
+        if head >= snapshot_ordinal {
+            return Ok(Recovery {
+                snapshot_ordinal,
+                head,
+                restored: 0,
+            });
+        }
+
+        let entries = snapshot.load_binary().await?;
+        // Every key in a snapshot has a record at or before its ordinal.
+        let first_ordinal = snapshot_ordinal + 1 - entries.len() as u64;
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM records").execute(&mut *tx).await?;
+        for ((key, value), ordinal) in entries.iter().zip(first_ordinal..) {
+            sqlx::query(INSERT_RECORD)
+                .bind(ordinal as i64)
+                .bind(key)
+                .bind(value)
+                .bind(now)
+                .bind("")
+                .bind("")
+                .bind(0)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if entries.is_empty() {
+            sqlx::query(INSERT_RECORD)
+                .bind(snapshot_ordinal as i64)
+                .bind("")
+                .bind(Vec::<u8>::new())
+                .bind(now)
+                .bind("")
+                .bind("")
+                .bind(0)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(Recovery {
+            snapshot_ordinal,
+            head: snapshot_ordinal,
+            restored: entries.len(),
+        })
+    }
+
+    /// Streams records after `ordinal` that pass `filter`, then follows new
+    /// writes.
+    ///
+    /// History comes from the database in batches; once the subscriber has
+    /// caught up, live records are pushed from the write path without
+    /// querying again. A subscriber that falls more than [`LIVE_CAPACITY`]
+    /// records behind goes back to the database.
+    pub fn subscribe_from(&self, ordinal: u64, filter: KeyFilter) -> Pin<Box<dyn Stream<Item = Record> + Send>> {
         let pool = self.pool.clone();
+        let scheduler = Arc::clone(&self.scheduler);
+        let subscriber = self.activity.track_subscriber();
+        let metrics = Arc::clone(&self.metrics);
+        // Subscribed before the first query, so every record written after
+        // it is in the channel; the ordinal check drops the overlap.
+        let mut live = self.live.subscribe();
         Box::pin(async_stream::stream! {
-            let mut conn = pool.acquire().await.unwrap();
+            let _subscriber = subscriber;
             let mut ordinal = ordinal as i64;
 
             loop {
-                let rows = sqlx::query_as::<_, (i64, String, Vec<u8>, i64)>(
-                    "SELECT ordinal, key, value, timestamp FROM records WHERE ordinal > ? ORDER BY ordinal LIMIT 100"
-                )
-                .bind(ordinal)
-                .fetch_all(&mut *conn)
-                .await
-                .unwrap();
-
-                if rows.is_empty() {
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    continue;
+                // Replaying history, yielding to live writes, until a batch
+                // comes back short.
+                loop {
+                    scheduler.catch_up().await;
+                    // Bounding the query by the head lets a short batch skip
+                    // past records the prefix filtered out.
+                    let head: Option<i64> = sqlx::query_scalar("SELECT MAX(ordinal) FROM records")
+                        .fetch_one(&pool)
+                        .await
+                        .unwrap();
+                    let head = head.unwrap_or(0);
+                    let rows = sqlx::query_as::<_, (i64, String, Vec<u8>, i64, String, String, i64)>(
+                        "SELECT ordinal, key, value, timestamp, client_id, worker_label, expires_at
+                         FROM records WHERE ordinal > ?1 AND ordinal <= ?2 AND substr(key, 1, length(?3)) = ?3
+                         ORDER BY ordinal LIMIT ?4"
+                    )
+                    .bind(ordinal)
+                    .bind(head)
+                    .bind(&filter.prefix)
+                    .bind(SUBSCRIBE_BATCH as i64)
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+                    let caught_up = rows.len() < SUBSCRIBE_BATCH;
+
+                    for (ord, key, value, timestamp, client_id, worker_label, expires_at) in rows {
+                        ordinal = ord;
+                        if filter.matches(&key) {
+                            yield Record {
+                                ordinal: ord as u64,
+                                key,
+                                value,
+                                timestamp,
+                                writer: ClientIdentity { client_id, worker_label },
+                                expires_at,
+                            };
+                        }
+                    }
+                    if caught_up {
+                        ordinal = ordinal.max(head);
+                        break;
+                    }
                 }
 
-                for (ord, key, value, timestamp) in rows {
-                    ordinal = ord;
-                    yield Record {
-                        ordinal: ord as u64,
-                        key,
-                        value,
-                        timestamp,
-                    };
+                loop {
+                    match live.recv().await {
+                        Ok((record, published)) => {
+                            if record.ordinal as i64 <= ordinal {
+                                continue;
+                            }
+                            ordinal = record.ordinal as i64;
+                            if filter.matches(&record.key) {
+                                metrics.record_fanout(published.elapsed());
+                                yield record;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => {
+                            live = live.resubscribe();
+                            break;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    }
                 }
             }
         })
     }
 
-    pub async fn get_latest_snapshot(&self) -> Result<Option<(u64, Vec<u8>)>, WriteError> {
+    /// Records of `key`, oldest first: the latest `limit` (0 for all) at or
+    /// before `until_ordinal` (0 for no bound). Records compaction dropped
+    /// are not included.
+    pub async fn history(&self, key: &str, until_ordinal: u64, limit: u32) -> Result<Vec<Record>, sqlx::Error> {
+        let until = if until_ordinal == 0 { i64::MAX } else { until_ordinal as i64 };
+        // A negative LIMIT is no limit in SQLite.
+        let limit = if limit == 0 { -1 } else { i64::from(limit) };
+        let rows = sqlx::query_as::<_, (i64, String, Vec<u8>, i64, String, String, i64)>(
+            "SELECT ordinal, key, value, timestamp, client_id, worker_label, expires_at
+             FROM records WHERE key = ? AND ordinal <= ?
+             ORDER BY ordinal DESC LIMIT ?",
+        )
+        .bind(key)
+        .bind(until)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .rev()
+            .map(|(ordinal, key, value, timestamp, client_id, worker_label, expires_at)| Record {
+                ordinal: ordinal as u64,
+                key,
+                value,
+                timestamp,
+                writer: ClientIdentity { client_id, worker_label },
+                expires_at,
+            })
+            .collect())
+    }
+
+    /// Ordinal to subscribe from so that the first record delivered is the
+    /// first one written at or after `timestamp` (Unix milliseconds).
+    pub async fn ordinal_before(&self, timestamp: i64) -> Result<u64, sqlx::Error> {
+        let (first, latest): (Option<i64>, Option<i64>) = sqlx::query_as(
+            "SELECT (SELECT MIN(ordinal) FROM records WHERE timestamp >= ?), MAX(ordinal) FROM records",
+        )
+        .bind(timestamp)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(match first {
+            Some(ordinal) => ordinal as u64 - 1,
+            None => latest.unwrap_or(0) as u64,
+        })
+    }
+
+    /// Collects counters describing the current state of the log.
+    pub async fn stats(&self) -> Result<StorageStats, sqlx::Error> {
+        let (latest, records, keys): (Option<i64>, i64, i64) = sqlx::query_as(
+            "SELECT MAX(ordinal), COUNT(*), COUNT(DISTINCT key) FROM records",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let log_bytes: i64 =
+            sqlx::query_scalar("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(StorageStats {
+            latest_ordinal: latest.unwrap_or(0) as u64,
+            record_count: records as u64,
+            key_count: keys as u64,
+            snapshot_ordinal: self.snapshot_ordinal(),
+            log_bytes: log_bytes as u64,
+            snapshot_bytes: self.snapshot_bytes(),
+            durability: self.durability,
+        })
+    }
+
+    #[cfg(feature = "snapshots")]
+    fn snapshot_bytes(&self) -> u64 {
+        self.snapshot.as_ref().map_or(0, |s| s.latest_size().unwrap_or(0))
+    }
+
+    #[cfg(not(feature = "snapshots"))]
+    fn snapshot_bytes(&self) -> u64 {
+        0
+    }
+
+    #[cfg(feature = "snapshots")]
+    fn snapshot_ordinal(&self) -> u64 {
+        self.snapshot.as_ref().map_or(0, |s| s.last_snapshot_ordinal())
+    }
+
+    #[cfg(not(feature = "snapshots"))]
+    fn snapshot_ordinal(&self) -> u64 {
+        0
+    }
+
+    /// The latest snapshot in pieces of at most `chunk` bytes, cut down to
+    /// keys starting with `key_prefix`. The pieces are read from the
+    /// snapshot file as the stream is polled. `None` without a snapshot.
+    pub async fn stream_latest_snapshot(
+        &self,
+        key_prefix: &str,
+        chunk: usize,
+    ) -> Result<Option<(u64, SnapshotChunks)>, WriteError> {
+        #[cfg(feature = "snapshots")]
         if let Some(ref snapshot) = self.snapshot {
-            let (ordinal, data) = snapshot.get_latest_snapshot().await?;
-            if let Some(data) = data {
-                return Ok(Some((ordinal, data)));
+            if let Some((ordinal, chunks)) = snapshot.stream_latest(key_prefix, chunk).await? {
+                let chunks = chunks.map(|chunk| chunk.map_err(WriteError::from));
+                return Ok(Some((ordinal, Box::pin(chunks))));
             }
         }
+        #[cfg(not(feature = "snapshots"))]
+        let _ = (key_prefix, chunk);
         Ok(None)
     }
+
+    /// The latest snapshot, cut down to keys starting with `key_prefix`, in
+    /// one buffer.
+    pub async fn get_latest_snapshot(&self, key_prefix: &str) -> Result<Option<(u64, Vec<u8>)>, WriteError> {
+        let Some((ordinal, mut chunks)) = self.stream_latest_snapshot(key_prefix, SNAPSHOT_READ_CHUNK).await? else {
+            return Ok(None);
+        };
+        let mut data = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+        Ok(Some((ordinal, data)))
+    }
+}
+
+/// Which records a subscription receives. The default matches every key.
+#[derive(Debug, Clone, Default)]
+pub struct KeyFilter {
+    /// Keys must start with this.
+    pub prefix: String,
+    /// When non-empty, keys must also be one of these.
+    pub keys: HashSet<String>,
+}
+
+impl KeyFilter {
+    pub fn matches(&self, key: &str) -> bool {
+        key.starts_with(&self.prefix) && (self.keys.is_empty() || self.keys.contains(key))
+    }
+}
+
+/// Outcome of [`Storage::recover`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// Newest snapshot on disk, 0 if there is none.
+    pub snapshot_ordinal: u64,
+    /// Last ordinal in the log after recovery; new writes follow it.
+    pub head: u64,
+    /// Snapshot entries written back because the database was behind.
+    pub restored: usize,
+}
+
+/// Outcome of [`Storage::compact`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    pub cutoff: u64,
+    pub removed: u64,
+}
+
+/// Point-in-time counters reported by [`Storage::stats`].
+#[derive(Debug, Clone, Default)]
+pub struct StorageStats {
+    pub latest_ordinal: u64,
+    pub record_count: u64,
+    pub key_count: u64,
+    pub snapshot_ordinal: u64,
+    /// Size of the database, free pages included.
+    pub log_bytes: u64,
+    /// Size of the latest binary snapshot, 0 without one.
+    pub snapshot_bytes: u64,
+    pub durability: Durability,
 }
 
 #[derive(Debug)]
 pub enum WriteError {
-    Conflict(u64),
+    /// `key_ordinal` is the newest record of the written key, which the
+    /// writer had not seen; `latest_ordinal` is the head of the log.
+    Conflict { latest_ordinal: u64, key_ordinal: u64 },
+    /// The value is `size` bytes, over the configured `limit`.
+    ValueTooLarge { size: u64, limit: u64 },
     Sql(sqlx::Error),
+    #[cfg(feature = "snapshots")]
     Snapshot(snapshot::Error),
 }
 
@@ -220,6 +982,7 @@ impl From<sqlx::Error> for WriteError {
     }
 }
 
+#[cfg(feature = "snapshots")]
 impl From<snapshot::Error> for WriteError {
     fn from(err: snapshot::Error) -> Self {
         WriteError::Snapshot(err)
@@ -229,8 +992,16 @@ impl From<snapshot::Error> for WriteError {
 impl std::fmt::Display for WriteError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            WriteError::Conflict(ord) => write!(f, "Conflict: latest ordinal is {}", ord),
+            WriteError::Conflict { latest_ordinal, key_ordinal } => write!(
+                f,
+                "Conflict: key last written at {}, latest ordinal is {}",
+                key_ordinal, latest_ordinal
+            ),
+            WriteError::ValueTooLarge { size, limit } => {
+                write!(f, "Value of {} bytes is over the limit of {} bytes", size, limit)
+            }
             WriteError::Sql(e) => write!(f, "Database error: {}", e),
+            #[cfg(feature = "snapshots")]
             WriteError::Snapshot(e) => write!(f, "Snapshot error: {}", e),
         }
     }
@@ -0,0 +1,54 @@
+use log_server::audit::{audit, Issue};
+
+async fn insert(pool: &sqlx::SqlitePool, ordinal: i64, key: &str, value: &[u8], timestamp: i64) {
+    sqlx::query("INSERT INTO records (ordinal, key, value, timestamp) VALUES (?, ?, ?, ?)")
+        .bind(ordinal)
+        .bind(key)
+        .bind(value)
+        .bind(timestamp)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_audit_clean_log() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    insert(&pool, 1, "map:1", b"a", 10).await;
+    insert(&pool, 2, "map:1", b"", 20).await;
+    insert(&pool, 3, "map:2", b"b", 30).await;
+
+    let report = audit(&pool).await.unwrap();
+
+    assert_eq!(report.records_scanned, 3);
+    assert!(report.is_clean(), "{}", report);
+}
+
+#[tokio::test]
+async fn test_audit_reports_issues() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    insert(&pool, 1, "map:1", b"a", 10).await;
+    insert(&pool, 2, "map:2", b"", 20).await;
+    // Ordinal 3 rewritten after 5 was written; 4 never landed.
+    insert(&pool, 3, "map:3", b"c", 90).await;
+    insert(&pool, 5, "map:5", b"e", 50).await;
+
+    let report = audit(&pool).await.unwrap();
+
+    assert_eq!(
+        report.issues,
+        vec![
+            Issue::OrphanTombstone {
+                ordinal: 2,
+                key: "map:2".to_string()
+            },
+            Issue::Gap { from: 4, to: 4 },
+            Issue::Rewritten {
+                ordinal: 3,
+                timestamp: 90,
+                next_ordinal: 5,
+                next_timestamp: 50
+            },
+        ]
+    );
+}
@@ -0,0 +1,65 @@
+use futures_util::StreamExt;
+use log_server::chaos::{FaultConfig, FaultInjector};
+use log_server_test::TestServer;
+use log_server_types::kv::{kv_server_client::KvServerClient, SubscribeRequest, WriteRequest};
+
+fn write_request(key: &str) -> WriteRequest {
+    WriteRequest {
+        ordinal: 0,
+        key: key.to_string(),
+        value: b"v".to_vec(),
+        latest_known: 0,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_injected_write_rejections() {
+    let faults = FaultInjector::new(7);
+    let server = TestServer::spawn_with_faults(faults.clone()).await;
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    faults.set_config(FaultConfig {
+        write_reject_rate: 1.0,
+        ..FaultConfig::default()
+    });
+    let requests = tokio_stream::iter(vec![write_request("a"), write_request("b")]);
+    let responses: Vec<_> = client.write(requests).await.unwrap().into_inner().collect().await;
+    assert_eq!(responses.len(), 2);
+    assert!(responses.iter().all(|r| !r.as_ref().unwrap().accepted));
+
+    faults.clear();
+    let mut responses = client
+        .write(tokio_stream::once(write_request("c")))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(responses.next().await.unwrap().unwrap().accepted);
+}
+
+#[tokio::test]
+async fn test_dropped_subscribe_stream() {
+    let faults = FaultInjector::new(7);
+    let server = TestServer::spawn_with_faults(faults.clone()).await;
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    client
+        .write(tokio_stream::once(write_request("a")))
+        .await
+        .unwrap()
+        .into_inner()
+        .next()
+        .await;
+
+    faults.set_config(FaultConfig {
+        stream_drop_rate: 1.0,
+        ..FaultConfig::default()
+    });
+    let mut records = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner();
+    let status = records.next().await.unwrap().unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+}
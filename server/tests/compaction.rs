@@ -0,0 +1,88 @@
+use futures_util::StreamExt;
+use log_server::models::ClientIdentity;
+use log_server::storage::{Compaction, KeyFilter, Storage};
+
+async fn write(storage: &Storage, key: &str, value: &str) {
+    storage
+        .write(key.to_string(), value.as_bytes().to_vec(), 0, &ClientIdentity::default())
+        .await
+        .unwrap();
+}
+
+async fn replay(storage: &Storage) -> Vec<(u64, String, Vec<u8>)> {
+    let latest = storage.stats().await.unwrap().record_count;
+    storage
+        .subscribe_from(0, KeyFilter::default())
+        .take(latest as usize)
+        .map(|record| (record.ordinal, record.key, record.value))
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn test_compaction_keeps_the_latest_record_per_key() {
+    let dir = std::env::temp_dir().join(format!("compaction-{}", std::process::id()));
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::with_snapshot(pool, dir.to_str().unwrap(), u64::MAX).unwrap();
+    storage.set_compaction_retain(1);
+
+    write(&storage, "map:1", "a").await;
+    write(&storage, "map:2", "b").await;
+    write(&storage, "map:1", "c").await;
+    write(&storage, "map:2", "").await;
+
+    // Nothing is compacted past the latest snapshot, and there is none yet.
+    assert_eq!(storage.compact().await.unwrap(), Compaction { cutoff: 0, removed: 0 });
+
+    storage.snapshot_now().await.unwrap();
+    write(&storage, "map:1", "d").await;
+    write(&storage, "map:1", "e").await;
+
+    // The cutoff is the snapshot at 4; 5 and 6 are newer and stay.
+    assert_eq!(storage.compact().await.unwrap(), Compaction { cutoff: 4, removed: 3 });
+    assert_eq!(
+        replay(&storage).await,
+        vec![
+            (3, "map:1".to_string(), b"c".to_vec()),
+            (5, "map:1".to_string(), b"d".to_vec()),
+            (6, "map:1".to_string(), b"e".to_vec()),
+        ]
+    );
+
+    let (ordinal, data) = storage.get_latest_snapshot("").await.unwrap().unwrap();
+    assert_eq!(ordinal, 4);
+    assert_eq!(
+        log_snapshot_format::decode(&data).unwrap(),
+        vec![("map:1".to_string(), b"c".to_vec())]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_compaction_without_retention_keeps_the_head() {
+    let dir = std::env::temp_dir().join(format!("compaction-head-{}", std::process::id()));
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::with_snapshot(pool, dir.to_str().unwrap(), u64::MAX).unwrap();
+    storage.set_compaction_retain(0);
+
+    write(&storage, "map:1", "a").await;
+    write(&storage, "map:2", "b").await;
+    write(&storage, "map:1", "").await;
+    storage.snapshot_now().await.unwrap();
+
+    // The tombstone at the head stays, so the next write does not reuse
+    // its ordinal.
+    assert_eq!(storage.compact().await.unwrap(), Compaction { cutoff: 2, removed: 0 });
+    let ordinal = storage
+        .write("map:3".to_string(), b"c".to_vec(), 0, &ClientIdentity::default())
+        .await
+        .unwrap();
+    assert_eq!(ordinal, 4);
+    assert_eq!(
+        replay(&storage).await.into_iter().map(|(ordinal, ..)| ordinal).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,83 @@
+use log_server::config::Config;
+use log_server::db::Durability;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse("# reloaded on SIGHUP\nlog_level = debug\n\nsnapshot_interval = 25 # ordinals\n").unwrap();
+    assert_eq!(config.log_level, LevelFilter::DEBUG);
+    assert_eq!(config.snapshot_interval, 25);
+
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+    assert!(Config::parse("snapshot_interval = 0").is_err());
+    assert!(Config::parse("log_levle = info").is_err());
+}
+
+#[test]
+fn test_parse_durability() {
+    assert_eq!(Config::default().durability, Durability::Full);
+    assert_eq!(
+        Config::parse("durability = group:50").unwrap().durability,
+        Durability::Group(Duration::from_millis(50))
+    );
+    assert_eq!(Config::parse("durability = buffered").unwrap().durability, Durability::Buffered);
+    assert!(Config::parse("durability = group:0").is_err());
+    assert!(Config::parse("durability = sometimes").is_err());
+}
+
+#[test]
+fn test_parse_maintenance() {
+    let config = Config::parse("maintenance_window = * 02:00-04:00\nmaintenance_tasks = vacuum, snapshot").unwrap();
+    assert_eq!(config.maintenance_window.unwrap().to_string(), "* 02:00-04:00");
+    assert_eq!(
+        config.maintenance_tasks,
+        [log_server::maintenance::Task::Vacuum, log_server::maintenance::Task::Snapshot]
+    );
+    assert!(Config::default().maintenance_window.is_none());
+}
+
+#[test]
+fn test_parse_catch_up_ratio() {
+    assert_eq!(Config::parse("catch_up_ratio = 0").unwrap().catch_up_ratio, 0);
+    assert!(Config::parse("catch_up_ratio = -1").is_err());
+}
+
+#[test]
+fn test_parse_compaction() {
+    let config = Config::parse("compaction_interval = 600\ncompaction_retain = 50").unwrap();
+    assert_eq!(config.compaction_interval, Some(std::time::Duration::from_secs(600)));
+    assert_eq!(config.compaction_retain, 50);
+    assert_eq!(Config::parse("compaction_interval = 0").unwrap().compaction_interval, None);
+    assert!(Config::parse("compaction_retain = all").is_err());
+}
+
+#[test]
+fn test_parse_max_value_size() {
+    assert_eq!(Config::parse("").unwrap().max_value_size, 1024 * 1024);
+    assert_eq!(Config::parse("max_value_size = 0").unwrap().max_value_size, 0);
+    assert!(Config::parse("max_value_size = 1MB").is_err());
+}
+
+#[test]
+fn test_parse_tls_and_token() {
+    let config = Config::parse("tls_cert = cert.pem\ntls_key = key.pem\nauth_token = s3cr3t").unwrap();
+    assert_eq!(config.tls_cert, Some("cert.pem".into()));
+    assert_eq!(config.tls_key, Some("key.pem".into()));
+    assert_eq!(config.auth_token.as_deref(), Some("s3cr3t"));
+    assert!(Config::parse("auth_token = two words").is_err());
+}
+
+#[test]
+fn test_parse_locations() {
+    let config = Config::parse("listen_addr = 0.0.0.0:6000\ndatabase_url = sqlite:/tmp/log.db\nsnapshot_dir = /tmp/snaps").unwrap();
+    assert_eq!(config.listen_addr, "0.0.0.0:6000".parse().unwrap());
+    assert_eq!(config.database_url, "sqlite:/tmp/log.db");
+    assert_eq!(config.snapshot_dir, std::path::PathBuf::from("/tmp/snaps"));
+    assert!(Config::parse("listen_addr = localhost").is_err());
+
+    let mut config = Config::default();
+    config.set("snapshot_interval", "7").unwrap();
+    assert_eq!(config.snapshot_interval, 7);
+    assert!(config.set("snapshot_interval", "0").is_err());
+}
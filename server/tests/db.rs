@@ -0,0 +1,66 @@
+use log_server::db;
+
+#[tokio::test]
+async fn test_init_pool_upgrades_old_schema() {
+    let path = std::env::temp_dir().join(format!("db-upgrade-{}.db", std::process::id()));
+    let url = format!("sqlite:{}", path.display());
+
+    db::ensure_database_file(&url).await.unwrap();
+    let old = sqlx::SqlitePool::connect(&url).await.unwrap();
+    sqlx::query(
+        "CREATE TABLE records (
+            ordinal INTEGER PRIMARY KEY AUTOINCREMENT,
+            key TEXT NOT NULL,
+            value BLOB,
+            timestamp INTEGER NOT NULL
+        )",
+    )
+    .execute(&old)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO records (key, value, timestamp) VALUES ('map:1', x'31', 0)")
+        .execute(&old)
+        .await
+        .unwrap();
+    old.close().await;
+
+    let pool = db::init_pool(&url).await.unwrap();
+    let (client_id, worker_label): (String, String) =
+        sqlx::query_as("SELECT client_id, worker_label FROM records WHERE ordinal = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!((client_id.as_str(), worker_label.as_str()), ("", ""));
+
+    // A second start must not try to add the columns again.
+    pool.close().await;
+    db::init_pool(&url).await.unwrap().close().await;
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_init_pool_applies_durability() {
+    let path = std::env::temp_dir().join(format!("db-durability-{}.db", std::process::id()));
+    let url = format!("sqlite:{}", path.display());
+
+    let group = db::Durability::Group(std::time::Duration::from_millis(10));
+    let pool = db::init_pool_with(&url, group).await.unwrap();
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&pool).await.unwrap();
+    let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous").fetch_one(&pool).await.unwrap();
+    assert_eq!(journal_mode, "wal");
+    assert_eq!(synchronous, 1);
+
+    let storage = log_server::storage::Storage::new(pool.clone()).with_durability(group);
+    assert_eq!(storage.stats().await.unwrap().durability.to_string(), "group:10");
+    pool.close().await;
+
+    let pool = db::init_pool_with(&url, db::Durability::Buffered).await.unwrap();
+    let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous").fetch_one(&pool).await.unwrap();
+    assert_eq!(synchronous, 0);
+    pool.close().await;
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
@@ -0,0 +1,40 @@
+use futures_util::StreamExt;
+use log_server::models::ClientIdentity;
+use log_server::storage::{KeyFilter, Storage};
+
+#[tokio::test]
+async fn test_expire_tombstones_lapsed_entries() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::new(pool);
+    let writer = ClientIdentity::default();
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let write = |key: &str, expires_at| {
+        storage.write_expiring(key.to_string(), b"v".to_vec(), expires_at, None, &writer)
+    };
+    write("map:1", now - 1).await.unwrap();
+    write("map:2", now + 60_000).await.unwrap();
+    write("map:3", now - 1).await.unwrap();
+    // Overwritten without a TTL, so it no longer expires.
+    storage.write("map:3".to_string(), b"w".to_vec(), 0, &writer).await.unwrap();
+
+    assert_eq!(storage.expire().await.unwrap(), 1);
+    assert_eq!(storage.expire().await.unwrap(), 0);
+
+    let records: Vec<_> = storage
+        .subscribe_from(0, KeyFilter::default())
+        .take(5)
+        .map(|record| (record.ordinal, record.key, record.value, record.expires_at > 0))
+        .collect()
+        .await;
+    assert_eq!(
+        records,
+        vec![
+            (1, "map:1".to_string(), b"v".to_vec(), true),
+            (2, "map:2".to_string(), b"v".to_vec(), true),
+            (3, "map:3".to_string(), b"v".to_vec(), true),
+            (4, "map:3".to_string(), b"w".to_vec(), false),
+            (5, "map:1".to_string(), Vec::new(), false),
+        ]
+    );
+}
@@ -0,0 +1,30 @@
+use log_server::handshake::ClientInfo;
+use log_server_types::metadata::{self, Handshake};
+use log_server_types::{features, PROTOCOL_VERSION};
+use tonic::service::Interceptor;
+use tonic::Request;
+
+#[test]
+fn test_handshake_round_trip() {
+    let mut handshake = Handshake::new("test-client/1.0", &[features::BATCH_WRITES]);
+    let request = handshake.call(Request::new(())).unwrap();
+
+    let info = ClientInfo::from_metadata(request.metadata());
+
+    assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(info.client.as_deref(), Some("test-client/1.0"));
+    assert!(info.capabilities.contains(features::BATCH_WRITES));
+    assert!(!info.capabilities.contains(features::PREFIX_SUBSCRIBE));
+}
+
+#[test]
+fn test_missing_handshake_is_legacy() {
+    let mut request = Request::new(());
+    request
+        .metadata_mut()
+        .insert(metadata::PROTOCOL_VERSION, "not-a-number".parse().unwrap());
+
+    let info = ClientInfo::from_metadata(request.metadata());
+
+    assert_eq!(info, ClientInfo::default());
+}
@@ -1,45 +1,15 @@
 use futures_util::StreamExt;
-use log_server_types::kv::{kv_server_client::KvServerClient, SubscribeRequest, WriteRequest};
-use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::net::TcpListener;
-use tokio::time::sleep;
-
-async fn start_test_server() -> (SocketAddr, tokio::task::JoinHandle<()>) {
-    let listener = TcpListener::bind("[::1]:0").await.unwrap();
-    let addr = listener.local_addr().unwrap();
-
-    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
-    let storage = Arc::new(log_server::storage::Storage::new(pool));
-    let server = log_server::grpc::create_server(storage);
-
-    let handle = tokio::spawn(async move {
-        tonic::transport::Server::builder()
-            .add_service(server)
-            .serve_with_incoming(
-                tokio_stream::wrappers::TcpListenerStream::new(listener).map(|r| r.map_err(|e| {
-                    println!("Error accepting connection: {}", e);
-                    std::io::Error::new(std::io::ErrorKind::Other, e)
-                })),
-            )
-            .await
-            .unwrap();
-    });
-
-    sleep(Duration::from_millis(100)).await;
-    (addr, handle)
-}
+use log_server_test::TestServer;
+use log_server_types::kv::{kv_server_client::KvServerClient, GetHistoryRequest, GetServerInfoRequest, SubscribeRequest, TransactionRequest, TransactionWrite, WriteRejection, WriteRequest};
 
 #[tokio::test]
 async fn test_subscribe() {
-    let (addr, _handle) = start_test_server().await;
-    let url = format!("http://{}", addr);
+    let server = TestServer::spawn().await;
 
-    let mut client = KvServerClient::connect(url).await.unwrap();
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
 
     let response = client
-        .subscribe(SubscribeRequest { start_ordinal: 0 })
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
         .await;
 
     assert!(response.is_ok());
@@ -47,16 +17,16 @@ async fn test_subscribe() {
 
 #[tokio::test]
 async fn test_write() {
-    let (addr, _handle) = start_test_server().await;
-    let url = format!("http://{}", addr);
+    let server = TestServer::spawn().await;
 
-    let mut client = KvServerClient::connect(url).await.unwrap();
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
 
     let request = WriteRequest {
         ordinal: 1,
         key: "test_key".to_string(),
         value: b"test_value".to_vec(),
         latest_known: 0,
+        ..Default::default()
     };
 
     let mut stream = client
@@ -70,3 +40,220 @@ async fn test_write() {
         assert!(resp.accepted);
     }
 }
+
+#[tokio::test]
+async fn test_write_responses_echo_request_ordinal() {
+    let server = TestServer::spawn().await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    let requests = (10..13).map(|ordinal| WriteRequest {
+        ordinal,
+        key: format!("key_{}", ordinal),
+        value: b"value".to_vec(),
+        latest_known: 0,
+        ..Default::default()
+    });
+
+    let responses: Vec<_> = client
+        .write(tokio_stream::iter(requests))
+        .await
+        .unwrap()
+        .into_inner()
+        .map(|response| response.unwrap().request_ordinal)
+        .collect()
+        .await;
+
+    assert_eq!(responses, vec![10, 11, 12]);
+}
+
+#[tokio::test]
+async fn test_records_carry_writer_identity() {
+    let server = TestServer::spawn().await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    let request = WriteRequest {
+        key: "test_key".to_string(),
+        value: b"test_value".to_vec(),
+        client_id: "client-1".to_string(),
+        worker_label: "worker #3".to_string(),
+        ..Default::default()
+    };
+    let mut responses = client
+        .write(tokio_stream::once(request))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(responses.next().await.unwrap().unwrap().accepted);
+
+    let record = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner()
+        .next()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.client_id, "client-1");
+    assert_eq!(record.worker_label, "worker #3");
+}
+
+#[tokio::test]
+async fn test_conditional_write_checks_the_key() {
+    let server = TestServer::spawn().await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    let write = |key: &str, latest_known| WriteRequest {
+        key: key.to_string(),
+        value: b"value".to_vec(),
+        latest_known,
+        if_unchanged: true,
+        ..Default::default()
+    };
+    let requests = vec![write("a", 0), write("b", 0), write("b", 1), write("a", 1), write("b", 2), write("a", 2)];
+    let responses: Vec<_> = client
+        .write(tokio_stream::iter(requests))
+        .await
+        .unwrap()
+        .into_inner()
+        .map(|response| {
+            let response = response.unwrap();
+            (response.accepted, response.key_ordinal)
+        })
+        .collect()
+        .await;
+
+    // "b" written at 2 rejects latest_known 1; other keys don't interfere.
+    assert_eq!(responses, vec![(true, 1), (true, 2), (false, 2), (true, 3), (true, 4), (false, 3)]);
+}
+
+#[tokio::test]
+async fn test_subscribe_filters_keys() {
+    let server = TestServer::spawn().await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    let write = |key: &str| WriteRequest {
+        key: key.to_string(),
+        value: b"value".to_vec(),
+        ..Default::default()
+    };
+    let requests = vec![write("map:1"), write("jobs:1"), write("map:2"), write("map:3"), write("jobs:2")];
+    client.write(tokio_stream::iter(requests)).await.unwrap().into_inner().collect::<Vec<_>>().await;
+
+    let subscribe = |key_prefix: &str, keys: &[&str], count| {
+        let request = SubscribeRequest {
+            key_prefix: key_prefix.to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut client = client.clone();
+        async move {
+            let records = client.subscribe(request).await.unwrap().into_inner();
+            records.take(count).map(|record| record.unwrap().key).collect::<Vec<_>>().await
+        }
+    };
+
+    assert_eq!(subscribe("jobs:", &[], 2).await, vec!["jobs:1", "jobs:2"]);
+    assert_eq!(subscribe("map:", &["map:1", "map:3", "jobs:1"], 2).await, vec!["map:1", "map:3"]);
+}
+
+#[tokio::test]
+async fn test_transaction_commits_all_or_nothing() {
+    let server = TestServer::spawn().await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+
+    let transaction = |keys: &[&str], latest_known| TransactionRequest {
+        writes: keys
+            .iter()
+            .map(|key| TransactionWrite { key: key.to_string(), value: b"value".to_vec() })
+            .collect(),
+        latest_known,
+        ..Default::default()
+    };
+
+    let committed = client.transaction(transaction(&["a", "b"], 0)).await.unwrap().into_inner();
+    assert!(committed.accepted);
+    assert_eq!(committed.first_ordinal, 1);
+
+    // "b" is at 2, so a writer that only saw 1 is rejected, "c" included.
+    let rejected = client.transaction(transaction(&["c", "b"], 1)).await.unwrap().into_inner();
+    assert!(!rejected.accepted);
+    assert_eq!(rejected.key_ordinal, 2);
+
+    let committed = client.transaction(transaction(&["c", "b"], 2)).await.unwrap().into_inner();
+    assert_eq!((committed.accepted, committed.first_ordinal), (true, 3));
+
+    let records = client.subscribe(SubscribeRequest::default()).await.unwrap().into_inner();
+    let keys: Vec<_> = records.take(4).map(|record| record.unwrap().key).collect().await;
+    assert_eq!(keys, vec!["a", "b", "c", "b"]);
+}
+
+#[tokio::test]
+async fn test_history_lists_one_key() {
+    let server = TestServer::spawn().await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    for (key, value) in [("a", "1"), ("b", "1"), ("a", "2"), ("a", "")] {
+        let write = TransactionRequest {
+            writes: vec![TransactionWrite { key: key.to_string(), value: value.as_bytes().to_vec() }],
+            latest_known: u64::MAX,
+            ..Default::default()
+        };
+        assert!(client.transaction(write).await.unwrap().into_inner().accepted);
+    }
+
+    let history = |until_ordinal, limit| {
+        let mut client = client.clone();
+        async move {
+            let request = GetHistoryRequest { key: "a".to_string(), until_ordinal, limit };
+            let records = client.get_history(request).await.unwrap().into_inner().records;
+            records.into_iter().map(|r| (r.ordinal, String::from_utf8(r.value).unwrap())).collect::<Vec<_>>()
+        }
+    };
+    let all = vec![(1, "1".to_string()), (3, "2".to_string()), (4, String::new())];
+    assert_eq!(history(0, 0).await, all);
+    assert_eq!(history(3, 0).await, all[..2]);
+    assert_eq!(history(0, 2).await, all[1..]);
+    assert_eq!(history(2, 1).await, all[..1]);
+}
+
+#[tokio::test]
+async fn test_values_over_the_limit_are_rejected() {
+    let server = TestServer::spawn().await;
+    server.storage().set_max_value_size(4);
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let info = client.get_server_info(GetServerInfoRequest::default()).await.unwrap().into_inner();
+    assert_eq!(info.max_value_size, 4);
+
+    let requests = [b"four".to_vec(), b"fiver".to_vec()].map(|value| WriteRequest {
+        key: "k".to_string(),
+        value,
+        ..Default::default()
+    });
+    let responses: Vec<_> = client
+        .write(tokio_stream::iter(requests))
+        .await
+        .unwrap()
+        .into_inner()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert!(responses[0].accepted);
+    assert!(!responses[1].accepted);
+    assert_eq!(responses[1].rejection(), WriteRejection::ValueTooLarge);
+
+    let write = TransactionRequest {
+        writes: vec![TransactionWrite { key: "k".to_string(), value: b"fiver".to_vec() }],
+        latest_known: u64::MAX,
+        ..Default::default()
+    };
+    let response = client.transaction(write).await.unwrap().into_inner();
+    assert!(!response.accepted);
+    assert_eq!(response.rejection(), WriteRejection::ValueTooLarge);
+}
@@ -0,0 +1,126 @@
+use std::io::Write;
+
+use std::sync::{Arc, Mutex};
+
+use log_server::logging::{JsonFormat, LogTarget, RotatingFile};
+use tracing_subscriber::fmt::MakeWriter;
+
+#[test]
+fn test_parse_log_target() {
+    assert_eq!("stdout".parse(), Ok(LogTarget::Stdout));
+    assert_eq!("journald".parse(), Ok(LogTarget::Syslog));
+    assert_eq!(
+        "file:/var/log/log-server.log".parse(),
+        Ok(LogTarget::File("/var/log/log-server.log".into()))
+    );
+    assert!("file:".parse::<LogTarget>().is_err());
+}
+
+#[test]
+fn test_json_format_keeps_fields_typed() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&buffer);
+    let subscriber = tracing_subscriber::fmt()
+        .event_format(JsonFormat)
+        .with_writer(move || SharedBuffer(Arc::clone(&sink)))
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(peer = %"127.0.0.1:4000", key = "map:1", ordinal = 42u64, latency_ms = 3u64, "write conflict");
+    });
+
+    let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    let event: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+    assert_eq!(event["level"], "WARN");
+    assert_eq!(event["message"], "write conflict");
+    assert_eq!(event["peer"], "127.0.0.1:4000");
+    assert_eq!(event["key"], "map:1");
+    assert_eq!(event["ordinal"], 42);
+    assert_eq!(event["latency_ms"], 3);
+}
+
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rotating_file_keeps_old_files() {
+    let dir = std::env::temp_dir().join(format!("log-server-rotate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.log");
+
+    let file = RotatingFile::open(&path, 16, 2).unwrap();
+    for line in ["first line\n", "second line\n", "third line\n", "fourth line\n"] {
+        file.make_writer().write_all(line.as_bytes()).unwrap();
+    }
+
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("server.log"), "fourth line\n");
+    assert_eq!(read("server.log.1"), "third line\n");
+    assert_eq!(read("server.log.2"), "second line\n");
+    assert!(!dir.join("server.log.3").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_syslog_message_format() {
+    use log_server::logging::Syslog;
+    use std::os::unix::net::UnixDatagram;
+
+    let path = std::env::temp_dir().join(format!("log-server-syslog-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let receiver = UnixDatagram::bind(&path).unwrap();
+
+    let syslog = Syslog::connect(&path).unwrap();
+    syslog.make_writer_for(&META).write_all(b"conflict on 'map:1'\n").unwrap();
+
+    let mut buf = [0u8; 256];
+    let len = receiver.recv(&mut buf).unwrap();
+    let message = String::from_utf8_lossy(&buf[..len]);
+    // daemon facility (3) * 8 + warning (4)
+    assert_eq!(
+        message,
+        format!("<28>log-server[{}]: conflict on 'map:1'", std::process::id())
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// A callsite for a hand-made WARN event, since writers only see metadata.
+#[cfg(unix)]
+struct Callsite;
+
+#[cfg(unix)]
+static CALLSITE: Callsite = Callsite;
+
+#[cfg(unix)]
+static META: tracing::Metadata<'static> = tracing::Metadata::new(
+    "event",
+    "test",
+    tracing::Level::WARN,
+    None,
+    None,
+    None,
+    tracing::field::FieldSet::new(&[], tracing::callsite::Identifier(&CALLSITE)),
+    tracing::metadata::Kind::EVENT,
+);
+
+#[cfg(unix)]
+impl tracing::callsite::Callsite for Callsite {
+    fn set_interest(&self, _: tracing::subscriber::Interest) {}
+
+    fn metadata(&self) -> &tracing::Metadata<'_> {
+        &META
+    }
+}
@@ -0,0 +1,37 @@
+use chrono::{TimeZone, Utc};
+use log_server::maintenance::{self, Task, Window};
+use log_server::storage::Storage;
+
+#[test]
+fn test_window_opening_times() {
+    let weekend: Window = "sat,sun 02:00-04:00".parse().unwrap();
+    // 2026-10-17 is a Saturday.
+    let saturday = |h, m| Utc.with_ymd_and_hms(2026, 10, 17, h, m, 0).unwrap();
+    assert_eq!(weekend.opened_at(saturday(3, 15)), Some(saturday(2, 0)));
+    assert_eq!(weekend.opened_at(saturday(4, 0)), None);
+    assert_eq!(weekend.opened_at(Utc.with_ymd_and_hms(2026, 10, 16, 3, 0, 0).unwrap()), None);
+    assert_eq!(weekend.to_string(), "sat,sun 02:00-04:00");
+
+    let nightly: Window = "* 22:00-01:00".parse().unwrap();
+    assert_eq!(nightly.opened_at(saturday(0, 30)), Some(Utc.with_ymd_and_hms(2026, 10, 16, 22, 0, 0).unwrap()));
+    assert_eq!(nightly.opened_at(saturday(23, 0)), Some(saturday(22, 0)));
+    assert_eq!(nightly.opened_at(saturday(12, 0)), None);
+
+    assert!("02:00-04:00".parse::<Window>().is_err());
+    assert!("* 02:00-02:00".parse::<Window>().is_err());
+    assert!("someday 02:00-04:00".parse::<Window>().is_err());
+    assert!(maintenance::parse_tasks("vacuum, defrag").is_err());
+}
+
+#[tokio::test]
+async fn test_run_tasks_records_results() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::new(pool);
+    storage.append("map:1".to_string(), b"1".to_vec()).await.unwrap();
+
+    let run = maintenance::run_tasks(&storage, &[Task::Analyze, Task::Vacuum]).await;
+
+    assert!(run.results.iter().all(|result| result.error.is_none()));
+    assert_eq!(storage.maintenance().last_run(), Some(run));
+    assert_eq!(storage.maintenance().running(), None);
+}
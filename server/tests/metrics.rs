@@ -0,0 +1,31 @@
+use futures_util::StreamExt;
+use log_server::models::ClientIdentity;
+use log_server::storage::{KeyFilter, Storage};
+
+#[tokio::test]
+async fn test_render_counts_writes_conflicts_and_fanout() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::new(pool);
+    let writer = ClientIdentity::default();
+
+    let mut records = storage.subscribe_from(0, KeyFilter::default());
+    storage.write("map:1".to_string(), b"one".to_vec(), 0, &writer).await.unwrap();
+    records.next().await.unwrap();
+    storage.write("map:1".to_string(), b"two".to_vec(), 0, &writer).await.unwrap();
+    records.next().await.unwrap();
+    assert!(storage.write_if_unchanged("map:1".to_string(), b"three".to_vec(), 1, &writer).await.is_err());
+
+    let text = log_server::metrics::render(&storage).await;
+    let lines: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+    for expected in [
+        "log_server_writes_total 2",
+        "log_server_write_conflicts_total 1",
+        "log_server_write_seconds_count 2",
+        "log_server_subscribe_fanout_seconds_count 1",
+        "log_server_subscribers 1",
+        "log_server_latest_ordinal 2",
+        "log_server_snapshot_lag 2",
+    ] {
+        assert!(lines.contains(&expected), "missing {:?} in\n{}", expected, text);
+    }
+}
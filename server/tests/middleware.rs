@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use log_server::grpc::create_server_with_layer;
+use log_server::storage::Storage;
+use log_server_types::kv::kv_server_client::KvServerClient;
+use log_server_types::kv::{StatsRequest, WriteRequest};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Code, Request, Status};
+
+fn check_token(request: Request<()>) -> Result<Request<()>, Status> {
+    match request.metadata().get("authorization") {
+        Some(token) if token == "Bearer secret" => Ok(request),
+        _ => Err(Status::unauthenticated("missing or wrong token")),
+    }
+}
+
+#[tokio::test]
+async fn test_layer_wraps_every_kv_call() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Arc::new(Storage::new(pool));
+    let service = create_server_with_layer(storage, tonic::service::InterceptorLayer::new(check_token));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let mut anonymous = KvServerClient::connect(format!("http://{}", addr)).await.unwrap();
+    let status = anonymous.stats(StatsRequest {}).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut authorized = KvServerClient::with_interceptor(channel, |mut request: Request<()>| {
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        Ok(request)
+    });
+
+    let request = WriteRequest {
+        key: "map:1".to_string(),
+        value: b"one".to_vec(),
+        ..Default::default()
+    };
+    let mut responses = authorized
+        .write(tokio_stream::once(request))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(responses.next().await.unwrap().unwrap().accepted);
+    assert_eq!(authorized.stats(StatsRequest {}).await.unwrap().into_inner().record_count, 1);
+}
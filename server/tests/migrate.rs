@@ -0,0 +1,35 @@
+use log_server::migrate::{migrate, summarize, Error};
+use log_server::models::ClientIdentity;
+use log_server::storage::Storage;
+
+#[tokio::test]
+async fn test_migrate_preserves_records() {
+    let dir = std::env::temp_dir().join(format!("log-server-migrate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let from = format!("sqlite:{}", dir.join("from.db").display());
+    let to = format!("sqlite:{}", dir.join("to.db").display());
+
+    let storage = Storage::new(log_server::db::init_pool(&from).await.unwrap());
+    for i in 0..5u64 {
+        storage
+            .write(format!("map:{}", i), i.to_string().into_bytes(), i, &ClientIdentity::default())
+            .await
+            .unwrap();
+    }
+
+    let summary = migrate(&from, &to).await.unwrap();
+    assert_eq!(summary.records, 5);
+
+    let copied = summarize(&log_server::db::init_pool(&to).await.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(copied, summary);
+
+    // A second run must not append on top of the copied log.
+    assert!(matches!(
+        migrate(&from, &to).await,
+        Err(Error::DestinationNotEmpty(5))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log_server::priority::Scheduler;
+
+#[tokio::test]
+async fn test_catch_up_runs_freely_without_writes() {
+    let scheduler = Scheduler::new(4);
+    tokio::time::timeout(Duration::from_millis(10), scheduler.catch_up())
+        .await
+        .expect("no write is waiting");
+
+    let unthrottled = Scheduler::new(0);
+    let _write = unthrottled.write();
+    tokio::time::timeout(Duration::from_millis(10), unthrottled.catch_up())
+        .await
+        .expect("ratio 0 disables throttling");
+}
+
+#[tokio::test]
+async fn test_catch_up_waits_for_ratio_writes_under_load() {
+    let scheduler = Arc::new(Scheduler::new(2));
+    let in_flight = scheduler.write();
+
+    let waiting = {
+        let scheduler = Arc::clone(&scheduler);
+        tokio::spawn(async move { scheduler.catch_up().await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiting.is_finished());
+
+    drop(scheduler.write());
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiting.is_finished());
+
+    drop(scheduler.write());
+    tokio::time::timeout(Duration::from_millis(50), waiting)
+        .await
+        .expect("two writes served")
+        .unwrap();
+    drop(in_flight);
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use log_server_test::TestServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn request(addr: std::net::SocketAddr, method: &str, path: &str, headers: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\n{}\r\n{}",
+        method,
+        path,
+        body.len(),
+        headers,
+        body
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response[9..12].parse().unwrap();
+    (status, response.split_once("\r\n\r\n").unwrap().1.to_string())
+}
+
+async fn spawn_gateway(server: &TestServer, token: Option<&str>) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = log_server::rest::router(Arc::clone(server.storage()), token);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    addr
+}
+
+#[tokio::test]
+async fn test_rest_gateway_reads_and_writes_keys() {
+    let server = TestServer::spawn().await;
+    server.storage().append("map:1".to_string(), b"one".to_vec()).await.unwrap();
+    let addr = spawn_gateway(&server, None).await;
+
+    let (status, body) = request(addr, "GET", "/v1/keys/map:1", "", "").await;
+    assert_eq!(status, 200);
+    let record: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(record["key"], "map:1");
+    assert_eq!(record["value"], "one");
+    assert_eq!(record["ordinal"], 1);
+
+    let (status, body) = request(addr, "PUT", "/v1/keys/map:%222%22", "", r#"{"value": "two"}"#).await;
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["ordinal"], 2);
+    let records = server.storage().history("map:\"2\"", 0, 0).await.unwrap();
+    assert_eq!(records.iter().map(|r| r.value.clone()).collect::<Vec<_>>(), vec![b"two".to_vec()]);
+
+    let (status, _) = request(addr, "DELETE", "/v1/keys/map:1", "", "").await;
+    assert_eq!(status, 200);
+    assert_eq!(request(addr, "GET", "/v1/keys/map:1", "", "").await.0, 404);
+    assert_eq!(request(addr, "GET", "/v1/keys/map:3", "", "").await.0, 404);
+    assert_eq!(request(addr, "PUT", "/v1/keys/map:3", "", r#"{"value": 3}"#).await.0, 400);
+}
+
+#[tokio::test]
+async fn test_rest_gateway_rejects_oversized_values() {
+    let server = TestServer::spawn().await;
+    server.storage().set_max_value_size(4);
+    let addr = spawn_gateway(&server, None).await;
+
+    let (status, _) = request(addr, "PUT", "/v1/keys/map:1", "", r#"{"value": "too long"}"#).await;
+    assert_eq!(status, 413);
+    assert!(server.storage().history("map:1", 0, 0).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_rest_gateway_checks_the_token() {
+    let server = TestServer::spawn().await;
+    let addr = spawn_gateway(&server, Some("s3cr3t")).await;
+
+    let body = r#"{"value": "one"}"#;
+    assert_eq!(request(addr, "PUT", "/v1/keys/map:1", "", body).await.0, 401);
+    assert_eq!(request(addr, "PUT", "/v1/keys/map:1", "Authorization: Bearer wrong\r\n", body).await.0, 401);
+    assert_eq!(request(addr, "PUT", "/v1/keys/map:1", "Authorization: Bearer s3cr3t\r\n", body).await.0, 200);
+    assert_eq!(request(addr, "GET", "/v1/keys/map:1", "", "").await.0, 401);
+    assert_eq!(request(addr, "GET", "/v1/keys/map:1", "Authorization: Bearer s3cr3t\r\n", "").await.0, 200);
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use log_server::models::ClientIdentity;
+use log_server::snapshot::{Error, Snapshot};
+use log_server::storage::Storage;
+use log_server_test::TestServer;
+use log_server_types::kv::GetSnapshotRequest;
+use log_server_types::kv::kv_server_client::KvServerClient;
+
+#[tokio::test]
+async fn test_binary_snapshot_round_trips() {
+    let dir = std::env::temp_dir().join(format!("snapshot-round-trip-{}", std::process::id()));
+    let snapshot = Snapshot::new(dir.to_str().unwrap(), 100).unwrap();
+    let records = vec![("map:1".to_string(), b"one".to_vec())];
+
+    snapshot.save_binary(7, &records).await.unwrap();
+    assert_eq!(snapshot.load_binary().await.unwrap(), records);
+    assert_eq!(snapshot.latest_ordinal().unwrap(), 7);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_load_binary_reports_truncated_file() {
+    let dir = std::env::temp_dir().join(format!("snapshot-truncated-{}", std::process::id()));
+    let snapshot = Snapshot::new(dir.to_str().unwrap(), 100).unwrap();
+    let data = log_snapshot_format::encode(&[("map:1", b"one")]).unwrap();
+    std::fs::write(dir.join("snapshot_1.bmap"), &data[..data.len() - 1]).unwrap();
+
+    assert!(matches!(
+        snapshot.load_binary().await,
+        Err(Error::Format(log_snapshot_format::Error::Truncated("value")))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_large_snapshots_stream_in_chunks() {
+    let dir = std::env::temp_dir().join(format!("snapshot-stream-{}", std::process::id()));
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::with_snapshot(pool, dir.to_str().unwrap(), u64::MAX).unwrap();
+    for i in 0..3 {
+        let value = vec![b'x'; 600 * 1024];
+        storage.write(format!("map:{}", i), value, 0, &ClientIdentity::default()).await.unwrap();
+    }
+    storage.snapshot_now().await.unwrap();
+    let server = TestServer::spawn_with_storage(Arc::new(storage)).await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let chunks: Vec<_> = client
+        .stream_snapshot(GetSnapshotRequest::default())
+        .await
+        .unwrap()
+        .into_inner()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    let whole = client.get_snapshot(GetSnapshotRequest::default()).await.unwrap().into_inner();
+
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| chunk.snapshot_ordinal == whole.snapshot_ordinal));
+    let data: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.data).collect();
+    assert_eq!(data, whole.snapshot_data);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_prefixed_snapshots_stream_only_their_keys() {
+    let dir = std::env::temp_dir().join(format!("snapshot-stream-prefix-{}", std::process::id()));
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::with_snapshot(pool, dir.to_str().unwrap(), u64::MAX).unwrap();
+    for i in 0..4 {
+        let value = vec![b'x'; 600 * 1024];
+        storage.write(format!("map:{}", i), value.clone(), 0, &ClientIdentity::default()).await.unwrap();
+        storage.write(format!("other:{}", i), value, 0, &ClientIdentity::default()).await.unwrap();
+    }
+    storage.snapshot_now().await.unwrap();
+    let server = TestServer::spawn_with_storage(Arc::new(storage)).await;
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let request = || GetSnapshotRequest {
+        key_prefix: "map:".to_string(),
+    };
+    let chunks: Vec<_> = client
+        .stream_snapshot(request())
+        .await
+        .unwrap()
+        .into_inner()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    let whole = client.get_snapshot(request()).await.unwrap().into_inner();
+
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| chunk.data.len() <= 1024 * 1024));
+    let data: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.data).collect();
+    assert_eq!(data, whole.snapshot_data);
+    let keys: Vec<_> = log_snapshot_format::decode(&data).unwrap().into_iter().map(|(key, _)| key).collect();
+    assert_eq!(keys, ["map:0", "map:1", "map:2", "map:3"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_recover_restores_a_database_behind_the_snapshot() {
+    let dir = std::env::temp_dir().join(format!("snapshot-recover-{}", std::process::id()));
+    let writer = ClientIdentity::default();
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::with_snapshot(pool, &dir, u64::MAX).unwrap();
+    for key in ["map:1", "map:2", "map:3"] {
+        storage.write(key.to_string(), b"value".to_vec(), 0, &writer).await.unwrap();
+    }
+    storage.write("map:2".to_string(), Vec::new(), 0, &writer).await.unwrap();
+    storage.snapshot_now().await.unwrap();
+
+    // Up to date: the log itself is kept.
+    let recovery = storage.recover().await.unwrap();
+    assert_eq!((recovery.snapshot_ordinal, recovery.head, recovery.restored), (4, 4, 0));
+
+    // A fresh database starts over from the snapshot.
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let restarted = Storage::with_snapshot(pool, &dir, 100).unwrap();
+    let recovery = restarted.recover().await.unwrap();
+    assert_eq!((recovery.snapshot_ordinal, recovery.head, recovery.restored), (4, 4, 2));
+    let ordinal = restarted.write("map:4".to_string(), b"value".to_vec(), 0, &writer).await.unwrap();
+    assert_eq!(ordinal, 5);
+    let stats = restarted.stats().await.unwrap();
+    assert_eq!((stats.key_count, stats.snapshot_ordinal), (3, 4));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_recover_from_an_empty_snapshot_keeps_counting_after_it() {
+    let dir = std::env::temp_dir().join(format!("snapshot-recover-empty-{}", std::process::id()));
+    let writer = ClientIdentity::default();
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::with_snapshot(pool, &dir, u64::MAX).unwrap();
+    storage.write("map:1".to_string(), b"value".to_vec(), 0, &writer).await.unwrap();
+    storage.write("map:1".to_string(), Vec::new(), 0, &writer).await.unwrap();
+    storage.snapshot_now().await.unwrap();
+
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let restarted = Storage::with_snapshot(pool.clone(), &dir, 2).unwrap();
+    let recovery = restarted.recover().await.unwrap();
+    assert_eq!((recovery.snapshot_ordinal, recovery.head, recovery.restored), (2, 2, 0));
+    let ordinal = restarted.write("map:2".to_string(), b"value".to_vec(), 0, &writer).await.unwrap();
+    assert_eq!(ordinal, 3);
+    let stats = restarted.stats().await.unwrap();
+    assert_eq!((stats.latest_ordinal, stats.snapshot_ordinal), (3, 2));
+
+    // The database is ahead of the snapshot now, so it is kept.
+    let again = Storage::with_snapshot(pool, &dir, 2).unwrap();
+    let recovery = again.recover().await.unwrap();
+    assert_eq!((recovery.snapshot_ordinal, recovery.head, recovery.restored), (2, 3, 0));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log_server_test::TestServer;
+use log_server_types::kv::{kv_server_client::KvServerClient, SubscribeRequest};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    response.split_once("\r\n\r\n").unwrap().1.to_string()
+}
+
+#[tokio::test]
+async fn test_status_page_reports_storage_state() {
+    let server = TestServer::spawn().await;
+    server.storage().append("map:1".to_string(), b"one".to_vec()).await.unwrap();
+    server.storage().activity().record_conflict("map:<1>", 1);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(log_server::status::serve_on(listener, Arc::clone(server.storage())));
+
+    let mut client = KvServerClient::connect(server.url()).await.unwrap();
+    let _subscription = client
+        .subscribe(SubscribeRequest { start_ordinal: 0, ..Default::default() })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let status: serde_json::Value = serde_json::from_str(&get(addr, "/status.json").await).unwrap();
+    assert_eq!(status["latest_ordinal"], 1);
+    assert_eq!(status["subscribers"], 1);
+    assert_eq!(status["recent_conflicts"][0]["key"], "map:<1>");
+
+    let page = get(addr, "/").await;
+    assert!(page.contains("map:&lt;1&gt;"));
+}
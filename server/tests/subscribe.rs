@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use log_server::models::ClientIdentity;
+use log_server::storage::{KeyFilter, Storage, LIVE_CAPACITY};
+
+async fn write(storage: &Storage, key: &str) {
+    storage
+        .write(key.to_string(), b"value".to_vec(), 0, &ClientIdentity::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_live_records_are_pushed() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::new(pool);
+    write(&storage, "map:1").await;
+
+    let mut records = storage.subscribe_from(0, KeyFilter::default());
+    assert_eq!(records.next().await.unwrap().ordinal, 1);
+
+    write(&storage, "map:2").await;
+    // Well under the old 100ms polling interval.
+    let record = tokio::time::timeout(Duration::from_millis(50), records.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((record.ordinal, record.key.as_str()), (2, "map:2"));
+}
+
+#[tokio::test]
+async fn test_lagging_subscriber_catches_up_from_the_database() {
+    let pool = log_server::db::init_pool("sqlite::memory:").await.unwrap();
+    let storage = Storage::new(pool);
+    write(&storage, "map:1").await;
+
+    let mut records = storage.subscribe_from(0, KeyFilter::default());
+    assert_eq!(records.next().await.unwrap().ordinal, 1);
+
+    let total = LIVE_CAPACITY as u64 + 50;
+    for i in 0..total {
+        write(&storage, &format!("map:{}", i)).await;
+    }
+
+    let ordinals: Vec<u64> = records.take(total as usize).map(|record| record.ordinal).collect().await;
+    assert_eq!(ordinals, (2..total + 2).collect::<Vec<_>>());
+}
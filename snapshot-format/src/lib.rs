@@ -0,0 +1,207 @@
+//! The BMAP snapshot format, shared by the server that writes snapshots and
+//! the clients that load them.
+//!
+//! All integers are little-endian:
+//!
+//! ```text
+//! magic    4 bytes   "BMAP"
+//! version  u32       currently 1
+//! count    u32       number of records
+//! count × {
+//!     key_len    u16
+//!     key        key_len bytes, UTF-8
+//!     value_len  u32
+//!     value      value_len bytes, empty for a deleted key
+//! }
+//! ```
+//!
+//! [`encode`] and [`decode`] work on whole buffers; [`Decoder`] accepts the
+//! same bytes in chunks of any size, and [`encode_header`] with
+//! [`encode_record`] produce them a record at a time.
+
+use thiserror::Error;
+
+pub const MAGIC: &[u8; 4] = b"BMAP";
+/// Version written by [`encode`]; [`decode`] accepts this version only.
+pub const VERSION: u32 = 1;
+/// Size of the magic, version and record count.
+pub const HEADER_LEN: usize = 12;
+
+/// A decoded record: the log key and its value.
+pub type Record = (String, Vec<u8>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("Invalid magic: {}", String::from_utf8_lossy(.0))]
+    InvalidMagic([u8; 4]),
+    #[error("Unsupported version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("Data truncated ({0})")]
+    Truncated(&'static str),
+    #[error("{0} bytes after the last record")]
+    TrailingData(usize),
+    #[error("Key too long: {0} bytes")]
+    KeyTooLong(usize),
+}
+
+/// The fixed-size start of a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    pub count: u32,
+}
+
+impl Header {
+    /// Reads and validates the header at the start of `data`.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let header = data.get(..HEADER_LEN).ok_or(Error::Truncated("header"))?;
+        let magic: [u8; 4] = header[0..4].try_into().unwrap();
+        if &magic != MAGIC {
+            return Err(Error::InvalidMagic(magic));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            version,
+            count: u32::from_le_bytes(header[8..12].try_into().unwrap()),
+        })
+    }
+}
+
+/// Encodes `records` in the current format version.
+pub fn encode<K: AsRef<str>, V: AsRef<[u8]>>(records: &[(K, V)]) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    buf.extend_from_slice(&encode_header(records.len() as u32));
+    for (key, value) in records {
+        encode_record(&mut buf, key.as_ref(), value.as_ref())?;
+    }
+    Ok(buf)
+}
+
+/// The header of a snapshot of `count` records, for writers that produce
+/// the records one by one with [`encode_record`].
+pub fn encode_header(count: u32) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[0..4].copy_from_slice(MAGIC);
+    header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..12].copy_from_slice(&count.to_le_bytes());
+    header
+}
+
+/// Appends one record to `buf`.
+pub fn encode_record(buf: &mut Vec<u8>, key: &str, value: &[u8]) -> Result<(), Error> {
+    let key = key.as_bytes();
+    let key_len = u16::try_from(key.len()).map_err(|_| Error::KeyTooLong(key.len()))?;
+    buf.extend_from_slice(&key_len.to_le_bytes());
+    buf.extend_from_slice(key);
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value);
+    Ok(())
+}
+
+/// Decodes a complete snapshot.
+pub fn decode(data: &[u8]) -> Result<Vec<Record>, Error> {
+    let mut records = Vec::new();
+    let mut decoder = Decoder::new();
+    decoder.feed(data, |key, value| records.push((key, value)))?;
+    decoder.finish()?;
+    Ok(records)
+}
+
+/// Incremental decoder.
+///
+/// Chunks can be split anywhere; only the bytes of a partially received
+/// record are buffered, so memory stays bounded by the largest record rather
+/// than the whole snapshot.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+    /// Records still expected, `None` until the header has been read.
+    remaining: Option<u32>,
+    records: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of records decoded so far.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Decodes as many records as `chunk` completes, passing each to
+    /// `on_record`.
+    pub fn feed(&mut self, chunk: &[u8], mut on_record: impl FnMut(String, Vec<u8>)) -> Result<(), Error> {
+        self.pending.extend_from_slice(chunk);
+        let mut offset = 0;
+
+        if self.remaining.is_none() {
+            if self.pending.len() < HEADER_LEN {
+                return Ok(());
+            }
+            self.remaining = Some(Header::parse(&self.pending)?.count);
+            offset = HEADER_LEN;
+        }
+
+        while let Some(remaining @ 1..) = self.remaining {
+            let Some((key, value, len)) = next_record(&self.pending[offset..]) else {
+                break;
+            };
+            offset += len;
+            self.records += 1;
+            self.remaining = Some(remaining - 1);
+            on_record(key, value);
+        }
+
+        self.pending.drain(..offset);
+        Ok(())
+    }
+
+    /// Checks that the snapshot ended right after its last record.
+    pub fn finish(self) -> Result<(), Error> {
+        match self.remaining {
+            None => Err(Error::Truncated("header")),
+            Some(0) if self.pending.is_empty() => Ok(()),
+            Some(0) => Err(Error::TrailingData(self.pending.len())),
+            Some(_) => {
+                let data = &self.pending;
+                let part = if data.len() < 2 {
+                    "key length"
+                } else {
+                    let key_len = u16::from_le_bytes([data[0], data[1]]) as usize;
+                    if data.len() < 2 + key_len {
+                        "key"
+                    } else if data.len() < 2 + key_len + 4 {
+                        "value length"
+                    } else {
+                        "value"
+                    }
+                };
+                Err(Error::Truncated(part))
+            }
+        }
+    }
+}
+
+/// Decodes one record from the front of `data` if it is complete, returning
+/// it with its encoded length.
+fn next_record(data: &[u8]) -> Option<(String, Vec<u8>, usize)> {
+    let key_len = u16::from_le_bytes(data.get(0..2)?.try_into().unwrap()) as usize;
+    let key = data.get(2..2 + key_len)?;
+    let offset = 2 + key_len;
+
+    let value_len = u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().unwrap()) as usize;
+    let value = data.get(offset + 4..offset + 4 + value_len)?;
+
+    Some((
+        String::from_utf8_lossy(key).to_string(),
+        value.to_vec(),
+        offset + 4 + value_len,
+    ))
+}
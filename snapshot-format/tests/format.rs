@@ -0,0 +1,87 @@
+use log_snapshot_format::{decode, encode, encode_header, encode_record, Decoder, Error, Header, VERSION};
+
+fn sample() -> Vec<(String, Vec<u8>)> {
+    vec![
+        ("map:1".to_string(), b"one".to_vec()),
+        ("map:2".to_string(), Vec::new()),
+        ("other".to_string(), b"three".to_vec()),
+    ]
+}
+
+#[test]
+fn test_round_trips() {
+    let data = encode(&sample()).unwrap();
+    assert_eq!(Header::parse(&data).unwrap(), Header { version: VERSION, count: 3 });
+    assert_eq!(decode(&data).unwrap(), sample());
+
+    let empty: &[(&str, &[u8])] = &[];
+    assert_eq!(decode(&encode(empty).unwrap()).unwrap(), Vec::new());
+}
+
+#[test]
+fn test_record_at_a_time_matches_encode() {
+    let mut data = encode_header(3).to_vec();
+    for (key, value) in sample() {
+        encode_record(&mut data, &key, &value).unwrap();
+    }
+    assert_eq!(data, encode(&sample()).unwrap());
+    assert_eq!(encode_record(&mut data, &"k".repeat(70_000), b""), Err(Error::KeyTooLong(70_000)));
+}
+
+#[test]
+fn test_chunked_decode_matches_whole_decode_at_every_chunk_size() {
+    let data = encode(&sample()).unwrap();
+
+    for size in 1..=data.len() {
+        let mut decoder = Decoder::new();
+        let mut records = Vec::new();
+        for chunk in data.chunks(size) {
+            decoder.feed(chunk, |key, value| records.push((key, value))).unwrap();
+        }
+        assert_eq!(decoder.records(), 3);
+        decoder.finish().unwrap();
+        assert_eq!(records, sample(), "chunk size {}", size);
+    }
+}
+
+#[test]
+fn test_truncated_input_is_an_error_at_every_length() {
+    let data = encode(&sample()).unwrap();
+    for len in 0..data.len() {
+        assert!(matches!(decode(&data[..len]), Err(Error::Truncated(_))), "accepted {} bytes", len);
+    }
+
+    let mut decoder = Decoder::new();
+    decoder.feed(&data[..data.len() - 1], |_, _| {}).unwrap();
+    assert_eq!(decoder.finish(), Err(Error::Truncated("value")));
+}
+
+#[test]
+fn test_huge_record_count_is_truncated_not_preallocated() {
+    let mut data = encode(&sample()).unwrap();
+    data[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(decode(&data), Err(Error::Truncated("key length")));
+}
+
+#[test]
+fn test_rejects_foreign_headers() {
+    let mut data = encode(&sample()).unwrap();
+    data[4..8].copy_from_slice(&2u32.to_le_bytes());
+    assert_eq!(decode(&data), Err(Error::UnsupportedVersion(2)));
+
+    data[0..4].copy_from_slice(b"BMP2");
+    assert_eq!(decode(&data), Err(Error::InvalidMagic(*b"BMP2")));
+}
+
+#[test]
+fn test_rejects_trailing_data() {
+    let mut data = encode(&sample()).unwrap();
+    data.extend_from_slice(b"xy");
+    assert_eq!(decode(&data), Err(Error::TrailingData(2)));
+}
+
+#[test]
+fn test_rejects_keys_longer_than_u16() {
+    let key = "k".repeat(u16::MAX as usize + 1);
+    assert_eq!(encode(&[(key, b"v")]), Err(Error::KeyTooLong(u16::MAX as usize + 1)));
+}
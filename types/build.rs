@@ -1,6 +1,12 @@
 fn main() {
     println!("cargo:rerun-if-changed=proto/kv.proto");
     tonic_prost_build::configure()
+        .type_attribute(
+            ".kv",
+            "#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]",
+        )
+        // proto3 fields are optional on the wire, so they are in JSON too.
+        .type_attribute(".kv", "#[cfg_attr(feature = \"serde\", serde(default))]")
         .compile_protos(&["proto/kv.proto"], &["proto/"])
         .expect("Failed to compile proto/kv.proto");
 }
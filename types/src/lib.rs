@@ -3,3 +3,94 @@ pub mod kv {
 }
 
 pub use kv::Record;
+
+/// Version of the KV protocol described by `kv.proto`.
+///
+/// Bumped when the meaning of existing fields changes. Servers that predate
+/// `GetServerInfo` are treated as version 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Names of optional capabilities advertised in `ServerInfo::features`.
+pub mod features {
+    /// Multiple requests on one `Write` stream, answered in order.
+    pub const BATCH_WRITES: &str = "batch-writes";
+    /// `Subscribe` and `GetSnapshot` filter by `key_prefix` on the server.
+    pub const PREFIX_SUBSCRIBE: &str = "prefix-subscribe";
+    /// `Subscribe` only sends the keys in `SubscribeRequest::keys`.
+    pub const KEY_SUBSCRIBE: &str = "key-subscribe";
+    /// The `StreamSnapshot` RPC sends snapshots in chunks instead of one
+    /// message.
+    pub const CHUNKED_SNAPSHOTS: &str = "chunked-snapshots";
+    /// `Subscribe` honours `SubscribeRequest::start_timestamp`.
+    pub const TIMESTAMP_SUBSCRIBE: &str = "timestamp-subscribe";
+    /// The `Stats` RPC is available.
+    pub const STATS: &str = "stats";
+    /// `WriteRequest::if_unchanged` is honoured.
+    pub const CONDITIONAL_WRITES: &str = "conditional-writes";
+    /// The `Compact` RPC is available.
+    pub const COMPACT: &str = "compact";
+    /// The `Transaction` RPC is available.
+    pub const TRANSACTIONS: &str = "transactions";
+    /// `WriteRequest::expires_at` is honoured.
+    pub const TTL: &str = "ttl";
+    /// The `GetHistory` RPC is available.
+    pub const HISTORY: &str = "history";
+}
+
+/// gRPC metadata keys of the client handshake, sent with every call.
+///
+/// Servers only log them, to point out outdated clients; every client is
+/// served the same way, including old ones that send nothing.
+pub mod metadata {
+    /// Protocol version the client speaks, as a decimal number.
+    pub const PROTOCOL_VERSION: &str = "x-log-protocol-version";
+    /// Client name and version, e.g. `log-map/0.1.0`, for logs.
+    pub const CLIENT: &str = "x-log-client";
+    /// Comma-separated capability names from [`crate::features`] that the
+    /// client knows how to use.
+    pub const CAPABILITIES: &str = "x-log-capabilities";
+
+    use tonic::metadata::{Ascii, MetadataValue};
+    use tonic::service::Interceptor;
+    use tonic::{Request, Status};
+
+    /// Client interceptor that attaches the handshake to every request.
+    ///
+    /// ```ignore
+    /// let client = KvServerClient::with_interceptor(channel, Handshake::new("logctl/0.1.0", &[]));
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct Handshake {
+        protocol_version: MetadataValue<Ascii>,
+        client: MetadataValue<Ascii>,
+        capabilities: MetadataValue<Ascii>,
+    }
+
+    impl Handshake {
+        /// Non-ASCII names are replaced with `unknown` rather than failing
+        /// every request.
+        pub fn new(client: &str, capabilities: &[&str]) -> Self {
+            let ascii = |value: String| {
+                value
+                    .parse()
+                    .unwrap_or_else(|_| MetadataValue::from_static("unknown"))
+            };
+
+            Self {
+                protocol_version: MetadataValue::from(crate::PROTOCOL_VERSION),
+                client: ascii(client.to_string()),
+                capabilities: ascii(capabilities.join(",")),
+            }
+        }
+    }
+
+    impl Interceptor for Handshake {
+        fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+            let metadata = request.metadata_mut();
+            metadata.insert(PROTOCOL_VERSION, self.protocol_version.clone());
+            metadata.insert(CLIENT, self.client.clone());
+            metadata.insert(CAPABILITIES, self.capabilities.clone());
+            Ok(request)
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use log_server_types::kv::{ServerInfo, WriteRequest};
+use log_server_types::Record;
+
+#[test]
+fn test_record_round_trips_through_json() {
+    let record = Record {
+        ordinal: 7,
+        key: "map:1".to_string(),
+        value: b"hi".to_vec(),
+        timestamp: 1_700_000_000_000,
+    };
+
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"ordinal": 7, "key": "map:1", "value": [104, 105], "timestamp": 1_700_000_000_000i64})
+    );
+    assert_eq!(serde_json::from_value::<Record>(json).unwrap(), record);
+}
+
+#[test]
+fn test_missing_fields_take_proto_defaults() {
+    let request: WriteRequest = serde_json::from_str(r#"{"key": "map:2"}"#).unwrap();
+    assert_eq!(request.key, "map:2");
+    assert_eq!(request.latest_known, 0);
+    assert!(request.value.is_empty());
+
+    let info: ServerInfo = serde_json::from_str(r#"{"features": ["stats"]}"#).unwrap();
+    assert_eq!(info.features, vec!["stats".to_string()]);
+}